@@ -32,4 +32,8 @@ pub enum Subcommand {
 
 	/// Revert the chain to a previous state.
 	Revert(sc_cli::RevertCmd),
+
+	/// Benchmark runtime pallets.
+	#[structopt(name = "benchmark")]
+	Benchmark(frame_benchmarking_cli::BenchmarkCmd),
 }
@@ -17,6 +17,7 @@ native_executor_instance!(
 	pub Executor,
 	runtime::api::dispatch,
 	runtime::native_version,
+	frame_benchmarking::benchmarking::HostFunctions,
 );
 
 type FullClient = sc_service::TFullClient<Block, RuntimeApi, Executor>;
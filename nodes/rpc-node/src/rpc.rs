@@ -2,8 +2,9 @@
 
 use std::sync::Arc;
 
-use runtime::{opaque::Block, Hash};
+use runtime::{opaque::Block, AccountId, Hash};
 use sp_api::ProvideRuntimeApi;
+use sc_client_api::ProofProvider;
 use sp_blockchain::{Error as BlockChainError, HeaderMetadata, HeaderBackend};
 use sp_block_builder::BlockBuilder;
 pub use sc_rpc_api::DenyUnsafe;
@@ -29,9 +30,12 @@ pub fn create_full<C, P>(
 ) -> jsonrpc_core::IoHandler<sc_rpc::Metadata> where
 	C: ProvideRuntimeApi<Block>,
 	C: HeaderBackend<Block> + HeaderMetadata<Block, Error=BlockChainError> + 'static,
+	C: ProofProvider<Block>,
 	C: Send + Sync + 'static,
 	C::Api: BlockBuilder<Block>,
 	C::Api: sum_storage_runtime_api::SumStorageApi<Block>,
+	C::Api: simple_crowdfund_runtime_api::CrowdfundApi<Block>,
+	C::Api: map_set_runtime_api::MapSetApi<Block, AccountId>,
 	P: TransactionPool + 'static,
 {
 	let mut io = jsonrpc_core::IoHandler::default();
@@ -45,7 +49,21 @@ pub fn create_full<C, P>(
 	// Add a second RPC extension
 	// Because this one calls a Runtime API it needs a reference to the client.
 	io.extend_with(
-		sum_storage_rpc::SumStorageApi::to_delegate(sum_storage_rpc::SumStorage::new(client))
+		sum_storage_rpc::SumStorageApi::to_delegate(sum_storage_rpc::SumStorage::new(client.clone()))
+	);
+
+	// Add an RPC that pages through the members of the map set, so that clients never have to
+	// download the whole set at once.
+	io.extend_with(
+		map_set_rpc::MapSetApi::<_, AccountId>::to_delegate(map_set_rpc::MapSet::new(client.clone()))
+	);
+
+	// Add an RPC that proves contributions to a crowdfund. Besides the Runtime API it uses the
+	// client to generate storage proofs.
+	io.extend_with(
+		simple_crowdfund_rpc::CrowdfundApi::<_, AccountId>::to_delegate(
+			simple_crowdfund_rpc::Crowdfund::new(client)
+		)
 	);
 
 	// The final RPC extension receives commands for the manual seal consensus engine.
@@ -5,16 +5,47 @@
 //! Funds can be added to the pot in two ways:
 //! * Anyone can make a donation through the `donate` extrinsic.
 //! * An imablance can be absorbed from somewhere else in the runtime.
-//! Funds can only be allocated by a root call to the `allocate` extrinsic/
+//! Funds can be allocated in two ways:
+//! * A root call to the `allocate` extrinsic.
+//! * A grant proposal approved by the charity's members.
+//!
+//! Anyone may propose a grant by naming a destination, an amount and the hash of a description.
+//! The proposer reserves `ProposalBond` for the duration of the vote. During the `VotingPeriod`,
+//! members of the `Members` account set vote on the grant. When the period ends the grant is
+//! approved if the ayes outnumber the nays. An approved grant is queued for payment and the bond is
+//! returned. A rejected grant's bond is slashed to the pot.
+//!
+//! Queued grants are paid at the end of every `SpendPeriod`, within a budget of `Budget` of the
+//! pot. Grants that do not fit in the budget, or whose payment fails, stay queued for the next
+//! period, but are dropped after `MaxPayoutPeriods` unpaid periods. A `Burn` fraction of the pot
+//! that is left is then burned, so that the pot does not grow indefinitely.
+//!
+//! The charity keeps track of the total amount given by each donor. Donors are recognised with a
+//! tier once their total reaches one of the `DonorTiers` thresholds. Donors may also set up a
+//! recurring donation, which is made automatically every `period` blocks until it is cancelled.
+//! Setting one up reserves a `RecurringDeposit`, which is returned when it ends. At most
+//! `MaxRecurringPerBlock` recurring donations are scheduled in a single block; further donations
+//! are scheduled in the next block that has room. A recurring donation the donor can no longer
+//! afford is cancelled.
 #![cfg_attr(not(feature = "std"), no_std)]
 
-use sp_runtime::{traits::AccountIdConversion, ModuleId};
+use account_set::AccountSetExt;
+use parity_scale_codec::{Decode, Encode};
+use sp_runtime::{
+	traits::{AccountIdConversion, One, Saturating, Zero},
+	ModuleId, Permill,
+};
 use sp_std::prelude::*;
 
 use frame_support::{
-	decl_event, decl_module, decl_storage,
+	decl_error, decl_event, decl_module, decl_storage,
 	dispatch::{DispatchError, DispatchResult},
-	traits::{Currency, ExistenceRequirement::AllowDeath, Imbalance, OnUnbalanced},
+	ensure,
+	traits::{
+		BalanceStatus, Currency, ExistenceRequirement, ExistenceRequirement::{AllowDeath, KeepAlive},
+		Get, Imbalance, OnUnbalanced, ReservableCurrency, WithdrawReason,
+	},
+	weights::Weight,
 };
 use frame_system::{self as system, ensure_root, ensure_signed};
 
@@ -24,6 +55,16 @@ mod tests;
 type BalanceOf<T> = <<T as Trait>::Currency as Currency<<T as system::Trait>::AccountId>>::Balance;
 type NegativeImbalanceOf<T> =
 	<<T as Trait>::Currency as Currency<<T as system::Trait>::AccountId>>::NegativeImbalance;
+type GrantOf<T> = Grant<
+	<T as system::Trait>::AccountId,
+	BalanceOf<T>,
+	<T as system::Trait>::BlockNumber,
+	<T as system::Trait>::Hash,
+>;
+type RecurringDonationOf<T> = RecurringDonation<BalanceOf<T>, <T as system::Trait>::BlockNumber>;
+
+/// Simple index for identifying a grant proposal
+pub type GrantIndex = u32;
 
 /// Hardcoded pallet ID; used to create the special Pot Account
 /// Must be exactly 8 characters long
@@ -33,12 +74,105 @@ pub trait Trait: system::Trait {
 	/// The overarching event type.
 	type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
 	/// The currency type that the charity deals in
-	type Currency: Currency<Self::AccountId>;
+	type Currency: ReservableCurrency<Self::AccountId>;
+
+	/// The accounts that vote on grant proposals
+	type Members: AccountSetExt<AccountId = Self::AccountId>;
+
+	/// The amount reserved from the proposer of a grant until the vote ends
+	type ProposalBond: Get<BalanceOf<Self>>;
+
+	/// The number of blocks during which members may vote on a grant
+	type VotingPeriod: Get<Self::BlockNumber>;
+
+	/// The number of blocks between payouts of approved grants
+	type SpendPeriod: Get<Self::BlockNumber>;
+
+	/// The fraction of the pot that may be paid out in a single spend period
+	type Budget: Get<Permill>;
+
+	/// The fraction of the pot left after the payouts that is burned at the end of each spend
+	/// period
+	type Burn: Get<Permill>;
+
+	/// The number of spend periods an approved grant may go unpaid before it is dropped
+	type MaxPayoutPeriods: Get<u32>;
+
+	/// The total donations needed to reach each donor tier, in ascending order
+	type DonorTiers: Get<Vec<BalanceOf<Self>>>;
+
+	/// The amount reserved from a donor while they have a recurring donation
+	type RecurringDeposit: Get<BalanceOf<Self>>;
+
+	/// The maximum number of recurring donations made in a single block, at least one
+	type MaxRecurringPerBlock: Get<u32>;
+}
+
+/// A proposal to pay a grant from the charity's pot
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct Grant<AccountId, Balance, BlockNumber, Hash> {
+	/// The account that proposed the grant and placed the bond
+	pub proposer: AccountId,
+	/// The account that receives the grant if it is approved
+	pub dest: AccountId,
+	/// The amount of the grant
+	pub amount: Balance,
+	/// The hash of a description of the grant's purpose, kept off chain
+	pub description: Hash,
+	/// The amount reserved from the proposer
+	pub bond: Balance,
+	/// The block at which voting ends
+	pub end: BlockNumber,
+	/// The number of members approving the grant
+	pub ayes: u32,
+	/// The number of members rejecting the grant
+	pub nays: u32,
+}
+
+/// A donation that is made automatically every `period` blocks until it is cancelled
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct RecurringDonation<Balance, BlockNumber> {
+	/// The amount donated each time
+	pub amount: Balance,
+	/// The number of blocks between donations
+	pub period: BlockNumber,
+	/// The block at which the next donation is due
+	pub next: BlockNumber,
+	/// The amount reserved from the donor until the recurring donation ends
+	pub deposit: Balance,
 }
 
 decl_storage! {
 	trait Store for Module<T: Trait> as SimpleTreasury {
-		// No storage items of our own, but we still need decl_storage to initialize the pot
+		/// Grant proposals that are being voted on
+		Grants get(fn grants): map hasher(twox_64_concat) GrantIndex => Option<GrantOf<T>>;
+
+		/// The total number of grants that have been proposed
+		GrantCount get(fn grant_count): GrantIndex;
+
+		/// The votes cast on each grant
+		GrantVotes get(fn grant_votes):
+			double_map hasher(twox_64_concat) GrantIndex, hasher(blake2_128_concat) T::AccountId => Option<bool>;
+
+		/// The grants whose voting period ends at each block
+		GrantEnds get(fn grant_ends): map hasher(twox_64_concat) T::BlockNumber => Vec<GrantIndex>;
+
+		/// Approved grants waiting to be paid, in order of approval (index, destination, amount,
+		/// unpaid spend periods)
+		Payouts get(fn payouts): Vec<(GrantIndex, T::AccountId, BalanceOf<T>, u32)>;
+
+		/// The total amount each donor has given to the charity
+		TotalDonated get(fn total_donated): map hasher(blake2_128_concat) T::AccountId => BalanceOf<T>;
+
+		/// The recurring donation of each donor, if any
+		Recurring get(fn recurring):
+			map hasher(blake2_128_concat) T::AccountId => Option<RecurringDonationOf<T>>;
+
+		/// The donors whose recurring donations are due at each block
+		RecurringDue get(fn recurring_due):
+			map hasher(twox_64_concat) T::BlockNumber => Vec<T::AccountId>;
 	}
 	add_extra_genesis {
 		build(|_config| {
@@ -56,6 +190,7 @@ decl_event!(
 	where
 		Balance = BalanceOf<T>,
 		<T as system::Trait>::AccountId,
+		<T as system::Trait>::BlockNumber,
 	{
 		/// Donor has made a charitable donation to the charity
 		DonationReceived(AccountId, Balance, Balance),
@@ -63,15 +198,78 @@ decl_event!(
 		ImbalanceAbsorbed(Balance, Balance),
 		/// Charity has allocated funds to a cause
 		FundsAllocated(AccountId, Balance, Balance),
+		/// A grant was proposed (index, proposer, destination, amount)
+		GrantProposed(GrantIndex, AccountId, AccountId, Balance),
+		/// A member voted on a grant (index, member, approve)
+		GrantVoted(GrantIndex, AccountId, bool),
+		/// A grant was approved by the members
+		GrantApproved(GrantIndex),
+		/// A spend period began with the given budget
+		Spending(Balance),
+		/// Part of the pot left after the payouts was burned
+		Burnt(Balance),
+		/// An approved grant went unpaid for `MaxPayoutPeriods` spend periods and was dropped
+		GrantDropped(GrantIndex),
+		/// A grant was rejected and the proposer's bond was slashed to the pot (index, bond)
+		GrantRejected(GrantIndex, Balance),
+		/// A donor's total donations reached a new tier (donor, tier)
+		TierReached(AccountId, u32),
+		/// A recurring donation was set up (donor, amount, period)
+		RecurringDonationSet(AccountId, Balance, BlockNumber),
+		/// A recurring donation was cancelled by the donor
+		RecurringDonationCancelled(AccountId),
+		/// A recurring donation could not be made and was cancelled (donor, amount)
+		RecurringDonationFailed(AccountId, Balance),
 		/// For testing purposes, to impl From<()> for TestEvent to assign `()` to balances::Event
 		NullEvent(u32), // u32 could be aliases as an error code for mocking setup
 	}
 );
 
+decl_error! {
+	pub enum Error for Module<T: Trait> {
+		/// The grant index specified does not exist
+		InvalidIndex,
+		/// Only members may vote on grants
+		NotMember,
+		/// The member has already voted on this grant
+		AlreadyVoted,
+		/// The proposer cannot afford the bond
+		InsufficientBond,
+		/// A recurring donation must be greater than zero
+		ZeroAmount,
+		/// A recurring donation must have a period of at least one block
+		ZeroPeriod,
+		/// The donor has no recurring donation
+		NoRecurringDonation,
+		/// The donor cannot afford the deposit of a recurring donation
+		InsufficientDeposit,
+	}
+}
+
 decl_module! {
 	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
 		fn deposit_event() = default;
 
+		type Error = Error<T>;
+
+		const ProposalBond: BalanceOf<T> = T::ProposalBond::get();
+
+		const VotingPeriod: T::BlockNumber = T::VotingPeriod::get();
+
+		const SpendPeriod: T::BlockNumber = T::SpendPeriod::get();
+
+		const Budget: Permill = T::Budget::get();
+
+		const Burn: Permill = T::Burn::get();
+
+		const MaxPayoutPeriods: u32 = T::MaxPayoutPeriods::get();
+
+		const DonorTiers: Vec<BalanceOf<T>> = T::DonorTiers::get();
+
+		const RecurringDeposit: BalanceOf<T> = T::RecurringDeposit::get();
+
+		const MaxRecurringPerBlock: u32 = T::MaxRecurringPerBlock::get();
+
 		/// Donate some funds to the charity
 		#[weight = 10_000]
 		fn donate(
@@ -79,11 +277,53 @@ decl_module! {
 			amount: BalanceOf<T>
 		) -> DispatchResult {
 			let donor = ensure_signed(origin)?;
+			Self::receive_donation(&donor, amount, AllowDeath)
+		}
 
-			T::Currency::transfer(&donor, &Self::account_id(), amount, AllowDeath)
-				.map_err(|_| DispatchError::Other("Can't make donation"))?;
+		/// Donate `amount` to the charity every `period` blocks, starting `period` blocks from now.
+		/// Replaces any recurring donation the caller already has. A new recurring donation
+		/// reserves `RecurringDeposit`, a replacement keeps the deposit of the old one.
+		#[weight = 10_000]
+		fn set_recurring_donation(
+			origin,
+			amount: BalanceOf<T>,
+			period: T::BlockNumber,
+		) -> DispatchResult {
+			let donor = ensure_signed(origin)?;
+			ensure!(!amount.is_zero(), Error::<T>::ZeroAmount);
+			ensure!(!period.is_zero(), Error::<T>::ZeroPeriod);
+
+			let deposit = match Self::recurring(&donor) {
+				Some(old) => {
+					Self::unschedule_recurring(&donor, old.next);
+					old.deposit
+				}
+				None => {
+					let deposit = T::RecurringDeposit::get();
+					T::Currency::reserve(&donor, deposit)
+						.map_err(|_| Error::<T>::InsufficientDeposit)?;
+					deposit
+				}
+			};
 
-			Self::deposit_event(RawEvent::DonationReceived(donor, amount, Self::pot()));
+			let due = <system::Module<T>>::block_number().saturating_add(period);
+			let next = Self::schedule_recurring(&donor, due);
+			<Recurring<T>>::insert(&donor, RecurringDonation { amount, period, next, deposit });
+
+			Self::deposit_event(RawEvent::RecurringDonationSet(donor, amount, period));
+			Ok(())
+		}
+
+		/// Cancel the caller's recurring donation
+		#[weight = 10_000]
+		fn cancel_recurring_donation(origin) -> DispatchResult {
+			let donor = ensure_signed(origin)?;
+			let recurring = <Recurring<T>>::take(&donor).ok_or(Error::<T>::NoRecurringDonation)?;
+
+			Self::unschedule_recurring(&donor, recurring.next);
+			T::Currency::unreserve(&donor, recurring.deposit);
+
+			Self::deposit_event(RawEvent::RecurringDonationCancelled(donor));
 			Ok(())
 		}
 
@@ -112,6 +352,97 @@ decl_module! {
 			Self::deposit_event(RawEvent::FundsAllocated(dest, amount, Self::pot()));
 			Ok(())
 		}
+
+		/// Propose a grant from the Charity's pot
+		///
+		/// The proposer reserves `ProposalBond`, which is returned if the members approve the grant
+		/// and slashed to the pot if they reject it.
+		#[weight = 10_000]
+		fn propose_grant(
+			origin,
+			dest: T::AccountId,
+			amount: BalanceOf<T>,
+			description: T::Hash,
+		) -> DispatchResult {
+			let proposer = ensure_signed(origin)?;
+
+			let bond = T::ProposalBond::get();
+			T::Currency::reserve(&proposer, bond).map_err(|_| Error::<T>::InsufficientBond)?;
+
+			let index = GrantCount::get();
+			GrantCount::put(index + 1);
+
+			let end = <system::Module<T>>::block_number() + T::VotingPeriod::get();
+			<Grants<T>>::insert(index, Grant {
+				proposer: proposer.clone(),
+				dest: dest.clone(),
+				amount,
+				description,
+				bond,
+				end,
+				ayes: 0,
+				nays: 0,
+			});
+			<GrantEnds<T>>::append(end, index);
+
+			Self::deposit_event(RawEvent::GrantProposed(index, proposer, dest, amount));
+			Ok(())
+		}
+
+		/// Vote on a grant proposal. Only members may vote, once per grant.
+		#[weight = 10_000]
+		fn vote_grant(origin, index: GrantIndex, approve: bool) -> DispatchResult {
+			let member = ensure_signed(origin)?;
+			ensure!(T::Members::contains(&member), Error::<T>::NotMember);
+
+			let mut grant = Self::grants(index).ok_or(Error::<T>::InvalidIndex)?;
+			ensure!(!<GrantVotes<T>>::contains_key(index, &member), Error::<T>::AlreadyVoted);
+
+			if approve {
+				grant.ayes += 1;
+			} else {
+				grant.nays += 1;
+			}
+			<Grants<T>>::insert(index, grant);
+			<GrantVotes<T>>::insert(index, &member, approve);
+
+			Self::deposit_event(RawEvent::GrantVoted(index, member, approve));
+			Ok(())
+		}
+
+		/// Concludes the votes on the grants whose voting period ends in this block and makes the
+		/// recurring donations that are due, of which there are at most `MaxRecurringPerBlock`.
+		/// Also accounts for the weight of the payouts made in `on_finalize` at the end of a spend
+		/// period.
+		fn on_initialize(n: T::BlockNumber) -> Weight {
+			let ending = <GrantEnds<T>>::take(n);
+			for index in ending.iter() {
+				Self::conclude_grant(*index);
+			}
+
+			let due = <RecurringDue<T>>::take(n);
+			for donor in due.iter() {
+				Self::make_recurring_donation(donor, n);
+			}
+
+			let mut weight = T::DbWeight::get().reads_writes(2, 2)
+				.saturating_add(T::DbWeight::get().reads_writes(4, 6).saturating_mul(ending.len() as Weight))
+				.saturating_add(T::DbWeight::get().reads_writes(6, 6).saturating_mul(due.len() as Weight));
+			if (n % T::SpendPeriod::get()).is_zero() {
+				let payouts = <Payouts<T>>::decode_len().unwrap_or(0) as Weight;
+				weight = weight.saturating_add(T::DbWeight::get().reads_writes(3, 3))
+					.saturating_add(T::DbWeight::get().reads_writes(2, 2).saturating_mul(payouts));
+			}
+			weight
+		}
+
+		/// Pays the queued grants and burns part of the remaining pot at the end of each spend
+		/// period
+		fn on_finalize(n: T::BlockNumber) {
+			if (n % T::SpendPeriod::get()).is_zero() {
+				Self::spend_funds();
+			}
+		}
 	}
 }
 
@@ -125,6 +456,140 @@ impl<T: Trait> Module<T> {
 	fn pot() -> BalanceOf<T> {
 		T::Currency::free_balance(&Self::account_id())
 	}
+
+	/// The donor tier reached by `who`, which is the number of `DonorTiers` thresholds their total
+	/// donations have reached. Zero means the donor has not reached any tier yet.
+	pub fn donor_tier(who: &T::AccountId) -> u32 {
+		let total = Self::total_donated(who);
+		T::DonorTiers::get().iter().filter(|threshold| total >= **threshold).count() as u32
+	}
+
+	/// Moves a donation into the pot and records it in the donor's total
+	fn receive_donation(
+		donor: &T::AccountId,
+		amount: BalanceOf<T>,
+		existence: ExistenceRequirement,
+	) -> DispatchResult {
+		T::Currency::transfer(donor, &Self::account_id(), amount, existence)
+			.map_err(|_| DispatchError::Other("Can't make donation"))?;
+
+		let tier = Self::donor_tier(donor);
+		<TotalDonated<T>>::mutate(donor, |total| *total = total.saturating_add(amount));
+		Self::deposit_event(RawEvent::DonationReceived(donor.clone(), amount, Self::pot()));
+
+		let new_tier = Self::donor_tier(donor);
+		if new_tier > tier {
+			Self::deposit_event(RawEvent::TierReached(donor.clone(), new_tier));
+		}
+		Ok(())
+	}
+
+	/// Makes a recurring donation that is due now and schedules the next one. The donor's account
+	/// is kept alive; if the donation cannot be made it is cancelled and the deposit is returned.
+	fn make_recurring_donation(donor: &T::AccountId, now: T::BlockNumber) {
+		let mut recurring = match Self::recurring(donor) {
+			Some(recurring) => recurring,
+			None => return,
+		};
+
+		match Self::receive_donation(donor, recurring.amount, KeepAlive) {
+			Ok(()) => {
+				let due = now.saturating_add(recurring.period);
+				recurring.next = Self::schedule_recurring(donor, due);
+				<Recurring<T>>::insert(donor, recurring);
+			}
+			Err(_) => {
+				<Recurring<T>>::remove(donor);
+				T::Currency::unreserve(donor, recurring.deposit);
+				Self::deposit_event(RawEvent::RecurringDonationFailed(donor.clone(), recurring.amount));
+			}
+		}
+	}
+
+	/// Schedules a recurring donation in the first block from `due` on that has room for it, and
+	/// returns that block
+	fn schedule_recurring(donor: &T::AccountId, due: T::BlockNumber) -> T::BlockNumber {
+		let limit = T::MaxRecurringPerBlock::get().max(1) as usize;
+		let mut at = due;
+		while <RecurringDue<T>>::decode_len(at).unwrap_or(0) >= limit {
+			at = at.saturating_add(One::one());
+		}
+		<RecurringDue<T>>::append(at, donor);
+		at
+	}
+
+	/// Removes a recurring donation from the block it is scheduled in
+	fn unschedule_recurring(donor: &T::AccountId, at: T::BlockNumber) {
+		<RecurringDue<T>>::mutate(at, |due| due.retain(|who| who != donor));
+	}
+
+	/// Queues an approved grant for payment and returns the bond, or slashes the bond of a rejected
+	/// grant
+	fn conclude_grant(index: GrantIndex) {
+		let grant = match <Grants<T>>::take(index) {
+			Some(grant) => grant,
+			None => return,
+		};
+		<GrantVotes<T>>::remove_prefix(index);
+
+		if grant.ayes > grant.nays {
+			T::Currency::unreserve(&grant.proposer, grant.bond);
+			<Payouts<T>>::append((index, grant.dest, grant.amount, 0));
+			Self::deposit_event(RawEvent::GrantApproved(index));
+		} else {
+			let _ = T::Currency::repatriate_reserved(
+				&grant.proposer,
+				&Self::account_id(),
+				grant.bond,
+				BalanceStatus::Free,
+			);
+			Self::deposit_event(RawEvent::GrantRejected(index, grant.bond));
+		}
+	}
+
+	/// Pays the queued grants that fit in this period's budget and drops those that went unpaid
+	/// for too long, then burns a fraction of the pot that is left. The pot's minimum balance is
+	/// never spent, so the pot stays alive.
+	fn spend_funds() {
+		let spendable = Self::pot().saturating_sub(T::Currency::minimum_balance());
+		let budget = T::Budget::get() * spendable;
+		Self::deposit_event(RawEvent::Spending(budget));
+
+		let mut remaining = budget;
+		let payouts = Self::payouts()
+			.into_iter()
+			.filter_map(|(index, dest, amount, periods)| {
+				if amount <= remaining
+					&& T::Currency::transfer(&Self::account_id(), &dest, amount, KeepAlive).is_ok()
+				{
+					remaining -= amount;
+					Self::deposit_event(RawEvent::FundsAllocated(dest, amount, Self::pot()));
+					return None;
+				}
+				let periods = periods.saturating_add(1);
+				if periods >= T::MaxPayoutPeriods::get() {
+					Self::deposit_event(RawEvent::GrantDropped(index));
+					return None;
+				}
+				Some((index, dest, amount, periods))
+			})
+			.collect::<Vec<_>>();
+		<Payouts<T>>::put(payouts);
+
+		let unspent = Self::pot().saturating_sub(T::Currency::minimum_balance());
+		let burn = T::Burn::get() * unspent;
+		if !burn.is_zero() {
+			let burnt = T::Currency::withdraw(
+				&Self::account_id(),
+				burn,
+				WithdrawReason::Transfer.into(),
+				KeepAlive,
+			);
+			if burnt.is_ok() {
+				Self::deposit_event(RawEvent::Burnt(burn));
+			}
+		}
+	}
 }
 
 // This implementation allows the charity to be the recipient of funds that are burned elsewhere in
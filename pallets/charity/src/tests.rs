@@ -1,14 +1,19 @@
 use crate::*;
+use account_set::AccountSet;
 use balances;
-use frame_support::{assert_err, assert_ok, impl_outer_event, impl_outer_origin, parameter_types};
+use frame_support::{
+	assert_err, assert_noop, assert_ok, impl_outer_event, impl_outer_origin, parameter_types,
+	traits::{OnFinalize, OnInitialize},
+};
 use frame_system::{self as system, EventRecord, Phase, RawOrigin};
 use sp_core::H256;
 use sp_io;
 use sp_runtime::{
 	testing::Header,
 	traits::{BlakeTwo256, IdentityLookup},
-	Perbill,
+	Perbill, Permill,
 };
+use sp_std::collections::btree_set::BTreeSet;
 
 impl_outer_origin! {
 	pub enum Origin for TestRuntime {}
@@ -26,6 +31,15 @@ parameter_types! {
 	pub const ExistentialDeposit: u64 = 1;
 	pub const TransferFee: u64 = 0;
 	pub const CreationFee: u64 = 0;
+	pub const ProposalBond: u64 = 2;
+	pub const VotingPeriod: u64 = 3;
+	pub const SpendPeriod: u64 = 5;
+	pub const Budget: Permill = Permill::from_percent(50);
+	pub const Burn: Permill = Permill::from_percent(20);
+	pub const MaxPayoutPeriods: u32 = 3;
+	pub DonorTiers: Vec<u64> = vec![12, 18];
+	pub const RecurringDeposit: u64 = 1;
+	pub const MaxRecurringPerBlock: u32 = 2;
 }
 impl system::Trait for TestRuntime {
 	type BaseCallFilter = ();
@@ -77,15 +91,49 @@ impl_outer_event! {
 	}
 }
 
+/// Accounts 1, 2 and 3 are members of the charity
+pub struct TestMembers;
+impl AccountSet for TestMembers {
+	type AccountId = u64;
+
+	fn accounts() -> BTreeSet<u64> {
+		vec![1, 2, 3].into_iter().collect()
+	}
+}
+impl AccountSetExt for TestMembers {}
+
 impl Trait for TestRuntime {
 	type Event = TestEvent;
 	type Currency = balances::Module<Self>;
+	type Members = TestMembers;
+	type ProposalBond = ProposalBond;
+	type VotingPeriod = VotingPeriod;
+	type SpendPeriod = SpendPeriod;
+	type Budget = Budget;
+	type Burn = Burn;
+	type MaxPayoutPeriods = MaxPayoutPeriods;
+	type DonorTiers = DonorTiers;
+	type RecurringDeposit = RecurringDeposit;
+	type MaxRecurringPerBlock = MaxRecurringPerBlock;
 }
 
 pub type System = system::Module<TestRuntime>;
 pub type Balances = balances::Module<TestRuntime>;
 pub type Charity = Module<TestRuntime>;
 
+/// Moves the chain to block `n`, running this pallet's hooks. Block `n` itself is not finalized.
+fn run_to_block(n: u64) {
+	while System::block_number() < n {
+		Charity::on_finalize(System::block_number());
+		System::set_block_number(System::block_number() + 1);
+		Charity::on_initialize(System::block_number());
+	}
+}
+
+fn description() -> H256 {
+	H256::repeat_byte(1)
+}
+
 // An alternative to `ExternalityBuilder` which includes custom configuration
 pub fn new_test_ext() -> sp_io::TestExternalities {
 	let mut t = system::GenesisConfig::default()
@@ -224,3 +272,317 @@ fn cant_allocate_too_much() {
 		);
 	})
 }
+
+#[test]
+fn propose_grant_reserves_bond() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Charity::propose_grant(Origin::signed(5), 4, 5, description()));
+
+		assert_eq!(Balances::reserved_balance(&5), 2);
+		assert_eq!(
+			Charity::grants(0),
+			Some(Grant {
+				proposer: 5,
+				dest: 4,
+				amount: 5,
+				description: description(),
+				bond: 2,
+				end: 4,
+				ayes: 0,
+				nays: 0,
+			})
+		);
+		assert_eq!(Charity::grant_ends(4), vec![0]);
+
+		// User 3 cannot afford the bond
+		assert_noop!(
+			Charity::propose_grant(Origin::signed(3), 4, 5, description()),
+			Error::<TestRuntime>::InsufficientBond
+		);
+	})
+}
+
+#[test]
+fn only_members_vote_once() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Charity::propose_grant(Origin::signed(5), 4, 5, description()));
+
+		assert_noop!(
+			Charity::vote_grant(Origin::signed(4), 0, true),
+			Error::<TestRuntime>::NotMember
+		);
+		assert_noop!(
+			Charity::vote_grant(Origin::signed(1), 1, true),
+			Error::<TestRuntime>::InvalidIndex
+		);
+		assert_ok!(Charity::vote_grant(Origin::signed(1), 0, true));
+		assert_noop!(
+			Charity::vote_grant(Origin::signed(1), 0, false),
+			Error::<TestRuntime>::AlreadyVoted
+		);
+		assert_eq!(Charity::grant_votes(0, 1), Some(true));
+	})
+}
+
+#[test]
+fn approved_grant_is_paid() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Charity::donate(Origin::signed(1), 10));
+		assert_ok!(Charity::propose_grant(Origin::signed(5), 4, 5, description()));
+		assert_ok!(Charity::vote_grant(Origin::signed(1), 0, true));
+		assert_ok!(Charity::vote_grant(Origin::signed(2), 0, true));
+		assert_ok!(Charity::vote_grant(Origin::signed(3), 0, false));
+
+		// The grant is queued and the bond returned when the voting period ends
+		run_to_block(4);
+		assert_eq!(Charity::payouts(), vec![(0, 4, 5, 0)]);
+		assert_eq!(Balances::free_balance(&5), 19);
+		assert_eq!(Balances::reserved_balance(&5), 0);
+		assert_eq!(Charity::grants(0), None);
+		assert_eq!(Charity::grant_votes(0, 1), None);
+
+		// The grant is paid at the end of the spend period
+		run_to_block(5);
+		assert_eq!(Balances::free_balance(&4), 3);
+		run_to_block(6);
+		assert_eq!(Balances::free_balance(&4), 8);
+		// A fifth of the spendable 5 that is left is burned
+		assert_eq!(Charity::pot(), 5);
+		assert!(Charity::payouts().is_empty());
+
+		let expected_event = TestEvent::charity(RawEvent::FundsAllocated(4, 5, 6));
+		assert!(System::events().iter().any(|a| a.event == expected_event));
+	})
+}
+
+#[test]
+fn approved_grant_waits_for_budget() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Charity::propose_grant(Origin::signed(5), 4, 5, description()));
+		assert_ok!(Charity::vote_grant(Origin::signed(1), 0, true));
+
+		// The pot only holds its minimum balance, so there is nothing to spend
+		run_to_block(6);
+		assert_eq!(Balances::free_balance(&4), 3);
+		assert_eq!(Charity::payouts(), vec![(0, 4, 5, 1)]);
+
+		// After a donation the grant fits in the next period's budget
+		assert_ok!(Charity::donate(Origin::signed(1), 10));
+		run_to_block(11);
+		assert_eq!(Balances::free_balance(&4), 8);
+		assert!(Charity::payouts().is_empty());
+	})
+}
+
+#[test]
+fn grants_beyond_budget_stay_queued() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Charity::donate(Origin::signed(1), 10));
+		assert_ok!(Charity::propose_grant(Origin::signed(5), 4, 6, description()));
+		assert_ok!(Charity::propose_grant(Origin::signed(5), 2, 3, description()));
+		assert_ok!(Charity::vote_grant(Origin::signed(1), 0, true));
+		assert_ok!(Charity::vote_grant(Origin::signed(1), 1, true));
+
+		// The budget is 5, so only the second grant is paid
+		run_to_block(6);
+		assert_eq!(Balances::free_balance(&2), 14);
+		assert_eq!(Balances::free_balance(&4), 3);
+		assert_eq!(Charity::payouts(), vec![(0, 4, 6, 1)]);
+	})
+}
+
+#[test]
+fn unpaid_grant_is_dropped() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Charity::propose_grant(Origin::signed(5), 4, 5, description()));
+		assert_ok!(Charity::vote_grant(Origin::signed(1), 0, true));
+
+		// The pot only holds its minimum balance, so the grant goes unpaid
+		run_to_block(11);
+		assert_eq!(Charity::payouts(), vec![(0, 4, 5, 2)]);
+
+		// It is dropped after `MaxPayoutPeriods` unpaid periods
+		run_to_block(16);
+		assert!(Charity::payouts().is_empty());
+		assert_eq!(Balances::free_balance(&4), 3);
+
+		let expected_event = TestEvent::charity(RawEvent::GrantDropped(0));
+		assert!(System::events().iter().any(|a| a.event == expected_event));
+	})
+}
+
+#[test]
+fn remaining_pot_is_burned() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Charity::donate(Origin::signed(1), 10));
+		let issuance = Balances::total_issuance();
+
+		// Nothing is spent, so a fifth of the spendable 10 is burned
+		run_to_block(6);
+		assert_eq!(Charity::pot(), 9);
+		assert_eq!(Balances::total_issuance(), issuance - 2);
+
+		let expected_event = TestEvent::charity(RawEvent::Spending(5));
+		assert!(System::events().iter().any(|a| a.event == expected_event));
+		let expected_event = TestEvent::charity(RawEvent::Burnt(2));
+		assert!(System::events().iter().any(|a| a.event == expected_event));
+	})
+}
+
+#[test]
+fn rejected_grant_slashes_bond() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Charity::propose_grant(Origin::signed(5), 4, 5, description()));
+		assert_ok!(Charity::vote_grant(Origin::signed(1), 0, true));
+		assert_ok!(Charity::vote_grant(Origin::signed(2), 0, false));
+
+		// A tie rejects the grant
+		run_to_block(4);
+		assert_eq!(Balances::free_balance(&4), 3);
+		assert_eq!(Balances::free_balance(&5), 17);
+		assert_eq!(Balances::reserved_balance(&5), 0);
+		assert_eq!(Charity::pot(), 1 + 2);
+
+		let expected_event = TestEvent::charity(RawEvent::GrantRejected(0, 2));
+		assert!(System::events().iter().any(|a| a.event == expected_event));
+	})
+}
+
+#[test]
+fn donations_are_totalled_into_tiers() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Charity::donate(Origin::signed(5), 6));
+		assert_eq!(Charity::total_donated(&5), 6);
+		assert_eq!(Charity::donor_tier(&5), 0);
+
+		assert_ok!(Charity::donate(Origin::signed(5), 6));
+		assert_eq!(Charity::total_donated(&5), 12);
+		assert_eq!(Charity::donor_tier(&5), 1);
+		let expected_event = TestEvent::charity(RawEvent::TierReached(5, 1));
+		assert!(System::events().iter().any(|a| a.event == expected_event));
+
+		// Other donors are tracked separately
+		assert_ok!(Charity::donate(Origin::signed(2), 10));
+		assert_eq!(Charity::total_donated(&2), 10);
+		assert_eq!(Charity::donor_tier(&2), 0);
+	})
+}
+
+#[test]
+fn recurring_donations_are_made_until_cancelled() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Charity::set_recurring_donation(Origin::signed(5), 3, 2));
+		let expected = RecurringDonation {
+			amount: 3,
+			period: 2,
+			next: 3,
+			deposit: 1,
+		};
+		assert_eq!(Charity::recurring(&5), Some(expected));
+		assert_eq!(Balances::reserved_balance(&5), 1);
+
+		run_to_block(2);
+		assert_eq!(Balances::free_balance(&5), 18);
+
+		run_to_block(3);
+		assert_eq!(Balances::free_balance(&5), 15);
+		assert_eq!(Charity::total_donated(&5), 3);
+
+		run_to_block(5);
+		assert_eq!(Balances::free_balance(&5), 12);
+		assert_eq!(Charity::recurring(&5).map(|r| r.next), Some(7));
+
+		// The donation due at block 7 is removed and the deposit returned after cancelling
+		assert_ok!(Charity::cancel_recurring_donation(Origin::signed(5)));
+		assert_eq!(Charity::recurring_due(7), Vec::<u64>::new());
+		assert_eq!(Balances::free_balance(&5), 13);
+		run_to_block(9);
+		assert_eq!(Balances::free_balance(&5), 13);
+		assert_eq!(Charity::total_donated(&5), 6);
+	})
+}
+
+#[test]
+fn recurring_donation_validates_input() {
+	new_test_ext().execute_with(|| {
+		assert_noop!(
+			Charity::set_recurring_donation(Origin::signed(5), 0, 2),
+			Error::<TestRuntime>::ZeroAmount
+		);
+		assert_noop!(
+			Charity::set_recurring_donation(Origin::signed(5), 3, 0),
+			Error::<TestRuntime>::ZeroPeriod
+		);
+		assert_noop!(
+			Charity::cancel_recurring_donation(Origin::signed(5)),
+			Error::<TestRuntime>::NoRecurringDonation
+		);
+		assert_noop!(
+			Charity::set_recurring_donation(Origin::signed(6), 3, 2),
+			Error::<TestRuntime>::InsufficientDeposit
+		);
+	})
+}
+
+#[test]
+fn unaffordable_recurring_donation_is_cancelled() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Charity::set_recurring_donation(Origin::signed(5), 8, 1));
+
+		run_to_block(3);
+		assert_eq!(Balances::free_balance(&5), 2);
+
+		// The third donation fails, which cancels the recurring donation and returns the deposit
+		run_to_block(4);
+		assert_eq!(Balances::free_balance(&5), 3);
+		assert_eq!(Balances::reserved_balance(&5), 0);
+		assert_eq!(Charity::recurring(&5), None);
+
+		let expected_event = TestEvent::charity(RawEvent::RecurringDonationFailed(5, 8));
+		assert!(System::events().iter().any(|a| a.event == expected_event));
+	})
+}
+
+#[test]
+fn recurring_donations_are_bounded_per_block() {
+	new_test_ext().execute_with(|| {
+		for donor in &[1, 2, 5] {
+			assert_ok!(Charity::set_recurring_donation(Origin::signed(*donor), 1, 2));
+		}
+
+		// Only `MaxRecurringPerBlock` donations are scheduled in block 3, the third one is moved to
+		// the next block
+		assert_eq!(Charity::recurring_due(3), vec![1, 2]);
+		assert_eq!(Charity::recurring(&5).map(|r| r.next), Some(4));
+
+		run_to_block(3);
+		assert_eq!(Charity::total_donated(&1), 1);
+		assert_eq!(Charity::total_donated(&2), 1);
+		assert_eq!(Charity::total_donated(&5), 0);
+
+		run_to_block(4);
+		assert_eq!(Charity::total_donated(&5), 1);
+		assert_eq!(Charity::recurring(&5).map(|r| r.next), Some(6));
+	})
+}
+
+#[test]
+fn replaced_and_cancelled_donations_free_their_slot() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Charity::set_recurring_donation(Origin::signed(1), 1, 2));
+		assert_ok!(Charity::set_recurring_donation(Origin::signed(2), 1, 2));
+
+		// Replacing a donation moves it and keeps its deposit
+		assert_ok!(Charity::set_recurring_donation(Origin::signed(1), 2, 4));
+		assert_eq!(Charity::recurring_due(3), vec![2]);
+		assert_eq!(Charity::recurring_due(5), vec![1]);
+		assert_eq!(Balances::reserved_balance(&1), 1);
+
+		// The slot left behind is used by the next donation that is due in block 3
+		assert_ok!(Charity::set_recurring_donation(Origin::signed(5), 1, 2));
+		assert_eq!(Charity::recurring_due(3), vec![2, 5]);
+
+		assert_ok!(Charity::cancel_recurring_donation(Origin::signed(2)));
+		assert_eq!(Charity::recurring_due(3), vec![5]);
+	})
+}
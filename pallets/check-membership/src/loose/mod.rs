@@ -3,12 +3,15 @@
 //! callers. If the caller is a member of the set, the pallet's `IsAMember` event is emitted. Otherwise a `NotAMember` error is returned.
 //!
 //! The list of approved members is provided by an external source and exposed through an associated
-//! type in this pallet's configuration trait. Any type that implements the `AccountSet` trait can be
-//! used to supply the membership set.
+//! type in this pallet's configuration trait. Any type that implements the `AccountSetExt` trait can
+//! be used to supply the membership set.
+//!
+//! The pallet also implements the `OnMembersChanged` trait, so the membership source can notify it
+//! whenever accounts gain or lose access.
 
 use frame_support::{decl_error, decl_event, decl_module, dispatch::DispatchResult, ensure};
 use frame_system::{self as system, ensure_signed};
-use account_set::AccountSet;
+use account_set::{AccountSetExt, OnMembersChanged};
 
 #[cfg(test)]
 mod tests;
@@ -20,7 +23,7 @@ pub trait Trait: system::Trait {
 	type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
 
 	/// A type that will supply a set of members to check access control against
-	type MembershipSource: AccountSet<AccountId = Self::AccountId>;
+	type MembershipSource: AccountSetExt<AccountId = Self::AccountId>;
 }
 
 decl_event!(
@@ -30,6 +33,10 @@ decl_event!(
 	{
 		/// The caller is a member.
 		IsAMember(AccountId),
+		/// An account joined the membership set and may now call this pallet.
+		AccessGranted(AccountId),
+		/// An account left the membership set and may no longer call this pallet.
+		AccessRevoked(AccountId),
 	}
 );
 
@@ -50,11 +57,8 @@ decl_module! {
 		fn check_membership(origin) -> DispatchResult {
 			let caller = ensure_signed(origin)?;
 
-			// Get the members from the `vec-set` pallet
-			let members = T::MembershipSource::accounts();
-
-			// Check whether the caller is a member
-			ensure!(members.contains(&caller), Error::<T>::NotAMember);
+			// Check whether the caller is a member without fetching the whole set
+			ensure!(T::MembershipSource::contains(&caller), Error::<T>::NotAMember);
 
 			// If the previous call didn't error, then the caller is a member, so emit the event
 			Self::deposit_event(RawEvent::IsAMember(caller));
@@ -62,3 +66,14 @@ decl_module! {
 		}
 	}
 }
+
+impl<T: Trait> OnMembersChanged<T::AccountId> for Module<T> {
+	fn on_members_changed(incoming: &[T::AccountId], outgoing: &[T::AccountId]) {
+		for member in incoming {
+			Self::deposit_event(RawEvent::AccessGranted(member.clone()));
+		}
+		for member in outgoing {
+			Self::deposit_event(RawEvent::AccessRevoked(member.clone()));
+		}
+	}
+}
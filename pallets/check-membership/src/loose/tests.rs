@@ -1,6 +1,6 @@
 use crate::loose::*;
 use frame_support::{assert_noop, assert_ok, impl_outer_event, impl_outer_origin, parameter_types};
-use frame_system as system;
+use frame_system::{self as system, EnsureRoot};
 use sp_core::H256;
 use sp_io::TestExternalities;
 use sp_runtime::{
@@ -21,6 +21,8 @@ parameter_types! {
 	pub const MaximumBlockWeight: u32 = 1024;
 	pub const MaximumBlockLength: u32 = 2 * 1024;
 	pub const AvailableBlockRatio: Perbill = Perbill::one();
+
+	pub const MaxMembers: u32 = 16;
 }
 impl system::Trait for TestRuntime {
 	type BaseCallFilter = ();
@@ -64,6 +66,11 @@ impl_outer_event! {
 
 impl vec_set::Trait for TestRuntime {
 	type Event = TestEvent;
+	type MaxMembers = MaxMembers;
+	type AddOrigin = EnsureRoot<u64>;
+	type RemoveOrigin = EnsureRoot<u64>;
+	type OnMembersChanged = CheckMembership;
+	type WeightInfo = ();
 }
 
 impl Trait for TestRuntime {
@@ -91,14 +98,14 @@ impl ExternalityBuilder {
 #[test]
 fn members_can_call() {
 	ExternalityBuilder::build().execute_with(|| {
-		assert_ok!(VecSet::add_member(Origin::signed(1)));
+		assert_ok!(VecSet::add_member(Origin::root(), 1));
 
 		assert_ok!(CheckMembership::check_membership(Origin::signed(1)));
 
 		let expected_event = TestEvent::check_membership(RawEvent::IsAMember(1));
 
 		assert_eq!(
-			System::events()[1].event,
+			System::events()[2].event,
 			expected_event,
 		);
 	})
@@ -113,3 +120,25 @@ fn non_members_cant_call() {
 		);
 	})
 }
+
+#[test]
+fn membership_changes_are_reported() {
+	ExternalityBuilder::build().execute_with(|| {
+		assert_ok!(VecSet::add_member(Origin::root(), 1));
+		assert_ok!(VecSet::swap_member(Origin::root(), 1, 2));
+
+		let events = System::events()
+			.into_iter()
+			.map(|record| record.event)
+			.filter(|event| matches!(event, TestEvent::check_membership(_)))
+			.collect::<Vec<_>>();
+		assert_eq!(
+			events,
+			vec![
+				TestEvent::check_membership(RawEvent::AccessGranted(1)),
+				TestEvent::check_membership(RawEvent::AccessGranted(2)),
+				TestEvent::check_membership(RawEvent::AccessRevoked(1)),
+			]
+		);
+	})
+}
@@ -1,197 +1,373 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 
-//! A pallet that demonstrates Fixed Point arithmetic in the context of two simple bank accounts
-//! that accrue compounding interest.
+//! A pallet that demonstrates Fixed Point arithmetic in the context of savings accounts that accrue
+//! compounding interest.
+//!
+//! Every account may hold two kinds of savings. Deposits are backed by the `Currency`: they are
+//! reserved from the depositor's balance, and interest is minted into the reserve as it accrues.
 //!
 //! The discrete account accrues interest every ten blocks and is implemented using
 //! Substrate's `Percent` implementation of fixed point.
 //!
 //! The continuous account accrues interest continuously and is implemented using
 //! Substrate-fixed's `I32F32` implementation of fixed point.
-
-use frame_support::{decl_event, decl_module, decl_storage, dispatch::DispatchResult};
-use frame_system::{self as system, ensure_signed};
+//!
+//! Both interest rates are stored on chain and may be changed by root. Rather than iterating over
+//! accounts, the pallet keeps a global interest index for each kind of account. An index is the
+//! value that one unit deposited at genesis would have grown to. Each account remembers the index
+//! at which it was last updated, so its interest is computed lazily, whenever the account is used.
+//!
+//! Interest compounds quickly, so an index can grow beyond what `FixedU128` represents. Indices
+//! saturate at the largest `FixedU128` rather than failing, so savings can always be withdrawn.
+
+use fixed_point::math::FixedMath;
+use frame_support::{
+	decl_error, decl_event, decl_module, decl_storage,
+	dispatch::DispatchResult,
+	ensure,
+	traits::{Currency, Imbalance, ReservableCurrency},
+};
+use frame_system::{self as system, ensure_root, ensure_signed};
 use parity_scale_codec::{Decode, Encode};
-use sp_arithmetic::Percent;
-use sp_runtime::traits::Zero;
-use sp_std::convert::TryInto;
-use substrate_fixed::{transcendental::exp, types::I32F32};
+use sp_arithmetic::{FixedPointNumber, FixedU128, Percent, Permill};
+use sp_runtime::{
+	traits::{Bounded, CheckedDiv, SaturatedConversion, Saturating, Zero},
+	RuntimeDebug,
+};
+use substrate_fixed::types::I32F32;
 
 #[cfg(test)]
 mod tests;
 
+/// The number of blocks between discrete interest payments
+pub const DISCRETE_PERIOD: u32 = 10;
+
+/// The largest exponent passed to `exp` at once. An `I32F32` only holds e ^ x for x up to about
+/// 21.5.
+const MAX_EXPONENT: i32 = 16;
+
+type BalanceOf<T> = <<T as Trait>::Currency as Currency<<T as system::Trait>::AccountId>>::Balance;
+
 pub trait Trait: system::Trait {
-	type Event: From<Event> + Into<<Self as system::Trait>::Event>;
+	type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
+
+	/// The currency in which savings are held
+	type Currency: ReservableCurrency<Self::AccountId>;
+}
+
+/// The two kinds of savings account
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, RuntimeDebug)]
+pub enum AccountKind {
+	/// Interest is paid every ten blocks
+	Discrete,
+	/// Interest is compounded continuously
+	Continuous,
 }
 
-#[derive(Encode, Decode, Default)]
-pub struct ContinuousAccountData<BlockNumber> {
-	/// The balance of the account after last manual adjustment
-	principal: I32F32,
-	/// The time (block height) at which the balance was last adjusted
-	deposit_date: BlockNumber,
+/// A savings account of a single user
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct SavingsAccount<Balance> {
+	/// The value of the account when it was last updated, all of which is reserved
+	pub principal: Balance,
+	/// The interest index at which the account was last updated
+	pub index: FixedU128,
+}
+
+/// A global interest index, see the module docs
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct InterestIndex<BlockNumber> {
+	/// The value of the index
+	pub value: FixedU128,
+	/// The block at which the index was last brought up to date
+	pub updated: BlockNumber,
+}
+
+impl<BlockNumber: Zero> Default for InterestIndex<BlockNumber> {
+	fn default() -> Self {
+		Self {
+			value: FixedU128::one(),
+			updated: Zero::zero(),
+		}
+	}
 }
 
 decl_storage! {
 	trait Store for Module<T: Trait> as Example {
-		/// Balance for the continuously compounded account
-		ContinuousAccount get(fn balance_compound): ContinuousAccountData<T::BlockNumber>;
-		/// Balance for the discrete interest account
-		DiscreteAccount get(fn discrete_account): u64;
+		/// The savings accounts of each user
+		Accounts get(fn accounts):
+			double_map hasher(blake2_128_concat) T::AccountId, hasher(twox_64_concat) AccountKind
+			=> Option<SavingsAccount<BalanceOf<T>>>;
+
+		/// The interest rate per block of the discrete accounts, paid every ten blocks
+		DiscreteRate get(fn discrete_rate): Percent = Percent::from_percent(5);
+		/// The interest rate per block of the continuous accounts
+		ContinuousRate get(fn continuous_rate): Permill = Permill::from_percent(5);
+
+		/// The interest index of the discrete accounts
+		DiscreteIndex get(fn discrete_index): InterestIndex<T::BlockNumber>;
+		/// The interest index of the continuous accounts
+		ContinuousIndex get(fn continuous_index): InterestIndex<T::BlockNumber>;
 	}
 }
 
 decl_event!(
-	pub enum Event {
+	pub enum Event<T>
+	where
+		Balance = BalanceOf<T>,
+		<T as system::Trait>::AccountId,
+	{
 		/// Deposited some balance into the compounding interest account
-		DepositedContinuous(u64),
+		DepositedContinuous(AccountId, Balance),
 		/// Withdrew some balance from the compounding interest account
-		WithdrewContinuous(u64),
+		WithdrewContinuous(AccountId, Balance),
 		/// Deposited some balance into the discrete interest account
-		DepositedDiscrete(u64),
+		DepositedDiscrete(AccountId, Balance),
 		/// Withdrew some balance from the discrete interest account
-		WithdrewDiscrete(u64),
-		/// Some interest has been applied to the discrete interest account
+		WithdrewDiscrete(AccountId, Balance),
+		/// Some interest has been applied to a discrete interest account
 		/// The associated data is just the interest amout (not the new or old balance)
-		/// This happens every ten blocks
-		DiscreteInterestApplied(u64),
+		DiscreteInterestApplied(AccountId, Balance),
+		/// Some interest has been applied to a compounding interest account
+		ContinuousInterestApplied(AccountId, Balance),
+		/// The discrete interest rate was changed
+		DiscreteRateSet(Percent),
+		/// The continuous interest rate was changed
+		ContinuousRateSet(Permill),
 	}
 );
 
+decl_error! {
+	pub enum Error for Module<T: Trait> {
+		/// The depositor's free balance is too low
+		InsufficientBalance,
+		/// The account does not hold enough savings
+		InsufficientSavings,
+	}
+}
+
 decl_module! {
 	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
 		fn deposit_event() = default;
 
+		type Error = Error<T>;
+
 		/// Deposit some funds into the compounding interest account
 		#[weight = 10_000]
-		fn deposit_continuous(origin, val_to_add: u64) -> DispatchResult {
-			ensure_signed(origin)?;
-
-			let current_block = system::Module::<T>::block_number();
-			let old_value = Self::value_of_continuous_account(&current_block);
+		fn deposit_continuous(origin, val_to_add: BalanceOf<T>) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+			Self::deposit_into(&who, AccountKind::Continuous, val_to_add)?;
 
-			// Update storage for compounding account
-			ContinuousAccount::<T>::put(
-				ContinuousAccountData {
-					principal: old_value + I32F32::from_num(val_to_add),
-					deposit_date: current_block,
-				}
-			);
-
-			// Emit event
-			Self::deposit_event(Event::DepositedContinuous(val_to_add));
+			Self::deposit_event(RawEvent::DepositedContinuous(who, val_to_add));
 			Ok(())
 		}
 
 		/// Withdraw some funds from the compounding interest account
 		#[weight = 10_000]
-		fn withdraw_continuous(origin, val_to_take: u64) -> DispatchResult {
-			ensure_signed(origin)?;
-
-			let current_block = system::Module::<T>::block_number();
-			let old_value = Self::value_of_continuous_account(&current_block);
+		fn withdraw_continuous(origin, val_to_take: BalanceOf<T>) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+			Self::withdraw_from(&who, AccountKind::Continuous, val_to_take)?;
 
-			// Update storage for compounding account
-			ContinuousAccount::<T>::put(
-				ContinuousAccountData {
-					principal: old_value - I32F32::from_num(val_to_take),
-					deposit_date: current_block,
-				}
-			);
-
-			// Emit event
-			Self::deposit_event(Event::WithdrewContinuous(val_to_take));
+			Self::deposit_event(RawEvent::WithdrewContinuous(who, val_to_take));
 			Ok(())
 		}
 
 		/// Deposit some funds into the discrete interest account
 		#[weight = 10_000]
-		fn deposit_discrete(origin, val_to_add: u64) -> DispatchResult {
-			ensure_signed(origin)?;
+		fn deposit_discrete(origin, val_to_add: BalanceOf<T>) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+			Self::deposit_into(&who, AccountKind::Discrete, val_to_add)?;
 
-			let old_value = DiscreteAccount::get();
+			Self::deposit_event(RawEvent::DepositedDiscrete(who, val_to_add));
+			Ok(())
+		}
 
-			// Update storage for discrete account
-			DiscreteAccount::put(old_value + val_to_add);
+		/// Withdraw some funds from the discrete interest account
+		#[weight = 10_000]
+		fn withdraw_discrete(origin, val_to_take: BalanceOf<T>) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+			Self::withdraw_from(&who, AccountKind::Discrete, val_to_take)?;
 
-			// Emit event
-			Self::deposit_event(Event::DepositedDiscrete(val_to_add));
+			Self::deposit_event(RawEvent::WithdrewDiscrete(who, val_to_take));
 			Ok(())
 		}
 
-		/// Withdraw some funds from the discrete interest account
+		/// Set the interest rate of the discrete accounts. Interest accrued so far is kept at
+		/// the old rate.
 		#[weight = 10_000]
-		fn withdraw_discrete(origin, val_to_take: u64) -> DispatchResult {
-			ensure_signed(origin)?;
+		fn set_discrete_rate(origin, rate: Percent) -> DispatchResult {
+			ensure_root(origin)?;
+			Self::update_index(AccountKind::Discrete);
+			DiscreteRate::put(rate);
 
-			let old_value = DiscreteAccount::get();
+			Self::deposit_event(RawEvent::DiscreteRateSet(rate));
+			Ok(())
+		}
 
-			// Update storage for discrete account
-			DiscreteAccount::put(old_value - val_to_take);
+		/// Set the interest rate of the continuous accounts. Interest accrued so far is kept at
+		/// the old rate.
+		#[weight = 10_000]
+		fn set_continuous_rate(origin, rate: Permill) -> DispatchResult {
+			ensure_root(origin)?;
+			Self::update_index(AccountKind::Continuous);
+			ContinuousRate::put(rate);
 
-			// Emit event
-			Self::deposit_event(Event::WithdrewDiscrete(val_to_take));
+			Self::deposit_event(RawEvent::ContinuousRateSet(rate));
 			Ok(())
 		}
+	}
+}
 
-		fn on_finalize(n: T::BlockNumber) {
-			// Apply newly-accrued discrete interest every ten blocks
-			if (n % 10.into()).is_zero() {
+impl<T: Trait> Module<T> {
+	/// The current value of an account, including the interest that has not been applied yet
+	pub fn value_of(who: &T::AccountId, kind: AccountKind) -> BalanceOf<T> {
+		match Self::accounts(who, kind) {
+			Some(account) => Self::value_at(&account, Self::current_index(kind)),
+			None => Zero::zero(),
+		}
+	}
 
-				// Calculate interest Interest = principal * rate * time
-				// We can use the `*` operator for multiplying a `Percent` by a u64
-				// because `Percent` implements the trait Mul<u64>
-				let interest = Self::discrete_interest_rate() * DiscreteAccount::get() * 10;
+	/// Reserves `amount` from `who` and adds it to their savings
+	fn deposit_into(who: &T::AccountId, kind: AccountKind, amount: BalanceOf<T>) -> DispatchResult {
+		ensure!(T::Currency::can_reserve(who, amount), Error::<T>::InsufficientBalance);
+		let mut account = Self::accrue(who, kind);
+		T::Currency::reserve(who, amount)?;
 
-				// The following line, although similar, does not work because
-				// u64 does not implement the trait Mul<Percent>
-				// let interest = DiscreteAccount::get() * Self::discrete_interest_rate() * 10;
+		account.principal = account.principal.saturating_add(amount);
+		<Accounts<T>>::insert(who, kind, account);
+		Ok(())
+	}
 
-				// Update the balance
-				let old_balance = DiscreteAccount::get();
-				DiscreteAccount::put(old_balance + interest);
+	/// Takes `amount` out of the savings of `who` and returns it to their free balance
+	fn withdraw_from(who: &T::AccountId, kind: AccountKind, amount: BalanceOf<T>) -> DispatchResult {
+		ensure!(Self::value_of(who, kind) >= amount, Error::<T>::InsufficientSavings);
+		let mut account = Self::accrue(who, kind);
+		// If the interest could not be credited, only the stored balance can be withdrawn
+		ensure!(account.principal >= amount, Error::<T>::InsufficientSavings);
+		T::Currency::unreserve(who, amount);
+
+		account.principal = account.principal.saturating_sub(amount);
+		if account.principal.is_zero() {
+			<Accounts<T>>::remove(who, kind);
+		} else {
+			<Accounts<T>>::insert(who, kind, account);
+		}
+		Ok(())
+	}
 
-				// Emit the event
-				Self::deposit_event(Event::DiscreteInterestApplied(interest));
-			}
+	/// Applies the interest accrued since the account was last updated. The interest is minted into
+	/// the owner's balance and reserved along with the rest of the savings. Returns the updated
+	/// account, or an empty account at the current index if `who` has no savings yet.
+	///
+	/// Accruing never fails. Interest that cannot be minted or reserved is not credited, and the
+	/// account keeps its stored balance.
+	fn accrue(who: &T::AccountId, kind: AccountKind) -> SavingsAccount<BalanceOf<T>> {
+		let index = Self::update_index(kind);
+		let mut account = match Self::accounts(who, kind) {
+			Some(account) => account,
+			None => return SavingsAccount { principal: Zero::zero(), index },
+		};
+
+		let interest = Self::value_at(&account, index).saturating_sub(account.principal);
+		let minted = T::Currency::deposit_creating(who, interest).peek();
+		let credited = if T::Currency::reserve(who, minted).is_ok() {
+			minted
+		} else {
+			Zero::zero()
+		};
+		if !credited.is_zero() {
+			let event = match kind {
+				AccountKind::Discrete => RawEvent::DiscreteInterestApplied(who.clone(), credited),
+				AccountKind::Continuous => {
+					RawEvent::ContinuousInterestApplied(who.clone(), credited)
+				}
+			};
+			Self::deposit_event(event);
 		}
+
+		account.principal = account.principal.saturating_add(credited);
+		account.index = index;
+		<Accounts<T>>::insert(who, kind, &account);
+		account
 	}
-}
 
-impl<T: Trait> Module<T> {
-	/// A helper function to evaluate the current value of the continuously compounding interest
-	/// account
-	fn value_of_continuous_account(now: &<T as system::Trait>::BlockNumber) -> I32F32 {
-		// Get the old state of the accout
-		let ContinuousAccountData {
-			principal,
-			deposit_date,
-		} = ContinuousAccount::<T>::get();
-
-		// Calculate the exponential function (lots of type conversion)
-		let elapsed_time_block_number = *now - deposit_date;
-		let elapsed_time_u32 = TryInto::try_into(elapsed_time_block_number)
-			.ok()
-			.expect("blockchain will not exceed 2^32 blocks; qed");
-		let elapsed_time_i32f32 = I32F32::from_num(elapsed_time_u32);
-		let exponent: I32F32 = Self::continuous_interest_rate() * elapsed_time_i32f32;
-		let exp_result : I32F32 = exp(exponent)
-			.expect("Interest will not overflow account (at least not until the learner has learned enough about fixed point :)");
-
-		// Return the result interest = principal * e ^ (rate * time)
-		principal * exp_result
+	/// The value of an account at the given interest index, which is the principal multiplied by
+	/// the growth of the index since the account was last updated
+	fn value_at(account: &SavingsAccount<BalanceOf<T>>, index: FixedU128) -> BalanceOf<T> {
+		let growth = index.checked_div(&account.index).unwrap_or_else(FixedU128::one);
+		growth
+			.saturating_mul_int(account.principal.saturated_into::<u128>())
+			.saturated_into()
 	}
 
-	/// A helper function to return the hard-coded 5% interest rate
-	fn discrete_interest_rate() -> Percent {
-		Percent::from_percent(5)
+	/// Brings the interest index of the given kind up to date and returns it
+	fn update_index(kind: AccountKind) -> FixedU128 {
+		let value = Self::current_index(kind);
+		let index = InterestIndex {
+			value,
+			updated: <system::Module<T>>::block_number(),
+		};
+		match kind {
+			AccountKind::Discrete => <DiscreteIndex<T>>::put(index),
+			AccountKind::Continuous => <ContinuousIndex<T>>::put(index),
+		}
+		value
+	}
+
+	/// The interest index of the given kind at the current block, saturating at the largest
+	/// `FixedU128`
+	fn current_index(kind: AccountKind) -> FixedU128 {
+		let now = <system::Module<T>>::block_number();
+		match kind {
+			AccountKind::Discrete => {
+				let InterestIndex { value, updated } = Self::discrete_index();
+
+				// Count the interest payments, which happen at every multiple of ten blocks
+				let period: T::BlockNumber = DISCRETE_PERIOD.into();
+				let payments = (now / period).saturating_sub(updated / period);
+
+				// Each payment multiplies the savings by 1 + rate * time
+				let rate = u32::from(Self::discrete_rate().deconstruct()) * DISCRETE_PERIOD;
+				let growth = FixedU128::one()
+					.saturating_add(FixedU128::saturating_from_rational(rate, 100u32));
+				let payments = payments.saturated_into::<u32>() as usize;
+				value.saturating_mul(growth.saturating_pow(payments))
+			}
+			AccountKind::Continuous => {
+				let InterestIndex { value, updated } = Self::continuous_index();
+
+				// Calculate the exponential function (lots of type conversion)
+				let elapsed_time_u32 = now.saturating_sub(updated).saturated_into::<u32>();
+				let elapsed_time_i32f32 = I32F32::saturating_from_num(elapsed_time_u32);
+				let mut exponent =
+					Self::continuous_interest_rate().saturating_mul(elapsed_time_i32f32);
+
+				// e ^ (rate * time) is applied in steps of at most `MAX_EXPONENT`, so `exp` never
+				// overflows. Each full step multiplies the index by more than a million, so it
+				// saturates after a few steps and the loop ends.
+				let mut value = value;
+				let max_exponent = I32F32::from_num(MAX_EXPONENT);
+				while exponent > I32F32::from_num(0) && value < FixedU128::max_value() {
+					let step = exponent.min(max_exponent);
+					exponent -= step;
+
+					// Convert e ^ step to Substrate's fixed point. An `I32F32` is its bits
+					// divided by 2 ^ 32.
+					let growth = match step.checked_exp() {
+						Some(e) => {
+							FixedU128::saturating_from_rational(e.to_bits() as u128, 1u128 << 32)
+						}
+						None => FixedU128::max_value(),
+					};
+					value = value.saturating_mul(growth);
+				}
+				value
+			}
+		}
 	}
 
-	/// A helper function to return the hard-coded 5% interest rate
+	/// A helper function to convert the stored continuous interest rate to `I32F32`
 	fn continuous_interest_rate() -> I32F32 {
-		// 1 / 20 is 5%. Same interest rate as the discrete account, but in the
-		// fancy substrate-fixed format. This I32F32 type represents a 32 bit
-		// signed integer where all 32 bits are fractional.
-		I32F32::from_num(1) / 20
+		// This I32F32 type represents a 64 bit signed number where 32 bits are fractional.
+		I32F32::from_num(Self::continuous_rate().deconstruct()) / I32F32::from_num(1_000_000)
 	}
 }
@@ -1,14 +1,15 @@
-use super::Event;
-use crate::{Module, Trait};
+use crate::*;
+use balances;
 use frame_support::{
-	assert_ok, impl_outer_event, impl_outer_origin, parameter_types, traits::OnFinalize,
+	assert_err, assert_noop, assert_ok, impl_outer_event, impl_outer_origin, parameter_types,
 };
+use frame_system::{self as system};
 use sp_core::H256;
 use sp_io::TestExternalities;
 use sp_runtime::{
 	testing::Header,
 	traits::{BlakeTwo256, IdentityLookup},
-	Perbill,
+	DispatchError, Perbill,
 };
 
 impl_outer_origin! {
@@ -23,8 +24,10 @@ parameter_types! {
 	pub const MaximumBlockWeight: u32 = 1024;
 	pub const MaximumBlockLength: u32 = 2 * 1024;
 	pub const AvailableBlockRatio: Perbill = Perbill::one();
+
+	pub const ExistentialDeposit: u64 = 1;
 }
-impl frame_system::Trait for TestRuntime {
+impl system::Trait for TestRuntime {
 	type BaseCallFilter = ();
 	type Origin = Origin;
 	type Index = u64;
@@ -46,43 +49,70 @@ impl frame_system::Trait for TestRuntime {
 	type AvailableBlockRatio = AvailableBlockRatio;
 	type Version = ();
 	type PalletInfo = ();
-	type AccountData = ();
+	type AccountData = balances::AccountData<u64>;
 	type OnNewAccount = ();
 	type OnKilledAccount = ();
 	type SystemWeightInfo = ();
 }
 
+impl balances::Trait for TestRuntime {
+	type Balance = u64;
+	type MaxLocks = ();
+	type Event = TestEvent;
+	type DustRemoval = ();
+	type ExistentialDeposit = ExistentialDeposit;
+	type AccountStore = system::Module<TestRuntime>;
+	type WeightInfo = ();
+}
+
 mod fixed_point {
 	pub use crate::Event;
 }
 
 impl_outer_event! {
 	pub enum TestEvent for TestRuntime {
-		fixed_point,
-		frame_system<T>,
+		fixed_point<T>,
+		system<T>,
+		balances<T>,
 	}
 }
 
 impl Trait for TestRuntime {
 	type Event = TestEvent;
+	type Currency = balances::Module<Self>;
 }
 
-pub type System = frame_system::Module<TestRuntime>;
+pub type System = system::Module<TestRuntime>;
+pub type Balances = balances::Module<TestRuntime>;
 pub type FixedPoint = Module<TestRuntime>;
 
 struct ExternalityBuilder;
 
 impl ExternalityBuilder {
 	pub fn build() -> TestExternalities {
-		let storage = frame_system::GenesisConfig::default()
+		let mut storage = system::GenesisConfig::default()
 			.build_storage::<TestRuntime>()
 			.unwrap();
+		balances::GenesisConfig::<TestRuntime> {
+			balances: vec![(1, 1000), (2, 10_000)],
+		}
+		.assimilate_storage(&mut storage)
+		.unwrap();
 		let mut ext = TestExternalities::from(storage);
 		ext.execute_with(|| System::set_block_number(1));
 		ext
 	}
 }
 
+fn our_events() -> Vec<RawEvent<u64, u64>> {
+	System::events()
+		.into_iter().map(|r| r.event)
+		.filter_map(|e| {
+			if let TestEvent::fixed_point(inner) = e { Some(inner) } else { None }
+		})
+		.collect()
+}
+
 #[test]
 fn deposit_withdraw_discrete_works() {
 	ExternalityBuilder::build().execute_with(|| {
@@ -93,22 +123,39 @@ fn deposit_withdraw_discrete_works() {
 		assert_ok!(FixedPoint::withdraw_discrete(Origin::signed(1), 5));
 
 		// Test that the expected events were emitted
-		let our_events = System::events()
-			.into_iter().map(|r| r.event)
-			.filter_map(|e| {
-				if let TestEvent::fixed_point(inner) = e { Some(inner) } else { None }
-			})
-			.collect::<Vec<_>>();
-
 		let expected_events = vec![
-			Event::DepositedDiscrete(10,),
-			Event::WithdrewDiscrete(5,),
+			RawEvent::DepositedDiscrete(1, 10),
+			RawEvent::WithdrewDiscrete(1, 5),
 		];
 
-		assert_eq!(our_events, expected_events);
+		assert_eq!(our_events(), expected_events);
 
-		// Check that five tokens are still there
-		assert_eq!(FixedPoint::discrete_account(), 5);
+		// Check that five tokens are still there, held in reserve
+		assert_eq!(FixedPoint::value_of(&1, AccountKind::Discrete), 5);
+		assert_eq!(Balances::reserved_balance(&1), 5);
+		assert_eq!(Balances::free_balance(&1), 995);
+	})
+}
+
+#[test]
+fn savings_are_limited_by_balance() {
+	ExternalityBuilder::build().execute_with(|| {
+		assert_noop!(
+			FixedPoint::deposit_discrete(Origin::signed(1), 1001),
+			Error::<TestRuntime>::InsufficientBalance
+		);
+
+		assert_ok!(FixedPoint::deposit_discrete(Origin::signed(1), 100));
+		assert_noop!(
+			FixedPoint::withdraw_discrete(Origin::signed(1), 101),
+			Error::<TestRuntime>::InsufficientSavings
+		);
+
+		// The discrete and continuous accounts are separate
+		assert_noop!(
+			FixedPoint::withdraw_continuous(Origin::signed(1), 1),
+			Error::<TestRuntime>::InsufficientSavings
+		);
 	})
 }
 
@@ -117,30 +164,103 @@ fn discrete_interest_works() {
 	ExternalityBuilder::build().execute_with(|| {
 		// Deposit 100 tokens
 		assert_ok!(FixedPoint::deposit_discrete(Origin::signed(1), 100));
+		let issuance = Balances::total_issuance();
 
 		// balance should not change after the 3rd block
-		FixedPoint::on_finalize(3);
-		assert_eq!(FixedPoint::discrete_account(), 100);
+		System::set_block_number(3);
+		assert_eq!(FixedPoint::value_of(&1, AccountKind::Discrete), 100);
 
-		// on_finalize should compute interest on 10th block
-		FixedPoint::on_finalize(10);
+		// interest is due on the 10th block
+		System::set_block_number(10);
+		assert_eq!(FixedPoint::value_of(&1, AccountKind::Discrete), 150);
 
-		// Test that the expected events were emitted
-		let our_events = System::events()
-			.into_iter().map(|r| r.event)
-			.filter_map(|e| {
-				if let TestEvent::fixed_point(inner) = e { Some(inner) } else { None }
-			})
-			.collect::<Vec<_>>();
+		// The interest is minted when the account is used
+		assert_ok!(FixedPoint::withdraw_discrete(Origin::signed(1), 150));
+		assert_eq!(FixedPoint::accounts(1, AccountKind::Discrete), None);
+		assert_eq!(Balances::free_balance(&1), 1050);
+		assert_eq!(Balances::reserved_balance(&1), 0);
+		assert_eq!(Balances::total_issuance(), issuance + 50);
 
 		let expected_events = vec![
-			Event::DepositedDiscrete(100,),
-			Event::DiscreteInterestApplied(50,),
+			RawEvent::DepositedDiscrete(1, 100),
+			RawEvent::DiscreteInterestApplied(1, 50),
+			RawEvent::WithdrewDiscrete(1, 150),
 		];
 
-		assert_eq!(our_events, expected_events);
+		assert_eq!(our_events(), expected_events);
+	})
+}
+
+#[test]
+fn discrete_interest_compounds() {
+	ExternalityBuilder::build().execute_with(|| {
+		assert_ok!(FixedPoint::deposit_discrete(Origin::signed(1), 100));
+
+		// Interest is paid on the interest of the first period
+		System::set_block_number(20);
+		assert_eq!(FixedPoint::value_of(&1, AccountKind::Discrete), 225);
+
+		// Accounts deposited later only earn interest from then on
+		assert_ok!(FixedPoint::deposit_discrete(Origin::signed(2), 100));
+		System::set_block_number(30);
+		assert_eq!(FixedPoint::value_of(&2, AccountKind::Discrete), 150);
+	})
+}
+
+#[test]
+fn continuous_interest_works() {
+	ExternalityBuilder::build().execute_with(|| {
+		assert_ok!(FixedPoint::deposit_continuous(Origin::signed(2), 1000));
+
+		// After 20 blocks at 5% the account has grown by a factor of e
+		System::set_block_number(21);
+		let value = FixedPoint::value_of(&2, AccountKind::Continuous);
+		assert!(value >= 2717 && value <= 2719);
+
+		assert_ok!(FixedPoint::withdraw_continuous(Origin::signed(2), value));
+		assert_eq!(Balances::free_balance(&2), 9000 + value);
+		assert_eq!(Balances::reserved_balance(&2), 0);
+	})
+}
+
+#[test]
+fn continuous_index_saturates() {
+	ExternalityBuilder::build().execute_with(|| {
+		assert_ok!(FixedPoint::deposit_continuous(Origin::signed(2), 1000));
+
+		// After 1000 blocks at 5% the growth e ^ 50 no longer fits in an `I32F32`
+		System::set_block_number(1001);
+		assert_eq!(FixedPoint::value_of(&2, AccountKind::Continuous), u64::max_value());
+		assert_ok!(FixedPoint::set_continuous_rate(Origin::root(), Permill::from_percent(1)));
+
+		// The interest cannot be minted, so the stored balance is still withdrawable
+		assert_err!(
+			FixedPoint::withdraw_continuous(Origin::signed(2), 1001),
+			Error::<TestRuntime>::InsufficientSavings
+		);
+		assert_ok!(FixedPoint::withdraw_continuous(Origin::signed(2), 1000));
+		assert_eq!(Balances::free_balance(&2), 10_000);
+
+		assert_ok!(FixedPoint::deposit_continuous(Origin::signed(2), 100));
+		assert_eq!(Balances::reserved_balance(&2), 100);
+	})
+}
+
+#[test]
+fn rate_changes_keep_accrued_interest() {
+	ExternalityBuilder::build().execute_with(|| {
+		assert_ok!(FixedPoint::deposit_discrete(Origin::signed(1), 100));
+
+		assert_noop!(
+			FixedPoint::set_discrete_rate(Origin::signed(1), Percent::from_percent(10)),
+			DispatchError::BadOrigin
+		);
 
-		// Check that the balance has updated
-		assert_eq!(FixedPoint::discrete_account(), 150);
+		// The first payment is made at 5%, the second at 10%
+		System::set_block_number(15);
+		assert_ok!(FixedPoint::set_discrete_rate(Origin::root(), Percent::from_percent(10)));
+		assert_eq!(FixedPoint::discrete_rate(), Percent::from_percent(10));
+		System::set_block_number(20);
+		assert_eq!(FixedPoint::value_of(&1, AccountKind::Discrete), 300);
 	})
 }
@@ -4,12 +4,44 @@
 //!
 //! WARNING: never use this code in production (for demonstration/teaching purposes only)
 //! it only checks for signed extrinsics to enable arbitrary minting/slashing!!!
+//!
+//! Besides the immediate `slash_funds`, slashes can go through a proposal flow. A reporter
+//! proposes a slash together with the hash of their evidence and reserves `ReporterBond`. The
+//! accused then has `ChallengePeriod` blocks to dispute it. Undisputed slashes execute
+//! automatically once the window closes, while disputed ones wait for root to resolve them. When a
+//! slash executes, the reporter gets their bond back and receives `ReporterBounty` of the slashed
+//! amount, and the rest of the `NegativeImbalance` is handed to `T::Slash`. When root rejects a
+//! slash, the reporter's bond is slashed instead. At most `MaxProposalsPerBlock` proposals may
+//! close their challenge window in the same block.
+//!
+//! The pallet also distributes rewards in eras of `EraLength` blocks. At the end of each era it
+//! pays `EraInflation` to the members of `T::Participants`, pro rata to their reserved balance
+//! (their stake). The pallet implements `OnUnbalanced` for negative imbalances, so slashes routed
+//! to it are collected in its pot. Collected slashes fund the rewards first, and only the
+//! remainder is newly minted. Slashes in excess of the era's rewards are carried over to the next era.
 
+use account_set::AccountSet;
 use frame_support::{
-	decl_event, decl_module,
-	traits::{Currency, Imbalance, OnUnbalanced, ReservableCurrency},
+	decl_error, decl_event, decl_module, decl_storage, ensure,
+	traits::{
+		Currency, ExistenceRequirement, Get, Imbalance, OnUnbalanced, ReservableCurrency,
+		WithdrawReasons,
+	},
+	weights::Weight,
+};
+use frame_system::{self as system, ensure_root, ensure_signed};
+use parity_scale_codec::{Decode, Encode};
+use sp_runtime::{
+	traits::{AccountIdConversion, Saturating, Zero},
+	ModuleId, Perbill,
 };
-use frame_system::{self as system, ensure_signed};
+use sp_std::prelude::*;
+
+#[cfg(test)]
+mod tests;
+
+/// Hardcoded pallet ID; used to create the pot that collects slashes between eras
+const PALLET_ID: ModuleId = ModuleId(*b"ex/imbal");
 
 // balance type using reservable currency type
 type BalanceOf<T> = <<T as Trait>::Currency as Currency<<T as system::Trait>::AccountId>>::Balance;
@@ -17,6 +49,12 @@ type PositiveImbalanceOf<T> =
 	<<T as Trait>::Currency as Currency<<T as system::Trait>::AccountId>>::PositiveImbalance;
 type NegativeImbalanceOf<T> =
 	<<T as Trait>::Currency as Currency<<T as system::Trait>::AccountId>>::NegativeImbalance;
+type SlashProposalOf<T> = SlashProposal<
+	<T as system::Trait>::AccountId,
+	BalanceOf<T>,
+	<T as system::Trait>::BlockNumber,
+	<T as system::Trait>::Hash,
+>;
 
 pub trait Trait: system::Trait + Sized {
 	/// The overarching event type
@@ -30,6 +68,74 @@ pub trait Trait: system::Trait + Sized {
 
 	/// Handler for the unbalanced decrement when slashing (burning collateral)
 	type Slash: OnUnbalanced<NegativeImbalanceOf<Self>>;
+
+	/// The number of blocks during which the accused may dispute a proposed slash
+	type ChallengePeriod: Get<Self::BlockNumber>;
+
+	/// The portion of an executed slash that is paid to the reporter
+	type ReporterBounty: Get<Perbill>;
+
+	/// The amount reserved from a reporter, which they lose if their proposal is rejected
+	type ReporterBond: Get<BalanceOf<Self>>;
+
+	/// The maximum number of slash proposals whose challenge window closes in a single block
+	type MaxProposalsPerBlock: Get<u32>;
+
+	/// The number of blocks in a reward era
+	type EraLength: Get<Self::BlockNumber>;
+
+	/// The total reward paid to participants at the end of each era
+	type EraInflation: Get<BalanceOf<Self>>;
+
+	/// The accounts that share the rewards of each era
+	type Participants: AccountSet<AccountId = Self::AccountId>;
+}
+
+/// Simple index for identifying a reward era.
+pub type EraIndex = u32;
+
+/// Simple index for identifying a slash proposal.
+pub type ProposalIndex = u32;
+
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct SlashProposal<AccountId, Balance, BlockNumber, Hash> {
+	/// The account that proposed the slash and receives the bounty
+	pub reporter: AccountId,
+	/// The account whose reserved funds will be slashed
+	pub accused: AccountId,
+	/// The amount of reserved funds to slash
+	pub amount: Balance,
+	/// The amount reserved from the reporter
+	pub bond: Balance,
+	/// Hash of the off-chain evidence supporting the slash
+	pub evidence: Hash,
+	/// The block at which the challenge window closes
+	pub challenge_end: BlockNumber,
+	/// Whether the accused has disputed the slash
+	pub disputed: bool,
+}
+
+decl_storage! {
+	trait Store for Module<T: Trait> as CurrencyImbalances {
+		/// Slash proposals that have not yet been executed or cancelled
+		SlashProposals get(fn slash_proposals):
+			map hasher(blake2_128_concat) ProposalIndex => Option<SlashProposalOf<T>>;
+
+		/// The total number of slash proposals that have so far been made
+		ProposalCount get(fn proposal_count): ProposalIndex;
+
+		/// The slash proposals whose challenge window closes at each block, at most
+		/// `MaxProposalsPerBlock` of them
+		ChallengeEnds get(fn challenge_ends):
+			map hasher(twox_64_concat) T::BlockNumber => Vec<ProposalIndex>;
+
+		/// The index of the current reward era
+		CurrentEra get(fn current_era): EraIndex;
+
+		/// Slashes collected in the pot that have not yet funded any rewards
+		CollectedSlashes get(fn collected_slashes): BalanceOf<T>;
+	}
 }
 
 decl_event!(
@@ -38,16 +144,66 @@ decl_event!(
 		AccountId = <T as system::Trait>::AccountId,
 		Balance = BalanceOf<T>,
 		BlockNumber = <T as system::Trait>::BlockNumber,
+		Hash = <T as system::Trait>::Hash,
 	{
 		SlashFunds(AccountId, Balance, BlockNumber),
 		RewardFunds(AccountId, Balance, BlockNumber),
+		/// A slash was proposed (index, reporter, accused, amount, evidence hash)
+		SlashProposed(ProposalIndex, AccountId, AccountId, Balance, Hash),
+		/// The accused disputed a slash proposal
+		SlashDisputed(ProposalIndex),
+		/// A slash was executed (index, accused, amount slashed, bounty paid to the reporter)
+		SlashExecuted(ProposalIndex, AccountId, Balance, Balance),
+		/// A disputed slash proposal was rejected (index, reporter bond slashed)
+		SlashCancelled(ProposalIndex, Balance),
+		/// Slashed funds were collected into the pot (amount, total collected)
+		SlashCollected(Balance, Balance),
+		/// An era ended (era, number of participants paid, amount minted, amount paid from slashes)
+		EraPaid(EraIndex, u32, Balance, Balance),
 	}
 );
 
+decl_error! {
+	pub enum Error for Module<T: Trait> {
+		/// A slash must be for a non-zero amount
+		ZeroAmount,
+		/// An account cannot report itself
+		SelfReport,
+		/// The proposal index specified does not exist
+		InvalidIndex,
+		/// Only the accused may dispute a slash proposal
+		NotAccused,
+		/// The challenge window of the proposal has closed
+		ChallengePeriodOver,
+		/// The proposal has already been disputed
+		AlreadyDisputed,
+		/// Only disputed proposals can be resolved
+		NotDisputed,
+		/// The reporter cannot afford the bond
+		InsufficientBond,
+		/// The maximum number of proposals already close their challenge window in this block
+		TooManyProposals,
+	}
+}
+
 decl_module! {
 	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
 		fn deposit_event() = default;
 
+		type Error = Error<T>;
+
+		const ChallengePeriod: T::BlockNumber = T::ChallengePeriod::get();
+
+		const ReporterBounty: Perbill = T::ReporterBounty::get();
+
+		const ReporterBond: BalanceOf<T> = T::ReporterBond::get();
+
+		const MaxProposalsPerBlock: u32 = T::MaxProposalsPerBlock::get();
+
+		const EraLength: T::BlockNumber = T::EraLength::get();
+
+		const EraInflation: BalanceOf<T> = T::EraInflation::get();
+
 		/// Slashes the specified amount of funds from the specified account
 		#[weight = 10_000]
 		pub fn slash_funds(origin, to_punish: T::AccountId, collateral: BalanceOf<T>) {
@@ -74,5 +230,202 @@ decl_module! {
 			let now = <system::Module<T>>::block_number();
 			Self::deposit_event(RawEvent::RewardFunds(to_reward, reward, now));
 		}
+
+		/// Proposes to slash the reserved funds of `accused`, reserving `ReporterBond` from the
+		/// reporter. The slash executes once the challenge window closes unless the accused
+		/// disputes it.
+		#[weight = 10_000]
+		pub fn propose_slash(
+			origin,
+			accused: T::AccountId,
+			amount: BalanceOf<T>,
+			evidence: T::Hash,
+		) {
+			let reporter = ensure_signed(origin)?;
+			ensure!(!amount.is_zero(), Error::<T>::ZeroAmount);
+			ensure!(reporter != accused, Error::<T>::SelfReport);
+
+			let now = <system::Module<T>>::block_number();
+			let challenge_end = now.saturating_add(T::ChallengePeriod::get());
+			ensure!(
+				(Self::challenge_ends(challenge_end).len() as u32) < T::MaxProposalsPerBlock::get(),
+				Error::<T>::TooManyProposals
+			);
+
+			let bond = T::ReporterBond::get();
+			T::Currency::reserve(&reporter, bond).map_err(|_| Error::<T>::InsufficientBond)?;
+
+			let index = ProposalCount::get();
+			// not protected against overflow, see safemath section
+			ProposalCount::put(index + 1);
+
+			<SlashProposals<T>>::insert(index, SlashProposal {
+				reporter: reporter.clone(),
+				accused: accused.clone(),
+				amount,
+				bond,
+				evidence,
+				challenge_end,
+				disputed: false,
+			});
+			<ChallengeEnds<T>>::append(challenge_end, index);
+
+			Self::deposit_event(RawEvent::SlashProposed(index, reporter, accused, amount, evidence));
+		}
+
+		/// Disputes a slash proposal. Must be called by the accused before the challenge
+		/// window closes. Disputed proposals are resolved by root.
+		#[weight = 10_000]
+		pub fn dispute_slash(origin, index: ProposalIndex) {
+			let who = ensure_signed(origin)?;
+			let mut proposal = Self::slash_proposals(index).ok_or(Error::<T>::InvalidIndex)?;
+
+			ensure!(who == proposal.accused, Error::<T>::NotAccused);
+			ensure!(!proposal.disputed, Error::<T>::AlreadyDisputed);
+			let now = <system::Module<T>>::block_number();
+			ensure!(now < proposal.challenge_end, Error::<T>::ChallengePeriodOver);
+
+			proposal.disputed = true;
+			<SlashProposals<T>>::insert(index, proposal);
+
+			Self::deposit_event(RawEvent::SlashDisputed(index));
+		}
+
+		/// Resolves a disputed slash proposal. If `uphold` is true the slash executes,
+		/// otherwise it is cancelled and the reporter's bond is slashed.
+		#[weight = 10_000]
+		pub fn resolve_slash(origin, index: ProposalIndex, uphold: bool) {
+			ensure_root(origin)?;
+			let proposal = Self::slash_proposals(index).ok_or(Error::<T>::InvalidIndex)?;
+			ensure!(proposal.disputed, Error::<T>::NotDisputed);
+
+			<SlashProposals<T>>::remove(index);
+			if uphold {
+				Self::execute_slash(index, proposal);
+			} else {
+				let imbalance = T::Currency::slash_reserved(&proposal.reporter, proposal.bond).0;
+				let lost = imbalance.peek();
+				T::Slash::on_unbalanced(imbalance);
+				Self::deposit_event(RawEvent::SlashCancelled(index, lost));
+			}
+		}
+
+		/// Executes the undisputed slash proposals whose challenge window closes in this block,
+		/// and pays the era rewards at the end of each era.
+		fn on_initialize(n: T::BlockNumber) -> Weight {
+			let mut weight = 0;
+			if (n % T::EraLength::get()).is_zero() {
+				weight = Self::end_era();
+			}
+
+			let ending = <ChallengeEnds<T>>::take(n);
+
+			for index in ending.iter() {
+				match Self::slash_proposals(index) {
+					Some(proposal) if !proposal.disputed => {
+						<SlashProposals<T>>::remove(index);
+						Self::execute_slash(*index, proposal);
+					}
+					_ => (),
+				}
+			}
+
+			T::DbWeight::get().reads_writes(1, 1)
+				.saturating_add(T::DbWeight::get().reads_writes(4, 4).saturating_mul(ending.len() as Weight))
+				.saturating_add(weight)
+		}
+	}
+}
+
+impl<T: Trait> Module<T> {
+	/// Slashes the reserved funds of the accused, returns the reporter's bond, pays the reporter
+	/// bounty out of the slashed funds and hands the rest of the imbalance to `T::Slash`.
+	fn execute_slash(index: ProposalIndex, proposal: SlashProposalOf<T>) {
+		T::Currency::unreserve(&proposal.reporter, proposal.bond);
+
+		let imbalance = T::Currency::slash_reserved(&proposal.accused, proposal.amount).0;
+		let slashed = imbalance.peek();
+
+		let bounty = T::ReporterBounty::get() * slashed;
+		let (bounty_imbalance, rest) = imbalance.split(bounty);
+		T::Currency::resolve_creating(&proposal.reporter, bounty_imbalance);
+		T::Slash::on_unbalanced(rest);
+
+		Self::deposit_event(RawEvent::SlashExecuted(index, proposal.accused, slashed, bounty));
+	}
+
+	/// The account ID of the pot that collects slashes between eras
+	pub fn account_id() -> T::AccountId {
+		PALLET_ID.into_account()
+	}
+
+	/// Pays the era reward to the participants pro rata to their stake and starts a new era.
+	/// Collected slashes fund the reward before any new funds are minted.
+	fn end_era() -> Weight {
+		let era = Self::current_era();
+		CurrentEra::put(era.saturating_add(1));
+
+		let stakes: Vec<(T::AccountId, BalanceOf<T>)> = T::Participants::accounts()
+			.into_iter()
+			.map(|who| {
+				let stake = T::Currency::reserved_balance(&who);
+				(who, stake)
+			})
+			.filter(|(_, stake)| !stake.is_zero())
+			.collect();
+		let total_stake = stakes
+			.iter()
+			.fold(<BalanceOf<T>>::zero(), |total, (_, stake)| total.saturating_add(*stake));
+
+		let inflation = T::EraInflation::get();
+		let mut total_imbalance = <PositiveImbalanceOf<T>>::zero();
+		if !total_stake.is_zero() {
+			for (who, stake) in stakes.iter() {
+				let share = Perbill::from_rational_approximation(*stake, total_stake) * inflation;
+				let r = T::Currency::deposit_into_existing(who, share).ok();
+				total_imbalance.maybe_subsume(r);
+			}
+		}
+		let paid = total_imbalance.peek();
+
+		// Funds slashed during the era offset the newly minted rewards
+		let collected = Self::collected_slashes();
+		let recycled = T::Currency::withdraw(
+			&Self::account_id(),
+			collected.min(paid),
+			WithdrawReasons::all(),
+			ExistenceRequirement::AllowDeath,
+		).unwrap_or_else(|_| <NegativeImbalanceOf<T>>::zero());
+		let from_slashes = recycled.peek();
+		<CollectedSlashes<T>>::put(collected.saturating_sub(from_slashes));
+
+		match total_imbalance.offset(recycled) {
+			Ok(minted) => T::Reward::on_unbalanced(minted),
+			Err(unused) => T::Slash::on_unbalanced(unused),
+		}
+
+		Self::deposit_event(RawEvent::EraPaid(
+			era,
+			stakes.len() as u32,
+			paid.saturating_sub(from_slashes),
+			from_slashes,
+		));
+
+		T::DbWeight::get().reads_writes(3, 3)
+			.saturating_add(T::DbWeight::get().reads_writes(2, 1).saturating_mul(stakes.len() as Weight))
+	}
+}
+
+// This implementation allows the pallet to collect funds slashed elsewhere in the runtime. The
+// collected funds pay for the rewards of the current era, reducing the amount that is minted.
+impl<T: Trait> OnUnbalanced<NegativeImbalanceOf<T>> for Module<T> {
+	fn on_nonzero_unbalanced(amount: NegativeImbalanceOf<T>) {
+		let numeric_amount = amount.peek();
+
+		T::Currency::resolve_creating(&Self::account_id(), amount);
+		let collected = Self::collected_slashes().saturating_add(numeric_amount);
+		<CollectedSlashes<T>>::put(collected);
+
+		Self::deposit_event(RawEvent::SlashCollected(numeric_amount, collected));
 	}
 }
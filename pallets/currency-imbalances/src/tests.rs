@@ -0,0 +1,359 @@
+use crate::*;
+use balances;
+use frame_support::{
+	assert_noop, assert_ok, impl_outer_event, impl_outer_origin, parameter_types,
+	traits::OnInitialize,
+};
+use frame_system::{self as system};
+use sp_core::H256;
+use sp_io;
+use sp_runtime::{
+	testing::Header,
+	traits::{BlakeTwo256, IdentityLookup},
+	DispatchError, Perbill,
+};
+use sp_std::collections::btree_set::BTreeSet;
+
+impl_outer_origin! {
+	pub enum Origin for TestRuntime {}
+}
+
+// Workaround for https://github.com/rust-lang/rust/issues/26925 . Remove when sorted.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TestRuntime;
+parameter_types! {
+	pub const BlockHashCount: u64 = 250;
+	pub const MaximumBlockWeight: u32 = 1024;
+	pub const MaximumBlockLength: u32 = 2 * 1024;
+	pub const AvailableBlockRatio: Perbill = Perbill::one();
+
+	pub const ExistentialDeposit: u64 = 1;
+	pub const ChallengePeriod: u64 = 5;
+	pub const ReporterBounty: Perbill = Perbill::from_percent(10);
+	pub const ReporterBond: u64 = 50;
+	pub const MaxProposalsPerBlock: u32 = 2;
+	pub const EraLength: u64 = 10;
+	pub const EraInflation: u64 = 100;
+}
+impl system::Trait for TestRuntime {
+	type BaseCallFilter = ();
+	type Origin = Origin;
+	type Index = u64;
+	type Call = ();
+	type BlockNumber = u64;
+	type Hash = H256;
+	type Hashing = BlakeTwo256;
+	type AccountId = u64;
+	type Lookup = IdentityLookup<Self::AccountId>;
+	type Header = Header;
+	type Event = TestEvent;
+	type BlockHashCount = BlockHashCount;
+	type MaximumBlockWeight = MaximumBlockWeight;
+	type DbWeight = ();
+	type BlockExecutionWeight = ();
+	type ExtrinsicBaseWeight = ();
+	type MaximumExtrinsicWeight = MaximumBlockWeight;
+	type MaximumBlockLength = MaximumBlockLength;
+	type AvailableBlockRatio = AvailableBlockRatio;
+	type Version = ();
+	type PalletInfo = ();
+	type AccountData = balances::AccountData<u64>;
+	type OnNewAccount = ();
+	type OnKilledAccount = ();
+	type SystemWeightInfo = ();
+}
+
+impl balances::Trait for TestRuntime {
+	type Balance = u64;
+	type MaxLocks = ();
+	type Event = TestEvent;
+	type DustRemoval = ();
+	type ExistentialDeposit = ExistentialDeposit;
+	type AccountStore = system::Module<TestRuntime>;
+	type WeightInfo = ();
+}
+
+mod currency_imbalances {
+	pub use crate::Event;
+}
+
+impl_outer_event! {
+	pub enum TestEvent for TestRuntime {
+		system<T>,
+		currency_imbalances<T>,
+		balances<T>,
+	}
+}
+
+/// Accounts 1, 2 and 3 take part in the reward eras
+pub struct TestParticipants;
+impl AccountSet for TestParticipants {
+	type AccountId = u64;
+
+	fn accounts() -> BTreeSet<u64> {
+		vec![1, 2, 3].into_iter().collect()
+	}
+}
+
+impl Trait for TestRuntime {
+	type Event = TestEvent;
+	type Currency = balances::Module<Self>;
+	type Reward = ();
+	type Slash = ();
+	type ChallengePeriod = ChallengePeriod;
+	type ReporterBounty = ReporterBounty;
+	type ReporterBond = ReporterBond;
+	type MaxProposalsPerBlock = MaxProposalsPerBlock;
+	type EraLength = EraLength;
+	type EraInflation = EraInflation;
+	type Participants = TestParticipants;
+}
+
+pub type System = system::Module<TestRuntime>;
+pub type Balances = balances::Module<TestRuntime>;
+pub type CurrencyImbalances = Module<TestRuntime>;
+
+/// Moves the chain to block `n`, running this pallet's `on_initialize`
+fn run_to_block(n: u64) {
+	while System::block_number() < n {
+		System::set_block_number(System::block_number() + 1);
+		CurrencyImbalances::on_initialize(System::block_number());
+	}
+}
+
+fn evidence() -> H256 {
+	H256::repeat_byte(7)
+}
+
+pub fn new_test_ext() -> sp_io::TestExternalities {
+	let mut t = system::GenesisConfig::default()
+		.build_storage::<TestRuntime>()
+		.unwrap();
+	balances::GenesisConfig::<TestRuntime> {
+		balances: vec![(1, 1000), (2, 1000), (3, 1000), (4, 10)],
+	}
+	.assimilate_storage(&mut t)
+	.unwrap();
+	let mut ext: sp_io::TestExternalities = t.into();
+	ext.execute_with(|| {
+		System::set_block_number(1);
+		// Account 2 has collateral that may be slashed
+		assert_ok!(<Balances as ReservableCurrency<u64>>::reserve(&2, 500));
+	});
+	ext
+}
+
+#[test]
+fn propose_slash_works() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(CurrencyImbalances::propose_slash(Origin::signed(1), 2, 200, evidence()));
+
+		assert_eq!(
+			CurrencyImbalances::slash_proposals(0),
+			Some(SlashProposal {
+				reporter: 1,
+				accused: 2,
+				amount: 200,
+				bond: 50,
+				evidence: evidence(),
+				challenge_end: 6,
+				disputed: false,
+			})
+		);
+		assert_eq!(CurrencyImbalances::challenge_ends(6), vec![0]);
+		assert_eq!(Balances::reserved_balance(&1), 50);
+
+		let expected_event =
+			TestEvent::currency_imbalances(RawEvent::SlashProposed(0, 1, 2, 200, evidence()));
+		assert!(System::events().iter().any(|a| a.event == expected_event));
+	})
+}
+
+#[test]
+fn propose_slash_validates_input() {
+	new_test_ext().execute_with(|| {
+		assert_noop!(
+			CurrencyImbalances::propose_slash(Origin::signed(1), 2, 0, evidence()),
+			Error::<TestRuntime>::ZeroAmount
+		);
+		assert_noop!(
+			CurrencyImbalances::propose_slash(Origin::signed(2), 2, 200, evidence()),
+			Error::<TestRuntime>::SelfReport
+		);
+		assert_noop!(
+			CurrencyImbalances::propose_slash(Origin::signed(4), 2, 200, evidence()),
+			Error::<TestRuntime>::InsufficientBond
+		);
+	})
+}
+
+#[test]
+fn challenge_ends_are_bounded_per_block() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(CurrencyImbalances::propose_slash(Origin::signed(1), 2, 100, evidence()));
+		assert_ok!(CurrencyImbalances::propose_slash(Origin::signed(3), 2, 100, evidence()));
+
+		// Both proposals close their window in block 6, which is now full
+		assert_noop!(
+			CurrencyImbalances::propose_slash(Origin::signed(1), 2, 100, evidence()),
+			Error::<TestRuntime>::TooManyProposals
+		);
+
+		// Proposals made in the next block close in block 7
+		System::set_block_number(2);
+		assert_ok!(CurrencyImbalances::propose_slash(Origin::signed(1), 2, 100, evidence()));
+		assert_eq!(CurrencyImbalances::challenge_ends(7), vec![2]);
+	})
+}
+
+#[test]
+fn undisputed_slash_executes_after_window() {
+	new_test_ext().execute_with(|| {
+		let issuance = Balances::total_issuance();
+		assert_ok!(CurrencyImbalances::propose_slash(Origin::signed(1), 2, 200, evidence()));
+
+		run_to_block(5);
+		assert_eq!(Balances::reserved_balance(&2), 500);
+
+		run_to_block(6);
+		assert_eq!(Balances::reserved_balance(&2), 300);
+		// The reporter gets their bond back plus 10% of the slash, the rest is burned by `()`
+		assert_eq!(Balances::reserved_balance(&1), 0);
+		assert_eq!(Balances::free_balance(&1), 1020);
+		assert_eq!(Balances::total_issuance(), issuance - 180);
+		assert_eq!(CurrencyImbalances::slash_proposals(0), None);
+
+		let expected_event = TestEvent::currency_imbalances(RawEvent::SlashExecuted(0, 2, 200, 20));
+		assert!(System::events().iter().any(|a| a.event == expected_event));
+	})
+}
+
+#[test]
+fn only_accused_can_dispute_within_window() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(CurrencyImbalances::propose_slash(Origin::signed(1), 2, 200, evidence()));
+
+		assert_noop!(
+			CurrencyImbalances::dispute_slash(Origin::signed(3), 0),
+			Error::<TestRuntime>::NotAccused
+		);
+
+		System::set_block_number(6);
+		assert_noop!(
+			CurrencyImbalances::dispute_slash(Origin::signed(2), 0),
+			Error::<TestRuntime>::ChallengePeriodOver
+		);
+	})
+}
+
+#[test]
+fn disputed_slash_waits_for_root() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(CurrencyImbalances::propose_slash(Origin::signed(1), 2, 200, evidence()));
+		assert_ok!(CurrencyImbalances::dispute_slash(Origin::signed(2), 0));
+		assert_noop!(
+			CurrencyImbalances::dispute_slash(Origin::signed(2), 0),
+			Error::<TestRuntime>::AlreadyDisputed
+		);
+
+		// The slash does not execute when the window closes
+		run_to_block(6);
+		assert_eq!(Balances::reserved_balance(&2), 500);
+		assert!(CurrencyImbalances::slash_proposals(0).is_some());
+
+		assert_noop!(
+			CurrencyImbalances::resolve_slash(Origin::signed(1), 0, true),
+			DispatchError::BadOrigin
+		);
+		assert_ok!(CurrencyImbalances::resolve_slash(Origin::root(), 0, true));
+		assert_eq!(Balances::reserved_balance(&2), 300);
+		assert_eq!(Balances::reserved_balance(&1), 0);
+		assert_eq!(Balances::free_balance(&1), 1020);
+	})
+}
+
+#[test]
+fn rejected_slash_costs_the_reporter_bond() {
+	new_test_ext().execute_with(|| {
+		let issuance = Balances::total_issuance();
+		assert_ok!(CurrencyImbalances::propose_slash(Origin::signed(1), 2, 200, evidence()));
+		assert_noop!(
+			CurrencyImbalances::resolve_slash(Origin::root(), 0, false),
+			Error::<TestRuntime>::NotDisputed
+		);
+		assert_ok!(CurrencyImbalances::dispute_slash(Origin::signed(2), 0));
+
+		assert_ok!(CurrencyImbalances::resolve_slash(Origin::root(), 0, false));
+		assert_eq!(Balances::reserved_balance(&2), 500);
+		assert_eq!(CurrencyImbalances::slash_proposals(0), None);
+
+		// The reporter's bond is slashed and burned by `()`
+		assert_eq!(Balances::reserved_balance(&1), 0);
+		assert_eq!(Balances::free_balance(&1), 950);
+		assert_eq!(Balances::total_issuance(), issuance - 50);
+
+		let expected_event = TestEvent::currency_imbalances(RawEvent::SlashCancelled(0, 50));
+		assert!(System::events().iter().any(|a| a.event == expected_event));
+	})
+}
+
+#[test]
+fn era_rewards_are_pro_rata_to_stake() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(<Balances as ReservableCurrency<u64>>::reserve(&3, 500));
+		let issuance = Balances::total_issuance();
+
+		// Account 2 stakes 500, account 3 stakes 500, account 1 has no stake
+		run_to_block(10);
+		assert_eq!(CurrencyImbalances::current_era(), 1);
+		assert_eq!(Balances::free_balance(&1), 1000);
+		assert_eq!(Balances::free_balance(&2), 550);
+		assert_eq!(Balances::free_balance(&3), 550);
+		assert_eq!(Balances::total_issuance(), issuance + 100);
+
+		let expected_event = TestEvent::currency_imbalances(RawEvent::EraPaid(0, 2, 100, 0));
+		assert!(System::events().iter().any(|a| a.event == expected_event));
+	})
+}
+
+#[test]
+fn collected_slashes_offset_minting() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(<Balances as ReservableCurrency<u64>>::reserve(&3, 500));
+		assert_ok!(<Balances as ReservableCurrency<u64>>::reserve(&1, 60));
+
+		// Route a slash of all of 1's stake to the pallet's pot
+		let imbalance = <Balances as ReservableCurrency<u64>>::slash_reserved(&1, 60).0;
+		CurrencyImbalances::on_unbalanced(imbalance);
+		assert_eq!(CurrencyImbalances::collected_slashes(), 60);
+		assert_eq!(Balances::free_balance(&CurrencyImbalances::account_id()), 60);
+		let issuance = Balances::total_issuance();
+
+		run_to_block(10);
+		assert_eq!(Balances::free_balance(&2), 550);
+		assert_eq!(Balances::free_balance(&3), 550);
+
+		// Only the part of the reward not covered by the slashes is minted
+		assert_eq!(CurrencyImbalances::collected_slashes(), 0);
+		assert_eq!(Balances::free_balance(&CurrencyImbalances::account_id()), 0);
+		assert_eq!(Balances::total_issuance(), issuance + 40);
+
+		let expected_event = TestEvent::currency_imbalances(RawEvent::EraPaid(0, 2, 40, 60));
+		assert!(System::events().iter().any(|a| a.event == expected_event));
+	})
+}
+
+#[test]
+fn excess_slashes_carry_over() {
+	new_test_ext().execute_with(|| {
+		let imbalance = <Balances as ReservableCurrency<u64>>::slash_reserved(&2, 250).0;
+		CurrencyImbalances::on_unbalanced(imbalance);
+		let issuance = Balances::total_issuance();
+
+		// Account 2 is the only staker left and receives the whole reward from the pot
+		run_to_block(10);
+		assert_eq!(Balances::free_balance(&2), 600);
+		assert_eq!(CurrencyImbalances::collected_slashes(), 150);
+		assert_eq!(Balances::total_issuance(), issuance);
+	})
+}
@@ -0,0 +1,19 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+#![allow(clippy::too_many_arguments)]
+#![allow(clippy::unnecessary_mut_passed)]
+
+use parity_scale_codec::Codec;
+use sp_arithmetic::FixedU128;
+
+// The runtime API exposes the price averages so that analytics clients can query them at any
+// block. It is implemented in the runtime amalgamator file.
+sp_api::decl_runtime_apis! {
+	pub trait FixedPointApi<BlockNumber> where BlockNumber: Codec {
+		/// The time weighted average price over the blocks after `from` up to and including `to`,
+		/// or `None` if either block has no checkpoint
+		fn time_weighted_average(from: BlockNumber, to: BlockNumber) -> Option<FixedU128>;
+
+		/// The exponential moving average of the price, or `None` before the first observation
+		fn moving_average() -> Option<FixedU128>;
+	}
+}
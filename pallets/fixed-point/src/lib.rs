@@ -14,17 +14,47 @@
 //! ## Substrate-fixed Implementation
 //! Here we use an external crate called substrate-fixed which implements more advanced
 //! mathematical operations including transcendental functions.
-
-use frame_support::{decl_error, decl_event, decl_module, decl_storage, dispatch::DispatchResult};
+//!
+//! ## Math Library
+//! The `math` module generalises these recipes into a small library of transcendental functions
+//! (`exp`, `ln`, `pow` and `sqrt`) for `I32F32`, `U64F64` and Substrate's `FixedU128`.
+//!
+//! ## Price Averages
+//! The `OracleOrigin` submits price observations as `FixedU128`s. At the end of every block, the
+//! latest price is added to a cumulative price, which is checkpointed for the last `HistoryDepth`
+//! blocks, and folded into an exponential moving average. The time weighted average over any
+//! window of checkpointed blocks is the difference between two checkpoints divided by the window's
+//! length.
+
+use frame_support::{
+	decl_error, decl_event, decl_module, decl_storage,
+	dispatch::DispatchResult,
+	traits::{EnsureOrigin, Get},
+	weights::Weight,
+};
 use frame_system::{self as system, ensure_signed};
-use sp_arithmetic::{traits::Saturating, Permill};
+use sp_arithmetic::{
+	traits::{SaturatedConversion, Saturating},
+	FixedPointNumber, FixedU128, Permill,
+};
 use substrate_fixed::types::U16F16;
 
+pub mod math;
+
 #[cfg(test)]
 mod tests;
 
 pub trait Trait: system::Trait {
 	type Event: From<Event> + Into<<Self as system::Trait>::Event>;
+
+	/// The number of blocks for which cumulative price checkpoints are kept
+	type HistoryDepth: Get<Self::BlockNumber>;
+
+	/// The weight of the latest price in the exponential moving average
+	type Smoothing: Get<Permill>;
+
+	/// The origin that may submit price observations
+	type OracleOrigin: EnsureOrigin<Self::Origin>;
 }
 
 decl_storage! {
@@ -35,6 +65,18 @@ decl_storage! {
 		FixedAccumulator get(fn fixed_value): U16F16 = U16F16::from_num(1);
 		/// Manual accumulator, value starts at 1 (multiplicative identity)
 		ManualAccumulator get(fn manual_value): u32 = 1 << 16;
+
+		/// The most recently observed price
+		LatestPrice get(fn latest_price): Option<FixedU128>;
+		/// The sum of the prices at the end of every block since the first observation, as the raw
+		/// inner value of a `FixedU128`. It wraps around on overflow, which leaves the differences
+		/// between checkpoints correct.
+		CumulativePrice get(fn cumulative_price): u128;
+		/// The cumulative price at the end of each of the last `HistoryDepth` blocks
+		PriceCheckpoints get(fn price_checkpoint):
+			map hasher(twox_64_concat) T::BlockNumber => Option<u128>;
+		/// The exponential moving average of the price at the end of each block
+		MovingAverage get(fn moving_average): Option<FixedU128>;
 	}
 }
 
@@ -48,6 +90,8 @@ decl_event!(
 		FixedUpdated(U16F16, U16F16),
 		/// Manual accumulator has been updated.
 		ManualUpdated(u32, u32),
+		/// A price has been observed.
+		PriceObserved(FixedU128),
 	}
 );
 
@@ -62,6 +106,50 @@ decl_module! {
 	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
 		fn deposit_event() = default;
 
+		/// The number of blocks for which cumulative price checkpoints are kept
+		const HistoryDepth: T::BlockNumber = T::HistoryDepth::get();
+
+		/// The weight of the latest price in the exponential moving average
+		const Smoothing: Permill = T::Smoothing::get();
+
+		/// Reserves the weight of updating the averages in `on_finalize`. An observation may still
+		/// arrive in this block, so the weight is reserved even before the first one.
+		fn on_initialize(_n: T::BlockNumber) -> Weight {
+			// Reading the latest price, the cumulative price and the moving average, then writing
+			// the cumulative price, two checkpoints and the moving average
+			T::DbWeight::get().reads_writes(3, 4)
+		}
+
+		fn on_finalize(n: T::BlockNumber) {
+			// Averages start with the first observation
+			let price = match Self::latest_price() {
+				Some(price) => price,
+				None => return,
+			};
+
+			// Wrapping rather than saturating keeps the differences between checkpoints exact
+			let cumulative = Self::cumulative_price().wrapping_add(price.into_inner());
+			CumulativePrice::put(cumulative);
+			<PriceCheckpoints<T>>::insert(n, cumulative);
+			if n >= T::HistoryDepth::get() {
+				<PriceCheckpoints<T>>::remove(n - T::HistoryDepth::get());
+			}
+
+			// Move the average towards the price by the smoothing factor
+			let average = match Self::moving_average() {
+				Some(average) if price >= average => {
+					let step = T::Smoothing::get() * (price.into_inner() - average.into_inner());
+					FixedU128::from_inner(average.into_inner().saturating_add(step))
+				}
+				Some(average) => {
+					let step = T::Smoothing::get() * (average.into_inner() - price.into_inner());
+					FixedU128::from_inner(average.into_inner().saturating_sub(step))
+				}
+				None => price,
+			};
+			MovingAverage::put(average);
+		}
+
 		/// Update the Permill accumulator implementation's value by multiplying it
 		/// by the new factor given in the extrinsic
 		#[weight = 10_000]
@@ -136,5 +224,32 @@ decl_module! {
 			Self::deposit_event(Event::ManualUpdated(new_factor, final_product));
 			Ok(())
 		}
+
+		/// Submit a price observation. The price is used for every block from this one until the
+		/// next observation. Only the `OracleOrigin` may submit observations.
+		#[weight = 10_000]
+		fn submit_observation(origin, price: FixedU128) -> DispatchResult {
+			T::OracleOrigin::ensure_origin(origin)?;
+
+			LatestPrice::put(price);
+
+			Self::deposit_event(Event::PriceObserved(price));
+			Ok(())
+		}
+	}
+}
+
+impl<T: Trait> Module<T> {
+	/// The time weighted average of the price over the blocks after `from` up to and including
+	/// `to`. `None` if the window is empty, or if either block has no checkpoint because it is
+	/// before the first observation or more than `HistoryDepth` blocks ago.
+	pub fn time_weighted_average(from: T::BlockNumber, to: T::BlockNumber) -> Option<FixedU128> {
+		if to <= from {
+			return None;
+		}
+		let start = Self::price_checkpoint(from)?;
+		let end = Self::price_checkpoint(to)?;
+		let blocks = (to - from).saturated_into::<u128>();
+		Some(FixedU128::from_inner(end.wrapping_sub(start) / blocks))
 	}
 }
@@ -0,0 +1,360 @@
+//! Transcendental functions for fixed point numbers.
+//!
+//! The `FixedMath` trait provides `exp`, `ln`, `pow` and `sqrt` for substrate-fixed's `I32F32` and
+//! `U64F64`, and for Substrate's `FixedU128`. Each function comes in a checked variant, which
+//! returns `None` if the result is undefined or does not fit in the type, and a saturating variant.
+//!
+//! All types share a single implementation. Arguments are converted to Substrate's `FixedI128`,
+//! which has 18 decimal places and a range of about ±1.7 * 10^20, and results are converted back.
+//! Results beyond that range are treated as overflows, even if the type itself could hold them.
+//! `ln` and `sqrt` reduce larger arguments by powers of four first, because their results are much
+//! smaller than their arguments.
+//!
+//! ## Error Bounds
+//!
+//! Before the result is converted back to the original type, the errors are at most
+//! * `exp`: 10^-15 relative to the result, plus 10^-18
+//! * `ln`: 10^-16
+//! * `sqrt`: 10^-17
+//! * `pow`: (10^-15 + |exponent| * 10^-16) relative to the result, plus 10^-18
+//!
+//! Converting the result back rounds it towards zero, which adds the resolution of the type:
+//! 2^-32 for `I32F32` and 10^-18 for `U64F64` and `FixedU128`.
+
+use sp_arithmetic::{helpers_128bit::multiply_by_rational, FixedI128, FixedPointNumber, FixedU128};
+use sp_std::convert::TryFrom;
+use substrate_fixed::types::{I32F32, U64F64};
+
+#[cfg(test)]
+mod tests;
+
+/// The raw representation of one in `FixedI128`
+const SCALE: i128 = 1_000_000_000_000_000_000;
+
+/// The raw representation of ln(2) in `FixedI128`
+const LN2: i128 = 693_147_180_559_945_309;
+
+/// The maximum number of terms of the power series in `exp`
+const MAX_TERMS: i128 = 40;
+
+/// Transcendental functions for a fixed point type
+pub trait FixedMath: Copy {
+	/// Converts the number to a `FixedI128`, or `None` if it is out of range
+	fn to_fixed_i128(self) -> Option<FixedI128>;
+
+	/// Converts a `FixedI128` to this type, rounding towards zero, or `None` if it is out of range
+	fn from_fixed_i128(value: FixedI128) -> Option<Self>;
+
+	/// The largest number of this type
+	fn max_fixed() -> Self;
+
+	/// The smallest number of this type
+	fn min_fixed() -> Self;
+
+	/// Shifts the bits of the number to the right, which divides it by 2 ^ `bits`
+	fn shr_fixed(self, bits: u32) -> Self;
+
+	/// Converts `self / 4 ^ k` to a `FixedI128` for the smallest `k` at which it is in range
+	fn to_reduced_fixed_i128(self) -> Option<(FixedI128, u32)> {
+		(0..64).find_map(|k| self.shr_fixed(2 * k).to_fixed_i128().map(|x| (x, k)))
+	}
+
+	/// e ^ self
+	fn checked_exp(self) -> Option<Self> {
+		unary(self, exp)
+	}
+
+	/// e ^ self, or the largest number on overflow
+	fn saturating_exp(self) -> Self {
+		self.checked_exp().unwrap_or_else(Self::max_fixed)
+	}
+
+	/// The natural logarithm. `None` if `self` is not positive.
+	fn checked_ln(self) -> Option<Self> {
+		// ln(x) = ln(x / 4 ^ k) + 2 * k * ln(2)
+		let (x, k) = self.to_reduced_fixed_i128()?;
+		let result = ln(x.into_inner())?.checked_add(i128::from(2 * k) * LN2)?;
+		Self::from_fixed_i128(FixedI128::from_inner(result))
+	}
+
+	/// The natural logarithm, or the smallest number if `self` is not positive or the logarithm is
+	/// below the range of the type
+	fn saturating_ln(self) -> Self {
+		self.checked_ln().unwrap_or_else(Self::min_fixed)
+	}
+
+	/// self ^ exponent. `None` if `self` is negative, or if it is zero and `exponent` is negative.
+	fn checked_powf(self, exponent: Self) -> Option<Self> {
+		let base = self.to_fixed_i128()?.into_inner();
+		let result = pow(base, exponent.to_fixed_i128()?.into_inner())?;
+		Self::from_fixed_i128(FixedI128::from_inner(result))
+	}
+
+	/// self ^ exponent, or zero if `self` is negative, or the largest number on overflow
+	fn saturating_powf(self, exponent: Self) -> Self {
+		self.checked_powf(exponent).unwrap_or_else(|| match self.to_fixed_i128() {
+			Some(base) if base.is_negative() => Self::zero_fixed(),
+			_ => Self::max_fixed(),
+		})
+	}
+
+	/// The square root. `None` if `self` is negative.
+	fn checked_sqrt(self) -> Option<Self> {
+		// sqrt(x) = sqrt(x / 4 ^ k) * 2 ^ k
+		let (x, k) = self.to_reduced_fixed_i128()?;
+		let result = sqrt(x.into_inner())?.checked_mul(1 << k)?;
+		Self::from_fixed_i128(FixedI128::from_inner(result))
+	}
+
+	/// The square root, or zero if `self` is negative
+	fn saturating_sqrt(self) -> Self {
+		self.checked_sqrt().unwrap_or_else(Self::zero_fixed)
+	}
+
+	/// Zero, in this type
+	fn zero_fixed() -> Self {
+		Self::from_fixed_i128(FixedI128::zero()).unwrap_or_else(Self::min_fixed)
+	}
+}
+
+impl FixedMath for I32F32 {
+	fn to_fixed_i128(self) -> Option<FixedI128> {
+		// An `I32F32` is its bits divided by 2 ^ 32
+		from_ratio(i128::from(self.to_bits()), 1 << 32)
+	}
+
+	fn from_fixed_i128(value: FixedI128) -> Option<Self> {
+		let bits = to_ratio(value, 1 << 32)?;
+		i64::try_from(bits).ok().map(Self::from_bits)
+	}
+
+	fn max_fixed() -> Self {
+		Self::from_bits(i64::max_value())
+	}
+
+	fn min_fixed() -> Self {
+		Self::from_bits(i64::min_value())
+	}
+
+	fn shr_fixed(self, bits: u32) -> Self {
+		Self::from_bits(self.to_bits() >> bits)
+	}
+}
+
+impl FixedMath for U64F64 {
+	fn to_fixed_i128(self) -> Option<FixedI128> {
+		// A `U64F64` is its bits divided by 2 ^ 64
+		let inner = mul_div(self.to_bits(), SCALE as u128, 1 << 64)?;
+		i128::try_from(inner).ok().map(FixedI128::from_inner)
+	}
+
+	fn from_fixed_i128(value: FixedI128) -> Option<Self> {
+		let inner = u128::try_from(value.into_inner()).ok()?;
+		mul_div(inner, 1 << 64, SCALE as u128).map(Self::from_bits)
+	}
+
+	fn max_fixed() -> Self {
+		Self::from_bits(u128::max_value())
+	}
+
+	fn min_fixed() -> Self {
+		Self::from_bits(0)
+	}
+
+	fn shr_fixed(self, bits: u32) -> Self {
+		Self::from_bits(self.to_bits() >> bits)
+	}
+}
+
+impl FixedMath for FixedU128 {
+	fn to_fixed_i128(self) -> Option<FixedI128> {
+		// Both types have 18 decimal places
+		i128::try_from(self.into_inner()).ok().map(FixedI128::from_inner)
+	}
+
+	fn from_fixed_i128(value: FixedI128) -> Option<Self> {
+		u128::try_from(value.into_inner()).ok().map(Self::from_inner)
+	}
+
+	fn max_fixed() -> Self {
+		Self::from_inner(u128::max_value())
+	}
+
+	fn min_fixed() -> Self {
+		Self::from_inner(0)
+	}
+
+	fn shr_fixed(self, bits: u32) -> Self {
+		Self::from_inner(self.into_inner() >> bits)
+	}
+}
+
+/// Applies a function on raw `FixedI128` values to a number of any fixed point type
+fn unary<F: FixedMath>(x: F, f: fn(i128) -> Option<i128>) -> Option<F> {
+	let result = f(x.to_fixed_i128()?.into_inner())?;
+	F::from_fixed_i128(FixedI128::from_inner(result))
+}
+
+/// `a * b / c`, or `None` if the result does not fit in a `u128`
+fn mul_div(a: u128, b: u128, c: u128) -> Option<u128> {
+	multiply_by_rational(a, b, c).ok()
+}
+
+/// Applies a sign to a magnitude, or `None` if the result does not fit in an `i128`
+fn signed(magnitude: u128, negative: bool) -> Option<i128> {
+	let value = i128::try_from(magnitude).ok()?;
+	Some(if negative { -value } else { value })
+}
+
+/// Converts `bits / one` to a `FixedI128`
+fn from_ratio(bits: i128, one: u128) -> Option<FixedI128> {
+	let inner = mul_div(bits.checked_abs()? as u128, SCALE as u128, one)?;
+	signed(inner, bits < 0).map(FixedI128::from_inner)
+}
+
+/// Converts a `FixedI128` to the bits of a type in which `one` represents one
+fn to_ratio(value: FixedI128, one: u128) -> Option<i128> {
+	let inner = value.into_inner();
+	signed(mul_div(inner.checked_abs()? as u128, one, SCALE as u128)?, inner < 0)
+}
+
+/// Multiplies two raw `FixedI128` values
+fn mul(a: i128, b: i128) -> Option<i128> {
+	let product = mul_div(a.checked_abs()? as u128, b.checked_abs()? as u128, SCALE as u128)?;
+	signed(product, (a < 0) != (b < 0))
+}
+
+/// Divides two raw `FixedI128` values
+fn div(a: i128, b: i128) -> Option<i128> {
+	if b == 0 {
+		return None;
+	}
+	let quotient = mul_div(a.checked_abs()? as u128, SCALE as u128, b.checked_abs()? as u128)?;
+	signed(quotient, (a < 0) != (b < 0))
+}
+
+/// e ^ x on raw `FixedI128` values
+fn exp(x: i128) -> Option<i128> {
+	if x < 0 {
+		// e ^ -x overflows long before e ^ x rounds to zero, so an overflow means zero
+		let reciprocal = x.checked_neg().and_then(exp);
+		return Some(reciprocal.and_then(|e| div(SCALE, e)).unwrap_or(0));
+	}
+
+	// Reduce the argument to r in [0, ln 2), so that e ^ x = e ^ r * 2 ^ k
+	let k = x / LN2;
+	if k >= 127 {
+		return None;
+	}
+	let r = x - k * LN2;
+
+	// e ^ r = 1 + r + r^2 / 2! + r^3 / 3! + ...
+	let mut term = SCALE;
+	let mut sum = SCALE;
+	for n in 1..MAX_TERMS {
+		term = term * r / SCALE / n;
+		if term == 0 {
+			break;
+		}
+		sum += term;
+	}
+
+	sum.checked_mul(1 << k)
+}
+
+/// The natural logarithm of raw `FixedI128` values
+fn ln(x: i128) -> Option<i128> {
+	if x <= 0 {
+		return None;
+	}
+
+	// Reduce the argument to y in [1, 2), so that ln(x) = ln(y) + k * ln(2)
+	let mut y = x;
+	let mut k = 0;
+	while y >= 2 * SCALE {
+		y >>= 1;
+		k += 1;
+	}
+	while y < SCALE {
+		y <<= 1;
+		k -= 1;
+	}
+
+	// ln(y) = 2 * atanh(z) = 2 * (z + z^3 / 3 + z^5 / 5 + ...) where z = (y - 1) / (y + 1) < 1/3
+	let z = (y - SCALE) * SCALE / (y + SCALE);
+	let z_squared = z * z / SCALE;
+	let mut term = z;
+	let mut sum = z;
+	let mut n = 3;
+	loop {
+		term = term * z_squared / SCALE;
+		if term == 0 {
+			break;
+		}
+		sum += term / n;
+		n += 2;
+	}
+
+	Some(k * LN2 + 2 * sum)
+}
+
+/// x ^ y on raw `FixedI128` values
+fn pow(x: i128, y: i128) -> Option<i128> {
+	if x < 0 {
+		return None;
+	}
+	if y == 0 {
+		return Some(SCALE);
+	}
+	if x == 0 {
+		return if y > 0 { Some(0) } else { None };
+	}
+
+	// x ^ y = e ^ (y * ln(x))
+	let ln_x = ln(x)?;
+	match mul(y, ln_x) {
+		Some(exponent) => exp(exponent),
+		// A hugely negative exponent rounds to zero
+		None if (y < 0) != (ln_x < 0) => Some(0),
+		None => None,
+	}
+}
+
+/// The square root of raw `FixedI128` values
+fn sqrt(x: i128) -> Option<i128> {
+	if x < 0 {
+		return None;
+	}
+	if x == 0 {
+		return Some(0);
+	}
+
+	// The raw result is sqrt(x * 10^18) = sqrt(x) * 10^9. Starting from that estimate, Newton's
+	// method overshoots once and then decreases towards the root.
+	let x = x as u128;
+	let mut root = integer_sqrt(x) * 1_000_000_000;
+	root = (root + mul_div(x, SCALE as u128, root)?) / 2;
+	loop {
+		let next = (root + mul_div(x, SCALE as u128, root)?) / 2;
+		if next >= root {
+			break;
+		}
+		root = next;
+	}
+
+	i128::try_from(root).ok()
+}
+
+/// The integer square root, rounded down
+fn integer_sqrt(n: u128) -> u128 {
+	if n < 2 {
+		return n;
+	}
+	let mut x = n;
+	let mut y = (n + 1) / 2;
+	while y < x {
+		x = y;
+		y = (x + n / x) / 2;
+	}
+	x
+}
@@ -0,0 +1,187 @@
+use super::FixedMath;
+use sp_arithmetic::{FixedPointNumber, FixedU128};
+use substrate_fixed::types::{I32F32, U64F64};
+
+/// Relative tolerance against the `f64` reference. The documented error bounds are at most about
+/// 1.3 * 10^-15 in the tested ranges. The rest allows for rounding the arguments of the reference
+/// to `f64`, which `exp` and `pow` amplify by up to 20 and 35 times.
+const RELATIVE: f64 = 1e-14;
+
+/// A deterministic xorshift generator, so failures can be reproduced
+struct Sampler(u64);
+
+impl Sampler {
+	fn new() -> Self {
+		Sampler(0x2545_f491_4f6c_dd1d)
+	}
+
+	/// A uniform sample in [low, high)
+	fn sample(&mut self, low: f64, high: f64) -> f64 {
+		self.0 ^= self.0 << 13;
+		self.0 ^= self.0 >> 7;
+		self.0 ^= self.0 << 17;
+		low + (high - low) * (self.0 >> 11) as f64 / (1u64 << 53) as f64
+	}
+}
+
+/// Conversions between a fixed point type and `f64` for comparing against the reference
+trait Reference: FixedMath {
+	/// The smallest positive number of the type
+	const RESOLUTION: f64;
+
+	fn from_f64(x: f64) -> Self;
+
+	fn to_f64(self) -> f64;
+}
+
+impl Reference for I32F32 {
+	const RESOLUTION: f64 = 1.0 / 4_294_967_296.0;
+
+	fn from_f64(x: f64) -> Self {
+		Self::from_num(x)
+	}
+
+	fn to_f64(self) -> f64 {
+		self.to_num()
+	}
+}
+
+impl Reference for U64F64 {
+	const RESOLUTION: f64 = 1.0 / 18_446_744_073_709_551_616.0;
+
+	fn from_f64(x: f64) -> Self {
+		Self::from_num(x)
+	}
+
+	fn to_f64(self) -> f64 {
+		self.to_num()
+	}
+}
+
+impl Reference for FixedU128 {
+	const RESOLUTION: f64 = 1e-18;
+
+	fn from_f64(x: f64) -> Self {
+		Self::from_inner((x * 1e18) as u128)
+	}
+
+	fn to_f64(self) -> f64 {
+		self.into_inner() as f64 / 1e18
+	}
+}
+
+/// Asserts that `actual` is within the tolerance of `expected`
+fn assert_close<F: Reference>(actual: Option<F>, expected: f64, input: f64) {
+	let actual = actual.expect("result should be in range").to_f64();
+	// Allow for the error of converting the input as well as the result
+	let tolerance = expected.abs() * RELATIVE + 4.0 * F::RESOLUTION + 1e-15;
+	assert!(
+		(actual - expected).abs() <= tolerance,
+		"input {}: expected {}, got {}",
+		input,
+		expected,
+		actual
+	);
+}
+
+/// Compares each function against `f64` on random arguments in the given ranges
+fn check_against_f64<F: Reference>(exp_range: (f64, f64), ln_range: (f64, f64)) {
+	let mut sampler = Sampler::new();
+	for _ in 0..1_000 {
+		let x = F::from_f64(sampler.sample(exp_range.0, exp_range.1));
+		assert_close(x.checked_exp(), x.to_f64().exp(), x.to_f64());
+
+		let y = F::from_f64(sampler.sample(ln_range.0, ln_range.1));
+		assert_close(y.checked_ln(), y.to_f64().ln(), y.to_f64());
+		assert_close(y.checked_sqrt(), y.to_f64().sqrt(), y.to_f64());
+
+		let exponent = F::from_f64(sampler.sample(0.0, 3.0));
+		let expected = y.to_f64().powf(exponent.to_f64());
+		assert_close(y.checked_powf(exponent), expected, y.to_f64());
+	}
+}
+
+#[test]
+fn i32f32_matches_f64() {
+	check_against_f64::<I32F32>((-10.0, 10.0), (1.0, 1_000.0));
+}
+
+#[test]
+fn u64f64_matches_f64() {
+	check_against_f64::<U64F64>((0.0, 20.0), (1.0, 100_000.0));
+}
+
+#[test]
+fn fixed_u128_matches_f64() {
+	check_against_f64::<FixedU128>((0.0, 20.0), (1.0, 100_000.0));
+}
+
+#[test]
+fn ln_below_one_matches_f64() {
+	let mut sampler = Sampler::new();
+	for _ in 0..1_000 {
+		let x = I32F32::from_f64(sampler.sample(0.001, 1.0));
+		assert_close(x.checked_ln(), x.to_f64().ln(), x.to_f64());
+	}
+}
+
+#[test]
+fn exact_values() {
+	let one = FixedU128::one();
+	assert_eq!(FixedU128::zero().checked_exp(), Some(one));
+	assert_eq!(one.checked_ln(), Some(FixedU128::zero()));
+	let sixteen = FixedU128::saturating_from_integer(16);
+	assert_eq!(sixteen.checked_sqrt(), Some(FixedU128::saturating_from_integer(4)));
+	assert_eq!(one.checked_powf(FixedU128::zero()), Some(one));
+	assert_eq!(FixedU128::zero().checked_sqrt(), Some(FixedU128::zero()));
+}
+
+#[test]
+fn undefined_results() {
+	assert_eq!(I32F32::from_num(0).checked_ln(), None);
+	assert_eq!(I32F32::from_num(0).saturating_ln(), I32F32::min_value());
+	assert_eq!(I32F32::from_num(-1).checked_sqrt(), None);
+	assert_eq!(I32F32::from_num(-1).saturating_sqrt(), I32F32::from_num(0));
+	assert_eq!(I32F32::from_num(-2).checked_powf(I32F32::from_num(0.5)), None);
+	assert_eq!(I32F32::from_num(-2).saturating_powf(I32F32::from_num(0.5)), I32F32::from_num(0));
+	assert_eq!(I32F32::from_num(0).checked_powf(I32F32::from_num(-1)), None);
+	assert_eq!(I32F32::from_num(0).saturating_powf(I32F32::from_num(-1)), I32F32::max_value());
+}
+
+#[test]
+fn results_out_of_range() {
+	// e ^ 22 is larger than 2 ^ 31
+	assert_eq!(I32F32::from_num(22).checked_exp(), None);
+	assert_eq!(I32F32::from_num(22).saturating_exp(), I32F32::max_value());
+	let thousand = FixedU128::saturating_from_integer(1_000);
+	assert_eq!(thousand.saturating_exp(), FixedU128::max_fixed());
+
+	// Unsigned types cannot hold negative logarithms
+	assert_eq!(U64F64::from_num(0.5).checked_ln(), None);
+	assert_eq!(U64F64::from_num(0.5).saturating_ln(), U64F64::from_num(0));
+
+	// Tiny results round to zero rather than overflowing
+	assert_eq!(I32F32::from_num(-100).checked_exp(), Some(I32F32::from_num(0)));
+	let tiny = I32F32::from_num(0.001).checked_powf(I32F32::from_num(1_000));
+	assert_eq!(tiny, Some(I32F32::from_num(0)));
+}
+
+#[test]
+fn arguments_out_of_range() {
+	// Beyond the range of `FixedI128`, `ln` and `sqrt` reduce the argument first
+	let mut sampler = Sampler::new();
+	for _ in 0..1_000 {
+		let x = FixedU128::from_f64(sampler.sample(2e20, 3.4e20));
+		assert_close(x.checked_ln(), x.to_f64().ln(), x.to_f64());
+		assert_close(x.checked_sqrt(), x.to_f64().sqrt(), x.to_f64());
+	}
+
+	let max = FixedU128::max_fixed();
+	assert_close(max.checked_ln(), max.to_f64().ln(), max.to_f64());
+	assert_close(max.checked_sqrt(), max.to_f64().sqrt(), max.to_f64());
+	assert_eq!(max.saturating_sqrt(), max.checked_sqrt().unwrap());
+
+	// `exp` and `pow` are still limited to the range of `FixedI128`
+	assert_eq!(max.checked_exp(), None);
+	assert_eq!(max.checked_powf(FixedU128::one()), None);
+}
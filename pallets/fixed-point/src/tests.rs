@@ -1,14 +1,17 @@
 use super::Event;
-use crate::{Error, Module, Trait};
-use frame_support::{assert_noop, assert_ok, impl_outer_event, impl_outer_origin, parameter_types};
-use frame_system::{self as system, EventRecord, Phase};
-use sp_arithmetic::Permill;
+use crate::{CumulativePrice, Error, Module, Trait};
+use frame_support::{
+	assert_noop, assert_ok, impl_outer_event, impl_outer_origin, parameter_types,
+	traits::OnFinalize,
+};
+use frame_system::{self as system, EnsureRoot, EventRecord, Phase};
+use sp_arithmetic::{FixedPointNumber, FixedU128, Permill};
 use sp_core::H256;
 use sp_io::TestExternalities;
 use sp_runtime::{
 	testing::Header,
 	traits::{BlakeTwo256, IdentityLookup},
-	Perbill,
+	DispatchError, Perbill,
 };
 use substrate_fixed::types::U16F16;
 
@@ -64,8 +67,16 @@ impl_outer_event! {
 	}
 }
 
+parameter_types! {
+	pub const HistoryDepth: u64 = 5;
+	pub const Smoothing: Permill = Permill::from_percent(50);
+}
+
 impl Trait for TestRuntime {
 	type Event = TestEvent;
+	type HistoryDepth = HistoryDepth;
+	type Smoothing = Smoothing;
+	type OracleOrigin = EnsureRoot<u64>;
 }
 
 pub type System = system::Module<TestRuntime>;
@@ -84,6 +95,18 @@ impl ExternalityBuilder {
 	}
 }
 
+/// Finalizes the current block and the blocks after it, up to and including block `n`
+fn finalize_to_block(n: u64) {
+	while System::block_number() <= n {
+		FixedPoint::on_finalize(System::block_number());
+		System::set_block_number(System::block_number() + 1);
+	}
+}
+
+fn price(n: u128) -> FixedU128 {
+	FixedU128::saturating_from_integer(n)
+}
+
 #[test]
 fn all_accumulators_start_at_one() {
 	ExternalityBuilder::build().execute_with(|| {
@@ -254,3 +277,101 @@ fn fixed_impl_overflows() {
 		);
 	})
 }
+
+#[test]
+fn averages_start_with_first_observation() {
+	ExternalityBuilder::build().execute_with(|| {
+		finalize_to_block(1);
+		assert_eq!(FixedPoint::price_checkpoint(1), None);
+		assert_eq!(FixedPoint::moving_average(), None);
+
+		assert_ok!(FixedPoint::submit_observation(Origin::root(), price(10)));
+		finalize_to_block(2);
+
+		assert_eq!(FixedPoint::price_checkpoint(2), Some(price(10).into_inner()));
+		assert_eq!(FixedPoint::moving_average(), Some(price(10)));
+		assert_eq!(
+			System::events()[0].event,
+			TestEvent::fixed_point(Event::PriceObserved(price(10)))
+		);
+	})
+}
+
+#[test]
+fn only_oracle_submits_observations() {
+	ExternalityBuilder::build().execute_with(|| {
+		assert_noop!(
+			FixedPoint::submit_observation(Origin::signed(1), price(10)),
+			DispatchError::BadOrigin
+		);
+		assert_ok!(FixedPoint::submit_observation(Origin::root(), price(10)));
+	})
+}
+
+#[test]
+fn time_weighted_average_works() {
+	ExternalityBuilder::build().execute_with(|| {
+		// The price is 10 during blocks 1 to 3 and 20 during blocks 4 and 5
+		assert_ok!(FixedPoint::submit_observation(Origin::root(), price(10)));
+		finalize_to_block(3);
+		assert_ok!(FixedPoint::submit_observation(Origin::root(), price(30)));
+		assert_ok!(FixedPoint::submit_observation(Origin::root(), price(20)));
+		finalize_to_block(5);
+
+		assert_eq!(FixedPoint::time_weighted_average(1, 5), Some(price(15)));
+		assert_eq!(FixedPoint::time_weighted_average(3, 5), Some(price(20)));
+		assert_eq!(FixedPoint::time_weighted_average(1, 2), Some(price(10)));
+
+		// Empty windows and blocks without checkpoints have no average
+		assert_eq!(FixedPoint::time_weighted_average(5, 5), None);
+		assert_eq!(FixedPoint::time_weighted_average(5, 3), None);
+		assert_eq!(FixedPoint::time_weighted_average(0, 5), None);
+		assert_eq!(FixedPoint::time_weighted_average(1, 6), None);
+	})
+}
+
+#[test]
+fn old_checkpoints_are_pruned() {
+	ExternalityBuilder::build().execute_with(|| {
+		assert_ok!(FixedPoint::submit_observation(Origin::root(), price(10)));
+		finalize_to_block(7);
+
+		assert_eq!(FixedPoint::price_checkpoint(2), None);
+		assert_eq!(FixedPoint::time_weighted_average(2, 7), None);
+		assert_eq!(FixedPoint::time_weighted_average(3, 7), Some(price(10)));
+	})
+}
+
+#[test]
+fn cumulative_price_wraps_around() {
+	ExternalityBuilder::build().execute_with(|| {
+		CumulativePrice::put(u128::max_value() - price(15).into_inner());
+		assert_ok!(FixedPoint::submit_observation(Origin::root(), price(10)));
+		finalize_to_block(3);
+
+		assert!(FixedPoint::cumulative_price() < price(30).into_inner());
+		assert_eq!(FixedPoint::time_weighted_average(1, 3), Some(price(10)));
+	})
+}
+
+#[test]
+fn moving_average_works() {
+	ExternalityBuilder::build().execute_with(|| {
+		assert_ok!(FixedPoint::submit_observation(Origin::root(), price(10)));
+		finalize_to_block(1);
+		assert_eq!(FixedPoint::moving_average(), Some(price(10)));
+
+		// With a smoothing factor of one half, the average moves halfway towards the price
+		assert_ok!(FixedPoint::submit_observation(Origin::root(), price(20)));
+		finalize_to_block(2);
+		assert_eq!(FixedPoint::moving_average(), Some(price(15)));
+		finalize_to_block(3);
+		let expected = FixedU128::saturating_from_rational(35, 2);
+		assert_eq!(FixedPoint::moving_average(), Some(expected));
+
+		assert_ok!(FixedPoint::submit_observation(Origin::root(), price(10)));
+		finalize_to_block(4);
+		let expected = FixedU128::saturating_from_rational(55, 4);
+		assert_eq!(FixedPoint::moving_average(), Some(expected));
+	})
+}
@@ -0,0 +1,284 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+//! A pallet that executes scheduled calls in order of priority.
+//!
+//! Users schedule a call with a priority. Each priority level has its own queue, built with the
+//! ringbuffer transient storage adapter from the `ringbuffer-queue` pallet. At the start of every
+//! block, `on_initialize` executes jobs from the highest priority queue first, for as long as they
+//! fit within the `MaxBlockWeight` budget. A job whose call fails is put back at the end of its
+//! queue and tried again in a later block, until it has been retried `MaxRetries` times.
+//!
+//! Scheduling a job reserves a `JobDeposit`, which is returned once the job has been executed or
+//! dropped. Jobs may schedule further jobs, because the queue is committed to storage before each
+//! call is dispatched.
+
+use codec::{Decode, Encode};
+use frame_support::{
+	decl_error, decl_event, decl_module, decl_storage,
+	dispatch::{DispatchResult, Parameter},
+	ensure, parameter_types,
+	traits::{Currency, Get, ReservableCurrency},
+	weights::{GetDispatchInfo, Weight},
+};
+use frame_system::{self as system, ensure_signed};
+use ringbuffer_queue::{
+	ringbuffer::{RingBufferTrait, RingBufferTransient},
+	OverflowPolicy,
+};
+use sp_runtime::{traits::Dispatchable, DispatchError, RuntimeDebug};
+use sp_std::prelude::*;
+
+#[cfg(test)]
+mod tests;
+
+pub type JobIndex = u16;
+
+/// The priority of a job. Higher priority jobs are executed first.
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, RuntimeDebug)]
+pub enum Priority {
+	High,
+	Normal,
+	Low,
+}
+
+/// The priority levels, from highest to lowest
+const PRIORITIES: [Priority; 3] = [Priority::High, Priority::Normal, Priority::Low];
+
+/// A scheduled call
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct Job<AccountId, Call, Balance> {
+	/// The account that scheduled the job, which is the origin of the call
+	owner: AccountId,
+	/// The call to execute
+	call: Call,
+	/// The number of times the call has failed
+	attempts: u8,
+	/// The deposit reserved from the owner until the job is executed or dropped
+	deposit: Balance,
+}
+
+type BalanceOf<T> = <<T as Trait>::Currency as Currency<<T as system::Trait>::AccountId>>::Balance;
+type JobOf<T> = Job<<T as system::Trait>::AccountId, <T as Trait>::Call, BalanceOf<T>>;
+
+parameter_types! {
+	/// Scheduling a job fails if its queue is full
+	pub const FailWhenFull: OverflowPolicy = OverflowPolicy::Error;
+}
+
+pub trait Trait: system::Trait {
+	type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
+
+	/// The calls that may be scheduled
+	type Call: Parameter + Dispatchable<Origin = <Self as system::Trait>::Origin> + GetDispatchInfo;
+
+	/// The currency in which job deposits are reserved
+	type Currency: ReservableCurrency<Self::AccountId>;
+
+	/// The amount reserved for each scheduled job
+	type JobDeposit: Get<BalanceOf<Self>>;
+
+	/// The maximum number of jobs in the queue of each priority level
+	type QueueCapacity: Get<JobIndex>;
+
+	/// The maximum total weight of the jobs executed at the start of a block
+	type MaxBlockWeight: Get<Weight>;
+
+	/// The number of times a failed job is retried before it is dropped
+	type MaxRetries: Get<u8>;
+}
+
+decl_storage! {
+	trait Store for Module<T: Trait> as JobQueue {
+		HighRange get(fn high_range): (JobIndex, JobIndex) = (0, 0);
+		HighJobs get(fn high_job): map hasher(twox_64_concat) JobIndex => Option<JobOf<T>>;
+		NormalRange get(fn normal_range): (JobIndex, JobIndex) = (0, 0);
+		NormalJobs get(fn normal_job): map hasher(twox_64_concat) JobIndex => Option<JobOf<T>>;
+		LowRange get(fn low_range): (JobIndex, JobIndex) = (0, 0);
+		LowJobs get(fn low_job): map hasher(twox_64_concat) JobIndex => Option<JobOf<T>>;
+	}
+}
+
+decl_event!(
+	pub enum Event<T>
+	where
+		AccountId = <T as system::Trait>::AccountId,
+	{
+		/// A job was scheduled
+		Scheduled(AccountId, Priority),
+		/// A job was executed successfully
+		Executed(AccountId, Priority),
+		/// A job failed and will be retried. The number is how often it has failed.
+		Retrying(AccountId, Priority, u8),
+		/// A job failed too often and was dropped
+		Failed(AccountId, Priority, DispatchError),
+	}
+);
+
+decl_error! {
+	pub enum Error for Module<T: Trait> {
+		/// The call is too heavy to ever be executed within the weight budget of a block
+		TooHeavy,
+		/// The queue of the priority level is full
+		QueueFull,
+	}
+}
+
+decl_module! {
+	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+		fn deposit_event() = default;
+
+		type Error = Error<T>;
+
+		/// The amount reserved for each scheduled job
+		const JobDeposit: BalanceOf<T> = T::JobDeposit::get();
+
+		/// The maximum number of jobs in the queue of each priority level
+		const QueueCapacity: JobIndex = T::QueueCapacity::get();
+
+		/// The maximum total weight of the jobs executed at the start of a block
+		const MaxBlockWeight: Weight = T::MaxBlockWeight::get();
+
+		/// The number of times a failed job is retried before it is dropped
+		const MaxRetries: u8 = T::MaxRetries::get();
+
+		fn on_initialize(_n: T::BlockNumber) -> Weight {
+			let mut used = 0;
+			for &priority in PRIORITIES.iter() {
+				// Lower priority jobs only run once all higher priority jobs have
+				if !Self::execute_jobs(priority, &mut used) {
+					break;
+				}
+			}
+
+			// Reading and writing the bounds of the queues
+			used.saturating_add(T::DbWeight::get().reads_writes(3, 3))
+		}
+
+		/// Schedule a call to be executed with the caller as its origin. The caller pays for the
+		/// weight of the call up front, and a `JobDeposit` is reserved until the job is executed or
+		/// dropped.
+		#[weight = call.get_dispatch_info().weight.saturating_add(10_000)]
+		fn schedule(origin, priority: Priority, call: Box<<T as Trait>::Call>) -> DispatchResult {
+			let owner = ensure_signed(origin)?;
+
+			// A job that can never fit in a block would block its queue forever
+			let weight = call.get_dispatch_info().weight.saturating_add(Self::job_overhead());
+			ensure!(weight <= T::MaxBlockWeight::get(), Error::<T>::TooHeavy);
+
+			let mut queue = Self::queue(priority);
+			ensure!(
+				queue.len() < T::QueueCapacity::get() as usize,
+				Error::<T>::QueueFull
+			);
+
+			let deposit = T::JobDeposit::get();
+			T::Currency::reserve(&owner, deposit)?;
+			let job = Job { owner: owner.clone(), call: *call, attempts: 0, deposit };
+			// There is room in the queue, so the job is added
+			let _ = queue.push(job);
+
+			Self::deposit_event(RawEvent::Scheduled(owner, priority));
+			Ok(())
+		}
+	}
+}
+
+impl<T: Trait> Module<T> {
+	/// Constructs the ringbuffer transient of a priority level and returns it as a boxed trait
+	/// object.
+	fn queue(priority: Priority) -> Box<dyn RingBufferTrait<JobOf<T>>> {
+		match priority {
+			Priority::High => Box::new(RingBufferTransient::<
+				JobOf<T>,
+				<Self as Store>::HighRange,
+				<Self as Store>::HighJobs,
+				T::QueueCapacity,
+				FailWhenFull,
+				JobIndex,
+			>::new()),
+			Priority::Normal => Box::new(RingBufferTransient::<
+				JobOf<T>,
+				<Self as Store>::NormalRange,
+				<Self as Store>::NormalJobs,
+				T::QueueCapacity,
+				FailWhenFull,
+				JobIndex,
+			>::new()),
+			Priority::Low => Box::new(RingBufferTransient::<
+				JobOf<T>,
+				<Self as Store>::LowRange,
+				<Self as Store>::LowJobs,
+				T::QueueCapacity,
+				FailWhenFull,
+				JobIndex,
+			>::new()),
+		}
+	}
+
+	/// The weight of handling a job apart from its call: reading and committing the bounds of its
+	/// queue, peeking at it, popping it, returning its deposit, and pushing it back if it fails
+	fn job_overhead() -> Weight {
+		T::DbWeight::get().reads_writes(4, 4)
+	}
+
+	/// Executes jobs from the queue of a priority level until the queue is empty or the next job
+	/// does not fit within the weight budget. Returns whether every job was attempted.
+	fn execute_jobs(priority: Priority, used: &mut Weight) -> bool {
+		let mut failed = Vec::new();
+		let mut attempted_all = true;
+
+		loop {
+			// The queue is committed to storage and dropped before the call is dispatched, because
+			// the call may schedule another job and so change the bounds of the queue
+			let job = {
+				let mut queue = Self::queue(priority);
+				let job = match queue.peek() {
+					Some(job) => job,
+					None => break,
+				};
+				let call_weight = job.call.get_dispatch_info().weight;
+				let weight = call_weight.saturating_add(Self::job_overhead());
+				if used.saturating_add(weight) > T::MaxBlockWeight::get() {
+					// Peeking at the job that did not fit
+					*used = used.saturating_add(T::DbWeight::get().reads(1));
+					attempted_all = false;
+					break;
+				}
+				queue.pop();
+				*used = used.saturating_add(weight);
+				job
+			};
+
+			let origin = system::RawOrigin::Signed(job.owner.clone()).into();
+			match job.call.clone().dispatch(origin) {
+				Ok(_) => {
+					T::Currency::unreserve(&job.owner, job.deposit);
+					Self::deposit_event(RawEvent::Executed(job.owner, priority));
+				}
+				Err(_) if job.attempts < T::MaxRetries::get() => {
+					let attempts = job.attempts + 1;
+					Self::deposit_event(RawEvent::Retrying(job.owner.clone(), priority, attempts));
+					failed.push(Job { attempts, ..job });
+				}
+				Err(e) => Self::drop_job(job, priority, e.error),
+			}
+		}
+
+		// Failed jobs go to the back of the queue, so they are not retried in this block. The jobs
+		// executed in this block may have filled the queue again, in which case they are dropped.
+		let mut queue = Self::queue(priority);
+		for job in failed {
+			if queue.push(job.clone()).is_err() {
+				Self::drop_job(job, priority, Error::<T>::QueueFull.into());
+			}
+		}
+
+		attempted_all
+	}
+
+	/// Drops a job that failed for good and returns its deposit
+	fn drop_job(job: JobOf<T>, priority: Priority, error: DispatchError) {
+		T::Currency::unreserve(&job.owner, job.deposit);
+		Self::deposit_event(RawEvent::Failed(job.owner, priority, error));
+	}
+}
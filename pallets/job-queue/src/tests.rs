@@ -0,0 +1,386 @@
+use super::RawEvent;
+use crate::{Error, Module, Priority, Trait};
+use balances;
+use frame_support::{
+	assert_noop, assert_ok, impl_outer_dispatch, impl_outer_event, impl_outer_origin,
+	parameter_types, traits::OnInitialize, weights::GetDispatchInfo,
+};
+use frame_system as system;
+use sp_core::H256;
+use sp_io::TestExternalities;
+use sp_runtime::{
+	testing::Header,
+	traits::{BlakeTwo256, IdentityLookup},
+	DispatchError, DispatchResult, Perbill,
+};
+
+// A pallet whose calls are scheduled as jobs in the tests
+mod test_jobs {
+	use frame_support::{decl_module, decl_storage, dispatch::DispatchResult};
+	use frame_system::{self as system, ensure_signed};
+	use sp_runtime::DispatchError;
+
+	pub trait Trait: system::Trait {
+		/// Schedules a job that records `id`
+		fn schedule_record(who: Self::AccountId, id: u32) -> DispatchResult;
+	}
+
+	decl_storage! {
+		trait Store for Module<T: Trait> as TestJobs {
+			/// The ids recorded by the `record` call, in order
+			pub Recorded get(fn recorded): Vec<u32>;
+		}
+	}
+
+	decl_module! {
+		pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+			#[weight = 100]
+			fn record(origin, id: u32) -> DispatchResult {
+				ensure_signed(origin)?;
+				Recorded::mutate(|recorded| recorded.push(id));
+				Ok(())
+			}
+
+			#[weight = 100]
+			fn fail(origin) -> DispatchResult {
+				ensure_signed(origin)?;
+				Err(DispatchError::Other("job failed"))
+			}
+
+			#[weight = 1_000]
+			fn heavy(origin) -> DispatchResult {
+				ensure_signed(origin)?;
+				Ok(())
+			}
+
+			#[weight = 100]
+			fn schedule(origin, id: u32) -> DispatchResult {
+				let who = ensure_signed(origin)?;
+				T::schedule_record(who, id)
+			}
+		}
+	}
+}
+
+impl_outer_origin! {
+	pub enum Origin for TestRuntime {}
+}
+
+impl_outer_dispatch! {
+	pub enum Call for TestRuntime where origin: Origin {
+		job_queue::JobQueue,
+		test_jobs::TestJobs,
+	}
+}
+
+// Workaround for https://github.com/rust-lang/rust/issues/26925 . Remove when sorted.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TestRuntime;
+parameter_types! {
+	pub const BlockHashCount: u64 = 250;
+	pub const MaximumBlockWeight: u32 = 1024;
+	pub const MaximumBlockLength: u32 = 2 * 1024;
+	pub const AvailableBlockRatio: Perbill = Perbill::one();
+	pub const ExistentialDeposit: u64 = 1;
+}
+impl system::Trait for TestRuntime {
+	type BaseCallFilter = ();
+	type Origin = Origin;
+	type Index = u64;
+	type Call = Call;
+	type BlockNumber = u64;
+	type Hash = H256;
+	type Hashing = BlakeTwo256;
+	type AccountId = u64;
+	type Lookup = IdentityLookup<Self::AccountId>;
+	type Header = Header;
+	type Event = TestEvent;
+	type BlockHashCount = BlockHashCount;
+	type MaximumBlockWeight = MaximumBlockWeight;
+	type DbWeight = ();
+	type BlockExecutionWeight = ();
+	type ExtrinsicBaseWeight = ();
+	type MaximumExtrinsicWeight = MaximumBlockWeight;
+	type MaximumBlockLength = MaximumBlockLength;
+	type AvailableBlockRatio = AvailableBlockRatio;
+	type Version = ();
+	type PalletInfo = ();
+	type AccountData = balances::AccountData<u64>;
+	type OnNewAccount = ();
+	type OnKilledAccount = ();
+	type SystemWeightInfo = ();
+}
+
+impl balances::Trait for TestRuntime {
+	type Balance = u64;
+	type MaxLocks = ();
+	type Event = TestEvent;
+	type DustRemoval = ();
+	type ExistentialDeposit = ExistentialDeposit;
+	type AccountStore = system::Module<TestRuntime>;
+	type WeightInfo = ();
+}
+
+mod job_queue {
+	pub use crate::Event;
+}
+
+impl_outer_event! {
+	pub enum TestEvent for TestRuntime {
+		job_queue<T>,
+		system<T>,
+		balances<T>,
+	}
+}
+
+parameter_types! {
+	pub const JobDeposit: u64 = 10;
+	pub const QueueCapacity: u16 = 4;
+	pub const MaxBlockWeight: u64 = 500;
+	pub const MaxRetries: u8 = 2;
+}
+
+impl Trait for TestRuntime {
+	type Event = TestEvent;
+	type Call = Call;
+	type Currency = balances::Module<Self>;
+	type JobDeposit = JobDeposit;
+	type QueueCapacity = QueueCapacity;
+	type MaxBlockWeight = MaxBlockWeight;
+	type MaxRetries = MaxRetries;
+}
+
+impl test_jobs::Trait for TestRuntime {
+	fn schedule_record(who: u64, id: u32) -> DispatchResult {
+		JobQueue::schedule(Origin::signed(who), Priority::Normal, record(id))
+	}
+}
+
+pub type System = system::Module<TestRuntime>;
+pub type Balances = balances::Module<TestRuntime>;
+pub type JobQueue = Module<TestRuntime>;
+pub type TestJobs = test_jobs::Module<TestRuntime>;
+
+struct ExternalityBuilder;
+
+impl ExternalityBuilder {
+	pub fn build() -> TestExternalities {
+		let mut storage = system::GenesisConfig::default()
+			.build_storage::<TestRuntime>()
+			.unwrap();
+		balances::GenesisConfig::<TestRuntime> {
+			balances: vec![(1, 100), (2, 5)],
+		}
+		.assimilate_storage(&mut storage)
+		.unwrap();
+		let mut ext = TestExternalities::from(storage);
+		ext.execute_with(|| System::set_block_number(1));
+		ext
+	}
+}
+
+fn record(id: u32) -> Box<Call> {
+	Box::new(Call::TestJobs(test_jobs::Call::record(id)))
+}
+
+fn fail() -> Box<Call> {
+	Box::new(Call::TestJobs(test_jobs::Call::fail()))
+}
+
+fn last_event() -> TestEvent {
+	System::events()
+		.pop()
+		.expect("an event should be deposited")
+		.event
+}
+
+#[test]
+fn jobs_run_in_priority_order() {
+	ExternalityBuilder::build().execute_with(|| {
+		assert_ok!(JobQueue::schedule(
+			Origin::signed(1),
+			Priority::Low,
+			record(1)
+		));
+		assert_ok!(JobQueue::schedule(
+			Origin::signed(1),
+			Priority::Normal,
+			record(2)
+		));
+		assert_ok!(JobQueue::schedule(
+			Origin::signed(1),
+			Priority::High,
+			record(3)
+		));
+		assert_ok!(JobQueue::schedule(
+			Origin::signed(1),
+			Priority::Normal,
+			record(4)
+		));
+		assert_eq!(
+			last_event(),
+			TestEvent::job_queue(RawEvent::Scheduled(1, Priority::Normal))
+		);
+
+		assert_eq!(JobQueue::on_initialize(2), 400);
+		assert_eq!(TestJobs::recorded(), vec![3, 2, 4, 1]);
+		assert_eq!(
+			last_event(),
+			TestEvent::job_queue(RawEvent::Executed(1, Priority::Low))
+		);
+		assert_eq!(JobQueue::normal_range(), (2, 2));
+		assert_eq!(JobQueue::normal_job(0), None);
+	})
+}
+
+#[test]
+fn jobs_beyond_weight_budget_wait() {
+	ExternalityBuilder::build().execute_with(|| {
+		for id in 1..5 {
+			assert_ok!(JobQueue::schedule(
+				Origin::signed(1),
+				Priority::High,
+				record(id)
+			));
+		}
+		for id in 5..7 {
+			assert_ok!(JobQueue::schedule(
+				Origin::signed(1),
+				Priority::Low,
+				record(id)
+			));
+		}
+
+		// Only five jobs of weight 100 fit in the budget of 500
+		assert_eq!(JobQueue::on_initialize(2), 500);
+		assert_eq!(TestJobs::recorded(), vec![1, 2, 3, 4, 5]);
+
+		assert_eq!(JobQueue::on_initialize(3), 100);
+		assert_eq!(TestJobs::recorded(), vec![1, 2, 3, 4, 5, 6]);
+	})
+}
+
+#[test]
+fn failed_jobs_are_retried() {
+	ExternalityBuilder::build().execute_with(|| {
+		assert_ok!(JobQueue::schedule(
+			Origin::signed(1),
+			Priority::Normal,
+			fail()
+		));
+		assert_ok!(JobQueue::schedule(
+			Origin::signed(1),
+			Priority::Normal,
+			record(1)
+		));
+
+		// The failed job goes to the back of the queue
+		JobQueue::on_initialize(2);
+		let retrying = TestEvent::job_queue(RawEvent::Retrying(1, Priority::Normal, 1));
+		assert!(System::events().iter().any(|event| event.event == retrying));
+		assert_eq!(TestJobs::recorded(), vec![1]);
+		assert_eq!(JobQueue::normal_range(), (2, 3));
+
+		JobQueue::on_initialize(3);
+		assert_eq!(
+			last_event(),
+			TestEvent::job_queue(RawEvent::Retrying(1, Priority::Normal, 2))
+		);
+
+		// After the maximum number of retries, the job is dropped
+		JobQueue::on_initialize(4);
+		assert_eq!(
+			last_event(),
+			TestEvent::job_queue(RawEvent::Failed(
+				1,
+				Priority::Normal,
+				DispatchError::Other("job failed")
+			))
+		);
+		assert_eq!(JobQueue::normal_range(), (4, 4));
+
+		// The deposit of the dropped job is returned
+		assert_eq!(Balances::reserved_balance(1), 0);
+	})
+}
+
+#[test]
+fn schedule_checks_weight_and_capacity() {
+	ExternalityBuilder::build().execute_with(|| {
+		let heavy = Box::new(Call::TestJobs(test_jobs::Call::heavy()));
+		assert_noop!(
+			JobQueue::schedule(Origin::signed(1), Priority::High, heavy),
+			Error::<TestRuntime>::TooHeavy
+		);
+
+		for id in 0..4 {
+			assert_ok!(JobQueue::schedule(
+				Origin::signed(1),
+				Priority::High,
+				record(id)
+			));
+		}
+		assert_noop!(
+			JobQueue::schedule(Origin::signed(1), Priority::High, record(4)),
+			Error::<TestRuntime>::QueueFull
+		);
+
+		// Each priority level has its own queue
+		assert_ok!(JobQueue::schedule(
+			Origin::signed(1),
+			Priority::Low,
+			record(4)
+		));
+	})
+}
+
+#[test]
+fn scheduling_pays_for_the_call() {
+	let call = Call::JobQueue(crate::Call::schedule(Priority::High, record(1)));
+	assert_eq!(call.get_dispatch_info().weight, 10_100);
+}
+
+#[test]
+fn jobs_can_schedule_jobs() {
+	ExternalityBuilder::build().execute_with(|| {
+		let schedule = Box::new(Call::TestJobs(test_jobs::Call::schedule(2)));
+		assert_ok!(JobQueue::schedule(
+			Origin::signed(1),
+			Priority::Normal,
+			schedule
+		));
+		assert_ok!(JobQueue::schedule(
+			Origin::signed(1),
+			Priority::Normal,
+			record(1)
+		));
+
+		// The job scheduled by the first job is added behind the second one
+		JobQueue::on_initialize(2);
+		assert_eq!(TestJobs::recorded(), vec![1, 2]);
+		assert_eq!(JobQueue::normal_range(), (3, 3));
+		assert_eq!(JobQueue::normal_job(2), None);
+		assert_eq!(Balances::reserved_balance(1), 0);
+	})
+}
+
+#[test]
+fn deposit_is_held_until_execution() {
+	ExternalityBuilder::build().execute_with(|| {
+		assert_ok!(JobQueue::schedule(
+			Origin::signed(1),
+			Priority::High,
+			record(1)
+		));
+		assert_eq!(Balances::reserved_balance(1), 10);
+		assert_eq!(Balances::free_balance(1), 90);
+
+		JobQueue::on_initialize(2);
+		assert_eq!(Balances::reserved_balance(1), 0);
+		assert_eq!(Balances::free_balance(1), 100);
+
+		// Account 2 cannot afford the deposit
+		assert!(JobQueue::schedule(Origin::signed(2), Priority::High, record(2)).is_err());
+		assert_eq!(JobQueue::high_range(), (1, 1));
+	})
+}
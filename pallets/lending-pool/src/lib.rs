@@ -0,0 +1,517 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+//! A simple lending pool that reuses the continuous compounding from the compounding interest
+//! pallet.
+//!
+//! Suppliers deposit funds into the pool and receive shares in return. The value of a share grows
+//! as borrowers pay interest, so suppliers earn by redeeming their shares later.
+//!
+//! Borrowers reserve collateral and may borrow up to `CollateralFactor` of it from the pool. The
+//! interest rate per block depends on the pool's utilization, which is the fraction of the pool
+//! that is lent out: `BaseRate + UtilizationMultiplier * utilization`. Interest compounds
+//! continuously into a global borrow index, which is brought up to date once per block, the first
+//! time the pool is used. Each loan stores its debt divided by the index, so a borrower's debt is
+//! computed lazily. The total borrows are computed from the sum of the scaled debts in the same
+//! way, so they always match the loans.
+//!
+//! A loan whose debt exceeds `CollateralFactor` of its collateral may be liquidated by anyone. The
+//! liquidator repays part of the debt and receives collateral worth the repaid amount plus a
+//! `LiquidationBonus`. Debt that is left once all of the collateral is seized is written off, and
+//! the suppliers bear the loss.
+//!
+//! The pool deals in a single currency, which is also used as collateral. A real lending market
+//! would support several assets and use a price oracle to compare them.
+
+use fixed_point::math::FixedMath;
+use frame_support::{
+	decl_error, decl_event, decl_module, decl_storage,
+	dispatch::DispatchResult,
+	ensure,
+	traits::{
+		BalanceStatus, Currency, ExistenceRequirement::{AllowDeath, KeepAlive}, Get,
+		ReservableCurrency,
+	},
+};
+use frame_system::{self as system, ensure_signed};
+use parity_scale_codec::{Decode, Encode};
+use sp_arithmetic::{FixedPointNumber, FixedU128, Perbill, Permill};
+use sp_runtime::{
+	traits::{AccountIdConversion, Bounded, CheckedDiv, SaturatedConversion, Saturating, Zero},
+	ModuleId, RuntimeDebug,
+};
+use substrate_fixed::types::I32F32;
+
+#[cfg(test)]
+mod tests;
+
+type BalanceOf<T> = <<T as Trait>::Currency as Currency<<T as system::Trait>::AccountId>>::Balance;
+
+/// Hardcoded pallet ID; used to create the account that holds the pool's funds
+/// Must be exactly 8 characters long
+const PALLET_ID: ModuleId = ModuleId(*b"lendpool");
+
+/// The largest exponent passed to `exp` at once. An `I32F32` only holds e ^ x for x up to about
+/// 21.5.
+const MAX_EXPONENT: i32 = 16;
+
+pub trait Trait: system::Trait {
+	/// The overarching event type
+	type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
+
+	/// The currency that is lent and used as collateral
+	type Currency: ReservableCurrency<Self::AccountId>;
+
+	/// The interest rate per block when nothing is borrowed
+	type BaseRate: Get<Perbill>;
+
+	/// The increase of the interest rate per block when everything is borrowed
+	type UtilizationMultiplier: Get<Perbill>;
+
+	/// The fraction of its collateral that a loan may borrow
+	type CollateralFactor: Get<Permill>;
+
+	/// The extra collateral a liquidator receives, as a fraction of the debt they repay
+	type LiquidationBonus: Get<Permill>;
+}
+
+/// The state of a single borrower
+#[derive(Encode, Decode, Clone, PartialEq, Eq, Default, RuntimeDebug)]
+pub struct Loan<Balance> {
+	/// The collateral reserved from the borrower
+	pub collateral: Balance,
+	/// The debt of the borrower divided by the borrow index. Multiplying it by the index gives
+	/// the debt at that index.
+	pub scaled_debt: Balance,
+}
+
+decl_storage! {
+	trait Store for Module<T: Trait> as LendingPool {
+		/// The pool shares held by each supplier
+		Shares get(fn shares): map hasher(blake2_128_concat) T::AccountId => BalanceOf<T>;
+
+		/// The total number of pool shares
+		TotalShares get(fn total_shares): BalanceOf<T>;
+
+		/// The loans of each borrower
+		Loans get(fn loans):
+			map hasher(blake2_128_concat) T::AccountId => Option<Loan<BalanceOf<T>>>;
+
+		/// The sum of the scaled debts of all loans
+		TotalScaledDebt get(fn total_scaled_debt): BalanceOf<T>;
+
+		/// The value that one unit borrowed at genesis would have grown to
+		BorrowIndex get(fn borrow_index): FixedU128 = FixedU128::one();
+
+		/// The block at which the borrow index was last brought up to date
+		LastAccrual get(fn last_accrual): T::BlockNumber;
+	}
+	add_extra_genesis {
+		build(|_config| {
+			// Create the pool's account, and ensure it has the minimum required deposit
+			let _ = T::Currency::make_free_balance_be(
+				&<Module<T>>::account_id(),
+				T::Currency::minimum_balance(),
+			);
+			<LastAccrual<T>>::put(<system::Module<T>>::block_number());
+		});
+	}
+}
+
+decl_event!(
+	pub enum Event<T>
+	where
+		Balance = BalanceOf<T>,
+		<T as system::Trait>::AccountId,
+	{
+		/// Funds were supplied to the pool (supplier, amount, shares)
+		Supplied(AccountId, Balance, Balance),
+		/// Shares were redeemed for funds (supplier, amount, shares)
+		Withdrawn(AccountId, Balance, Balance),
+		/// Collateral was reserved (borrower, amount)
+		CollateralDeposited(AccountId, Balance),
+		/// Collateral was released (borrower, amount)
+		CollateralWithdrawn(AccountId, Balance),
+		/// Funds were borrowed from the pool (borrower, amount)
+		Borrowed(AccountId, Balance),
+		/// Debt was repaid (borrower, amount)
+		Repaid(AccountId, Balance),
+		/// A loan was liquidated (liquidator, borrower, repaid, collateral seized)
+		Liquidated(AccountId, AccountId, Balance, Balance),
+		/// Debt that was no longer backed by collateral was written off (borrower, amount)
+		DebtWrittenOff(AccountId, Balance),
+	}
+);
+
+decl_error! {
+	pub enum Error for Module<T: Trait> {
+		/// The account cannot afford the operation
+		InsufficientBalance,
+		/// The supplier does not hold enough shares
+		InsufficientShares,
+		/// The pool does not hold enough funds that are not lent out
+		InsufficientLiquidity,
+		/// The borrower does not have enough collateral for the operation
+		InsufficientCollateral,
+		/// The account has no loan
+		NoLoan,
+		/// The loan is sufficiently collateralized and may not be liquidated
+		NotLiquidatable,
+		/// A borrower may not liquidate their own loan
+		SelfLiquidation,
+	}
+}
+
+decl_module! {
+	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+		fn deposit_event() = default;
+
+		type Error = Error<T>;
+
+		const BaseRate: Perbill = T::BaseRate::get();
+
+		const UtilizationMultiplier: Perbill = T::UtilizationMultiplier::get();
+
+		const CollateralFactor: Permill = T::CollateralFactor::get();
+
+		const LiquidationBonus: Permill = T::LiquidationBonus::get();
+
+		/// Supply funds to the pool in exchange for shares
+		#[weight = 10_000]
+		fn supply(origin, amount: BalanceOf<T>) -> DispatchResult {
+			let supplier = ensure_signed(origin)?;
+			Self::accrue_interest();
+
+			// Shares are issued at the current share price, or one for one into an empty pool
+			let total_shares = Self::total_shares();
+			let pool_value = Self::pool_value();
+			let shares = if total_shares.is_zero() || pool_value.is_zero() {
+				amount
+			} else {
+				Self::mul_ratio(amount, total_shares, pool_value)
+			};
+
+			T::Currency::transfer(&supplier, &Self::account_id(), amount, AllowDeath)
+				.map_err(|_| Error::<T>::InsufficientBalance)?;
+			<Shares<T>>::mutate(&supplier, |owned| *owned = owned.saturating_add(shares));
+			<TotalShares<T>>::put(total_shares.saturating_add(shares));
+
+			Self::deposit_event(RawEvent::Supplied(supplier, amount, shares));
+			Ok(())
+		}
+
+		/// Redeem shares for their value in funds
+		#[weight = 10_000]
+		fn withdraw(origin, shares: BalanceOf<T>) -> DispatchResult {
+			let supplier = ensure_signed(origin)?;
+			ensure!(Self::shares(&supplier) >= shares, Error::<T>::InsufficientShares);
+			Self::accrue_interest();
+
+			let total_shares = Self::total_shares();
+			let amount = Self::mul_ratio(shares, Self::pool_value(), total_shares);
+			ensure!(Self::cash() >= amount, Error::<T>::InsufficientLiquidity);
+
+			T::Currency::transfer(&Self::account_id(), &supplier, amount, KeepAlive)?;
+			<Shares<T>>::mutate(&supplier, |owned| *owned = owned.saturating_sub(shares));
+			<TotalShares<T>>::put(total_shares.saturating_sub(shares));
+
+			Self::deposit_event(RawEvent::Withdrawn(supplier, amount, shares));
+			Ok(())
+		}
+
+		/// Reserve funds as collateral for borrowing
+		#[weight = 10_000]
+		fn deposit_collateral(origin, amount: BalanceOf<T>) -> DispatchResult {
+			let borrower = ensure_signed(origin)?;
+			T::Currency::reserve(&borrower, amount).map_err(|_| Error::<T>::InsufficientBalance)?;
+
+			<Loans<T>>::mutate(&borrower, |loan| {
+				let loan = loan.get_or_insert_with(Loan::default);
+				loan.collateral = loan.collateral.saturating_add(amount);
+			});
+
+			Self::deposit_event(RawEvent::CollateralDeposited(borrower, amount));
+			Ok(())
+		}
+
+		/// Release collateral, as long as the remaining collateral covers the debt
+		#[weight = 10_000]
+		fn withdraw_collateral(origin, amount: BalanceOf<T>) -> DispatchResult {
+			let borrower = ensure_signed(origin)?;
+			let index = Self::accrue_interest();
+			let mut loan = Self::loans(&borrower).ok_or(Error::<T>::NoLoan)?;
+
+			ensure!(loan.collateral >= amount, Error::<T>::InsufficientCollateral);
+			loan.collateral -= amount;
+			ensure!(Self::is_healthy(&loan, index), Error::<T>::InsufficientCollateral);
+
+			T::Currency::unreserve(&borrower, amount);
+			Self::store_loan(&borrower, loan);
+
+			Self::deposit_event(RawEvent::CollateralWithdrawn(borrower, amount));
+			Ok(())
+		}
+
+		/// Borrow funds from the pool against the caller's collateral
+		#[weight = 10_000]
+		fn borrow(origin, amount: BalanceOf<T>) -> DispatchResult {
+			let borrower = ensure_signed(origin)?;
+			let index = Self::accrue_interest();
+			let mut loan = Self::loans(&borrower).ok_or(Error::<T>::NoLoan)?;
+
+			// Rounding up keeps the borrower from owing less than they borrowed
+			let scaled = Self::scale(amount, index, true);
+			loan.scaled_debt = loan.scaled_debt.saturating_add(scaled);
+			ensure!(Self::is_healthy(&loan, index), Error::<T>::InsufficientCollateral);
+			ensure!(Self::cash() >= amount, Error::<T>::InsufficientLiquidity);
+
+			T::Currency::transfer(&Self::account_id(), &borrower, amount, KeepAlive)?;
+			<TotalScaledDebt<T>>::mutate(|total| *total = total.saturating_add(scaled));
+			Self::store_loan(&borrower, loan);
+
+			Self::deposit_event(RawEvent::Borrowed(borrower, amount));
+			Ok(())
+		}
+
+		/// Repay debt. Repaying more than the debt repays all of it.
+		#[weight = 10_000]
+		fn repay(origin, amount: BalanceOf<T>) -> DispatchResult {
+			let borrower = ensure_signed(origin)?;
+			let index = Self::accrue_interest();
+			let mut loan = Self::loans(&borrower).ok_or(Error::<T>::NoLoan)?;
+
+			let amount = amount.min(Self::debt(loan.scaled_debt, index));
+			let scaled = Self::scaled_repayment(&loan, amount, index);
+			T::Currency::transfer(&borrower, &Self::account_id(), amount, AllowDeath)
+				.map_err(|_| Error::<T>::InsufficientBalance)?;
+			loan.scaled_debt -= scaled;
+			<TotalScaledDebt<T>>::mutate(|total| *total = total.saturating_sub(scaled));
+			Self::store_loan(&borrower, loan);
+
+			Self::deposit_event(RawEvent::Repaid(borrower, amount));
+			Ok(())
+		}
+
+		/// Repay part of an undercollateralized loan in exchange for its collateral plus a bonus
+		#[weight = 10_000]
+		fn liquidate(origin, borrower: T::AccountId, amount: BalanceOf<T>) -> DispatchResult {
+			let liquidator = ensure_signed(origin)?;
+			ensure!(liquidator != borrower, Error::<T>::SelfLiquidation);
+			let index = Self::accrue_interest();
+			let mut loan = Self::loans(&borrower).ok_or(Error::<T>::NoLoan)?;
+			ensure!(!Self::is_healthy(&loan, index), Error::<T>::NotLiquidatable);
+
+			let debt = Self::debt(loan.scaled_debt, index);
+			let repaid = amount.min(debt);
+			let scaled = Self::scaled_repayment(&loan, repaid, index);
+			let seized = repaid
+				.saturating_add(T::LiquidationBonus::get() * repaid)
+				.min(loan.collateral);
+
+			T::Currency::transfer(&liquidator, &Self::account_id(), repaid, AllowDeath)
+				.map_err(|_| Error::<T>::InsufficientBalance)?;
+			let remaining = T::Currency::repatriate_reserved(
+				&borrower,
+				&liquidator,
+				seized,
+				BalanceStatus::Free,
+			)?;
+			let seized = seized - remaining;
+
+			loan.scaled_debt -= scaled;
+			// If less than expected could be moved, the borrower's reserve is used up
+			loan.collateral = if remaining.is_zero() {
+				loan.collateral - seized
+			} else {
+				Zero::zero()
+			};
+			<TotalScaledDebt<T>>::mutate(|total| *total = total.saturating_sub(scaled));
+			Self::deposit_event(RawEvent::Liquidated(liquidator, borrower.clone(), repaid, seized));
+
+			// Nothing backs the rest of the debt anymore, so it will never be repaid
+			if loan.collateral.is_zero() && !loan.scaled_debt.is_zero() {
+				let written_off = debt - repaid;
+				let scaled_debt = loan.scaled_debt;
+				loan.scaled_debt = Zero::zero();
+				<TotalScaledDebt<T>>::mutate(|total| *total = total.saturating_sub(scaled_debt));
+				Self::deposit_event(RawEvent::DebtWrittenOff(borrower.clone(), written_off));
+			}
+			Self::store_loan(&borrower, loan);
+			Ok(())
+		}
+	}
+}
+
+impl<T: Trait> Module<T> {
+	/// The account ID that holds the pool's funds
+	pub fn account_id() -> T::AccountId {
+		PALLET_ID.into_account()
+	}
+
+	/// The funds in the pool that are not lent out. The pool's minimum balance is never lent.
+	fn cash() -> BalanceOf<T> {
+		T::Currency::free_balance(&Self::account_id())
+			.saturating_sub(T::Currency::minimum_balance())
+	}
+
+	/// The value of all shares, which is the cash plus what is owed to the pool
+	fn pool_value() -> BalanceOf<T> {
+		Self::cash().saturating_add(Self::total_borrows())
+	}
+
+	/// The total debt of all borrowers at the last accrual
+	pub fn total_borrows() -> BalanceOf<T> {
+		Self::debt(Self::total_scaled_debt(), Self::borrow_index())
+	}
+
+	/// The current value of a supplier's shares
+	pub fn supply_value(who: &T::AccountId) -> BalanceOf<T> {
+		let total_shares = Self::total_shares();
+		if total_shares.is_zero() {
+			return Zero::zero();
+		}
+		let total_borrows = Self::debt(Self::total_scaled_debt(), Self::current_index());
+		let pool_value = Self::cash().saturating_add(total_borrows);
+		Self::mul_ratio(Self::shares(who), pool_value, total_shares)
+	}
+
+	/// The current debt of a borrower, including interest that has not been accrued yet
+	pub fn debt_of(who: &T::AccountId) -> BalanceOf<T> {
+		match Self::loans(who) {
+			Some(loan) => Self::debt(loan.scaled_debt, Self::current_index()),
+			None => Zero::zero(),
+		}
+	}
+
+	/// The fraction of the pool that is lent out
+	pub fn utilization() -> Perbill {
+		let total_borrows = Self::total_borrows();
+		let pool_value = Self::pool_value();
+		if pool_value.is_zero() {
+			return Perbill::zero();
+		}
+		Perbill::from_rational_approximation(total_borrows, pool_value)
+	}
+
+	/// The interest rate per block at the current utilization
+	pub fn borrow_rate() -> Perbill {
+		let utilization_rate = T::UtilizationMultiplier::get().saturating_mul(Self::utilization());
+		T::BaseRate::get().saturating_add(utilization_rate)
+	}
+
+	/// Brings the borrow index up to date and returns it. Does nothing if the index was already
+	/// updated in this block.
+	fn accrue_interest() -> FixedU128 {
+		let now = <system::Module<T>>::block_number();
+		let old_index = Self::borrow_index();
+		if Self::last_accrual() == now {
+			return old_index;
+		}
+
+		let index = Self::current_index();
+		BorrowIndex::put(index);
+		<LastAccrual<T>>::put(now);
+		index
+	}
+
+	/// The borrow index at the current block. The borrow rate compounds continuously since the
+	/// last accrual, so the index grows by e ^ (rate * time), saturating at the largest
+	/// `FixedU128`. While nothing is borrowed no interest is owed, so the index does not grow.
+	fn current_index() -> FixedU128 {
+		let index = Self::borrow_index();
+		if Self::total_scaled_debt().is_zero() {
+			return index;
+		}
+
+		// Calculate the exponential function (lots of type conversion)
+		let now = <system::Module<T>>::block_number();
+		let elapsed_time_u32 = now.saturating_sub(Self::last_accrual()).saturated_into::<u32>();
+		let rate = I32F32::from_num(Self::borrow_rate().deconstruct())
+			/ I32F32::from_num(1_000_000_000);
+		let elapsed_time_i32f32 = I32F32::saturating_from_num(elapsed_time_u32);
+		let mut exponent = rate.saturating_mul(elapsed_time_i32f32);
+
+		// e ^ (rate * time) is applied in steps of at most `MAX_EXPONENT`, so `exp` never
+		// overflows. The index saturates after a few full steps, which ends the loop.
+		let mut index = index;
+		let max_exponent = I32F32::from_num(MAX_EXPONENT);
+		while exponent > I32F32::from_num(0) && index < FixedU128::max_value() {
+			let step = exponent.min(max_exponent);
+			exponent -= step;
+
+			// An `I32F32` is its bits divided by 2 ^ 32
+			let growth = match step.checked_exp() {
+				Some(e) => FixedU128::saturating_from_rational(e.to_bits() as u128, 1u128 << 32),
+				None => FixedU128::max_value(),
+			};
+			index = index.saturating_mul(growth);
+		}
+		index
+	}
+
+	/// Stores a loan, or removes it once it holds neither collateral nor debt
+	fn store_loan(who: &T::AccountId, loan: Loan<BalanceOf<T>>) {
+		if loan.collateral.is_zero() && loan.scaled_debt.is_zero() {
+			<Loans<T>>::remove(who);
+		} else {
+			<Loans<T>>::insert(who, loan);
+		}
+	}
+
+	/// Whether the loan's debt at `index` is covered by `CollateralFactor` of its collateral
+	fn is_healthy(loan: &Loan<BalanceOf<T>>, index: FixedU128) -> bool {
+		Self::debt(loan.scaled_debt, index) <= T::CollateralFactor::get() * loan.collateral
+	}
+
+	/// The debt at `index` of a scaled debt, rounded down. The debt of each loan and the total
+	/// borrows are both computed this way.
+	fn debt(scaled_debt: BalanceOf<T>, index: FixedU128) -> BalanceOf<T> {
+		index
+			.saturating_mul_int(scaled_debt.saturated_into::<u128>())
+			.saturated_into()
+	}
+
+	/// The scaled debt of an amount of debt at `index`, rounded up if `round_up` is set and down
+	/// otherwise
+	fn scale(amount: BalanceOf<T>, index: FixedU128, round_up: bool) -> BalanceOf<T> {
+		let amount = FixedU128::saturating_from_integer(amount.saturated_into::<u128>());
+		let scaled = match amount.checked_div(&index) {
+			Some(scaled) => scaled.into_inner(),
+			None => return Zero::zero(),
+		};
+		let accuracy = FixedU128::accuracy();
+		let whole = scaled / accuracy;
+		if round_up && scaled % accuracy != 0 {
+			whole.saturating_add(1).saturated_into()
+		} else {
+			whole.saturated_into()
+		}
+	}
+
+	/// The scaled debt that repaying `amount` of a loan's debt at `index` removes. Repaying the
+	/// whole debt removes all of it, otherwise it is rounded down in favor of the pool.
+	fn scaled_repayment(
+		loan: &Loan<BalanceOf<T>>,
+		amount: BalanceOf<T>,
+		index: FixedU128,
+	) -> BalanceOf<T> {
+		if amount >= Self::debt(loan.scaled_debt, index) {
+			loan.scaled_debt
+		} else {
+			Self::scale(amount, index, false).min(loan.scaled_debt)
+		}
+	}
+
+	/// Calculates `amount * numerator / denominator`
+	fn mul_ratio(
+		amount: BalanceOf<T>,
+		numerator: BalanceOf<T>,
+		denominator: BalanceOf<T>,
+	) -> BalanceOf<T> {
+		FixedU128::saturating_from_rational(
+			numerator.saturated_into::<u128>(),
+			denominator.saturated_into::<u128>(),
+		)
+		.saturating_mul_int(amount.saturated_into::<u128>())
+		.saturated_into()
+	}
+}
@@ -0,0 +1,324 @@
+use crate::*;
+use balances;
+use frame_support::{assert_noop, assert_ok, impl_outer_event, impl_outer_origin, parameter_types};
+use frame_system::{self as system};
+use sp_core::H256;
+use sp_io;
+use sp_runtime::{
+	testing::Header,
+	traits::{BlakeTwo256, IdentityLookup},
+};
+
+impl_outer_origin! {
+	pub enum Origin for TestRuntime {}
+}
+
+// Workaround for https://github.com/rust-lang/rust/issues/26925 . Remove when sorted.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TestRuntime;
+parameter_types! {
+	pub const BlockHashCount: u64 = 250;
+	pub const MaximumBlockWeight: u32 = 1024;
+	pub const MaximumBlockLength: u32 = 2 * 1024;
+	pub const AvailableBlockRatio: Perbill = Perbill::one();
+
+	pub const ExistentialDeposit: u64 = 1;
+	pub const BaseRate: Perbill = Perbill::from_percent(1);
+	pub const UtilizationMultiplier: Perbill = Perbill::from_percent(10);
+	pub const CollateralFactor: Permill = Permill::from_percent(50);
+	pub const LiquidationBonus: Permill = Permill::from_percent(10);
+}
+impl system::Trait for TestRuntime {
+	type BaseCallFilter = ();
+	type Origin = Origin;
+	type Index = u64;
+	type Call = ();
+	type BlockNumber = u64;
+	type Hash = H256;
+	type Hashing = BlakeTwo256;
+	type AccountId = u64;
+	type Lookup = IdentityLookup<Self::AccountId>;
+	type Header = Header;
+	type Event = TestEvent;
+	type BlockHashCount = BlockHashCount;
+	type MaximumBlockWeight = MaximumBlockWeight;
+	type DbWeight = ();
+	type BlockExecutionWeight = ();
+	type ExtrinsicBaseWeight = ();
+	type MaximumExtrinsicWeight = MaximumBlockWeight;
+	type MaximumBlockLength = MaximumBlockLength;
+	type AvailableBlockRatio = AvailableBlockRatio;
+	type Version = ();
+	type PalletInfo = ();
+	type AccountData = balances::AccountData<u64>;
+	type OnNewAccount = ();
+	type OnKilledAccount = ();
+	type SystemWeightInfo = ();
+}
+
+impl balances::Trait for TestRuntime {
+	type Balance = u64;
+	type MaxLocks = ();
+	type Event = TestEvent;
+	type DustRemoval = ();
+	type ExistentialDeposit = ExistentialDeposit;
+	type AccountStore = system::Module<TestRuntime>;
+	type WeightInfo = ();
+}
+
+mod lending_pool {
+	pub use crate::Event;
+}
+
+impl_outer_event! {
+	pub enum TestEvent for TestRuntime {
+		system<T>,
+		lending_pool<T>,
+		balances<T>,
+	}
+}
+
+impl Trait for TestRuntime {
+	type Event = TestEvent;
+	type Currency = balances::Module<Self>;
+	type BaseRate = BaseRate;
+	type UtilizationMultiplier = UtilizationMultiplier;
+	type CollateralFactor = CollateralFactor;
+	type LiquidationBonus = LiquidationBonus;
+}
+
+pub type System = system::Module<TestRuntime>;
+pub type Balances = balances::Module<TestRuntime>;
+pub type LendingPool = Module<TestRuntime>;
+
+pub fn new_test_ext() -> sp_io::TestExternalities {
+	let mut t = system::GenesisConfig::default()
+		.build_storage::<TestRuntime>()
+		.unwrap();
+	balances::GenesisConfig::<TestRuntime> {
+		balances: vec![(1, 1000), (2, 1000), (3, 1000)],
+	}
+	.assimilate_storage(&mut t)
+	.unwrap();
+	crate::GenesisConfig {}
+		.assimilate_storage::<TestRuntime>(&mut t)
+		.unwrap();
+	let mut ext: sp_io::TestExternalities = t.into();
+	ext.execute_with(|| System::set_block_number(1));
+	ext
+}
+
+/// Account 1 supplies 500 and account 2 borrows 200 against 400 of collateral, so that the pool
+/// is 40% utilized and the borrow rate is 1% + 10% * 40% = 5% per block
+fn open_loan() {
+	assert_ok!(LendingPool::supply(Origin::signed(1), 500));
+	assert_ok!(LendingPool::deposit_collateral(Origin::signed(2), 400));
+	assert_ok!(LendingPool::borrow(Origin::signed(2), 200));
+}
+
+#[test]
+fn supply_and_withdraw_work() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(LendingPool::supply(Origin::signed(1), 500));
+		assert_eq!(LendingPool::shares(&1), 500);
+		assert_eq!(Balances::free_balance(&LendingPool::account_id()), 501);
+
+		assert_ok!(LendingPool::withdraw(Origin::signed(1), 200));
+		assert_eq!(LendingPool::shares(&1), 300);
+		assert_eq!(LendingPool::total_shares(), 300);
+		assert_eq!(Balances::free_balance(&1), 700);
+
+		assert_noop!(
+			LendingPool::withdraw(Origin::signed(1), 301),
+			Error::<TestRuntime>::InsufficientShares
+		);
+
+		let expected_event = TestEvent::lending_pool(RawEvent::Withdrawn(1, 200, 200));
+		assert!(System::events().iter().any(|a| a.event == expected_event));
+	})
+}
+
+#[test]
+fn borrowing_requires_collateral_and_liquidity() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(LendingPool::supply(Origin::signed(1), 100));
+		assert_noop!(
+			LendingPool::borrow(Origin::signed(2), 10),
+			Error::<TestRuntime>::NoLoan
+		);
+
+		assert_ok!(LendingPool::deposit_collateral(Origin::signed(2), 400));
+		assert_eq!(Balances::reserved_balance(&2), 400);
+		assert_noop!(
+			LendingPool::borrow(Origin::signed(2), 201),
+			Error::<TestRuntime>::InsufficientCollateral
+		);
+		assert_noop!(
+			LendingPool::borrow(Origin::signed(2), 150),
+			Error::<TestRuntime>::InsufficientLiquidity
+		);
+
+		assert_ok!(LendingPool::borrow(Origin::signed(2), 100));
+		assert_eq!(Balances::free_balance(&2), 700);
+		assert_eq!(LendingPool::total_borrows(), 100);
+		assert_eq!(LendingPool::utilization(), Perbill::from_percent(100));
+	})
+}
+
+#[test]
+fn interest_accrues_to_suppliers() {
+	new_test_ext().execute_with(|| {
+		open_loan();
+		assert_eq!(LendingPool::borrow_rate(), Perbill::from_percent(5));
+
+		// After 20 blocks at 5% the debt has grown by a factor of e
+		System::set_block_number(21);
+		let debt = LendingPool::debt_of(&2);
+		assert!(debt >= 543 && debt <= 544);
+
+		// The supplier's shares are worth the cash plus the debt
+		let value = LendingPool::supply_value(&1);
+		assert!(value >= 843 && value <= 844);
+
+		// Accruing brings the total borrows up to date
+		assert_ok!(LendingPool::repay(Origin::signed(2), 0));
+		assert_eq!(LendingPool::total_borrows(), debt);
+	})
+}
+
+#[test]
+fn total_borrows_match_the_loans() {
+	new_test_ext().execute_with(|| {
+		open_loan();
+		assert_ok!(LendingPool::deposit_collateral(Origin::signed(3), 400));
+		assert_ok!(LendingPool::borrow(Origin::signed(3), 77));
+
+		// The total and the loans are rounded the same way as interest accrues, so the total
+		// exceeds the sum of the debts by less than one unit per loan
+		for block in 2..10 {
+			System::set_block_number(block);
+			assert_ok!(LendingPool::repay(Origin::signed(2), 0));
+			let debts = LendingPool::debt_of(&2) + LendingPool::debt_of(&3);
+			assert!(LendingPool::total_borrows() >= debts);
+			assert!(LendingPool::total_borrows() <= debts + 1);
+		}
+
+		// Nothing is left once every loan is repaid
+		assert_ok!(LendingPool::repay(Origin::signed(2), 1000));
+		assert_ok!(LendingPool::repay(Origin::signed(3), 1000));
+		assert_eq!(LendingPool::total_borrows(), 0);
+		assert_eq!(LendingPool::total_scaled_debt(), 0);
+	})
+}
+
+#[test]
+fn repay_and_release_collateral() {
+	new_test_ext().execute_with(|| {
+		open_loan();
+
+		// The debt must stay covered by the remaining collateral
+		assert_noop!(
+			LendingPool::withdraw_collateral(Origin::signed(2), 1),
+			Error::<TestRuntime>::InsufficientCollateral
+		);
+
+		// Repaying more than the debt repays all of it
+		assert_ok!(LendingPool::repay(Origin::signed(2), 1000));
+		assert_eq!(LendingPool::debt_of(&2), 0);
+		assert_eq!(LendingPool::total_borrows(), 0);
+		assert_eq!(Balances::free_balance(&2), 600);
+
+		assert_ok!(LendingPool::withdraw_collateral(Origin::signed(2), 400));
+		assert_eq!(Balances::reserved_balance(&2), 0);
+		assert_eq!(LendingPool::loans(&2), None);
+	})
+}
+
+#[test]
+fn undercollateralized_loans_are_liquidated() {
+	new_test_ext().execute_with(|| {
+		open_loan();
+		assert_noop!(
+			LendingPool::liquidate(Origin::signed(3), 2, 100),
+			Error::<TestRuntime>::NotLiquidatable
+		);
+		assert_noop!(
+			LendingPool::liquidate(Origin::signed(2), 2, 100),
+			Error::<TestRuntime>::SelfLiquidation
+		);
+
+		// One block of interest pushes the debt over half of the collateral
+		System::set_block_number(2);
+		assert_eq!(LendingPool::debt_of(&2), 210);
+
+		assert_ok!(LendingPool::liquidate(Origin::signed(3), 2, 100));
+		assert_eq!(Balances::free_balance(&3), 1010);
+		assert_eq!(Balances::reserved_balance(&2), 290);
+		assert_eq!(
+			LendingPool::loans(&2).map(|loan| loan.collateral),
+			Some(290)
+		);
+		assert_eq!(LendingPool::debt_of(&2), 110);
+
+		let expected_event = TestEvent::lending_pool(RawEvent::Liquidated(3, 2, 100, 110));
+		assert!(System::events().iter().any(|a| a.event == expected_event));
+	})
+}
+
+#[test]
+fn interest_starts_with_the_first_loan() {
+	new_test_ext().execute_with(|| {
+		// No interest is owed while nothing is borrowed, however long the pool is unused
+		System::set_block_number(10_000);
+		open_loan();
+		assert_eq!(LendingPool::borrow_index(), FixedU128::one());
+		assert_eq!(LendingPool::debt_of(&2), 200);
+
+		System::set_block_number(10_001);
+		assert_eq!(LendingPool::debt_of(&2), 210);
+	})
+}
+
+#[test]
+fn borrow_index_saturates() {
+	new_test_ext().execute_with(|| {
+		open_loan();
+
+		// After 1000 blocks at 5% the growth e ^ 50 no longer fits in an `I32F32`
+		System::set_block_number(1001);
+		assert_eq!(LendingPool::debt_of(&2), u64::max_value());
+
+		// Seizing all of the collateral writes off the rest of the debt
+		assert_ok!(LendingPool::liquidate(Origin::signed(3), 2, 400));
+		assert_eq!(Balances::free_balance(&3), 1000);
+		assert_eq!(LendingPool::loans(&2), None);
+		assert_eq!(LendingPool::total_borrows(), 0);
+
+		let expected_event =
+			TestEvent::lending_pool(RawEvent::DebtWrittenOff(2, u64::max_value() - 400));
+		assert!(System::events().iter().any(|a| a.event == expected_event));
+	})
+}
+
+#[test]
+fn liquidation_seizes_only_the_remaining_reserve() {
+	new_test_ext().execute_with(|| {
+		open_loan();
+
+		// Some other pallet slashes most of the borrower's reserve
+		let _ = Balances::slash_reserved(&2, 300);
+		System::set_block_number(2);
+
+		// Only 100 of the 110 can be seized, which uses up the collateral
+		assert_ok!(LendingPool::liquidate(Origin::signed(3), 2, 100));
+		assert_eq!(Balances::free_balance(&3), 1000);
+		assert_eq!(Balances::reserved_balance(&2), 0);
+		assert_eq!(LendingPool::loans(&2), None);
+		assert_eq!(LendingPool::total_borrows(), 0);
+
+		let liquidated = TestEvent::lending_pool(RawEvent::Liquidated(3, 2, 100, 100));
+		let written_off = TestEvent::lending_pool(RawEvent::DebtWrittenOff(2, 110));
+		assert!(System::events().iter().any(|a| a.event == liquidated));
+		assert!(System::events().iter().any(|a| a.event == written_off));
+	})
+}
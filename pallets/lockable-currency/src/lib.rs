@@ -1,16 +1,30 @@
 //! A pallet to demonstrate the `LockableCurrency` trait
 //! borrows collateral locking logic from pallet_staking
+//!
+//! Each account may hold several named locks at once. Every lock carries its own
+//! `WithdrawReasons` and an expiry block. Locks that reach their expiry are removed
+//! automatically in `on_initialize`. At most `MaxExpiriesPerBlock` locks may expire in
+//! a single block; a lock whose expiry block is full expires in the next block with room.
+
+#![cfg_attr(not(feature = "std"), no_std)]
 
 use frame_support::{
-	decl_event, decl_module,
+	decl_error, decl_event, decl_module, decl_storage,
 	dispatch::DispatchResult,
-	traits::{Currency, LockIdentifier, LockableCurrency, WithdrawReason, WithdrawReasons},
+	ensure,
+	sp_runtime::traits::{One, Saturating},
+	traits::{Currency, Get, LockIdentifier, LockableCurrency, WithdrawReasons},
+	weights::Weight,
 };
 use frame_system::{self as system, ensure_signed};
+use parity_scale_codec::{Decode, Encode};
+use sp_std::prelude::*;
 
-const EXAMPLE_ID: LockIdentifier = *b"example ";
+#[cfg(test)]
+mod tests;
 
 type BalanceOf<T> = <<T as Trait>::Currency as Currency<<T as system::Trait>::AccountId>>::Balance;
+type LockInfoOf<T> = LockInfo<BalanceOf<T>, <T as system::Trait>::BlockNumber>;
 
 pub trait Trait: system::Trait {
 	/// The lockable currency type
@@ -18,53 +32,144 @@ pub trait Trait: system::Trait {
 
 	/// The overarching event type
 	type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
+
+	/// The maximum number of locks that may expire in a single block, at least one
+	type MaxExpiriesPerBlock: Get<u32>;
+}
+
+/// Details of a single lock held by this pallet
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct LockInfo<Balance, BlockNumber> {
+	/// The amount of funds locked
+	pub amount: Balance,
+	/// The operations the locked funds may not be used for
+	pub reasons: WithdrawReasons,
+	/// The block at which the lock is removed. This is later than the requested expiry when that
+	/// block already had `MaxExpiriesPerBlock` expiries.
+	pub expires_at: BlockNumber,
+}
+
+decl_storage! {
+	trait Store for Module<T: Trait> as LockableCurrency {
+		/// The active locks of each account, indexed by lock identifier
+		Locks get(fn locks):
+			double_map hasher(blake2_128_concat) T::AccountId, hasher(blake2_128_concat) LockIdentifier
+			=> Option<LockInfoOf<T>>;
+
+		/// The locks scheduled to expire at each block, at most `MaxExpiriesPerBlock` of them
+		Expiries get(fn expiries):
+			map hasher(twox_64_concat) T::BlockNumber => Vec<(T::AccountId, LockIdentifier)>;
+	}
 }
 
 decl_event!(
 	pub enum Event<T>
 	where
 		Balance = BalanceOf<T>,
-		<T as system::Trait>::AccountId
+		<T as system::Trait>::AccountId,
+		<T as system::Trait>::BlockNumber,
 	{
-		Locked(AccountId, Balance),
-		ExtendedLock(AccountId, Balance),
-		Unlocked(AccountId),
+		/// Funds were locked (account, lock id, amount, expiry)
+		Locked(AccountId, LockIdentifier, Balance, BlockNumber),
+		/// An existing lock was extended (account, lock id, amount, expiry)
+		ExtendedLock(AccountId, LockIdentifier, Balance, BlockNumber),
+		/// A single lock was removed by its owner
+		Unlocked(AccountId, LockIdentifier),
+		/// All locks of an account were removed by its owner
+		UnlockedAll(AccountId),
+		/// A lock reached its expiry and was removed
+		Expired(AccountId, LockIdentifier),
 	}
 );
 
+decl_error! {
+	pub enum Error for Module<T: Trait> {
+		/// The expiry of a lock must be in the future
+		ExpiryInPast,
+		/// No lock exists with the given identifier
+		NoSuchLock,
+	}
+}
+
 decl_module! {
 	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
 		fn deposit_event() = default;
 
-		/// Locks the specified amount of tokens from the caller
+		type Error = Error<T>;
+
+		const MaxExpiriesPerBlock: u32 = T::MaxExpiriesPerBlock::get();
+
+		/// Locks the specified amount of tokens from the caller under the given identifier
+		/// until the `expires_at` block, or the next block with room for another expiry.
+		/// Replaces any lock that already uses this identifier.
 		#[weight = 10_000]
-		fn lock_capital(origin, amount: BalanceOf<T>) -> DispatchResult {
+		fn lock_capital(
+			origin,
+			id: LockIdentifier,
+			amount: BalanceOf<T>,
+			reasons: WithdrawReasons,
+			expires_at: T::BlockNumber,
+		) -> DispatchResult {
 			let user = ensure_signed(origin)?;
+			let now = <system::Module<T>>::block_number();
+			ensure!(expires_at > now, Error::<T>::ExpiryInPast);
+
+			// A replaced lock no longer expires at its old block
+			let expires_at = match Self::locks(&user, id) {
+				Some(old) if old.expires_at == expires_at => expires_at,
+				Some(old) => {
+					Self::cancel_expiry(&user, id, old.expires_at);
+					Self::schedule_expiry(&user, id, expires_at)
+				}
+				None => Self::schedule_expiry(&user, id, expires_at),
+			};
+
+			T::Currency::set_lock(id, &user, amount, reasons);
+			<Locks<T>>::insert(&user, id, LockInfo { amount, reasons, expires_at });
+
+			Self::deposit_event(RawEvent::Locked(user, id, amount, expires_at));
+			Ok(())
+		}
 
-			T::Currency::set_lock(
-				EXAMPLE_ID,
-				&user,
-				amount,
-				WithdrawReasons::except(WithdrawReason::TransactionPayment),
-			);
+		/// Extends an existing lock. The locked amount and the expiry can only grow; the
+		/// given reasons are added to those already in effect.
+		#[weight = 10_000]
+		fn extend_lock(
+			origin,
+			id: LockIdentifier,
+			amount: BalanceOf<T>,
+			reasons: WithdrawReasons,
+			expires_at: T::BlockNumber,
+		) -> DispatchResult {
+			let user = ensure_signed(origin)?;
+			let mut lock = Self::locks(&user, id).ok_or(Error::<T>::NoSuchLock)?;
+
+			if expires_at > lock.expires_at {
+				Self::cancel_expiry(&user, id, lock.expires_at);
+				lock.expires_at = Self::schedule_expiry(&user, id, expires_at);
+			}
 
-			Self::deposit_event(RawEvent::Locked(user, amount));
+			T::Currency::extend_lock(id, &user, amount, reasons);
+			lock.amount = lock.amount.max(amount);
+			lock.reasons = lock.reasons | reasons;
+			<Locks<T>>::insert(&user, id, &lock);
+
+			Self::deposit_event(RawEvent::ExtendedLock(user, id, lock.amount, lock.expires_at));
 			Ok(())
 		}
 
-		/// Extends the lock period
+		/// Releases a single lock before its expiry
 		#[weight = 10_000]
-		fn extend_lock(origin, amount: BalanceOf<T>) -> DispatchResult {
+		fn unlock(origin, id: LockIdentifier) -> DispatchResult {
 			let user = ensure_signed(origin)?;
+			let lock = Self::locks(&user, id).ok_or(Error::<T>::NoSuchLock)?;
 
-			T::Currency::extend_lock(
-				EXAMPLE_ID,
-				&user,
-				amount,
-				WithdrawReasons::except(WithdrawReason::TransactionPayment),
-			);
+			T::Currency::remove_lock(id, &user);
+			<Locks<T>>::remove(&user, id);
+			Self::cancel_expiry(&user, id, lock.expires_at);
 
-			Self::deposit_event(RawEvent::ExtendedLock(user, amount));
+			Self::deposit_event(RawEvent::Unlocked(user, id));
 			Ok(())
 		}
 
@@ -73,12 +178,63 @@ decl_module! {
 		fn unlock_all(origin) -> DispatchResult {
 			let user = ensure_signed(origin)?;
 
-			T::Currency::remove_lock(EXAMPLE_ID, &user);
+			for (id, lock) in <Locks<T>>::iter_prefix(&user) {
+				T::Currency::remove_lock(id, &user);
+				Self::cancel_expiry(&user, id, lock.expires_at);
+			}
+			<Locks<T>>::remove_prefix(&user);
 
-			Self::deposit_event(RawEvent::Unlocked(user));
+			Self::deposit_event(RawEvent::UnlockedAll(user));
 			Ok(())
 		}
 
-		// use dilution and imbalances types
+		/// Removes the locks that expire in this block, of which there are at most
+		/// `MaxExpiriesPerBlock`
+		fn on_initialize(n: T::BlockNumber) -> Weight {
+			let expiring = <Expiries<T>>::take(n);
+			for (who, id) in expiring.iter() {
+				Self::expire_lock(who, *id);
+			}
+
+			T::DbWeight::get().reads_writes(1, 1)
+				.saturating_add(T::DbWeight::get().reads_writes(2, 2).saturating_mul(expiring.len() as Weight))
+		}
+	}
+}
+
+impl<T: Trait> Module<T> {
+	/// Adds a lock to the expiries of the first block from `expires_at` that has room for it and
+	/// returns that block
+	fn schedule_expiry(
+		who: &T::AccountId,
+		id: LockIdentifier,
+		expires_at: T::BlockNumber,
+	) -> T::BlockNumber {
+		let limit = T::MaxExpiriesPerBlock::get().max(1) as usize;
+		let mut at = expires_at;
+		while <Expiries<T>>::decode_len(at).unwrap_or(0) >= limit {
+			at = at.saturating_add(One::one());
+		}
+		<Expiries<T>>::append(at, (who.clone(), id));
+		at
+	}
+
+	/// Removes a lock from the expiries of the given block, so no stale entries are left behind
+	/// when a lock is replaced, extended or unlocked early
+	fn cancel_expiry(who: &T::AccountId, id: LockIdentifier, expires_at: T::BlockNumber) {
+		let mut expiring = <Expiries<T>>::take(expires_at);
+		expiring.retain(|(account, lock_id)| !(account == who && *lock_id == id));
+		if !expiring.is_empty() {
+			<Expiries<T>>::insert(expires_at, expiring);
+		}
+	}
+
+	/// Removes a lock that has reached its expiry
+	fn expire_lock(who: &T::AccountId, id: LockIdentifier) {
+		if <Locks<T>>::contains_key(who, id) {
+			T::Currency::remove_lock(id, who);
+			<Locks<T>>::remove(who, id);
+			Self::deposit_event(RawEvent::Expired(who.clone(), id));
+		}
 	}
 }
@@ -0,0 +1,282 @@
+use crate::*;
+use balances;
+use frame_support::{
+	assert_noop, assert_ok, impl_outer_event, impl_outer_origin, parameter_types,
+	traits::{OnInitialize, WithdrawReason},
+};
+use frame_system::{self as system};
+use sp_core::H256;
+use sp_io;
+use sp_runtime::{
+	testing::Header,
+	traits::{BlakeTwo256, IdentityLookup},
+	Perbill,
+};
+
+impl_outer_origin! {
+	pub enum Origin for TestRuntime {}
+}
+
+// Workaround for https://github.com/rust-lang/rust/issues/26925 . Remove when sorted.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TestRuntime;
+parameter_types! {
+	pub const BlockHashCount: u64 = 250;
+	pub const MaximumBlockWeight: u32 = 1024;
+	pub const MaximumBlockLength: u32 = 2 * 1024;
+	pub const AvailableBlockRatio: Perbill = Perbill::one();
+
+	pub const ExistentialDeposit: u64 = 1;
+	pub const MaxExpiriesPerBlock: u32 = 2;
+}
+impl system::Trait for TestRuntime {
+	type BaseCallFilter = ();
+	type Origin = Origin;
+	type Index = u64;
+	type Call = ();
+	type BlockNumber = u64;
+	type Hash = H256;
+	type Hashing = BlakeTwo256;
+	type AccountId = u64;
+	type Lookup = IdentityLookup<Self::AccountId>;
+	type Header = Header;
+	type Event = TestEvent;
+	type BlockHashCount = BlockHashCount;
+	type MaximumBlockWeight = MaximumBlockWeight;
+	type DbWeight = ();
+	type BlockExecutionWeight = ();
+	type ExtrinsicBaseWeight = ();
+	type MaximumExtrinsicWeight = MaximumBlockWeight;
+	type MaximumBlockLength = MaximumBlockLength;
+	type AvailableBlockRatio = AvailableBlockRatio;
+	type Version = ();
+	type PalletInfo = ();
+	type AccountData = balances::AccountData<u64>;
+	type OnNewAccount = ();
+	type OnKilledAccount = ();
+	type SystemWeightInfo = ();
+}
+
+impl balances::Trait for TestRuntime {
+	type Balance = u64;
+	type MaxLocks = ();
+	type Event = TestEvent;
+	type DustRemoval = ();
+	type ExistentialDeposit = ExistentialDeposit;
+	type AccountStore = system::Module<TestRuntime>;
+	type WeightInfo = ();
+}
+
+mod lockable_currency {
+	pub use crate::Event;
+}
+
+impl_outer_event! {
+	pub enum TestEvent for TestRuntime {
+		system<T>,
+		lockable_currency<T>,
+		balances<T>,
+	}
+}
+
+impl Trait for TestRuntime {
+	type Currency = balances::Module<Self>;
+	type Event = TestEvent;
+	type MaxExpiriesPerBlock = MaxExpiriesPerBlock;
+}
+
+pub type System = system::Module<TestRuntime>;
+pub type Balances = balances::Module<TestRuntime>;
+pub type LockableCurrency = Module<TestRuntime>;
+
+const STAKING: LockIdentifier = *b"staking ";
+const VOTING: LockIdentifier = *b"voting  ";
+
+fn all_reasons() -> WithdrawReasons {
+	WithdrawReasons::all()
+}
+
+fn transfer_reasons() -> WithdrawReasons {
+	WithdrawReasons::from(WithdrawReason::Transfer)
+}
+
+/// Moves the chain to block `n`, running this pallet's `on_initialize`
+fn run_to_block(n: u64) {
+	while System::block_number() < n {
+		System::set_block_number(System::block_number() + 1);
+		LockableCurrency::on_initialize(System::block_number());
+	}
+}
+
+pub fn new_test_ext() -> sp_io::TestExternalities {
+	let mut t = system::GenesisConfig::default()
+		.build_storage::<TestRuntime>()
+		.unwrap();
+	balances::GenesisConfig::<TestRuntime> {
+		balances: vec![(1, 1000), (2, 1000), (3, 1000), (4, 1000)],
+	}
+	.assimilate_storage(&mut t)
+	.unwrap();
+	let mut ext: sp_io::TestExternalities = t.into();
+	ext.execute_with(|| System::set_block_number(1));
+	ext
+}
+
+#[test]
+fn lock_capital_works() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(LockableCurrency::lock_capital(Origin::signed(1), STAKING, 400, all_reasons(), 10));
+
+		assert_eq!(
+			LockableCurrency::locks(1, STAKING),
+			Some(LockInfo { amount: 400, reasons: all_reasons(), expires_at: 10 })
+		);
+		assert_eq!(LockableCurrency::expiries(10), vec![(1, STAKING)]);
+		assert_eq!(Balances::locks(1).len(), 1);
+
+		let expected_event = TestEvent::lockable_currency(RawEvent::Locked(1, STAKING, 400, 10));
+		assert!(System::events().iter().any(|a| a.event == expected_event));
+	})
+}
+
+#[test]
+fn cannot_lock_with_past_expiry() {
+	new_test_ext().execute_with(|| {
+		assert_noop!(
+			LockableCurrency::lock_capital(Origin::signed(1), STAKING, 400, all_reasons(), 1),
+			Error::<TestRuntime>::ExpiryInPast
+		);
+	})
+}
+
+#[test]
+fn multiple_locks_per_account() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(LockableCurrency::lock_capital(Origin::signed(1), STAKING, 400, all_reasons(), 10));
+		assert_ok!(LockableCurrency::lock_capital(Origin::signed(1), VOTING, 700, transfer_reasons(), 20));
+
+		assert_eq!(Balances::locks(1).len(), 2);
+		let mut ids: Vec<LockIdentifier> = <Locks<TestRuntime>>::iter_prefix(1).map(|(id, _)| id).collect();
+		ids.sort();
+		assert_eq!(ids, vec![STAKING, VOTING]);
+
+		// Removing one lock leaves the other in place
+		assert_ok!(LockableCurrency::unlock(Origin::signed(1), STAKING));
+		assert_eq!(LockableCurrency::locks(1, STAKING), None);
+		assert!(LockableCurrency::locks(1, VOTING).is_some());
+		assert_eq!(Balances::locks(1).len(), 1);
+	})
+}
+
+#[test]
+fn unlock_all_removes_every_lock() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(LockableCurrency::lock_capital(Origin::signed(1), STAKING, 400, all_reasons(), 10));
+		assert_ok!(LockableCurrency::lock_capital(Origin::signed(1), VOTING, 700, transfer_reasons(), 20));
+
+		assert_ok!(LockableCurrency::unlock_all(Origin::signed(1)));
+		assert_eq!(<Locks<TestRuntime>>::iter_prefix(1).count(), 0);
+		assert!(Balances::locks(1).is_empty());
+	})
+}
+
+#[test]
+fn unlock_requires_existing_lock() {
+	new_test_ext().execute_with(|| {
+		assert_noop!(
+			LockableCurrency::unlock(Origin::signed(1), STAKING),
+			Error::<TestRuntime>::NoSuchLock
+		);
+	})
+}
+
+#[test]
+fn locks_expire_automatically() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(LockableCurrency::lock_capital(Origin::signed(1), STAKING, 400, all_reasons(), 5));
+
+		run_to_block(4);
+		assert!(LockableCurrency::locks(1, STAKING).is_some());
+
+		run_to_block(5);
+		assert_eq!(LockableCurrency::locks(1, STAKING), None);
+		assert!(Balances::locks(1).is_empty());
+
+		let expected_event = TestEvent::lockable_currency(RawEvent::Expired(1, STAKING));
+		assert!(System::events().iter().any(|a| a.event == expected_event));
+	})
+}
+
+#[test]
+fn extended_lock_expires_later() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(LockableCurrency::lock_capital(Origin::signed(1), STAKING, 400, transfer_reasons(), 5));
+		assert_ok!(LockableCurrency::extend_lock(Origin::signed(1), STAKING, 600, all_reasons(), 8));
+
+		assert_eq!(
+			LockableCurrency::locks(1, STAKING),
+			Some(LockInfo { amount: 600, reasons: all_reasons(), expires_at: 8 })
+		);
+
+		// The expiry moves from block 5 to block 8
+		assert!(LockableCurrency::expiries(5).is_empty());
+		assert_eq!(LockableCurrency::expiries(8), vec![(1, STAKING)]);
+
+		run_to_block(5);
+		assert!(LockableCurrency::locks(1, STAKING).is_some());
+
+		run_to_block(8);
+		assert_eq!(LockableCurrency::locks(1, STAKING), None);
+	})
+}
+
+#[test]
+fn relocking_leaves_no_stale_expiry() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(LockableCurrency::lock_capital(Origin::signed(1), STAKING, 400, all_reasons(), 5));
+		assert_ok!(LockableCurrency::lock_capital(Origin::signed(1), STAKING, 400, all_reasons(), 5));
+		assert_ok!(LockableCurrency::lock_capital(Origin::signed(1), STAKING, 300, all_reasons(), 7));
+
+		assert!(LockableCurrency::expiries(5).is_empty());
+		assert_eq!(LockableCurrency::expiries(7), vec![(1, STAKING)]);
+
+		// Unlocking early clears the expiry as well
+		assert_ok!(LockableCurrency::unlock(Origin::signed(1), STAKING));
+		assert!(LockableCurrency::expiries(7).is_empty());
+	})
+}
+
+#[test]
+fn expiries_are_bounded_per_block() {
+	new_test_ext().execute_with(|| {
+		for who in 1..=2 {
+			assert_ok!(LockableCurrency::lock_capital(Origin::signed(who), STAKING, 100, all_reasons(), 5));
+		}
+
+		// Block 5 already holds `MaxExpiriesPerBlock` expiries, so the lock expires in block 6
+		assert_ok!(LockableCurrency::lock_capital(Origin::signed(3), STAKING, 100, all_reasons(), 5));
+		assert_eq!(LockableCurrency::locks(3, STAKING).map(|lock| lock.expires_at), Some(6));
+		let expected_event = TestEvent::lockable_currency(RawEvent::Locked(3, STAKING, 100, 6));
+		assert!(System::events().iter().any(|a| a.event == expected_event));
+
+		// Extending a lock moves it the same way
+		assert_ok!(LockableCurrency::lock_capital(Origin::signed(4), STAKING, 100, all_reasons(), 4));
+		assert_ok!(LockableCurrency::extend_lock(Origin::signed(4), STAKING, 100, all_reasons(), 5));
+		assert_eq!(LockableCurrency::locks(4, STAKING).map(|lock| lock.expires_at), Some(6));
+		assert!(LockableCurrency::expiries(4).is_empty());
+		assert_eq!(LockableCurrency::expiries(6), vec![(3, STAKING), (4, STAKING)]);
+
+		// Relocking until the same block does not take another slot
+		assert_ok!(LockableCurrency::lock_capital(Origin::signed(1), STAKING, 200, all_reasons(), 5));
+		assert_eq!(LockableCurrency::expiries(5), vec![(1, STAKING), (2, STAKING)]);
+
+		run_to_block(5);
+		assert_eq!(LockableCurrency::locks(1, STAKING), None);
+		assert_eq!(LockableCurrency::locks(2, STAKING), None);
+		assert!(LockableCurrency::locks(3, STAKING).is_some());
+
+		run_to_block(6);
+		assert_eq!(LockableCurrency::locks(3, STAKING), None);
+		assert_eq!(LockableCurrency::locks(4, STAKING), None);
+	})
+}
@@ -0,0 +1,239 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+//! A lottery that draws its winner from a source of randomness
+//!
+//! Lottery rounds last `RoundLength + DrawDelay` blocks. During the first `RoundLength` blocks of
+//! a round, users buy tickets for `TicketPrice`, which is paid into the pot. Ticket sales then
+//! close, and at the end of the round, `DrawDelay` blocks later, `on_finalize` draws the winning
+//! ticket with the `RandomnessSource`. A `Fee` of the pot is handed to `T::FeeDestination`, for
+//! example the charity's pot, and the winner receives the rest. The next round starts in the next
+//! block.
+//!
+//! The delay makes sure the randomness used in the draw only depends on inputs that were submitted
+//! after the last ticket was sold, so ticket buyers can neither know nor influence the outcome.
+//! With the randomness beacon, a whole beacon round has to start after sales close and end before
+//! the draw. The last tickets may be sold while a beacon round is in progress, so the delay must
+//! be at least two beacon rounds of `CommitPeriod + RevealPeriod` blocks. Lottery rounds should
+//! also be a multiple of a beacon round, so that sales always close at the same point of a beacon
+//! round. The collective flip pallet mixes the hashes of the last 81 blocks, so it needs a delay
+//! of more than 81 blocks.
+//!
+//! The subject of the draw is the pallet ID and the round index, which nobody can change. A shared
+//! nonce would let ticket holders, who can compute the outcome once the seed is known, bump the
+//! nonce until their own ticket wins.
+
+use frame_support::{
+	decl_error, decl_event, decl_module, decl_storage,
+	dispatch::DispatchResult,
+	ensure,
+	traits::{
+		Currency,
+		ExistenceRequirement::{AllowDeath, KeepAlive},
+		Get, OnUnbalanced, Randomness, WithdrawReasons,
+	},
+	weights::Weight,
+};
+use frame_system::{self as system, ensure_signed};
+use parity_scale_codec::Encode;
+use sp_core::H256;
+use sp_runtime::{
+	traits::{AccountIdConversion, One, Saturating, Zero},
+	ModuleId, Permill,
+};
+use sp_std::prelude::*;
+
+#[cfg(test)]
+mod tests;
+
+type BalanceOf<T> = <<T as Trait>::Currency as Currency<<T as system::Trait>::AccountId>>::Balance;
+type NegativeImbalanceOf<T> =
+	<<T as Trait>::Currency as Currency<<T as system::Trait>::AccountId>>::NegativeImbalance;
+
+/// Simple index for identifying a lottery round
+pub type RoundIndex = u32;
+
+/// Hardcoded pallet ID; used to create the account that holds the pot
+/// Must be exactly 8 characters long
+const PALLET_ID: ModuleId = ModuleId(*b"lottery!");
+
+/// The weight of decoding a ticket when the winner is drawn
+const DRAW_WEIGHT_PER_TICKET: Weight = 100_000;
+
+pub trait Trait: system::Trait {
+	/// The overarching event type
+	type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
+
+	/// The currency in which tickets are bought and prizes are paid
+	type Currency: Currency<Self::AccountId>;
+
+	/// The source of randomness for drawing the winning ticket
+	type RandomnessSource: Randomness<H256>;
+
+	/// The price of a ticket, which must be at least the existential deposit
+	type TicketPrice: Get<BalanceOf<Self>>;
+
+	/// The number of blocks at the start of each round during which tickets are sold
+	type RoundLength: Get<Self::BlockNumber>;
+
+	/// The number of blocks between the end of ticket sales and the draw
+	type DrawDelay: Get<Self::BlockNumber>;
+
+	/// The fraction of the pot that is paid to `FeeDestination` instead of the winner
+	type Fee: Get<Permill>;
+
+	/// Handler for the fee taken from the pot
+	type FeeDestination: OnUnbalanced<NegativeImbalanceOf<Self>>;
+
+	/// The maximum number of tickets sold in a round
+	type MaxTickets: Get<u32>;
+}
+
+decl_storage! {
+	trait Store for Module<T: Trait> as Lottery {
+		/// The index of the current round
+		Round get(fn round): RoundIndex;
+
+		/// The owners of the tickets sold in the current round, one entry per ticket
+		Tickets get(fn tickets): Vec<T::AccountId>;
+	}
+}
+
+decl_event!(
+	pub enum Event<T>
+	where
+		AccountId = <T as system::Trait>::AccountId,
+		Balance = BalanceOf<T>,
+	{
+		/// A ticket was bought (buyer, round)
+		TicketBought(AccountId, RoundIndex),
+		/// A round was drawn (round, winner, prize, fee)
+		Drawn(RoundIndex, AccountId, Balance, Balance),
+		/// A round ended without any tickets sold (round)
+		NoTickets(RoundIndex),
+	}
+);
+
+decl_error! {
+	pub enum Error for Module<T: Trait> {
+		/// Tickets are only sold during the first `RoundLength` blocks of a round
+		SalesClosed,
+		/// All tickets of the round have been sold
+		SoldOut,
+	}
+}
+
+decl_module! {
+	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+		fn deposit_event() = default;
+
+		type Error = Error<T>;
+
+		/// The price of a ticket
+		const TicketPrice: BalanceOf<T> = T::TicketPrice::get();
+
+		/// The number of blocks at the start of each round during which tickets are sold
+		const RoundLength: T::BlockNumber = T::RoundLength::get();
+
+		/// The number of blocks between the end of ticket sales and the draw
+		const DrawDelay: T::BlockNumber = T::DrawDelay::get();
+
+		/// The fraction of the pot that is paid as a fee
+		const Fee: Permill = T::Fee::get();
+
+		/// The maximum number of tickets sold in a round
+		const MaxTickets: u32 = T::MaxTickets::get();
+
+		/// Buy a ticket for the current round
+		#[weight = 10_000]
+		fn buy_ticket(origin) -> DispatchResult {
+			let buyer = ensure_signed(origin)?;
+			let now = <system::Module<T>>::block_number();
+			ensure!(now % Self::round_period() < T::RoundLength::get(), Error::<T>::SalesClosed);
+			let mut tickets = Self::tickets();
+			ensure!((tickets.len() as u32) < T::MaxTickets::get(), Error::<T>::SoldOut);
+
+			T::Currency::transfer(&buyer, &Self::account_id(), T::TicketPrice::get(), KeepAlive)?;
+			tickets.push(buyer.clone());
+			<Tickets<T>>::put(tickets);
+
+			Self::deposit_event(RawEvent::TicketBought(buyer, Self::round()));
+			Ok(())
+		}
+
+		/// Reserves the weight of the draw in the last block of a round
+		fn on_initialize(n: T::BlockNumber) -> Weight {
+			if Self::is_draw_block(n) {
+				let tickets = <Tickets<T>>::decode_len().unwrap_or(0) as Weight;
+				T::DbWeight::get().reads_writes(8, 6)
+					.saturating_add(DRAW_WEIGHT_PER_TICKET.saturating_mul(tickets))
+			} else {
+				0
+			}
+		}
+
+		fn on_finalize(n: T::BlockNumber) {
+			if Self::is_draw_block(n) {
+				Self::draw();
+			}
+		}
+	}
+}
+
+impl<T: Trait> Module<T> {
+	/// The account ID that holds the pot
+	pub fn account_id() -> T::AccountId {
+		PALLET_ID.into_account()
+	}
+
+	/// The funds in the pot
+	pub fn pot() -> BalanceOf<T> {
+		T::Currency::free_balance(&Self::account_id())
+	}
+
+	/// The number of blocks in a round
+	fn round_period() -> T::BlockNumber {
+		T::RoundLength::get().saturating_add(T::DrawDelay::get())
+	}
+
+	/// Whether `n` is the last block of a round, in which the winner is drawn
+	fn is_draw_block(n: T::BlockNumber) -> bool {
+		(n.saturating_add(One::one()) % Self::round_period()).is_zero()
+	}
+
+	/// Draws the winner of the current round, pays out the pot and starts the next round
+	fn draw() {
+		let round = Self::round();
+		Round::put(round.wrapping_add(1));
+
+		let tickets = <Tickets<T>>::take();
+		if tickets.is_empty() {
+			Self::deposit_event(RawEvent::NoTickets(round));
+			return;
+		}
+
+		// Using a subject is recommended to prevent accidental re-use of the seed. The round
+		// index changes once per draw and cannot be changed by anyone else.
+		let subject = (PALLET_ID.0, round).encode();
+		let random = T::RandomnessSource::random(&subject);
+		let winner = &tickets[(random.to_low_u64_be() % tickets.len() as u64) as usize];
+
+		let pot_account = Self::account_id();
+		let pot = Self::pot();
+		let fee = T::Fee::get() * pot;
+		let fee_paid =
+			match T::Currency::withdraw(&pot_account, fee, WithdrawReasons::all(), AllowDeath) {
+				Ok(imbalance) => {
+					T::FeeDestination::on_unbalanced(imbalance);
+					fee
+				}
+				Err(_) => Zero::zero(),
+			};
+
+		// If the prize cannot be paid, for example because it is less than the existential
+		// deposit, it stays in the pot for the next round
+		let prize = pot.saturating_sub(fee_paid);
+		if T::Currency::transfer(&pot_account, winner, prize, AllowDeath).is_ok() {
+			Self::deposit_event(RawEvent::Drawn(round, winner.clone(), prize, fee_paid));
+		}
+	}
+}
@@ -0,0 +1,299 @@
+use crate::*;
+use balances;
+use frame_support::{
+	assert_noop, assert_ok, impl_outer_event, impl_outer_origin, parameter_types,
+	traits::{OnFinalize, OnInitialize, Randomness},
+};
+use sp_core::H256;
+use sp_runtime::{
+	testing::Header,
+	traits::{BlakeTwo256, IdentityLookup},
+	Perbill,
+};
+use std::cell::{Cell, RefCell};
+
+impl_outer_origin! {
+	pub enum Origin for TestRuntime {}
+}
+
+// Workaround for https://github.com/rust-lang/rust/issues/26925 . Remove when sorted.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TestRuntime;
+parameter_types! {
+	pub const BlockHashCount: u64 = 250;
+	pub const MaximumBlockWeight: u32 = 1024;
+	pub const MaximumBlockLength: u32 = 2 * 1024;
+	pub const AvailableBlockRatio: Perbill = Perbill::one();
+
+	pub const ExistentialDeposit: u64 = 1;
+	pub const TicketPrice: u64 = 10;
+	pub const RoundLength: u64 = 4;
+	pub const DrawDelay: u64 = 4;
+	pub const Fee: Permill = Permill::from_percent(10);
+	pub const MaxTickets: u32 = 4;
+}
+impl system::Trait for TestRuntime {
+	type BaseCallFilter = ();
+	type Origin = Origin;
+	type Index = u64;
+	type Call = ();
+	type BlockNumber = u64;
+	type Hash = H256;
+	type Hashing = BlakeTwo256;
+	type AccountId = u64;
+	type Lookup = IdentityLookup<Self::AccountId>;
+	type Header = Header;
+	type Event = TestEvent;
+	type BlockHashCount = BlockHashCount;
+	type MaximumBlockWeight = MaximumBlockWeight;
+	type DbWeight = ();
+	type BlockExecutionWeight = ();
+	type ExtrinsicBaseWeight = ();
+	type MaximumExtrinsicWeight = MaximumBlockWeight;
+	type MaximumBlockLength = MaximumBlockLength;
+	type AvailableBlockRatio = AvailableBlockRatio;
+	type Version = ();
+	type PalletInfo = ();
+	type AccountData = balances::AccountData<u64>;
+	type OnNewAccount = ();
+	type OnKilledAccount = ();
+	type SystemWeightInfo = ();
+}
+
+impl balances::Trait for TestRuntime {
+	type Balance = u64;
+	type MaxLocks = ();
+	type Event = TestEvent;
+	type DustRemoval = ();
+	type ExistentialDeposit = ExistentialDeposit;
+	type AccountStore = system::Module<TestRuntime>;
+	type WeightInfo = ();
+}
+
+mod lottery {
+	pub use crate::Event;
+}
+
+impl_outer_event! {
+	pub enum TestEvent for TestRuntime {
+		system<T>,
+		lottery<T>,
+		balances<T>,
+	}
+}
+
+thread_local! {
+	static RANDOM: Cell<u64> = Cell::new(0);
+	static USED_SEED: Cell<Option<u64>> = Cell::new(None);
+	static USED_SUBJECT: RefCell<Vec<u8>> = RefCell::new(Vec::new());
+}
+
+/// The number of blocks in a round of the beacon that `TestRandomness` stands in for
+const BEACON_ROUND: u64 = 2;
+
+/// The latest beacon round whose seed is known in block `n`. Like in the randomness beacon, a
+/// round's seed is known once the round is over.
+fn beacon_seed(n: u64) -> Option<u64> {
+	(n / BEACON_ROUND).checked_sub(1)
+}
+
+/// A source of randomness that each test may set. It records the subject and the beacon seed it
+/// would have used.
+pub struct TestRandomness;
+impl Randomness<H256> for TestRandomness {
+	fn random(subject: &[u8]) -> H256 {
+		USED_SEED.with(|used| used.set(beacon_seed(System::block_number())));
+		USED_SUBJECT.with(|used| *used.borrow_mut() = subject.to_vec());
+		H256::from_low_u64_be(RANDOM.with(|random| random.get()))
+	}
+}
+
+fn set_random(value: u64) {
+	RANDOM.with(|random| random.set(value));
+}
+
+/// The account that stands in for the charity's pot
+const CHARITY: u64 = 100;
+
+/// Collects the fees into the `CHARITY` account
+pub struct ToCharity;
+impl OnUnbalanced<balances::NegativeImbalance<TestRuntime>> for ToCharity {
+	fn on_nonzero_unbalanced(amount: balances::NegativeImbalance<TestRuntime>) {
+		let _ = Balances::resolve_creating(&CHARITY, amount);
+	}
+}
+
+impl Trait for TestRuntime {
+	type Event = TestEvent;
+	type Currency = balances::Module<Self>;
+	type RandomnessSource = TestRandomness;
+	type TicketPrice = TicketPrice;
+	type RoundLength = RoundLength;
+	type DrawDelay = DrawDelay;
+	type Fee = Fee;
+	type FeeDestination = ToCharity;
+	type MaxTickets = MaxTickets;
+}
+
+pub type System = system::Module<TestRuntime>;
+pub type Balances = balances::Module<TestRuntime>;
+pub type Lottery = Module<TestRuntime>;
+
+/// Moves the chain to block `n`, finalizing every block before it
+fn run_to_block(n: u64) {
+	while System::block_number() < n {
+		Lottery::on_finalize(System::block_number());
+		System::set_block_number(System::block_number() + 1);
+	}
+}
+
+fn buy(who: u64) -> DispatchResult {
+	Lottery::buy_ticket(Origin::signed(who))
+}
+
+fn last_event() -> TestEvent {
+	System::events()
+		.pop()
+		.expect("an event should be deposited")
+		.event
+}
+
+pub fn new_test_ext() -> sp_io::TestExternalities {
+	let mut t = system::GenesisConfig::default()
+		.build_storage::<TestRuntime>()
+		.unwrap();
+	balances::GenesisConfig::<TestRuntime> {
+		balances: vec![(1, 100), (2, 100), (3, 100), (4, 10)],
+	}
+	.assimilate_storage(&mut t)
+	.unwrap();
+	let mut ext = sp_io::TestExternalities::from(t);
+	ext.execute_with(|| System::set_block_number(1));
+	ext
+}
+
+#[test]
+fn winner_receives_pot_minus_fee() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(buy(1));
+		assert_ok!(buy(2));
+		assert_ok!(buy(2));
+		assert_ok!(buy(3));
+		assert_eq!(Lottery::pot(), 40);
+		assert_eq!(Lottery::tickets(), vec![1, 2, 2, 3]);
+
+		// The third ticket wins
+		set_random(6);
+		run_to_block(8);
+		assert_eq!(Balances::free_balance(2), 80 + 36);
+		assert_eq!(Balances::free_balance(CHARITY), 4);
+		assert_eq!(Lottery::pot(), 0);
+		assert_eq!(
+			last_event(),
+			TestEvent::lottery(RawEvent::Drawn(0, 2, 36, 4))
+		);
+	})
+}
+
+#[test]
+fn rounds_restart_automatically() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(buy(1));
+		run_to_block(8);
+		assert_eq!(Lottery::round(), 1);
+		assert!(Lottery::tickets().is_empty());
+
+		// Tickets are sold again in the new round
+		assert_ok!(buy(3));
+		assert_eq!(
+			last_event(),
+			TestEvent::lottery(RawEvent::TicketBought(3, 1))
+		);
+		run_to_block(16);
+		assert_eq!(Balances::free_balance(3), 99);
+
+		// A round without tickets is skipped
+		run_to_block(24);
+		assert_eq!(last_event(), TestEvent::lottery(RawEvent::NoTickets(2)));
+		assert_eq!(Lottery::round(), 3);
+	})
+}
+
+#[test]
+fn sales_close_before_draw() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(buy(1));
+		run_to_block(4);
+		assert_noop!(buy(1), Error::<TestRuntime>::SalesClosed);
+		run_to_block(7);
+		assert_noop!(buy(1), Error::<TestRuntime>::SalesClosed);
+		run_to_block(8);
+		assert_ok!(buy(1));
+	})
+}
+
+#[test]
+fn ticket_sales_are_limited() {
+	new_test_ext().execute_with(|| {
+		// The buyer must be able to pay and stay alive
+		assert_noop!(buy(4), balances::Error::<TestRuntime, _>::KeepAlive);
+
+		for _ in 0..4 {
+			assert_ok!(buy(1));
+		}
+		assert_noop!(buy(2), Error::<TestRuntime>::SoldOut);
+	})
+}
+
+#[test]
+fn fee_moves_to_charity() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(buy(1));
+		let issuance = Balances::total_issuance();
+		run_to_block(8);
+		// The fee moves from the pot to the charity, so no funds are created or destroyed
+		assert_eq!(Balances::total_issuance(), issuance);
+		assert_eq!(Balances::free_balance(CHARITY), 1);
+		assert_eq!(Balances::free_balance(1), 99);
+	})
+}
+
+#[test]
+fn draw_uses_seed_from_after_sales_close() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(buy(1));
+
+		// The last buyer may have taken part in the beacon round that ends when sales close
+		let seed_at_close = beacon_seed(RoundLength::get());
+		run_to_block(8);
+		let used_seed = USED_SEED.with(|used| used.get());
+		assert!(used_seed > seed_at_close);
+	})
+}
+
+#[test]
+fn draw_subject_is_the_round() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(buy(1));
+		run_to_block(8);
+		assert_ok!(buy(1));
+		run_to_block(16);
+
+		// The subject only depends on the round, so nobody can steer it
+		let subject = USED_SUBJECT.with(|used| used.borrow().clone());
+		assert_eq!(subject, (PALLET_ID.0, 1 as RoundIndex).encode());
+	})
+}
+
+#[test]
+fn draw_weight_is_reserved() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(buy(1));
+		assert_ok!(buy(2));
+
+		// Only the last block of the round draws the winner
+		assert_eq!(Lottery::on_initialize(6), 0);
+		let weight = Lottery::on_initialize(7);
+		assert_eq!(weight, 2 * DRAW_WEIGHT_PER_TICKET);
+	})
+}
@@ -0,0 +1,72 @@
+//! RPC interface for paging through the members of a map set.
+
+use codec::Codec;
+use jsonrpc_core::{Error as RpcError, ErrorCode, Result};
+use jsonrpc_derive::rpc;
+use map_set_runtime_api::MapSetApi as MapSetRuntimeApi;
+use sp_api::ProvideRuntimeApi;
+use sp_blockchain::HeaderBackend;
+use sp_runtime::{generic::BlockId, traits::Block as BlockT};
+use std::sync::Arc;
+
+/// The largest page that a single call returns, regardless of the requested `limit`
+pub const MAX_PAGE_SIZE: u32 = 1_000;
+
+#[rpc]
+pub trait MapSetApi<BlockHash, AccountId> {
+	/// Returns up to `limit` members, starting after `start_key`, or at the first member if it is
+	/// `None`. Pass the last member of a page as the `start_key` to get the next page. An empty
+	/// page means there are no more members.
+	#[rpc(name = "mapSet_membersPaged")]
+	fn members_paged(
+		&self,
+		start_key: Option<AccountId>,
+		limit: u32,
+		at: Option<BlockHash>,
+	) -> Result<Vec<AccountId>>;
+}
+
+/// A struct that implements the `MapSetApi`.
+pub struct MapSet<C, M> {
+	client: Arc<C>,
+	_marker: std::marker::PhantomData<M>,
+}
+
+impl<C, M> MapSet<C, M> {
+	/// Create new `MapSet` instance with the given reference to the client.
+	pub fn new(client: Arc<C>) -> Self {
+		Self {
+			client,
+			_marker: Default::default(),
+		}
+	}
+}
+
+impl<C, Block, AccountId> MapSetApi<<Block as BlockT>::Hash, AccountId> for MapSet<C, Block>
+where
+	Block: BlockT,
+	AccountId: Codec,
+	C: Send + Sync + 'static,
+	C: ProvideRuntimeApi<Block>,
+	C: HeaderBackend<Block>,
+	C::Api: MapSetRuntimeApi<Block, AccountId>,
+{
+	fn members_paged(
+		&self,
+		start_key: Option<AccountId>,
+		limit: u32,
+		at: Option<<Block as BlockT>::Hash>,
+	) -> Result<Vec<AccountId>> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(||
+			// If the block hash is not supplied assume the best block.
+			self.client.info().best_hash));
+
+		api.members_paged(&at, start_key, limit.min(MAX_PAGE_SIZE))
+			.map_err(|e| RpcError {
+				code: ErrorCode::ServerError(1),
+				message: "Unable to query the members.".into(),
+				data: Some(format!("{:?}", e).into()),
+			})
+	}
+}
@@ -0,0 +1,16 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+#![allow(clippy::too_many_arguments)]
+#![allow(clippy::unnecessary_mut_passed)]
+
+use parity_scale_codec::Codec;
+use sp_std::vec::Vec;
+
+// The runtime API pages through the members of a map set, so clients never have to fetch the
+// whole set at once. It is implemented in the runtime amalgamator file.
+sp_api::decl_runtime_apis! {
+	pub trait MapSetApi<AccountId> where AccountId: Codec {
+		/// Up to `limit` members, starting after `start_key`, or at the first member if it is
+		/// `None`
+		fn members_paged(start_key: Option<AccountId>, limit: u32) -> Vec<AccountId>;
+	}
+}
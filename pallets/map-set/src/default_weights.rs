@@ -0,0 +1,49 @@
+//! Default weights of the map set. Only `reset_members` touches every member, the other calls
+//! read and write a fixed number of storage entries whatever the size of the set. The values are
+//! estimated by hand, not measured, and follow the shape of the benchmarks in `benchmarking.rs`.
+//! Runtimes should measure weights for their own hardware with the kitchen node's `benchmark`
+//! subcommand and implement `WeightInfo` with them.
+//!
+//! Each weight also covers the `OnMembersChanged` hook, assuming it deposits about one event per
+//! account that joins or leaves the set, as the loosely coupled check membership pallet does.
+
+use frame_support::weights::{constants::RocksDbWeight as DbWeight, Weight};
+
+/// The weight of the `OnMembersChanged` hook for `changed` accounts joining or leaving the set
+fn on_members_changed(changed: Weight) -> Weight {
+	(5_000_000 as Weight)
+		.saturating_add(DbWeight::get().writes(1))
+		.saturating_mul(changed)
+}
+
+impl crate::WeightInfo for () {
+	fn add_member(_m: u32) -> Weight {
+		(40_000_000 as Weight)
+			.saturating_add(DbWeight::get().reads_writes(2, 2))
+			.saturating_add(on_members_changed(1))
+	}
+	fn remove_member(_m: u32) -> Weight {
+		(38_000_000 as Weight)
+			.saturating_add(DbWeight::get().reads_writes(2, 2))
+			.saturating_add(on_members_changed(1))
+	}
+	fn swap_member(_m: u32) -> Weight {
+		(45_000_000 as Weight)
+			.saturating_add(DbWeight::get().reads_writes(2, 2))
+			.saturating_add(on_members_changed(2))
+	}
+	fn reset_members(m: u32, n: u32) -> Weight {
+		let (m, n) = (m as Weight, n as Weight);
+		(30_000_000 as Weight)
+			.saturating_add((3_000_000 as Weight).saturating_mul(m))
+			.saturating_add((3_000_000 as Weight).saturating_mul(n))
+			.saturating_add(DbWeight::get().reads(m.saturating_add(1)))
+			.saturating_add(DbWeight::get().writes(m.saturating_add(n).saturating_add(1)))
+			.saturating_add(on_members_changed(m.saturating_add(n)))
+	}
+	fn change_key(_m: u32) -> Weight {
+		(40_000_000 as Weight)
+			.saturating_add(DbWeight::get().reads_writes(2, 2))
+			.saturating_add(on_members_changed(2))
+	}
+}
@@ -2,24 +2,55 @@
 
 //! A pallet that implements a storage set on top of a storage map and demonstrates performance
 //! tradeoffs when using vec sets.
+//!
+//! The weights of the calls come from `WeightInfo`, which the benchmarks measure, and are
+//! evaluated at `MaxMembers`.
 
-use account_set::AccountSet;
-use frame_support::storage::IterableStorageMap;
+use account_set::{AccountSet, AccountSetExt, OnMembersChanged};
+use frame_support::storage::{IterableStorageMap, StoragePrefixedMap};
 use frame_support::{
 	decl_error, decl_event, decl_module, decl_storage, dispatch::DispatchResult, ensure,
+	traits::{EnsureOrigin, Get},
+	weights::Weight,
 };
 use frame_system::{self as system, ensure_signed};
+use parity_scale_codec::Decode;
 use sp_std::collections::btree_set::BTreeSet;
 use sp_std::prelude::*;
 
+#[cfg(feature = "runtime-benchmarks")]
+mod benchmarking;
+mod default_weights;
 #[cfg(test)]
 mod tests;
 
-/// A maximum number of members. When membership reaches this number, no new members may join.
-pub const MAX_MEMBERS: u32 = 16;
+/// The weights of this pallet's calls, as functions of the number of members
+pub trait WeightInfo {
+	fn add_member(m: u32) -> Weight;
+	fn remove_member(m: u32) -> Weight;
+	fn swap_member(m: u32) -> Weight;
+	fn reset_members(m: u32, n: u32) -> Weight;
+	fn change_key(m: u32) -> Weight;
+}
 
 pub trait Trait: system::Trait {
 	type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
+
+	/// A maximum number of members. When membership reaches this number, no new members may join.
+	type MaxMembers: Get<u32>;
+
+	/// The origin that may add members. Swapping and resetting members requires both origins.
+	type AddOrigin: EnsureOrigin<Self::Origin>;
+
+	/// The origin that may remove members
+	type RemoveOrigin: EnsureOrigin<Self::Origin>;
+
+	/// A hook that is notified whenever accounts join or leave the set. Its cost is part of the
+	/// weights in `WeightInfo`.
+	type OnMembersChanged: OnMembersChanged<Self::AccountId>;
+
+	/// The weights of this pallet's calls
+	type WeightInfo: WeightInfo;
 }
 
 decl_storage! {
@@ -41,14 +72,20 @@ decl_event!(
 		MemberAdded(AccountId),
 		/// Removed a member
 		MemberRemoved(AccountId),
+		/// Swapped a member for a new one (removed, added)
+		MembersSwapped(AccountId, AccountId),
+		/// Replaced the whole membership
+		MembersReset,
+		/// A member changed their account (old, new)
+		KeyChanged(AccountId, AccountId),
 	}
 );
 
 decl_error! {
 	pub enum Error for Module<T: Trait> {
-		/// Cannot join as a member because you are already a member
+		/// Cannot add the account as a member because it is already a member
 		AlreadyMember,
-		/// Cannot give up membership because you are not currently a member
+		/// Cannot remove the account because it is not currently a member
 		NotMember,
 		/// Cannot add another member because the limit is already reached
 		MembershipLimitReached,
@@ -61,13 +98,16 @@ decl_module! {
 
 		type Error = Error<T>;
 
+		/// A maximum number of members
+		const MaxMembers: u32 = T::MaxMembers::get();
+
 		/// Adds a member to the membership set
-		#[weight = 10_000]
-		fn add_member(origin) -> DispatchResult {
-			let new_member = ensure_signed(origin)?;
+		#[weight = T::WeightInfo::add_member(T::MaxMembers::get())]
+		fn add_member(origin, new_member: T::AccountId) -> DispatchResult {
+			T::AddOrigin::ensure_origin(origin)?;
 
 			let member_count = MemberCount::get();
-			ensure!(member_count < MAX_MEMBERS, Error::<T>::MembershipLimitReached);
+			ensure!(member_count < T::MaxMembers::get(), Error::<T>::MembershipLimitReached);
 
 			// We don't want to add duplicate members, so we check whether the potential new
 			// member is already present in the list. Because the membership is stored as a hash
@@ -77,23 +117,126 @@ decl_module! {
 			// Insert the new member and emit the event
 			Members::<T>::insert(&new_member, ());
 			MemberCount::put(member_count + 1); // overflow check not necessary because of maximum
+			T::OnMembersChanged::on_members_changed(&[new_member.clone()], &[]);
 			Self::deposit_event(RawEvent::MemberAdded(new_member));
 			Ok(())
 		}
 
 		/// Removes a member.
-		#[weight = 10_000]
-		fn remove_member(origin) -> DispatchResult {
-			let old_member = ensure_signed(origin)?;
+		#[weight = T::WeightInfo::remove_member(T::MaxMembers::get())]
+		fn remove_member(origin, old_member: T::AccountId) -> DispatchResult {
+			T::RemoveOrigin::ensure_origin(origin)?;
 
 			ensure!(Members::<T>::contains_key(&old_member), Error::<T>::NotMember);
 
 			Members::<T>::remove(&old_member);
 			MemberCount::mutate(|v| *v -= 1);
+			T::OnMembersChanged::on_members_changed(&[], &[old_member.clone()]);
 			Self::deposit_event(RawEvent::MemberRemoved(old_member));
 			Ok(())
 		}
 
+		/// Replaces the member `remove` with the new member `add` in a single call.
+		/// The size of the set does not change, so this works even when the set is full.
+		#[weight = T::WeightInfo::swap_member(T::MaxMembers::get())]
+		fn swap_member(origin, remove: T::AccountId, add: T::AccountId) -> DispatchResult {
+			T::AddOrigin::ensure_origin(origin.clone())?;
+			T::RemoveOrigin::ensure_origin(origin)?;
+
+			if remove == add {
+				return Ok(());
+			}
+
+			ensure!(Members::<T>::contains_key(&remove), Error::<T>::NotMember);
+			ensure!(!Members::<T>::contains_key(&add), Error::<T>::AlreadyMember);
+
+			Members::<T>::remove(&remove);
+			Members::<T>::insert(&add, ());
+			T::OnMembersChanged::on_members_changed(&[add.clone()], &[remove.clone()]);
+			Self::deposit_event(RawEvent::MembersSwapped(remove, add));
+			Ok(())
+		}
+
+		/// Replaces the whole membership with `members`. Duplicate accounts are only added once.
+		#[weight = T::WeightInfo::reset_members(T::MaxMembers::get(), members.len() as u32)]
+		fn reset_members(origin, members: Vec<T::AccountId>) -> DispatchResult {
+			T::AddOrigin::ensure_origin(origin.clone())?;
+			T::RemoveOrigin::ensure_origin(origin)?;
+
+			let new_members = members.into_iter().collect::<BTreeSet<_>>();
+			ensure!(
+				(new_members.len() as u32) <= T::MaxMembers::get(),
+				Error::<T>::MembershipLimitReached
+			);
+
+			// Draining the map removes every old member, which is affordable because the set is
+			// bounded by `MaxMembers`
+			let old_members = <Members<T> as IterableStorageMap<T::AccountId, ()>>::drain()
+				.map(|(acct, _)| acct)
+				.collect::<BTreeSet<_>>();
+			for member in &new_members {
+				Members::<T>::insert(member, ());
+			}
+			MemberCount::put(new_members.len() as u32);
+
+			let incoming = new_members.difference(&old_members).cloned().collect::<Vec<_>>();
+			let outgoing = old_members.difference(&new_members).cloned().collect::<Vec<_>>();
+			T::OnMembersChanged::on_members_changed(&incoming, &outgoing);
+			Self::deposit_event(RawEvent::MembersReset);
+			Ok(())
+		}
+
+		/// Lets a member move their membership to a new account.
+		#[weight = T::WeightInfo::change_key(T::MaxMembers::get())]
+		fn change_key(origin, new: T::AccountId) -> DispatchResult {
+			let old = ensure_signed(origin)?;
+
+			if old == new {
+				return Ok(());
+			}
+
+			ensure!(Members::<T>::contains_key(&old), Error::<T>::NotMember);
+			ensure!(!Members::<T>::contains_key(&new), Error::<T>::AlreadyMember);
+
+			Members::<T>::remove(&old);
+			Members::<T>::insert(&new, ());
+			T::OnMembersChanged::on_members_changed(&[new.clone()], &[old.clone()]);
+			Self::deposit_event(RawEvent::KeyChanged(old, new));
+			Ok(())
+		}
+	}
+}
+
+impl<T: Trait> Module<T> {
+	/// Checks whether `who` is a member with a single storage read
+	pub fn is_member(who: &T::AccountId) -> bool {
+		Members::<T>::contains_key(who)
+	}
+
+	/// Returns up to `limit` members, starting after `start_key`, or at the first member if it is
+	/// `None`. Members are returned in the order of their hashed storage keys, so passing the last
+	/// member of a page as the `start_key` of the next call pages through the whole set.
+	pub fn members_paged(start_key: Option<T::AccountId>, limit: u32) -> Vec<T::AccountId> {
+		let prefix = <Members<T> as StoragePrefixedMap<()>>::final_prefix();
+		let mut key = match start_key {
+			Some(who) => Members::<T>::hashed_key_for(&who),
+			None => prefix.to_vec(),
+		};
+
+		let mut members = Vec::new();
+		while (members.len() as u32) < limit {
+			match sp_io::storage::next_key(&key) {
+				Some(next) if next.starts_with(&prefix) => {
+					// The `blake2_128_concat` hasher appends the encoded account to a 16 byte hash
+					if let Ok(who) = T::AccountId::decode(&mut &next[prefix.len() + 16..]) {
+						members.push(who);
+					}
+					key = next;
+				}
+				_ => break,
+			}
+		}
+		members
 	}
 }
 
@@ -106,3 +249,9 @@ impl<T: Trait> AccountSet for Module<T> {
 			.collect::<BTreeSet<_>>()
 	}
 }
+
+impl<T: Trait> AccountSetExt for Module<T> {
+	fn contains(who: &T::AccountId) -> bool {
+		Self::is_member(who)
+	}
+}
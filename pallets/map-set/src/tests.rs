@@ -1,13 +1,14 @@
 use crate::*;
 use frame_support::{assert_noop, assert_ok, impl_outer_event, impl_outer_origin, parameter_types};
-use frame_system as system;
+use frame_system::{self as system, EnsureRoot};
 use sp_core::H256;
 use sp_io::TestExternalities;
 use sp_runtime::{
 	testing::Header,
 	traits::{BlakeTwo256, IdentityLookup},
-	Perbill,
+	DispatchError, Perbill,
 };
+use std::cell::RefCell;
 
 impl_outer_origin! {
 	pub enum Origin for TestRuntime {}
@@ -21,6 +22,8 @@ parameter_types! {
 	pub const MaximumBlockWeight: u32 = 1024;
 	pub const MaximumBlockLength: u32 = 2 * 1024;
 	pub const AvailableBlockRatio: Perbill = Perbill::one();
+
+	pub const MaxMembers: u32 = 16;
 }
 impl system::Trait for TestRuntime {
 	type BaseCallFilter = ();
@@ -61,14 +64,35 @@ impl_outer_event! {
 	}
 }
 
+thread_local! {
+	static CHANGES: RefCell<Vec<(Vec<u64>, Vec<u64>)>> = RefCell::new(Vec::new());
+}
+
+/// Records every membership change so tests can inspect what the hook received
+pub struct RecordChanges;
+impl OnMembersChanged<u64> for RecordChanges {
+	fn on_members_changed(incoming: &[u64], outgoing: &[u64]) {
+		CHANGES.with(|changes| changes.borrow_mut().push((incoming.to_vec(), outgoing.to_vec())));
+	}
+}
+
+fn last_change() -> Option<(Vec<u64>, Vec<u64>)> {
+	CHANGES.with(|changes| changes.borrow().last().cloned())
+}
+
 impl Trait for TestRuntime {
 	type Event = TestEvent;
+	type MaxMembers = MaxMembers;
+	type AddOrigin = EnsureRoot<u64>;
+	type RemoveOrigin = EnsureRoot<u64>;
+	type OnMembersChanged = RecordChanges;
+	type WeightInfo = ();
 }
 
 pub type System = system::Module<TestRuntime>;
 pub type MapSet = Module<TestRuntime>;
 
-struct ExternalityBuilder;
+pub struct ExternalityBuilder;
 
 impl ExternalityBuilder {
 	pub fn build() -> TestExternalities {
@@ -84,7 +108,7 @@ impl ExternalityBuilder {
 #[test]
 fn add_member_works() {
 	ExternalityBuilder::build().execute_with(|| {
-		assert_ok!(MapSet::add_member(Origin::signed(1)));
+		assert_ok!(MapSet::add_member(Origin::root(), 1));
 
 		let expected_event = TestEvent::vec_set(RawEvent::MemberAdded(1));
 
@@ -100,10 +124,10 @@ fn add_member_works() {
 #[test]
 fn cant_add_duplicate_members() {
 	ExternalityBuilder::build().execute_with(|| {
-		assert_ok!(MapSet::add_member(Origin::signed(1)));
+		assert_ok!(MapSet::add_member(Origin::root(), 1));
 
 		assert_noop!(
-			MapSet::add_member(Origin::signed(1)),
+			MapSet::add_member(Origin::root(), 1),
 			Error::<TestRuntime>::AlreadyMember
 		);
 	})
@@ -114,12 +138,12 @@ fn cant_exceed_max_members() {
 	ExternalityBuilder::build().execute_with(|| {
 		// Add 16 members, reaching the max
 		for i in 0..16 {
-			assert_ok!(MapSet::add_member(Origin::signed(i)));
+			assert_ok!(MapSet::add_member(Origin::root(), i));
 		}
 
 		// Try to add the 17th member exceeding the max
 		assert_noop!(
-			MapSet::add_member(Origin::signed(16)),
+			MapSet::add_member(Origin::root(), 16),
 			Error::<TestRuntime>::MembershipLimitReached
 		);
 	})
@@ -128,8 +152,8 @@ fn cant_exceed_max_members() {
 #[test]
 fn remove_member_works() {
 	ExternalityBuilder::build().execute_with(|| {
-		assert_ok!(MapSet::add_member(Origin::signed(1)));
-		assert_ok!(MapSet::remove_member(Origin::signed(1)));
+		assert_ok!(MapSet::add_member(Origin::root(), 1));
+		assert_ok!(MapSet::remove_member(Origin::root(), 1));
 
 		// check correct event emission
 		let expected_event = TestEvent::vec_set(RawEvent::MemberRemoved(1));
@@ -149,8 +173,145 @@ fn remove_member_handles_errors() {
 	ExternalityBuilder::build().execute_with(|| {
 		// 2 is NOT previously added as a member
 		assert_noop!(
-			MapSet::remove_member(Origin::signed(2)),
+			MapSet::remove_member(Origin::root(), 2),
+			Error::<TestRuntime>::NotMember
+		);
+	})
+}
+
+#[test]
+fn is_member_works() {
+	ExternalityBuilder::build().execute_with(|| {
+		for i in &[5, 1, 3] {
+			assert_ok!(MapSet::add_member(Origin::root(), *i));
+		}
+
+		assert!(MapSet::is_member(&3));
+		assert!(!MapSet::is_member(&2));
+
+		assert_ok!(MapSet::remove_member(Origin::root(), 3));
+		assert!(!MapSet::is_member(&3));
+	})
+}
+
+#[test]
+fn only_admin_origins_manage_members() {
+	ExternalityBuilder::build().execute_with(|| {
+		assert_noop!(MapSet::add_member(Origin::signed(1), 1), DispatchError::BadOrigin);
+		assert_ok!(MapSet::add_member(Origin::root(), 1));
+		assert_noop!(MapSet::remove_member(Origin::signed(1), 1), DispatchError::BadOrigin);
+		assert_noop!(MapSet::swap_member(Origin::signed(1), 1, 2), DispatchError::BadOrigin);
+		assert_noop!(MapSet::reset_members(Origin::signed(1), vec![2]), DispatchError::BadOrigin);
+	})
+}
+
+#[test]
+fn swap_member_works() {
+	ExternalityBuilder::build().execute_with(|| {
+		assert_ok!(MapSet::add_member(Origin::root(), 1));
+		assert_ok!(MapSet::add_member(Origin::root(), 3));
+
+		assert_noop!(
+			MapSet::swap_member(Origin::root(), 2, 4),
 			Error::<TestRuntime>::NotMember
 		);
+		assert_noop!(
+			MapSet::swap_member(Origin::root(), 1, 3),
+			Error::<TestRuntime>::AlreadyMember
+		);
+
+		assert_ok!(MapSet::swap_member(Origin::root(), 1, 4));
+		assert!(!MapSet::is_member(&1));
+		assert!(MapSet::is_member(&4));
+		assert_eq!(last_change(), Some((vec![4], vec![1])));
+		assert_eq!(
+			System::events().pop().unwrap().event,
+			TestEvent::vec_set(RawEvent::MembersSwapped(1, 4))
+		);
+	})
+}
+
+#[test]
+fn reset_members_works() {
+	ExternalityBuilder::build().execute_with(|| {
+		assert_ok!(MapSet::add_member(Origin::root(), 1));
+		assert_ok!(MapSet::add_member(Origin::root(), 2));
+
+		// Duplicates are ignored
+		assert_ok!(MapSet::reset_members(Origin::root(), vec![3, 2, 3]));
+		assert!(!MapSet::is_member(&1));
+		assert!(MapSet::is_member(&2));
+		assert!(MapSet::is_member(&3));
+		assert_eq!(last_change(), Some((vec![3], vec![1])));
+
+		// The new membership must still respect the maximum
+		assert_noop!(
+			MapSet::reset_members(Origin::root(), (0..17).collect()),
+			Error::<TestRuntime>::MembershipLimitReached
+		);
+	})
+}
+
+#[test]
+fn change_key_works() {
+	ExternalityBuilder::build().execute_with(|| {
+		assert_ok!(MapSet::add_member(Origin::root(), 1));
+		assert_ok!(MapSet::add_member(Origin::root(), 2));
+
+		assert_noop!(
+			MapSet::change_key(Origin::signed(3), 4),
+			Error::<TestRuntime>::NotMember
+		);
+		assert_noop!(
+			MapSet::change_key(Origin::signed(1), 2),
+			Error::<TestRuntime>::AlreadyMember
+		);
+
+		assert_ok!(MapSet::change_key(Origin::signed(1), 5));
+		assert!(!MapSet::is_member(&1));
+		assert!(MapSet::is_member(&5));
+		assert_eq!(last_change(), Some((vec![5], vec![1])));
+		assert_eq!(
+			System::events().pop().unwrap().event,
+			TestEvent::vec_set(RawEvent::KeyChanged(1, 5))
+		);
+	})
+}
+
+#[test]
+fn members_paged_works() {
+	ExternalityBuilder::build().execute_with(|| {
+		assert_eq!(MapSet::members_paged(None, 3), Vec::<u64>::new());
+		assert_ok!(MapSet::reset_members(Origin::root(), (0..10).collect()));
+
+		// Page through the set, continuing after the last member of each page
+		let mut members = Vec::new();
+		let mut start_key = None;
+		loop {
+			let page = MapSet::members_paged(start_key, 3);
+			assert!(page.len() <= 3);
+			match page.last() {
+				Some(last) => start_key = Some(*last),
+				None => break,
+			}
+			members.extend(page);
+		}
+
+		// Every member appears exactly once
+		assert_eq!(members.len(), 10);
+		members.sort();
+		assert_eq!(members, (0..10).collect::<Vec<_>>());
+
+		// A limit of zero returns nothing
+		assert_eq!(MapSet::members_paged(None, 0), Vec::<u64>::new());
+	})
+}
+
+#[test]
+fn contains_checks_single_member() {
+	ExternalityBuilder::build().execute_with(|| {
+		assert_ok!(MapSet::add_member(Origin::root(), 1));
+		assert!(<MapSet as AccountSetExt>::contains(&1));
+		assert!(!<MapSet as AccountSetExt>::contains(&2));
 	})
 }
@@ -0,0 +1,243 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+//! A randomness beacon driven by commit-reveal rounds
+//!
+//! Each round starts with a commit phase of `CommitPeriod` blocks, in which participants submit
+//! the hash of a secret and reserve `CommitDeposit`. It is followed by a reveal phase of
+//! `RevealPeriod` blocks, in which they reveal their secrets. Once the round is over, the XOR of
+//! all revealed secrets is hashed together with the previous seed to form the new seed. As long as
+//! a single participant chose their secret at random, nobody can predict the seed.
+//!
+//! The last participant to reveal can still choose between two seeds by keeping their secret to
+//! themselves. Participants who do not reveal therefore lose their deposit to `T::Slash`.
+//!
+//! The pallet implements `Randomness<H256>`, so it can replace `randomness-collective-flip` as the
+//! source of randomness of other pallets.
+
+use frame_support::{
+	decl_error, decl_event, decl_module, decl_storage,
+	dispatch::DispatchResult,
+	ensure,
+	storage::IterableStorageMap,
+	traits::{Currency, Get, Imbalance, OnUnbalanced, Randomness, ReservableCurrency},
+	weights::Weight,
+};
+use frame_system::{self as system, ensure_signed};
+use parity_scale_codec::Encode;
+use sp_core::H256;
+use sp_io::hashing::blake2_256;
+use sp_runtime::traits::{Hash, Saturating};
+use sp_std::prelude::*;
+
+#[cfg(test)]
+mod tests;
+
+type BalanceOf<T> = <<T as Trait>::Currency as Currency<<T as system::Trait>::AccountId>>::Balance;
+type NegativeImbalanceOf<T> =
+	<<T as Trait>::Currency as Currency<<T as system::Trait>::AccountId>>::NegativeImbalance;
+
+/// Simple index for identifying a round of the beacon
+pub type RoundIndex = u32;
+
+pub trait Trait: system::Trait {
+	/// The overarching event type
+	type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
+
+	/// The currency in which commit deposits are reserved
+	type Currency: ReservableCurrency<Self::AccountId>;
+
+	/// The amount reserved by each participant until they reveal their secret
+	type CommitDeposit: Get<BalanceOf<Self>>;
+
+	/// The number of blocks during which participants may commit to a secret
+	type CommitPeriod: Get<Self::BlockNumber>;
+
+	/// The number of blocks during which participants may reveal their secret
+	type RevealPeriod: Get<Self::BlockNumber>;
+
+	/// The maximum number of participants in a round
+	type MaxParticipants: Get<u32>;
+
+	/// Handler for the deposits of participants who did not reveal their secret
+	type Slash: OnUnbalanced<NegativeImbalanceOf<Self>>;
+}
+
+decl_storage! {
+	trait Store for Module<T: Trait> as RandomnessBeacon {
+		/// The index of the current round
+		Round get(fn round): RoundIndex;
+
+		/// The block in which the current round started
+		RoundStart get(fn round_start): T::BlockNumber;
+
+		/// The commitments of the current round that have not been revealed yet, together with
+		/// the deposit reserved for each of them
+		Commitments get(fn commitment):
+			map hasher(blake2_128_concat) T::AccountId => Option<(T::Hash, BalanceOf<T>)>;
+
+		/// The number of participants that committed in the current round
+		Participants get(fn participants): u32;
+
+		/// The XOR of the secrets revealed in the current round
+		Accumulator get(fn accumulator): H256;
+
+		/// The number of secrets revealed in the current round
+		Reveals get(fn reveals): u32;
+
+		/// The seed produced by the latest round in which any secret was revealed
+		Seed get(fn seed): H256;
+	}
+}
+
+decl_event!(
+	pub enum Event<T>
+	where
+		AccountId = <T as system::Trait>::AccountId,
+		Balance = BalanceOf<T>,
+	{
+		/// A participant committed to a secret (participant, round)
+		Committed(AccountId, RoundIndex),
+		/// A participant revealed their secret (participant, round)
+		Revealed(AccountId, RoundIndex),
+		/// A participant did not reveal their secret and lost their deposit
+		Slashed(AccountId, Balance),
+		/// A round was closed (round, new seed, number of revealed secrets)
+		RoundClosed(RoundIndex, H256, u32),
+	}
+);
+
+decl_error! {
+	pub enum Error for Module<T: Trait> {
+		/// Commitments are only accepted during the commit phase of a round
+		NotCommitPhase,
+		/// Secrets are only accepted during the reveal phase of a round
+		NotRevealPhase,
+		/// The participant already committed to a secret in this round
+		AlreadyCommitted,
+		/// The round already has the maximum number of participants
+		TooManyParticipants,
+		/// The participant did not commit to a secret in this round
+		NoCommitment,
+		/// The secret does not match the commitment
+		InvalidReveal,
+	}
+}
+
+decl_module! {
+	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+		fn deposit_event() = default;
+
+		type Error = Error<T>;
+
+		/// The amount reserved by each participant until they reveal their secret
+		const CommitDeposit: BalanceOf<T> = T::CommitDeposit::get();
+
+		/// The number of blocks during which participants may commit to a secret
+		const CommitPeriod: T::BlockNumber = T::CommitPeriod::get();
+
+		/// The number of blocks during which participants may reveal their secret
+		const RevealPeriod: T::BlockNumber = T::RevealPeriod::get();
+
+		/// The maximum number of participants in a round
+		const MaxParticipants: u32 = T::MaxParticipants::get();
+
+		fn on_initialize(n: T::BlockNumber) -> Weight {
+			let round_end = Self::round_start()
+				.saturating_add(T::CommitPeriod::get())
+				.saturating_add(T::RevealPeriod::get());
+			if n < round_end {
+				return T::DbWeight::get().reads(1);
+			}
+			Self::close_round(n)
+		}
+
+		/// Commit to a secret by submitting the hash of the secret and the caller's account.
+		///
+		/// Including the account in the hash prevents others from copying the commitment.
+		#[weight = 10_000]
+		fn commit(origin, commitment: T::Hash) -> DispatchResult {
+			let participant = ensure_signed(origin)?;
+			let now = <system::Module<T>>::block_number();
+			let reveal_start = Self::round_start().saturating_add(T::CommitPeriod::get());
+			ensure!(now < reveal_start, Error::<T>::NotCommitPhase);
+			ensure!(
+				!<Commitments<T>>::contains_key(&participant),
+				Error::<T>::AlreadyCommitted
+			);
+			let participants = Self::participants();
+			ensure!(participants < T::MaxParticipants::get(), Error::<T>::TooManyParticipants);
+
+			let deposit = T::CommitDeposit::get();
+			T::Currency::reserve(&participant, deposit)?;
+			<Commitments<T>>::insert(&participant, (commitment, deposit));
+			Participants::put(participants + 1);
+
+			Self::deposit_event(RawEvent::Committed(participant, Self::round()));
+			Ok(())
+		}
+
+		/// Reveal the secret committed to in the commit phase and get the deposit back
+		#[weight = 10_000]
+		fn reveal(origin, secret: H256) -> DispatchResult {
+			let participant = ensure_signed(origin)?;
+			let now = <system::Module<T>>::block_number();
+			let reveal_start = Self::round_start().saturating_add(T::CommitPeriod::get());
+			ensure!(now >= reveal_start, Error::<T>::NotRevealPhase);
+
+			let (commitment, deposit) = Self::commitment(&participant)
+				.ok_or(Error::<T>::NoCommitment)?;
+			ensure!(
+				T::Hashing::hash_of(&(secret, &participant)) == commitment,
+				Error::<T>::InvalidReveal
+			);
+
+			<Commitments<T>>::remove(&participant);
+			T::Currency::unreserve(&participant, deposit);
+			Accumulator::mutate(|accumulator| *accumulator ^= secret);
+			Reveals::mutate(|reveals| *reveals += 1);
+
+			Self::deposit_event(RawEvent::Revealed(participant, Self::round()));
+			Ok(())
+		}
+	}
+}
+
+impl<T: Trait> Module<T> {
+	/// Slashes the participants who did not reveal, updates the seed and starts a new round in
+	/// block `n`. Returns the weight consumed.
+	fn close_round(n: T::BlockNumber) -> Weight {
+		let mut slashed = 0;
+		for (participant, (_, deposit)) in <Commitments<T>>::drain() {
+			let (imbalance, _) = T::Currency::slash_reserved(&participant, deposit);
+			Self::deposit_event(RawEvent::Slashed(participant, imbalance.peek()));
+			T::Slash::on_unbalanced(imbalance);
+			slashed += 1;
+		}
+
+		let reveals = Reveals::take();
+		let accumulator = Accumulator::take();
+		// Without any reveals the round contributes no entropy, so the seed stays the same
+		if reveals > 0 {
+			Seed::put(H256::from(blake2_256(
+				&(Self::seed(), accumulator).encode(),
+			)));
+		}
+
+		let round = Self::round();
+		Self::deposit_event(RawEvent::RoundClosed(round, Self::seed(), reveals));
+		Round::put(round.wrapping_add(1));
+		<RoundStart<T>>::put(n);
+		Participants::kill();
+
+		// Reading and writing the round's state, and reading, writing and slashing each commitment
+		T::DbWeight::get().reads_writes(6 + 2 * slashed, 6 + 2 * slashed)
+	}
+}
+
+/// The seed only changes once per round, so values drawn within the same round differ only by
+/// their subject. Before the first round with any reveals, the seed is zero and not random at all.
+impl<T: Trait> Randomness<H256> for Module<T> {
+	fn random(subject: &[u8]) -> H256 {
+		H256::from(blake2_256(&(Self::seed(), subject).encode()))
+	}
+}
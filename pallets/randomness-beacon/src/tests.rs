@@ -0,0 +1,276 @@
+use crate::*;
+use balances;
+use frame_support::{
+	assert_noop, assert_ok, impl_outer_event, impl_outer_origin, parameter_types,
+	traits::OnInitialize,
+};
+use sp_runtime::{
+	testing::Header,
+	traits::{BlakeTwo256, IdentityLookup},
+	Perbill,
+};
+
+impl_outer_origin! {
+	pub enum Origin for TestRuntime {}
+}
+
+// Workaround for https://github.com/rust-lang/rust/issues/26925 . Remove when sorted.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TestRuntime;
+parameter_types! {
+	pub const BlockHashCount: u64 = 250;
+	pub const MaximumBlockWeight: u32 = 1024;
+	pub const MaximumBlockLength: u32 = 2 * 1024;
+	pub const AvailableBlockRatio: Perbill = Perbill::one();
+
+	pub const ExistentialDeposit: u64 = 1;
+	pub const CommitDeposit: u64 = 10;
+	pub const CommitPeriod: u64 = 3;
+	pub const RevealPeriod: u64 = 2;
+	pub const MaxParticipants: u32 = 3;
+}
+impl system::Trait for TestRuntime {
+	type BaseCallFilter = ();
+	type Origin = Origin;
+	type Index = u64;
+	type Call = ();
+	type BlockNumber = u64;
+	type Hash = H256;
+	type Hashing = BlakeTwo256;
+	type AccountId = u64;
+	type Lookup = IdentityLookup<Self::AccountId>;
+	type Header = Header;
+	type Event = TestEvent;
+	type BlockHashCount = BlockHashCount;
+	type MaximumBlockWeight = MaximumBlockWeight;
+	type DbWeight = ();
+	type BlockExecutionWeight = ();
+	type ExtrinsicBaseWeight = ();
+	type MaximumExtrinsicWeight = MaximumBlockWeight;
+	type MaximumBlockLength = MaximumBlockLength;
+	type AvailableBlockRatio = AvailableBlockRatio;
+	type Version = ();
+	type PalletInfo = ();
+	type AccountData = balances::AccountData<u64>;
+	type OnNewAccount = ();
+	type OnKilledAccount = ();
+	type SystemWeightInfo = ();
+}
+
+impl balances::Trait for TestRuntime {
+	type Balance = u64;
+	type MaxLocks = ();
+	type Event = TestEvent;
+	type DustRemoval = ();
+	type ExistentialDeposit = ExistentialDeposit;
+	type AccountStore = system::Module<TestRuntime>;
+	type WeightInfo = ();
+}
+
+mod beacon {
+	pub use crate::Event;
+}
+
+impl_outer_event! {
+	pub enum TestEvent for TestRuntime {
+		system<T>,
+		beacon<T>,
+		balances<T>,
+		randomness,
+	}
+}
+
+impl Trait for TestRuntime {
+	type Event = TestEvent;
+	type Currency = balances::Module<Self>;
+	type CommitDeposit = CommitDeposit;
+	type CommitPeriod = CommitPeriod;
+	type RevealPeriod = RevealPeriod;
+	type MaxParticipants = MaxParticipants;
+	// Slashed deposits are burned
+	type Slash = ();
+}
+
+// The randomness pallet draws its randomness from the beacon
+impl randomness::Trait for TestRuntime {
+	type Event = TestEvent;
+	type RandomnessSource = Beacon;
+}
+
+pub type System = system::Module<TestRuntime>;
+pub type Balances = balances::Module<TestRuntime>;
+pub type Beacon = Module<TestRuntime>;
+pub type RandomnessDemo = randomness::Module<TestRuntime>;
+
+/// Moves the chain to block `n`, running the beacon's `on_initialize` in every new block
+fn run_to_block(n: u64) {
+	while System::block_number() < n {
+		System::set_block_number(System::block_number() + 1);
+		Beacon::on_initialize(System::block_number());
+	}
+}
+
+fn secret(byte: u8) -> H256 {
+	H256::repeat_byte(byte)
+}
+
+/// Commits `who` to the secret made of `byte`
+fn commit(who: u64, byte: u8) -> DispatchResult {
+	let commitment = BlakeTwo256::hash_of(&(secret(byte), who));
+	Beacon::commit(Origin::signed(who), commitment)
+}
+
+fn reveal(who: u64, byte: u8) -> DispatchResult {
+	Beacon::reveal(Origin::signed(who), secret(byte))
+}
+
+fn last_event() -> TestEvent {
+	System::events()
+		.pop()
+		.expect("an event should be deposited")
+		.event
+}
+
+pub fn new_test_ext() -> sp_io::TestExternalities {
+	let mut t = system::GenesisConfig::default()
+		.build_storage::<TestRuntime>()
+		.unwrap();
+	balances::GenesisConfig::<TestRuntime> {
+		balances: vec![(1, 100), (2, 100), (3, 100), (4, 100), (5, 5)],
+	}
+	.assimilate_storage(&mut t)
+	.unwrap();
+	let mut ext = sp_io::TestExternalities::from(t);
+	ext.execute_with(|| System::set_block_number(1));
+	ext
+}
+
+#[test]
+fn reveals_update_seed() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(commit(1, 1));
+		assert_ok!(commit(2, 2));
+		assert_eq!(Balances::reserved_balance(1), 10);
+
+		run_to_block(3);
+		assert_ok!(reveal(1, 1));
+		assert_ok!(reveal(2, 2));
+		assert_eq!(Balances::reserved_balance(1), 0);
+		assert_eq!(Beacon::accumulator(), secret(1) ^ secret(2));
+
+		// The round ends after the reveal phase
+		run_to_block(4);
+		assert_eq!(Beacon::seed(), H256::zero());
+		run_to_block(5);
+		let seed = H256::from(blake2_256(&(H256::zero(), secret(3)).encode()));
+		assert_eq!(Beacon::seed(), seed);
+		assert_eq!(
+			last_event(),
+			TestEvent::beacon(RawEvent::RoundClosed(0, seed, 2))
+		);
+		assert_eq!(Beacon::round(), 1);
+		assert_eq!(Beacon::round_start(), 5);
+		assert_eq!(Beacon::accumulator(), H256::zero());
+
+		// The next round builds on the previous seed
+		assert_ok!(commit(1, 7));
+		run_to_block(8);
+		assert_ok!(reveal(1, 7));
+		run_to_block(10);
+		let next = H256::from(blake2_256(&(seed, secret(7)).encode()));
+		assert_eq!(Beacon::seed(), next);
+	})
+}
+
+#[test]
+fn non_revealers_are_slashed() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(commit(1, 1));
+		assert_ok!(commit(2, 2));
+		run_to_block(3);
+		assert_ok!(reveal(1, 1));
+
+		let issuance = Balances::total_issuance();
+		run_to_block(5);
+		assert_eq!(Balances::free_balance(1), 100);
+		assert_eq!(Balances::free_balance(2), 90);
+		assert_eq!(Balances::reserved_balance(2), 0);
+		assert_eq!(Balances::total_issuance(), issuance - 10);
+		assert_eq!(Beacon::commitment(2), None);
+		assert!(System::events()
+			.iter()
+			.any(|record| record.event == TestEvent::beacon(RawEvent::Slashed(2, 10))));
+
+		// Only the revealed secret went into the seed
+		let seed = H256::from(blake2_256(&(H256::zero(), secret(1)).encode()));
+		assert_eq!(Beacon::seed(), seed);
+	})
+}
+
+#[test]
+fn round_without_reveals_keeps_seed() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(commit(1, 1));
+		run_to_block(5);
+		assert_eq!(Beacon::seed(), H256::zero());
+		assert_eq!(
+			last_event(),
+			TestEvent::beacon(RawEvent::RoundClosed(0, H256::zero(), 0))
+		);
+	})
+}
+
+#[test]
+fn phases_are_enforced() {
+	new_test_ext().execute_with(|| {
+		assert_noop!(reveal(1, 1), Error::<TestRuntime>::NotRevealPhase);
+		assert_ok!(commit(1, 1));
+		assert_noop!(commit(1, 2), Error::<TestRuntime>::AlreadyCommitted);
+		assert_ok!(commit(2, 2));
+		assert_ok!(commit(3, 3));
+		assert_noop!(commit(4, 4), Error::<TestRuntime>::TooManyParticipants);
+
+		run_to_block(3);
+		assert_noop!(commit(4, 4), Error::<TestRuntime>::NotCommitPhase);
+		assert_noop!(reveal(4, 4), Error::<TestRuntime>::NoCommitment);
+		// A secret that does not match, or someone else's secret, is rejected
+		assert_noop!(reveal(1, 2), Error::<TestRuntime>::InvalidReveal);
+		assert_noop!(reveal(2, 1), Error::<TestRuntime>::InvalidReveal);
+
+		// The commitment of the last round does not carry over
+		run_to_block(5);
+		assert_ok!(commit(4, 4));
+		assert_noop!(reveal(1, 1), Error::<TestRuntime>::NotRevealPhase);
+	})
+}
+
+#[test]
+fn commit_requires_deposit() {
+	new_test_ext().execute_with(|| {
+		assert_noop!(
+			commit(5, 5),
+			balances::Error::<TestRuntime, _>::InsufficientBalance
+		);
+	})
+}
+
+#[test]
+fn randomness_pallet_consumes_beacon() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(commit(1, 1));
+		run_to_block(3);
+		assert_ok!(reveal(1, 1));
+		run_to_block(5);
+
+		assert_ok!(RandomnessDemo::consume_randomness(Origin::signed(1)));
+		// The randomness pallet uses its nonce, starting at zero, as the subject
+		let seed = Beacon::random_seed();
+		let result = Beacon::random(&0u32.encode());
+		assert_ne!(seed, H256::zero());
+		assert_ne!(result, seed);
+		assert_eq!(
+			last_event(),
+			TestEvent::randomness(randomness::Event::RandomnessConsumed(seed, result))
+		);
+	})
+}
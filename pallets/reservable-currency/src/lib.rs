@@ -1,18 +1,37 @@
 //! A pallet to demonstrate the `ReservableCurrency` trait
 //! borrows collateral locking logic from pallet_treasury
+//!
+//! It also contains a small escrow system built on reserves. A payer opens an escrow which
+//! reserves the funds and names a payee, an arbiter and a deadline. The payer or the arbiter
+//! may release the funds to the payee, and the payee or the arbiter may refund them to the payer.
+//! Neither party can settle the escrow in its own favour.
+//! If nothing happens before the deadline the payer can reclaim the funds. Either party may
+//! raise a dispute before the deadline, after which only the arbiter can settle the escrow.
+//! Payments to the payee are made with `repatriate_reserved`.
+//!
+//! Reserves can be held under a name so that it is possible to tell which feature holds which
+//! funds. Each account keeps a ledger of the amount held under every `ReserveIdentifier`, and the
+//! sum of those amounts, which is kept in storage too, never exceeds the account's reserved
+//! balance. If some other pallet slashes the reserved balance below the sum, named reserves of the
+//! account can no longer be added or moved. Escrows use their own named reserve, `ESCROW_ID`.
 
 use frame_support::{
-	decl_event, decl_module,
-	dispatch::DispatchResult,
-	traits::{Currency, ExistenceRequirement::AllowDeath, ReservableCurrency},
+	decl_error, decl_event, decl_module, decl_storage,
+	dispatch::{DispatchError, DispatchResult},
+	ensure,
+	traits::{BalanceStatus, Currency, ExistenceRequirement::AllowDeath, ReservableCurrency},
 };
 use frame_system::{self as system, ensure_signed};
+use parity_scale_codec::{Decode, Encode};
+use sp_runtime::traits::{Saturating, Zero};
 
 #[cfg(test)]
 mod tests;
 
 // balance type using reservable currency type
 type BalanceOf<T> = <<T as Trait>::Currency as Currency<<T as system::Trait>::AccountId>>::Balance;
+type EscrowOf<T> =
+	Escrow<<T as system::Trait>::AccountId, BalanceOf<T>, <T as system::Trait>::BlockNumber>;
 
 pub trait Trait: system::Trait + Sized {
 	// overarching event type
@@ -22,6 +41,62 @@ pub trait Trait: system::Trait + Sized {
 	type Currency: Currency<Self::AccountId> + ReservableCurrency<Self::AccountId>;
 }
 
+/// Identifier for a named reserve. Must be exactly 8 bytes long
+pub type ReserveIdentifier = [u8; 8];
+
+/// The named reserve that holds escrowed funds
+pub const ESCROW_ID: ReserveIdentifier = *b"escrow  ";
+
+/// Simple index for identifying an escrow.
+pub type EscrowIndex = u32;
+
+/// The state of an escrow that has not yet been settled
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub enum EscrowStatus {
+	/// The escrow can be released, refunded or reclaimed
+	Open,
+	/// One of the parties disputed the escrow; only the arbiter can settle it
+	Disputed,
+}
+
+/// Funds held in reserve by a payer on behalf of a payee
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct Escrow<AccountId, Balance, BlockNumber> {
+	/// The account whose funds are held in reserve
+	pub payer: AccountId,
+	/// The account that receives the funds when the escrow is released
+	pub payee: AccountId,
+	/// The account that may settle the escrow on behalf of both parties
+	pub arbiter: AccountId,
+	/// The amount held in reserve
+	pub amount: Balance,
+	/// Block number after which the payer may reclaim an undisputed escrow
+	pub deadline: BlockNumber,
+	/// Whether the escrow has been disputed
+	pub status: EscrowStatus,
+}
+
+decl_storage! {
+	trait Store for Module<T: Trait> as ReservableCurrency {
+		/// Info on all of the open escrows
+		Escrows get(fn escrows): map hasher(blake2_128_concat) EscrowIndex => Option<EscrowOf<T>>;
+
+		/// The total number of escrows that have so far been opened
+		EscrowCount get(fn escrow_count): EscrowIndex;
+
+		/// The amount each account holds under each named reserve
+		NamedReserves get(fn named_reserves):
+			double_map hasher(blake2_128_concat) T::AccountId, hasher(blake2_128_concat) ReserveIdentifier
+			=> BalanceOf<T>;
+
+		/// The sum of all named reserves of each account
+		NamedReservesTotal get(fn named_reserves_total):
+			map hasher(blake2_128_concat) T::AccountId => BalanceOf<T>;
+	}
+}
+
 decl_event!(
 	pub enum Event<T>
 	where
@@ -33,13 +108,62 @@ decl_event!(
 		UnlockFunds(AccountId, Balance, BlockNumber),
 		// sender, dest, amount, block number
 		TransferFunds(AccountId, AccountId, Balance, BlockNumber),
+		// account, reserve id, amount
+		ReservedNamed(AccountId, ReserveIdentifier, Balance),
+		// account, reserve id, amount
+		UnreservedNamed(AccountId, ReserveIdentifier, Balance),
+		// source, dest, reserve id, amount moved
+		RepatriatedNamed(AccountId, AccountId, ReserveIdentifier, Balance),
+		// escrow index, payer, payee, amount, deadline
+		EscrowOpened(EscrowIndex, AccountId, AccountId, Balance, BlockNumber),
+		// escrow index, payee, amount
+		EscrowReleased(EscrowIndex, AccountId, Balance),
+		// escrow index, payer, amount
+		EscrowRefunded(EscrowIndex, AccountId, Balance),
+		// escrow index, account raising the dispute
+		EscrowDisputed(EscrowIndex, AccountId),
+		// escrow index, amount paid to payee, amount returned to payer
+		EscrowResolved(EscrowIndex, Balance, Balance),
 	}
 );
 
+decl_error! {
+	pub enum Error for Module<T: Trait> {
+		/// Funds held under a named reserve cannot be unreserved directly
+		FundsNamedReserved,
+		/// The named reserve holds less than the amount requested
+		InsufficientNamedReserve,
+		/// The reserve identifier is managed by this pallet and cannot be used directly
+		ReservedIdentifier,
+		/// The named reserves of the account exceed its reserved balance
+		UnbackedNamedReserves,
+		/// The escrow must end after it starts
+		DeadlineTooEarly,
+		/// An escrow cannot be opened for a zero amount
+		ZeroAmount,
+		/// The escrow index specified does not exist
+		InvalidIndex,
+		/// The caller is not allowed to perform this action on the escrow
+		NotPermitted,
+		/// The escrow has been disputed and must be settled by the arbiter
+		EscrowDisputed,
+		/// The escrow is not disputed
+		EscrowNotDisputed,
+		/// The deadline of the escrow has passed
+		DeadlinePassed,
+		/// The deadline of the escrow has not yet passed
+		DeadlineNotReached,
+		/// The arbiter cannot award the payee more than the escrowed amount
+		AwardTooLarge,
+	}
+}
+
 decl_module! {
 	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
 		fn deposit_event() = default;
 
+		type Error = Error<T>;
+
 		/// Reserves the specified amount of funds from the caller
 		#[weight = 10_000]
 		pub fn reserve_funds(origin, amount: BalanceOf<T>) -> DispatchResult {
@@ -58,6 +182,7 @@ decl_module! {
 		#[weight = 10_000]
 		pub fn unreserve_funds(origin, amount: BalanceOf<T>) -> DispatchResult {
 			let unlocker = ensure_signed(origin)?;
+			ensure!(amount <= Self::unnamed_reserve(&unlocker), Error::<T>::FundsNamedReserved);
 
 			T::Currency::unreserve(&unlocker, amount);
 			// ReservableCurrency::unreserve does not fail (it will lock up as much as amount)
@@ -92,6 +217,9 @@ decl_module! {
 		) -> DispatchResult {
 			let _ = ensure_signed(origin)?; // dangerous because can be called with any signature (so dont do this in practice ever!)
 
+			// Funds held under a named reserve are never taken as collateral
+			let collateral = collateral.min(Self::unnamed_reserve(&to_punish));
+
 						// If collateral is bigger than to_punish's reserved_balance, store what's left in overdraft.
 			let overdraft = T::Currency::unreserve(&to_punish, collateral);
 
@@ -102,5 +230,296 @@ decl_module! {
 
 			Ok(())
 		}
+
+		/// Reserves the specified amount of funds from the caller under a named reserve
+		#[weight = 10_000]
+		pub fn reserve_named(origin, id: ReserveIdentifier, amount: BalanceOf<T>) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+			ensure!(id != ESCROW_ID, Error::<T>::ReservedIdentifier);
+			ensure!(Self::named_reserves_are_backed(&who), Error::<T>::UnbackedNamedReserves);
+
+			Self::do_reserve_named(&who, id, amount)
+					.map_err(|_| "locker can't afford to lock the amount requested")?;
+
+			Self::deposit_event(RawEvent::ReservedNamed(who, id, amount));
+			Ok(())
+		}
+
+		/// Unreserves the specified amount of funds from a named reserve of the caller
+		#[weight = 10_000]
+		pub fn unreserve_named(origin, id: ReserveIdentifier, amount: BalanceOf<T>) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+			ensure!(id != ESCROW_ID, Error::<T>::ReservedIdentifier);
+			ensure!(amount <= Self::named_reserves(&who, id), Error::<T>::InsufficientNamedReserve);
+
+			let unreserved = Self::do_unreserve_named(&who, id, amount);
+
+			Self::deposit_event(RawEvent::UnreservedNamed(who, id, unreserved));
+			Ok(())
+		}
+
+		/// Moves funds from a named reserve of the caller to `dest`. With `BalanceStatus::Reserved`
+		/// the funds are held under the same named reserve of `dest`.
+		#[weight = 10_000]
+		pub fn repatriate_named(
+			origin,
+			id: ReserveIdentifier,
+			dest: T::AccountId,
+			amount: BalanceOf<T>,
+			status: BalanceStatus,
+		) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+			ensure!(id != ESCROW_ID, Error::<T>::ReservedIdentifier);
+			ensure!(amount <= Self::named_reserves(&who, id), Error::<T>::InsufficientNamedReserve);
+
+			let moved = Self::do_repatriate_named(&who, &dest, id, amount, status)?;
+
+			Self::deposit_event(RawEvent::RepatriatedNamed(who, dest, id, moved));
+			Ok(())
+		}
+
+		/// Opens an escrow by reserving the specified amount of funds from the caller
+		#[weight = 10_000]
+		pub fn open_escrow(
+			origin,
+			payee: T::AccountId,
+			arbiter: T::AccountId,
+			amount: BalanceOf<T>,
+			deadline: T::BlockNumber,
+		) -> DispatchResult {
+			let payer = ensure_signed(origin)?;
+			let now = <system::Module<T>>::block_number();
+
+			ensure!(!amount.is_zero(), Error::<T>::ZeroAmount);
+			ensure!(deadline > now, Error::<T>::DeadlineTooEarly);
+			ensure!(Self::named_reserves_are_backed(&payer), Error::<T>::UnbackedNamedReserves);
+
+			Self::do_reserve_named(&payer, ESCROW_ID, amount)
+					.map_err(|_| "payer can't afford to escrow the amount requested")?;
+
+			let index = EscrowCount::get();
+			// not protected against overflow, see safemath section
+			EscrowCount::put(index + 1);
+
+			<Escrows<T>>::insert(index, Escrow {
+				payer: payer.clone(),
+				payee: payee.clone(),
+				arbiter,
+				amount,
+				deadline,
+				status: EscrowStatus::Open,
+			});
+
+			Self::deposit_event(RawEvent::EscrowOpened(index, payer, payee, amount, deadline));
+			Ok(())
+		}
+
+		/// Releases the escrowed funds to the payee. May be called by the payer or the arbiter.
+		#[weight = 10_000]
+		pub fn release_escrow(origin, index: EscrowIndex) -> DispatchResult {
+			let caller = ensure_signed(origin)?;
+			let escrow = Self::escrows(index).ok_or(Error::<T>::InvalidIndex)?;
+
+			ensure!(caller == escrow.payer || caller == escrow.arbiter, Error::<T>::NotPermitted);
+			ensure!(escrow.status == EscrowStatus::Open, Error::<T>::EscrowDisputed);
+
+			let (paid, _) = Self::settle(&escrow, escrow.amount)?;
+			<Escrows<T>>::remove(index);
+
+			Self::deposit_event(RawEvent::EscrowReleased(index, escrow.payee, paid));
+			Ok(())
+		}
+
+		/// Returns the escrowed funds to the payer. May be called by the payee or the arbiter.
+		#[weight = 10_000]
+		pub fn refund_escrow(origin, index: EscrowIndex) -> DispatchResult {
+			let caller = ensure_signed(origin)?;
+			let escrow = Self::escrows(index).ok_or(Error::<T>::InvalidIndex)?;
+
+			ensure!(caller == escrow.payee || caller == escrow.arbiter, Error::<T>::NotPermitted);
+			ensure!(escrow.status == EscrowStatus::Open, Error::<T>::EscrowDisputed);
+
+			let (_, refunded) = Self::settle(&escrow, Zero::zero())?;
+			<Escrows<T>>::remove(index);
+
+			Self::deposit_event(RawEvent::EscrowRefunded(index, escrow.payer, refunded));
+			Ok(())
+		}
+
+		/// Returns the escrowed funds to the payer once the deadline has passed without the
+		/// escrow being released, refunded or disputed.
+		#[weight = 10_000]
+		pub fn reclaim_escrow(origin, index: EscrowIndex) -> DispatchResult {
+			let caller = ensure_signed(origin)?;
+			let escrow = Self::escrows(index).ok_or(Error::<T>::InvalidIndex)?;
+
+			ensure!(caller == escrow.payer, Error::<T>::NotPermitted);
+			ensure!(escrow.status == EscrowStatus::Open, Error::<T>::EscrowDisputed);
+			let now = <system::Module<T>>::block_number();
+			ensure!(now >= escrow.deadline, Error::<T>::DeadlineNotReached);
+
+			let (_, refunded) = Self::settle(&escrow, Zero::zero())?;
+			<Escrows<T>>::remove(index);
+
+			Self::deposit_event(RawEvent::EscrowRefunded(index, escrow.payer, refunded));
+			Ok(())
+		}
+
+		/// Disputes an escrow before its deadline. May be called by the payer or the payee.
+		/// A disputed escrow can only be settled by the arbiter.
+		#[weight = 10_000]
+		pub fn dispute_escrow(origin, index: EscrowIndex) -> DispatchResult {
+			let caller = ensure_signed(origin)?;
+			let mut escrow = Self::escrows(index).ok_or(Error::<T>::InvalidIndex)?;
+
+			ensure!(caller == escrow.payer || caller == escrow.payee, Error::<T>::NotPermitted);
+			ensure!(escrow.status == EscrowStatus::Open, Error::<T>::EscrowDisputed);
+			let now = <system::Module<T>>::block_number();
+			ensure!(now < escrow.deadline, Error::<T>::DeadlinePassed);
+
+			escrow.status = EscrowStatus::Disputed;
+			<Escrows<T>>::insert(index, escrow);
+
+			Self::deposit_event(RawEvent::EscrowDisputed(index, caller));
+			Ok(())
+		}
+
+		/// Settles a disputed escrow. The arbiter awards `to_payee` to the payee and the rest of
+		/// the escrowed funds are returned to the payer.
+		#[weight = 10_000]
+		pub fn resolve_dispute(origin, index: EscrowIndex, to_payee: BalanceOf<T>) -> DispatchResult {
+			let caller = ensure_signed(origin)?;
+			let escrow = Self::escrows(index).ok_or(Error::<T>::InvalidIndex)?;
+
+			ensure!(caller == escrow.arbiter, Error::<T>::NotPermitted);
+			ensure!(escrow.status == EscrowStatus::Disputed, Error::<T>::EscrowNotDisputed);
+			ensure!(to_payee <= escrow.amount, Error::<T>::AwardTooLarge);
+
+			let (paid, refunded) = Self::settle(&escrow, to_payee)?;
+			<Escrows<T>>::remove(index);
+
+			Self::deposit_event(RawEvent::EscrowResolved(index, paid, refunded));
+			Ok(())
+		}
+	}
+}
+
+impl<T: Trait> Module<T> {
+	/// Reserves funds of an account under the given identifier
+	pub fn do_reserve_named(
+		who: &T::AccountId,
+		id: ReserveIdentifier,
+		amount: BalanceOf<T>,
+	) -> DispatchResult {
+		ensure!(Self::named_reserves_are_backed(who), Error::<T>::UnbackedNamedReserves);
+		T::Currency::reserve(who, amount)?;
+		Self::increase_named(who, id, amount);
+		Ok(())
+	}
+
+	/// Unreserves up to `amount` from a named reserve of an account.
+	/// Returns the amount that was actually unreserved.
+	pub fn do_unreserve_named(
+		who: &T::AccountId,
+		id: ReserveIdentifier,
+		amount: BalanceOf<T>,
+	) -> BalanceOf<T> {
+		let amount = amount.min(Self::named_reserves(who, id));
+		// `unreserve` returns the part of the amount that could not be unreserved
+		let unreserved = amount.saturating_sub(T::Currency::unreserve(who, amount));
+		Self::reduce_named(who, id, unreserved);
+		unreserved
+	}
+
+	/// Moves up to `amount` from a named reserve of `slashed` to `beneficiary`.
+	/// Returns the amount that was actually moved.
+	pub fn do_repatriate_named(
+		slashed: &T::AccountId,
+		beneficiary: &T::AccountId,
+		id: ReserveIdentifier,
+		amount: BalanceOf<T>,
+		status: BalanceStatus,
+	) -> Result<BalanceOf<T>, DispatchError> {
+		ensure!(Self::named_reserves_are_backed(slashed), Error::<T>::UnbackedNamedReserves);
+		if status == BalanceStatus::Reserved {
+			ensure!(
+				Self::named_reserves_are_backed(beneficiary),
+				Error::<T>::UnbackedNamedReserves
+			);
+		}
+
+		let amount = amount.min(Self::named_reserves(slashed, id));
+		// `repatriate_reserved` returns the part of the amount that could not be moved
+		let remaining = T::Currency::repatriate_reserved(slashed, beneficiary, amount, status)?;
+		let moved = amount.saturating_sub(remaining);
+
+		Self::reduce_named(slashed, id, moved);
+		if status == BalanceStatus::Reserved {
+			Self::increase_named(beneficiary, id, moved);
+		}
+		Ok(moved)
+	}
+
+	/// Invariant check: the named reserves of an account never exceed its reserved balance.
+	/// Adding to or moving named reserves is refused while it does not hold.
+	pub fn named_reserves_are_backed(who: &T::AccountId) -> bool {
+		Self::named_reserves_total(who) <= T::Currency::reserved_balance(who)
+	}
+
+	/// The part of an account's reserved balance that is not held under any named reserve
+	fn unnamed_reserve(who: &T::AccountId) -> BalanceOf<T> {
+		T::Currency::reserved_balance(who).saturating_sub(Self::named_reserves_total(who))
+	}
+
+	/// Raises a named reserve and the account's total in the ledger
+	fn increase_named(who: &T::AccountId, id: ReserveIdentifier, amount: BalanceOf<T>) {
+		<NamedReserves<T>>::mutate(who, id, |reserved| *reserved = reserved.saturating_add(amount));
+		<NamedReservesTotal<T>>::mutate(who, |total| *total = total.saturating_add(amount));
+	}
+
+	/// Lowers a named reserve and the account's total in the ledger, removing the entries once
+	/// they reach zero
+	fn reduce_named(who: &T::AccountId, id: ReserveIdentifier, amount: BalanceOf<T>) {
+		let reserved = Self::named_reserves(who, id);
+		let amount = amount.min(reserved);
+		let remaining = reserved - amount;
+		if remaining.is_zero() {
+			<NamedReserves<T>>::remove(who, id);
+		} else {
+			<NamedReserves<T>>::insert(who, id, remaining);
+		}
+
+		let total = Self::named_reserves_total(who).saturating_sub(amount);
+		if total.is_zero() {
+			<NamedReservesTotal<T>>::remove(who);
+		} else {
+			<NamedReservesTotal<T>>::insert(who, total);
+		}
+	}
+
+	/// Closes an escrow, paying `to_payee` to the payee and returning the rest to the payer.
+	/// Returns the amounts paid to the payee and returned to the payer.
+	///
+	/// `to_payee` must be no greater than the escrowed amount. If the payment fails, for example
+	/// because the payee's account does not exist, nothing is changed and the escrow must be kept.
+	fn settle(
+		escrow: &EscrowOf<T>,
+		to_payee: BalanceOf<T>,
+	) -> Result<(BalanceOf<T>, BalanceOf<T>), DispatchError> {
+		let mut paid = Zero::zero();
+		if !to_payee.is_zero() {
+			paid = Self::do_repatriate_named(
+				&escrow.payer,
+				&escrow.payee,
+				ESCROW_ID,
+				to_payee,
+				BalanceStatus::Free,
+			)?;
+		}
+
+		let to_payer = escrow.amount.saturating_sub(paid);
+		Self::do_unreserve_named(&escrow.payer, ESCROW_ID, to_payer);
+
+		Ok((paid, to_payer))
 	}
 }
@@ -1,6 +1,9 @@
 use crate::*;
 use balances;
-use frame_support::{assert_ok, impl_outer_event, impl_outer_origin, parameter_types};
+use frame_support::{
+	assert_noop, assert_ok, impl_outer_event, impl_outer_origin, parameter_types,
+	traits::ReservableCurrency as _,
+};
 use frame_system::{self as system};
 use sp_core::H256;
 use sp_io;
@@ -188,3 +191,331 @@ fn new_test_ext_unreserve_and_transfer() {
 		assert_eq!(Balances::free_balance(&2), 15000);
 	})
 }
+
+/// Opens an escrow from 1 to 2 with 3 as arbiter, 4000 funds and a deadline at block 10
+fn open_default_escrow() -> EscrowIndex {
+	assert_ok!(ReservableCurrency::open_escrow(Origin::signed(1), 2, 3, 4000, 10));
+	ReservableCurrency::escrow_count() - 1
+}
+
+#[test]
+fn open_escrow_reserves_funds() {
+	new_test_ext().execute_with(|| {
+		let index = open_default_escrow();
+
+		assert_eq!(Balances::free_balance(&1), 6000);
+		assert_eq!(Balances::reserved_balance(&1), 4000);
+		assert_eq!(ReservableCurrency::named_reserves(1, ESCROW_ID), 4000);
+		assert_eq!(
+			ReservableCurrency::escrows(index),
+			Some(Escrow {
+				payer: 1,
+				payee: 2,
+				arbiter: 3,
+				amount: 4000,
+				deadline: 10,
+				status: EscrowStatus::Open,
+			})
+		);
+		let open_event = TestEvent::reservable_currency(RawEvent::EscrowOpened(index, 1, 2, 4000, 10));
+		assert!(System::events().iter().any(|a| a.event == open_event));
+	})
+}
+
+#[test]
+fn open_escrow_validates_input() {
+	new_test_ext().execute_with(|| {
+		assert_noop!(
+			ReservableCurrency::open_escrow(Origin::signed(1), 2, 3, 4000, 1),
+			Error::<TestRuntime>::DeadlineTooEarly
+		);
+		assert_noop!(
+			ReservableCurrency::open_escrow(Origin::signed(1), 2, 3, 0, 10),
+			Error::<TestRuntime>::ZeroAmount
+		);
+	})
+}
+
+#[test]
+fn escrowed_funds_cannot_be_unreserved() {
+	new_test_ext().execute_with(|| {
+		open_default_escrow();
+		assert_ok!(ReservableCurrency::reserve_funds(Origin::signed(1), 1000));
+
+		assert_noop!(
+			ReservableCurrency::unreserve_funds(Origin::signed(1), 2000),
+			Error::<TestRuntime>::FundsNamedReserved
+		);
+		assert_ok!(ReservableCurrency::unreserve_funds(Origin::signed(1), 1000));
+		assert_eq!(Balances::reserved_balance(&1), 4000);
+
+		// Only the funds outside of any named reserve are taken as collateral
+		assert_ok!(ReservableCurrency::reserve_funds(Origin::signed(1), 500));
+		assert_ok!(ReservableCurrency::unreserve_and_transfer(Origin::signed(5), 1, 5, 6000));
+		assert_eq!(Balances::reserved_balance(&1), 4000);
+		assert_eq!(Balances::free_balance(&5), 14500);
+	})
+}
+
+#[test]
+fn payer_or_arbiter_can_release() {
+	new_test_ext().execute_with(|| {
+		let index = open_default_escrow();
+		// The payee cannot pay itself
+		assert_noop!(
+			ReservableCurrency::release_escrow(Origin::signed(2), index),
+			Error::<TestRuntime>::NotPermitted
+		);
+		assert_ok!(ReservableCurrency::release_escrow(Origin::signed(1), index));
+
+		assert_eq!(Balances::reserved_balance(&1), 0);
+		assert_eq!(Balances::free_balance(&1), 6000);
+		assert_eq!(Balances::free_balance(&2), 15000);
+		assert_eq!(ReservableCurrency::escrows(index), None);
+		assert_eq!(ReservableCurrency::named_reserves(1, ESCROW_ID), 0);
+		let release_event = TestEvent::reservable_currency(RawEvent::EscrowReleased(index, 2, 4000));
+		assert!(System::events().iter().any(|a| a.event == release_event));
+
+		let index = open_default_escrow();
+		assert_ok!(ReservableCurrency::release_escrow(Origin::signed(3), index));
+		assert_eq!(Balances::free_balance(&2), 19000);
+	})
+}
+
+#[test]
+fn payee_or_arbiter_can_refund() {
+	new_test_ext().execute_with(|| {
+		let index = open_default_escrow();
+		// The payer cannot take the funds back before the deadline
+		assert_noop!(
+			ReservableCurrency::refund_escrow(Origin::signed(1), index),
+			Error::<TestRuntime>::NotPermitted
+		);
+		assert_ok!(ReservableCurrency::refund_escrow(Origin::signed(2), index));
+
+		assert_eq!(Balances::reserved_balance(&1), 0);
+		assert_eq!(Balances::free_balance(&1), 10000);
+		assert_eq!(Balances::free_balance(&2), 11000);
+		let refund_event = TestEvent::reservable_currency(RawEvent::EscrowRefunded(index, 1, 4000));
+		assert!(System::events().iter().any(|a| a.event == refund_event));
+
+		let index = open_default_escrow();
+		assert_ok!(ReservableCurrency::refund_escrow(Origin::signed(3), index));
+		assert_eq!(Balances::free_balance(&1), 10000);
+	})
+}
+
+#[test]
+fn failed_release_keeps_escrow() {
+	new_test_ext().execute_with(|| {
+		// Account 9 does not exist, so the funds cannot be repatriated to it
+		assert_ok!(ReservableCurrency::open_escrow(Origin::signed(1), 9, 3, 4000, 10));
+		let index = ReservableCurrency::escrow_count() - 1;
+		assert_noop!(
+			ReservableCurrency::release_escrow(Origin::signed(3), index),
+			balances::Error::<TestRuntime, _>::DeadAccount
+		);
+		assert!(ReservableCurrency::escrows(index).is_some());
+		assert_eq!(ReservableCurrency::named_reserves(1, ESCROW_ID), 4000);
+
+		// The arbiter can still return the funds to the payer
+		assert_ok!(ReservableCurrency::refund_escrow(Origin::signed(3), index));
+		assert_eq!(Balances::free_balance(&1), 10000);
+	})
+}
+
+#[test]
+fn payer_reclaims_after_deadline() {
+	new_test_ext().execute_with(|| {
+		let index = open_default_escrow();
+		assert_noop!(
+			ReservableCurrency::reclaim_escrow(Origin::signed(1), index),
+			Error::<TestRuntime>::DeadlineNotReached
+		);
+
+		System::set_block_number(10);
+		assert_noop!(
+			ReservableCurrency::reclaim_escrow(Origin::signed(2), index),
+			Error::<TestRuntime>::NotPermitted
+		);
+		assert_ok!(ReservableCurrency::reclaim_escrow(Origin::signed(1), index));
+		assert_eq!(Balances::free_balance(&1), 10000);
+		assert_eq!(ReservableCurrency::escrows(index), None);
+	})
+}
+
+#[test]
+fn disputes_are_settled_by_arbiter() {
+	new_test_ext().execute_with(|| {
+		let index = open_default_escrow();
+		assert_noop!(
+			ReservableCurrency::dispute_escrow(Origin::signed(3), index),
+			Error::<TestRuntime>::NotPermitted
+		);
+		assert_ok!(ReservableCurrency::dispute_escrow(Origin::signed(2), index));
+		let dispute_event = TestEvent::reservable_currency(RawEvent::EscrowDisputed(index, 2));
+		assert!(System::events().iter().any(|a| a.event == dispute_event));
+
+		// Neither party can settle a disputed escrow, even after the deadline
+		System::set_block_number(10);
+		assert_noop!(
+			ReservableCurrency::release_escrow(Origin::signed(1), index),
+			Error::<TestRuntime>::EscrowDisputed
+		);
+		assert_noop!(
+			ReservableCurrency::reclaim_escrow(Origin::signed(1), index),
+			Error::<TestRuntime>::EscrowDisputed
+		);
+		assert_noop!(
+			ReservableCurrency::resolve_dispute(Origin::signed(1), index, 1000),
+			Error::<TestRuntime>::NotPermitted
+		);
+		assert_noop!(
+			ReservableCurrency::resolve_dispute(Origin::signed(3), index, 5000),
+			Error::<TestRuntime>::AwardTooLarge
+		);
+
+		// The arbiter splits the funds between payee and payer
+		assert_ok!(ReservableCurrency::resolve_dispute(Origin::signed(3), index, 1000));
+		assert_eq!(Balances::reserved_balance(&1), 0);
+		assert_eq!(Balances::free_balance(&1), 9000);
+		assert_eq!(Balances::free_balance(&2), 12000);
+		let resolve_event = TestEvent::reservable_currency(RawEvent::EscrowResolved(index, 1000, 3000));
+		assert!(System::events().iter().any(|a| a.event == resolve_event));
+	})
+}
+
+#[test]
+fn cannot_dispute_after_deadline() {
+	new_test_ext().execute_with(|| {
+		let index = open_default_escrow();
+		System::set_block_number(10);
+		assert_noop!(
+			ReservableCurrency::dispute_escrow(Origin::signed(1), index),
+			Error::<TestRuntime>::DeadlinePassed
+		);
+	})
+}
+
+#[test]
+fn reserve_named_tracks_purpose() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(ReservableCurrency::reserve_named(Origin::signed(1), *b"deposit ", 1000));
+		assert_ok!(ReservableCurrency::reserve_named(Origin::signed(1), *b"bond    ", 2000));
+		assert_ok!(ReservableCurrency::reserve_funds(Origin::signed(1), 500));
+
+		assert_eq!(Balances::reserved_balance(&1), 3500);
+		assert_eq!(ReservableCurrency::named_reserves(1, *b"deposit "), 1000);
+		assert_eq!(ReservableCurrency::named_reserves(1, *b"bond    "), 2000);
+		assert_eq!(ReservableCurrency::named_reserves_total(&1), 3000);
+		assert!(ReservableCurrency::named_reserves_are_backed(&1));
+
+		let reserve_event = TestEvent::reservable_currency(RawEvent::ReservedNamed(1, *b"bond    ", 2000));
+		assert!(System::events().iter().any(|a| a.event == reserve_event));
+
+		// Named funds can't be unreserved anonymously
+		assert_noop!(
+			ReservableCurrency::unreserve_funds(Origin::signed(1), 501),
+			Error::<TestRuntime>::FundsNamedReserved
+		);
+	})
+}
+
+#[test]
+fn unreserve_named_works() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(ReservableCurrency::reserve_named(Origin::signed(1), *b"deposit ", 1000));
+		assert_noop!(
+			ReservableCurrency::unreserve_named(Origin::signed(1), *b"deposit ", 1001),
+			Error::<TestRuntime>::InsufficientNamedReserve
+		);
+
+		assert_ok!(ReservableCurrency::unreserve_named(Origin::signed(1), *b"deposit ", 400));
+		assert_eq!(ReservableCurrency::named_reserves(1, *b"deposit "), 600);
+		assert_eq!(Balances::reserved_balance(&1), 600);
+
+		assert_ok!(ReservableCurrency::unreserve_named(Origin::signed(1), *b"deposit ", 600));
+		assert!(!<NamedReserves<TestRuntime>>::contains_key(1, *b"deposit "));
+		assert_eq!(Balances::free_balance(&1), 10000);
+
+		let unreserve_event = TestEvent::reservable_currency(RawEvent::UnreservedNamed(1, *b"deposit ", 600));
+		assert!(System::events().iter().any(|a| a.event == unreserve_event));
+	})
+}
+
+#[test]
+fn ledger_follows_actual_unreserve() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(ReservableCurrency::reserve_named(Origin::signed(1), *b"deposit ", 1000));
+
+		// Some other pallet slashes part of the reserved balance
+		let _ = Balances::slash_reserved(&1, 400);
+		assert!(!ReservableCurrency::named_reserves_are_backed(&1));
+		assert_noop!(
+			ReservableCurrency::reserve_named(Origin::signed(1), *b"bond    ", 100),
+			Error::<TestRuntime>::UnbackedNamedReserves
+		);
+
+		// Only what could actually be unreserved leaves the ledger
+		assert_ok!(ReservableCurrency::unreserve_named(Origin::signed(1), *b"deposit ", 1000));
+		assert_eq!(ReservableCurrency::named_reserves(1, *b"deposit "), 400);
+		assert_eq!(ReservableCurrency::named_reserves_total(&1), 400);
+		let unreserve_event = TestEvent::reservable_currency(RawEvent::UnreservedNamed(1, *b"deposit ", 600));
+		assert!(System::events().iter().any(|a| a.event == unreserve_event));
+	})
+}
+
+#[test]
+fn repatriate_named_works() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(ReservableCurrency::reserve_named(Origin::signed(1), *b"deposit ", 1000));
+
+		// Moving into the free balance of the destination
+		assert_ok!(ReservableCurrency::repatriate_named(
+			Origin::signed(1),
+			*b"deposit ",
+			2,
+			300,
+			BalanceStatus::Free
+		));
+		assert_eq!(ReservableCurrency::named_reserves(1, *b"deposit "), 700);
+		assert_eq!(Balances::free_balance(&2), 11300);
+
+		// Moving into the same named reserve of the destination
+		assert_ok!(ReservableCurrency::repatriate_named(
+			Origin::signed(1),
+			*b"deposit ",
+			2,
+			700,
+			BalanceStatus::Reserved
+		));
+		assert!(!<NamedReserves<TestRuntime>>::contains_key(1, *b"deposit "));
+		assert_eq!(ReservableCurrency::named_reserves(2, *b"deposit "), 700);
+		assert_eq!(Balances::reserved_balance(&2), 700);
+		assert!(ReservableCurrency::named_reserves_are_backed(&1));
+		assert!(ReservableCurrency::named_reserves_are_backed(&2));
+
+		let repatriate_event =
+			TestEvent::reservable_currency(RawEvent::RepatriatedNamed(1, 2, *b"deposit ", 700));
+		assert!(System::events().iter().any(|a| a.event == repatriate_event));
+	})
+}
+
+#[test]
+fn escrow_reserve_cannot_be_used_directly() {
+	new_test_ext().execute_with(|| {
+		open_default_escrow();
+		assert_noop!(
+			ReservableCurrency::reserve_named(Origin::signed(1), ESCROW_ID, 100),
+			Error::<TestRuntime>::ReservedIdentifier
+		);
+		assert_noop!(
+			ReservableCurrency::unreserve_named(Origin::signed(1), ESCROW_ID, 100),
+			Error::<TestRuntime>::ReservedIdentifier
+		);
+		assert_noop!(
+			ReservableCurrency::repatriate_named(Origin::signed(1), ESCROW_ID, 2, 100, BalanceStatus::Free),
+			Error::<TestRuntime>::ReservedIdentifier
+		);
+	})
+}
@@ -1,21 +1,38 @@
 //! A pallet that demonstrates the Transient Storage Adapter pattern through
 //! the concrete example of a ringbuffer queue
+//!
+//! Besides the single shared queue, every account may own any number of keyed
+//! queues. The owner of a keyed queue reserves a deposit for its storage while
+//! the queue holds any items.
 
 #![cfg_attr(not(feature = "std"), no_std)]
 
 use codec::{Decode, Encode};
-use frame_support::{decl_event, decl_module, decl_storage, dispatch::DispatchResult};
+use frame_support::{
+	decl_error, decl_event, decl_module, decl_storage,
+	dispatch::DispatchResult,
+	ensure,
+	traits::{Currency, Get, ReservableCurrency},
+};
 use frame_system::{self as system, ensure_signed};
 use sp_std::prelude::*;
 
-mod ringbuffer;
+pub mod ringbuffer;
 
-use ringbuffer::{RingBufferTrait, RingBufferTransient};
+pub use ringbuffer::OverflowPolicy;
+use ringbuffer::{
+	BufferFull, KeyedRingBufferTransient, Pushed, RingBufferTrait, RingBufferTransient,
+};
 
 #[cfg(test)]
 mod tests;
 
-type BufferIndex = u8;
+pub type BufferIndex = u16;
+
+/// The id of one of an account's keyed queues
+pub type QueueId = u32;
+
+type BalanceOf<T> = <<T as Trait>::Currency as Currency<<T as system::Trait>::AccountId>>::Balance;
 
 #[derive(Encode, Decode, Default, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "std", derive(Debug))]
@@ -26,12 +43,37 @@ pub struct ValueStruct {
 
 pub trait Trait: system::Trait {
 	type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
+
+	/// The maximum number of items in the queue, which may be at most `BufferIndex::max_value()`
+	type Capacity: Get<BufferIndex>;
+
+	/// What to do when an item is added to a full queue
+	type OverflowPolicy: Get<OverflowPolicy>;
+
+	/// The currency in which queue deposits are reserved
+	type Currency: ReservableCurrency<Self::AccountId>;
+
+	/// The deposit reserved by the owner of a non-empty keyed queue
+	type QueueDeposit: Get<BalanceOf<Self>>;
 }
 
 decl_storage! {
 	trait Store for Module<T: Trait> as RingBufferQueue {
 		BufferMap get(fn get_value): map hasher(twox_64_concat) BufferIndex => ValueStruct;
 		BufferRange get(fn range): (BufferIndex, BufferIndex) = (0, 0);
+
+		/// The items of the keyed queues, by owner and by queue id and index
+		KeyedMap get(fn keyed_value):
+			double_map hasher(blake2_128_concat) T::AccountId,
+				hasher(twox_64_concat) (QueueId, BufferIndex) => ValueStruct;
+		/// The bounds of the non-empty keyed queues
+		KeyedRange get(fn keyed_range):
+			double_map hasher(blake2_128_concat) T::AccountId,
+				hasher(twox_64_concat) QueueId => (BufferIndex, BufferIndex);
+		/// The deposits reserved for the non-empty keyed queues
+		QueueDeposits get(fn queue_deposit):
+			double_map hasher(blake2_128_concat) T::AccountId,
+				hasher(twox_64_concat) QueueId => Option<BalanceOf<T>>;
 	}
 }
 
@@ -39,16 +81,42 @@ decl_event!(
 	pub enum Event<T>
 	where
 		AccountId = <T as system::Trait>::AccountId,
+		Balance = BalanceOf<T>,
 	{
 		Popped(i32, bool),
+		/// The queue was full, so the oldest item was removed to make room for a new one
+		Overwritten(i32, bool),
+		/// The queue was full, so a new item was dropped
+		Rejected(i32, bool),
 		DummyEvent(AccountId),
+		/// An item was popped from a keyed queue
+		PoppedFrom(AccountId, QueueId, i32, bool),
+		/// A keyed queue received its first item, so its owner reserved a deposit
+		QueueCreated(AccountId, QueueId, Balance),
+		/// A keyed queue was emptied, so its deposit was returned
+		QueueRemoved(AccountId, QueueId, Balance),
 	}
 );
 
+decl_error! {
+	pub enum Error for Module<T: Trait> {
+		/// The queue is full
+		QueueFull,
+		/// The keyed queue does not exist, because it has no items
+		NoSuchQueue,
+	}
+}
+
 decl_module! {
 	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
 		fn deposit_event() = default;
 
+		/// The maximum number of items in the queue
+		const Capacity: BufferIndex = T::Capacity::get();
+
+		/// The deposit reserved by the owner of a non-empty keyed queue
+		const QueueDeposit: BalanceOf<T> = T::QueueDeposit::get();
+
 		/// Add an item to the queue
 		#[weight = 10_000]
 		pub fn add_to_queue(origin, integer: i32, boolean: bool) -> DispatchResult {
@@ -56,9 +124,7 @@ decl_module! {
 			let _user = ensure_signed(origin)?;
 
 			let mut queue = Self::queue_transient();
-			queue.push(ValueStruct{ integer, boolean });
-
-			Ok(())
+			Self::push_item(&mut *queue, ValueStruct{ integer, boolean })
 		}
 
 		/// Add several items to the queue
@@ -68,8 +134,15 @@ decl_module! {
 			let _user = ensure_signed(origin)?;
 
 			let mut queue = Self::queue_transient();
+
+			// Check the space up front so that either all or none of the items are added
+			if T::OverflowPolicy::get() == OverflowPolicy::Error {
+				let space = usize::from(T::Capacity::get()).saturating_sub(queue.len());
+				ensure!(integers.len() <= space, Error::<T>::QueueFull);
+			}
+
 			for integer in integers {
-				queue.push(ValueStruct{ integer, boolean });
+				Self::push_item(&mut *queue, ValueStruct{ integer, boolean })?;
 			}
 
 			Ok(())
@@ -88,6 +161,44 @@ decl_module! {
 
 			Ok(())
 		}
+
+		/// Add an item to one of the caller's keyed queues, creating it if it is empty
+		#[weight = 10_000]
+		pub fn add_to_keyed_queue(
+			origin,
+			id: QueueId,
+			integer: i32,
+			boolean: bool,
+		) -> DispatchResult {
+			let owner = ensure_signed(origin)?;
+
+			// Reserve the deposit first, so a failed reservation leaves the queue untouched
+			if !<QueueDeposits<T>>::contains_key(&owner, id) {
+				let deposit = T::QueueDeposit::get();
+				T::Currency::reserve(&owner, deposit)?;
+				<QueueDeposits<T>>::insert(&owner, id, deposit);
+				Self::deposit_event(RawEvent::QueueCreated(owner.clone(), id, deposit));
+			}
+
+			let mut queue = Self::keyed_queue_transient(owner.clone(), id);
+			let result = Self::push_item(&mut *queue, ValueStruct{ integer, boolean });
+			// The item may not have been added, in which case the queue can still be empty
+			Self::remove_if_empty(&owner, id, &*queue);
+			result
+		}
+
+		/// Remove and return an item from one of the caller's keyed queues
+		#[weight = 10_000]
+		pub fn pop_from_keyed_queue(origin, id: QueueId) -> DispatchResult {
+			let owner = ensure_signed(origin)?;
+
+			let mut queue = Self::keyed_queue_transient(owner.clone(), id);
+			let ValueStruct{ integer, boolean } = queue.pop().ok_or(Error::<T>::NoSuchQueue)?;
+			Self::deposit_event(RawEvent::PoppedFrom(owner.clone(), id, integer, boolean));
+			Self::remove_if_empty(&owner, id, &*queue);
+
+			Ok(())
+		}
 	}
 }
 
@@ -101,7 +212,60 @@ impl<T: Trait> Module<T> {
 			ValueStruct,
 			<Self as Store>::BufferRange,
 			<Self as Store>::BufferMap,
+			T::Capacity,
+			T::OverflowPolicy,
 			BufferIndex,
 		>::new())
 	}
+
+	/// Constructs the ringbuffer transient of a keyed queue and returns it as a boxed trait object.
+	fn keyed_queue_transient(
+		owner: T::AccountId,
+		id: QueueId,
+	) -> Box<dyn RingBufferTrait<ValueStruct>> {
+		Box::new(KeyedRingBufferTransient::<
+			T::AccountId,
+			QueueId,
+			ValueStruct,
+			<Self as Store>::KeyedRange,
+			<Self as Store>::KeyedMap,
+			T::Capacity,
+			T::OverflowPolicy,
+			BufferIndex,
+		>::new(owner, id))
+	}
+
+	/// Returns the deposit of a keyed queue once it is empty.
+	///
+	/// The bounds of an empty queue are removed from storage when the transient is dropped.
+	fn remove_if_empty(
+		owner: &T::AccountId,
+		id: QueueId,
+		queue: &dyn RingBufferTrait<ValueStruct>,
+	) {
+		if !queue.is_empty() {
+			return;
+		}
+		if let Some(deposit) = <QueueDeposits<T>>::take(owner, id) {
+			T::Currency::unreserve(owner, deposit);
+			Self::deposit_event(RawEvent::QueueRemoved(owner.clone(), id, deposit));
+		}
+	}
+
+	/// Push an item onto the queue, reporting what happened according to the overflow policy
+	fn push_item(
+		queue: &mut dyn RingBufferTrait<ValueStruct>,
+		item: ValueStruct,
+	) -> DispatchResult {
+		let (integer, boolean) = (item.integer, item.boolean);
+		match queue.push(item) {
+			Ok(Pushed::Added) => {}
+			Ok(Pushed::Overwrote(oldest)) => {
+				Self::deposit_event(RawEvent::Overwritten(oldest.integer, oldest.boolean));
+			}
+			Ok(Pushed::Rejected) => Self::deposit_event(RawEvent::Rejected(integer, boolean)),
+			Err(BufferFull) => return Err(Error::<T>::QueueFull.into()),
+		}
+		Ok(())
+	}
 }
@@ -3,6 +3,9 @@
 //! This pallet provides a trait and implementation for a ringbuffer that
 //! abstracts over storage items and presents them as a FIFO queue.
 //!
+//! The ringbuffer holds at most `Capacity` items. What happens when an item is
+//! pushed onto a full ringbuffer is decided by the `OverflowPolicy`.
+//!
 //! Usage Example:
 //! ```rust, ignore
 //! use ringbuffer::{RingBufferTrait, RingBufferTransient};
@@ -14,19 +17,57 @@
 //!     SomeStruct,
 //!     <TestModule as Store>::TestRange,
 //!     <TestModule as Store>::TestMap,
+//!     Capacity,
+//!     Policy,
 //! >;
 //! {
 //!     let mut ring: Box<RingBuffer> = Box::new(Transient::new());
-//!     ring.push(SomeStruct { foo: 1, bar: 2 });
+//!     ring.push(SomeStruct { foo: 1, bar: 2 })?;
 //! } // `ring.commit()` will be called on `drop` here and syncs to storage
 //! ```
 //!
 //! Note: You might want to introduce a helper function that wraps the complex
 //! types and just returns the boxed trait object.
+//!
+//! `KeyedRingBufferTransient` implements the same interface for one of many
+//! queues kept in double maps, so that every key gets its own FIFO queue.
 
 use codec::{Codec, EncodeLike};
-use core::marker::PhantomData;
-use frame_support::storage::{StorageMap, StorageValue};
+use core::{convert::TryInto, iter, marker::PhantomData};
+use frame_support::{
+	storage::{StorageDoubleMap, StorageMap, StorageValue},
+	traits::Get,
+};
+use sp_std::prelude::*;
+
+/// What to do when an item is pushed onto a full ringbuffer
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub enum OverflowPolicy {
+	/// Remove the oldest item to make room for the new one
+	Overwrite,
+	/// Drop the new item
+	Reject,
+	/// Drop the new item and return `BufferFull` from `push`
+	Error,
+}
+
+/// The result of pushing an item onto the ringbuffer
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub enum Pushed<Item> {
+	/// The item was added to the end of the queue
+	Added,
+	/// The item was added after removing the oldest item, which is returned
+	Overwrote(Item),
+	/// The ringbuffer was full, so the item was dropped
+	Rejected,
+}
+
+/// The error returned by `push` if the ringbuffer is full and the policy is `OverflowPolicy::Error`
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct BufferFull;
 
 /// Trait object presenting the ringbuffer interface.
 pub trait RingBufferTrait<Item>
@@ -40,11 +81,23 @@ where
 	/// Implementation note: Call in `drop` to increase ergonomics.
 	fn commit(&self);
 	/// Push an item onto the end of the queue.
-	fn push(&mut self, i: Item);
+	///
+	/// If the queue is full, the result depends on the overflow policy.
+	fn push(&mut self, i: Item) -> Result<Pushed<Item>, BufferFull>;
 	/// Pop an item from the start of the queue.
 	///
 	/// Returns `None` if the queue is empty.
 	fn pop(&mut self) -> Option<Item>;
+	/// Return the item at the start of the queue without removing it.
+	///
+	/// Returns `None` if the queue is empty.
+	fn peek(&self) -> Option<Item>;
+	/// Iterate over the items in the queue, from start to end.
+	fn iter(&self) -> Box<dyn Iterator<Item = Item> + '_>;
+	/// Pop up to `n` items from the start of the queue.
+	fn drain(&mut self, n: usize) -> Vec<Item>;
+	/// Return the number of items in the queue.
+	fn len(&self) -> usize;
 	/// Return whether the queue is empty.
 	fn is_empty(&self) -> bool;
 }
@@ -75,29 +128,39 @@ impl_wrapping_ops!(u64);
 
 type DefaultIdx = u16;
 /// Transient backing data that is the backbone of the trait object.
-pub struct RingBufferTransient<Item, B, M, Index = DefaultIdx>
+///
+/// The indices wrap around at `Index::max_value`, so `Capacity` may be at most
+/// `Index::max_value`.
+pub struct RingBufferTransient<Item, B, M, C, P, Index = DefaultIdx>
 where
 	Item: Codec + EncodeLike,
 	B: StorageValue<(Index, Index), Query = (Index, Index)>,
-	M: StorageMap<Index, Item, Query = Item>,
-	Index: Codec + EncodeLike + Eq + WrappingOps + From<u8> + Copy,
+	M: StorageMap<Index, Item>,
+	M::Query: Into<Option<Item>>,
+	C: Get<Index>,
+	P: Get<OverflowPolicy>,
+	Index: Codec + EncodeLike + Ord + WrappingOps + From<u8> + TryInto<usize> + Copy,
 {
 	start: Index,
 	end: Index,
-	_phantom: PhantomData<(Item, B, M)>,
+	_phantom: PhantomData<(Item, B, M, C, P)>,
 }
 
-impl<Item, B, M, Index> RingBufferTransient<Item, B, M, Index>
+impl<Item, B, M, C, P, Index> RingBufferTransient<Item, B, M, C, P, Index>
 where
 	Item: Codec + EncodeLike,
 	B: StorageValue<(Index, Index), Query = (Index, Index)>,
-	M: StorageMap<Index, Item, Query = Item>,
-	Index: Codec + EncodeLike + Eq + WrappingOps + From<u8> + Copy,
+	M: StorageMap<Index, Item>,
+	M::Query: Into<Option<Item>>,
+	C: Get<Index>,
+	P: Get<OverflowPolicy>,
+	Index: Codec + EncodeLike + Ord + WrappingOps + From<u8> + TryInto<usize> + Copy,
 {
 	/// Create a new `RingBufferTransient` that backs the ringbuffer implementation.
 	///
 	/// Initializes itself from the bounds storage `B`.
-	pub fn new() -> RingBufferTransient<Item, B, M, Index> {
+	#[allow(clippy::new_without_default)]
+	pub fn new() -> RingBufferTransient<Item, B, M, C, P, Index> {
 		let (start, end) = B::get();
 		RingBufferTransient {
 			start,
@@ -105,14 +168,22 @@ where
 			_phantom: PhantomData,
 		}
 	}
+
+	/// The number of items in the queue, as an `Index`
+	fn count(&self) -> Index {
+		self.end.wrapping_sub(self.start)
+	}
 }
 
-impl<Item, B, M, Index> Drop for RingBufferTransient<Item, B, M, Index>
+impl<Item, B, M, C, P, Index> Drop for RingBufferTransient<Item, B, M, C, P, Index>
 where
 	Item: Codec + EncodeLike,
 	B: StorageValue<(Index, Index), Query = (Index, Index)>,
-	M: StorageMap<Index, Item, Query = Item>,
-	Index: Codec + EncodeLike + Eq + WrappingOps + From<u8> + Copy,
+	M: StorageMap<Index, Item>,
+	M::Query: Into<Option<Item>>,
+	C: Get<Index>,
+	P: Get<OverflowPolicy>,
+	Index: Codec + EncodeLike + Ord + WrappingOps + From<u8> + TryInto<usize> + Copy,
 {
 	/// Commit on `drop`.
 	fn drop(&mut self) {
@@ -121,12 +192,15 @@ where
 }
 
 /// Ringbuffer implementation based on `RingBufferTransient`
-impl<Item, B, M, Index> RingBufferTrait<Item> for RingBufferTransient<Item, B, M, Index>
+impl<Item, B, M, C, P, Index> RingBufferTrait<Item> for RingBufferTransient<Item, B, M, C, P, Index>
 where
 	Item: Codec + EncodeLike,
 	B: StorageValue<(Index, Index), Query = (Index, Index)>,
-	M: StorageMap<Index, Item, Query = Item>,
-	Index: Codec + EncodeLike + Eq + WrappingOps + From<u8> + Copy,
+	M: StorageMap<Index, Item>,
+	M::Query: Into<Option<Item>>,
+	C: Get<Index>,
+	P: Get<OverflowPolicy>,
+	Index: Codec + EncodeLike + Ord + WrappingOps + From<u8> + TryInto<usize> + Copy,
 {
 	/// Commit the (potentially) changed bounds to storage.
 	fn commit(&self) {
@@ -136,17 +210,36 @@ where
 	/// Push an item onto the end of the queue.
 	///
 	/// Will insert the new item, but will not update the bounds in storage.
-	fn push(&mut self, item: Item) {
+	fn push(&mut self, item: Item) -> Result<Pushed<Item>, BufferFull> {
+		let capacity = C::get();
+		if capacity == 0.into() {
+			// there is no room for any item, not even by overwriting
+			return match P::get() {
+				OverflowPolicy::Error => Err(BufferFull),
+				_ => Ok(Pushed::Rejected),
+			};
+		}
+
+		let mut result = Pushed::Added;
+		if self.count() >= capacity {
+			match P::get() {
+				// make room by removing the oldest item in the FIFO ringbuffer
+				OverflowPolicy::Overwrite => {
+					if let Some(oldest) = self.pop() {
+						result = Pushed::Overwrote(oldest);
+					}
+				}
+				OverflowPolicy::Reject => return Ok(Pushed::Rejected),
+				OverflowPolicy::Error => return Err(BufferFull),
+			}
+		}
+
 		M::insert(self.end, item);
 		// this will intentionally overflow and wrap around when bonds_end
 		// reaches `Index::max_value` because we want a ringbuffer.
-		let next_index = self.end.wrapping_add(1.into());
-		if next_index == self.start {
-			// queue presents as empty but is not
-			// --> overwrite the oldest item in the FIFO ringbuffer
-			self.start = self.start.wrapping_add(1.into());
-		}
-		self.end = next_index;
+		self.end = self.end.wrapping_add(1.into());
+
+		Ok(result)
 	}
 
 	/// Pop an item from the start of the queue.
@@ -162,6 +255,231 @@ where
 		item.into()
 	}
 
+	/// Return the item at the start of the queue without removing it.
+	fn peek(&self) -> Option<Item> {
+		if self.is_empty() {
+			return None;
+		}
+		M::get(self.start).into()
+	}
+
+	/// Iterate over the items in the queue, reading each one from storage as it is reached.
+	fn iter(&self) -> Box<dyn Iterator<Item = Item> + '_> {
+		let indices =
+			iter::successors(Some(self.start), |index| Some(index.wrapping_add(1.into())));
+		Box::new(
+			indices
+				.take(self.len())
+				.filter_map(|index| M::get(index).into()),
+		)
+	}
+
+	/// Pop up to `n` items from the start of the queue.
+	///
+	/// Will remove the items, but will not update the bounds in storage.
+	fn drain(&mut self, n: usize) -> Vec<Item> {
+		iter::from_fn(|| self.pop()).take(n).collect()
+	}
+
+	/// Return the number of items in the queue.
+	fn len(&self) -> usize {
+		// the length is at most the capacity, which is small enough for a `usize` in practice
+		self.count().try_into().unwrap_or(usize::max_value())
+	}
+
+	/// Return whether to consider the queue empty.
+	fn is_empty(&self) -> bool {
+		self.start == self.end
+	}
+}
+
+/// Transient backing data of one of many ringbuffers that share their storage items.
+///
+/// The bounds of each queue are stored in `B` under `(key1, key2)` and its items
+/// are stored in `M` under `(key1, (key2, index))`. Because all queues of a
+/// `key1` share the first key, they can be removed at once with `remove_prefix`.
+///
+/// The bounds are removed from storage when the queue is committed empty, so an
+/// empty queue takes up no storage at all.
+pub struct KeyedRingBufferTransient<K1, K2, Item, B, M, C, P, Index = DefaultIdx>
+where
+	K1: Codec + EncodeLike + Clone,
+	K2: Codec + EncodeLike + Clone,
+	Item: Codec + EncodeLike,
+	B: StorageDoubleMap<K1, K2, (Index, Index), Query = (Index, Index)>,
+	M: StorageDoubleMap<K1, (K2, Index), Item>,
+	M::Query: Into<Option<Item>>,
+	C: Get<Index>,
+	P: Get<OverflowPolicy>,
+	Index: Codec + EncodeLike + Ord + WrappingOps + From<u8> + TryInto<usize> + Copy,
+{
+	key1: K1,
+	key2: K2,
+	start: Index,
+	end: Index,
+	_phantom: PhantomData<(Item, B, M, C, P)>,
+}
+
+impl<K1, K2, Item, B, M, C, P, Index> KeyedRingBufferTransient<K1, K2, Item, B, M, C, P, Index>
+where
+	K1: Codec + EncodeLike + Clone,
+	K2: Codec + EncodeLike + Clone,
+	Item: Codec + EncodeLike,
+	B: StorageDoubleMap<K1, K2, (Index, Index), Query = (Index, Index)>,
+	M: StorageDoubleMap<K1, (K2, Index), Item>,
+	M::Query: Into<Option<Item>>,
+	C: Get<Index>,
+	P: Get<OverflowPolicy>,
+	Index: Codec + EncodeLike + Ord + WrappingOps + From<u8> + TryInto<usize> + Copy,
+{
+	/// Create a new `KeyedRingBufferTransient` for the queue at `(key1, key2)`.
+	///
+	/// Initializes itself from the bounds storage `B`.
+	pub fn new(key1: K1, key2: K2) -> KeyedRingBufferTransient<K1, K2, Item, B, M, C, P, Index> {
+		let (start, end) = B::get(&key1, &key2);
+		KeyedRingBufferTransient {
+			key1,
+			key2,
+			start,
+			end,
+			_phantom: PhantomData,
+		}
+	}
+
+	/// The number of items in the queue, as an `Index`
+	fn count(&self) -> Index {
+		self.end.wrapping_sub(self.start)
+	}
+
+	/// The key of the item at `index` in the item storage `M`
+	fn item_key(&self, index: Index) -> (K2, Index) {
+		(self.key2.clone(), index)
+	}
+}
+
+impl<K1, K2, Item, B, M, C, P, Index> Drop
+	for KeyedRingBufferTransient<K1, K2, Item, B, M, C, P, Index>
+where
+	K1: Codec + EncodeLike + Clone,
+	K2: Codec + EncodeLike + Clone,
+	Item: Codec + EncodeLike,
+	B: StorageDoubleMap<K1, K2, (Index, Index), Query = (Index, Index)>,
+	M: StorageDoubleMap<K1, (K2, Index), Item>,
+	M::Query: Into<Option<Item>>,
+	C: Get<Index>,
+	P: Get<OverflowPolicy>,
+	Index: Codec + EncodeLike + Ord + WrappingOps + From<u8> + TryInto<usize> + Copy,
+{
+	/// Commit on `drop`.
+	fn drop(&mut self) {
+		<Self as RingBufferTrait<Item>>::commit(self);
+	}
+}
+
+/// Ringbuffer implementation based on `KeyedRingBufferTransient`
+impl<K1, K2, Item, B, M, C, P, Index> RingBufferTrait<Item>
+	for KeyedRingBufferTransient<K1, K2, Item, B, M, C, P, Index>
+where
+	K1: Codec + EncodeLike + Clone,
+	K2: Codec + EncodeLike + Clone,
+	Item: Codec + EncodeLike,
+	B: StorageDoubleMap<K1, K2, (Index, Index), Query = (Index, Index)>,
+	M: StorageDoubleMap<K1, (K2, Index), Item>,
+	M::Query: Into<Option<Item>>,
+	C: Get<Index>,
+	P: Get<OverflowPolicy>,
+	Index: Codec + EncodeLike + Ord + WrappingOps + From<u8> + TryInto<usize> + Copy,
+{
+	/// Commit the (potentially) changed bounds to storage, or remove them if the
+	/// queue is empty.
+	///
+	/// The bounds of an empty queue are removed whatever their value, so the
+	/// indices of a queue start at zero again once it has been emptied.
+	fn commit(&self) {
+		if self.is_empty() {
+			B::remove(&self.key1, &self.key2);
+		} else {
+			B::insert(&self.key1, &self.key2, (self.start, self.end));
+		}
+	}
+
+	/// Push an item onto the end of the queue.
+	///
+	/// Will insert the new item, but will not update the bounds in storage.
+	fn push(&mut self, item: Item) -> Result<Pushed<Item>, BufferFull> {
+		let capacity = C::get();
+		if capacity == 0.into() {
+			// there is no room for any item, not even by overwriting
+			return match P::get() {
+				OverflowPolicy::Error => Err(BufferFull),
+				_ => Ok(Pushed::Rejected),
+			};
+		}
+
+		let mut result = Pushed::Added;
+		if self.count() >= capacity {
+			match P::get() {
+				// make room by removing the oldest item in the FIFO ringbuffer
+				OverflowPolicy::Overwrite => {
+					if let Some(oldest) = self.pop() {
+						result = Pushed::Overwrote(oldest);
+					}
+				}
+				OverflowPolicy::Reject => return Ok(Pushed::Rejected),
+				OverflowPolicy::Error => return Err(BufferFull),
+			}
+		}
+
+		M::insert(&self.key1, self.item_key(self.end), item);
+		self.end = self.end.wrapping_add(1.into());
+
+		Ok(result)
+	}
+
+	/// Pop an item from the start of the queue.
+	///
+	/// Will remove the item, but will not update the bounds in storage.
+	fn pop(&mut self) -> Option<Item> {
+		if self.is_empty() {
+			return None;
+		}
+		let item = M::take(&self.key1, self.item_key(self.start));
+		self.start = self.start.wrapping_add(1.into());
+
+		item.into()
+	}
+
+	/// Return the item at the start of the queue without removing it.
+	fn peek(&self) -> Option<Item> {
+		if self.is_empty() {
+			return None;
+		}
+		M::get(&self.key1, self.item_key(self.start)).into()
+	}
+
+	/// Iterate over the items in the queue, reading each one from storage as it is reached.
+	fn iter(&self) -> Box<dyn Iterator<Item = Item> + '_> {
+		let indices =
+			iter::successors(Some(self.start), |index| Some(index.wrapping_add(1.into())));
+		Box::new(
+			indices
+				.take(self.len())
+				.filter_map(move |index| M::get(&self.key1, self.item_key(index)).into()),
+		)
+	}
+
+	/// Pop up to `n` items from the start of the queue.
+	///
+	/// Will remove the items, but will not update the bounds in storage.
+	fn drain(&mut self, n: usize) -> Vec<Item> {
+		iter::from_fn(|| self.pop()).take(n).collect()
+	}
+
+	/// Return the number of items in the queue.
+	fn len(&self) -> usize {
+		self.count().try_into().unwrap_or(usize::max_value())
+	}
+
 	/// Return whether to consider the queue empty.
 	fn is_empty(&self) -> bool {
 		self.start == self.end
@@ -204,6 +522,17 @@ mod tests {
 
 	type TestIdx = u8;
 
+	parameter_types! {
+		pub const TestCapacity: TestIdx = TestIdx::max_value();
+		pub const SmallCapacity: TestIdx = 3;
+		pub const Capacity8: u8 = 4;
+		pub const Capacity16: u16 = 4;
+		pub const Capacity32: u32 = 4;
+		pub const Overwrite: OverflowPolicy = OverflowPolicy::Overwrite;
+		pub const Reject: OverflowPolicy = OverflowPolicy::Reject;
+		pub const Error: OverflowPolicy = OverflowPolicy::Error;
+	}
+
 	#[derive(Clone, PartialEq, Encode, Decode, Default, Debug)]
 	pub struct SomeStruct {
 		foo: u64,
@@ -214,6 +543,16 @@ mod tests {
 		trait Store for Module<T: Trait> as RingBufferTest {
 			TestMap get(fn get_test_value): map hasher(twox_64_concat) TestIdx => SomeStruct;
 			TestRange get(fn get_test_range): (TestIdx, TestIdx) = (0, 0);
+			TestMap16 get(fn get_test_value_16): map hasher(twox_64_concat) u16 => SomeStruct;
+			TestRange16 get(fn get_test_range_16): (u16, u16) = (0, 0);
+			TestMap32 get(fn get_test_value_32): map hasher(twox_64_concat) u32 => SomeStruct;
+			TestRange32 get(fn get_test_range_32): (u32, u32) = (0, 0);
+			KeyedMap get(fn get_keyed_value):
+				double_map hasher(twox_64_concat) u64, hasher(twox_64_concat) (u8, TestIdx)
+				=> SomeStruct;
+			KeyedRange get(fn get_keyed_range):
+				double_map hasher(twox_64_concat) u64, hasher(twox_64_concat) u8
+				=> (TestIdx, TestIdx);
 		}
 	}
 
@@ -275,14 +614,66 @@ mod tests {
 		SomeStruct,
 		<TestModule as Store>::TestRange,
 		<TestModule as Store>::TestMap,
+		TestCapacity,
+		Overwrite,
+		TestIdx,
+	>;
+	// Small ringbuffers with each overflow policy.
+	type SmallTransient<Policy> = RingBufferTransient<
+		SomeStruct,
+		<TestModule as Store>::TestRange,
+		<TestModule as Store>::TestMap,
+		SmallCapacity,
+		Policy,
 		TestIdx,
 	>;
+	// Ringbuffers with a capacity of four and each index type.
+	type Transient8 = RingBufferTransient<
+		SomeStruct,
+		<TestModule as Store>::TestRange,
+		<TestModule as Store>::TestMap,
+		Capacity8,
+		Overwrite,
+		u8,
+	>;
+	type Transient16 = RingBufferTransient<
+		SomeStruct,
+		<TestModule as Store>::TestRange16,
+		<TestModule as Store>::TestMap16,
+		Capacity16,
+		Overwrite,
+		u16,
+	>;
+	type Transient32 = RingBufferTransient<
+		SomeStruct,
+		<TestModule as Store>::TestRange32,
+		<TestModule as Store>::TestMap32,
+		Capacity32,
+		Overwrite,
+		u32,
+	>;
+
+	// Small ringbuffers keyed by an account and a queue id.
+	type KeyedTransient = KeyedRingBufferTransient<
+		u64,
+		u8,
+		SomeStruct,
+		<TestModule as Store>::KeyedRange,
+		<TestModule as Store>::KeyedMap,
+		SmallCapacity,
+		Overwrite,
+		TestIdx,
+	>;
+
+	fn item(bar: u64) -> SomeStruct {
+		SomeStruct { foo: 42, bar }
+	}
 
 	#[test]
 	fn simple_push() {
 		new_test_ext().execute_with(|| {
 			let mut ring: Box<RingBuffer> = Box::new(Transient::new());
-			ring.push(SomeStruct { foo: 1, bar: 2 });
+			assert_eq!(ring.push(SomeStruct { foo: 1, bar: 2 }), Ok(Pushed::Added));
 			ring.commit();
 			let start_end = TestModule::get_test_range();
 			assert_eq!(start_end, (0, 1));
@@ -296,7 +687,7 @@ mod tests {
 		new_test_ext().execute_with(|| {
 			{
 				let mut ring: Box<RingBuffer> = Box::new(Transient::new());
-				ring.push(SomeStruct { foo: 1, bar: 2 });
+				assert_eq!(ring.push(SomeStruct { foo: 1, bar: 2 }), Ok(Pushed::Added));
 			}
 			let start_end = TestModule::get_test_range();
 			assert_eq!(start_end, (0, 1));
@@ -309,7 +700,7 @@ mod tests {
 	fn simple_pop() {
 		new_test_ext().execute_with(|| {
 			let mut ring: Box<RingBuffer> = Box::new(Transient::new());
-			ring.push(SomeStruct { foo: 1, bar: 2 });
+			assert_eq!(ring.push(SomeStruct { foo: 1, bar: 2 }), Ok(Pushed::Added));
 
 			let item = ring.pop();
 			ring.commit();
@@ -325,7 +716,7 @@ mod tests {
 			let mut ring: Box<RingBuffer> = Box::new(Transient::new());
 
 			for i in 1..(TestIdx::max_value() as u64) + 2 {
-				ring.push(SomeStruct { foo: 42, bar: i });
+				assert!(ring.push(SomeStruct { foo: 42, bar: i }).is_ok());
 			}
 			ring.commit();
 			let start_end = TestModule::get_test_range();
@@ -356,11 +747,173 @@ mod tests {
 			);
 
 			for i in 1..4 {
-				ring.push(SomeStruct { foo: 21, bar: i });
+				assert!(ring.push(SomeStruct { foo: 21, bar: i }).is_ok());
 			}
 			ring.commit();
 			let start_end = TestModule::get_test_range();
 			assert_eq!(start_end, (4, 3));
 		})
 	}
+
+	#[test]
+	fn overwrite_returns_oldest_item() {
+		new_test_ext().execute_with(|| {
+			let mut ring = SmallTransient::<Overwrite>::new();
+			for i in 1..4 {
+				assert_eq!(ring.push(item(i)), Ok(Pushed::Added));
+			}
+			assert_eq!(ring.push(item(4)), Ok(Pushed::Overwrote(item(1))));
+			assert_eq!(ring.len(), 3);
+			assert_eq!(
+				ring.iter().map(|i| i.bar).collect::<Vec<_>>(),
+				vec![2, 3, 4]
+			);
+		})
+	}
+
+	#[test]
+	fn reject_drops_new_item() {
+		new_test_ext().execute_with(|| {
+			let mut ring = SmallTransient::<Reject>::new();
+			for i in 1..4 {
+				assert_eq!(ring.push(item(i)), Ok(Pushed::Added));
+			}
+			assert_eq!(ring.push(item(4)), Ok(Pushed::Rejected));
+			assert_eq!(ring.len(), 3);
+			assert_eq!(
+				ring.iter().map(|i| i.bar).collect::<Vec<_>>(),
+				vec![1, 2, 3]
+			);
+
+			// there is room again after a pop
+			assert_eq!(ring.pop(), Some(item(1)));
+			assert_eq!(ring.push(item(5)), Ok(Pushed::Added));
+		})
+	}
+
+	#[test]
+	fn error_policy_returns_error() {
+		new_test_ext().execute_with(|| {
+			let mut ring = SmallTransient::<Error>::new();
+			for i in 1..4 {
+				assert_eq!(ring.push(item(i)), Ok(Pushed::Added));
+			}
+			assert_eq!(ring.push(item(4)), Err(BufferFull));
+			ring.commit();
+			assert_eq!(TestModule::get_test_range(), (0, 3));
+		})
+	}
+
+	#[test]
+	fn peek_iter_and_drain() {
+		new_test_ext().execute_with(|| {
+			let mut ring: Box<RingBuffer> = Box::new(Transient::new());
+			assert_eq!(ring.peek(), None);
+			assert_eq!(ring.len(), 0);
+
+			for i in 1..6 {
+				assert!(ring.push(item(i)).is_ok());
+			}
+			// peeking and iterating leave the queue untouched
+			assert_eq!(ring.peek(), Some(item(1)));
+			assert_eq!(ring.iter().count(), 5);
+			assert_eq!(ring.len(), 5);
+
+			assert_eq!(ring.drain(2), vec![item(1), item(2)]);
+			assert_eq!(ring.peek(), Some(item(3)));
+
+			// draining more items than there are empties the queue
+			assert_eq!(ring.drain(10), vec![item(3), item(4), item(5)]);
+			assert!(ring.is_empty());
+			ring.commit();
+			assert_eq!(TestModule::get_test_range(), (5, 5));
+		})
+	}
+
+	// Fills a ringbuffer with a capacity of four whose indices start two below the
+	// largest index, so the indices wrap around while the ringbuffer is full.
+	macro_rules! wraparound_test {
+		($name:ident, $transient:ty, $range:ident, $index:ty) => {
+			#[test]
+			fn $name() {
+				new_test_ext().execute_with(|| {
+					let start = <$index>::max_value() - 2;
+					<TestModule as Store>::$range::put((start, start));
+
+					let mut ring = <$transient>::new();
+					for i in 1..5 {
+						assert_eq!(ring.push(item(i)), Ok(Pushed::Added));
+					}
+					assert_eq!(ring.push(item(5)), Ok(Pushed::Overwrote(item(1))));
+					assert_eq!(ring.push(item(6)), Ok(Pushed::Overwrote(item(2))));
+					assert_eq!(ring.len(), 4);
+					assert_eq!(ring.peek(), Some(item(3)));
+					let bars: Vec<_> = ring.iter().map(|i| i.bar).collect();
+					assert_eq!(bars, vec![3, 4, 5, 6]);
+
+					ring.commit();
+					assert_eq!(
+						<TestModule as Store>::$range::get(),
+						(<$index>::max_value(), 3)
+					);
+
+					assert_eq!(ring.drain(2), vec![item(3), item(4)]);
+					ring.commit();
+					assert_eq!(<TestModule as Store>::$range::get(), (1, 3));
+				})
+			}
+		};
+	}
+
+	wraparound_test!(wraparound_at_capacity_u8, Transient8, TestRange, u8);
+	wraparound_test!(wraparound_at_capacity_u16, Transient16, TestRange16, u16);
+	wraparound_test!(wraparound_at_capacity_u32, Transient32, TestRange32, u32);
+
+	#[test]
+	fn keyed_queues_are_independent() {
+		new_test_ext().execute_with(|| {
+			{
+				let mut first: Box<RingBuffer> = Box::new(KeyedTransient::new(1, 0));
+				let mut second: Box<RingBuffer> = Box::new(KeyedTransient::new(1, 1));
+				let mut other: Box<RingBuffer> = Box::new(KeyedTransient::new(2, 0));
+				for i in 1..5 {
+					assert!(first.push(item(i)).is_ok());
+				}
+				assert_eq!(second.push(item(10)), Ok(Pushed::Added));
+				assert_eq!(other.push(item(20)), Ok(Pushed::Added));
+
+				// the capacity applies to each queue on its own
+				assert_eq!(first.len(), 3);
+				assert_eq!(first.peek(), Some(item(2)));
+				assert_eq!(second.pop(), Some(item(10)));
+			}
+			assert_eq!(TestModule::get_keyed_range(1, 0), (1, 4));
+			assert_eq!(TestModule::get_keyed_value(1, (0, 1)), item(2));
+			assert_eq!(TestModule::get_keyed_range(2, 0), (0, 1));
+
+			let ring = KeyedTransient::new(2, 0);
+			assert_eq!(ring.iter().collect::<Vec<_>>(), vec![item(20)]);
+		})
+	}
+
+	#[test]
+	fn keyed_queue_is_removed_when_empty() {
+		new_test_ext().execute_with(|| {
+			{
+				let mut ring = KeyedTransient::new(1, 0);
+				for i in 1..3 {
+					assert!(ring.push(item(i)).is_ok());
+				}
+			}
+			assert!(<TestModule as Store>::KeyedRange::contains_key(1, 0));
+
+			{
+				let mut ring = KeyedTransient::new(1, 0);
+				assert_eq!(ring.drain(5), vec![item(1), item(2)]);
+			}
+			assert!(!<TestModule as Store>::KeyedRange::contains_key(1, 0));
+			assert!(!<TestModule as Store>::KeyedMap::contains_key(1, (0, 0)));
+			assert!(!<TestModule as Store>::KeyedMap::contains_key(1, (0, 1)));
+		})
+	}
 }
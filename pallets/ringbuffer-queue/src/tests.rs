@@ -1,6 +1,12 @@
-use super::{RawEvent, ValueStruct};
-use crate::{Module, Trait};
-use frame_support::{assert_ok, impl_outer_event, impl_outer_origin, parameter_types};
+use super::{OverflowPolicy, QueueId, RawEvent, ValueStruct};
+use crate::{Error, Module, Trait};
+use balances;
+use frame_support::{
+	assert_noop, assert_ok,
+	dispatch::DispatchResult,
+	impl_outer_event, impl_outer_origin, parameter_types,
+	traits::{Get, ReservableCurrency},
+};
 use frame_system as system;
 use sp_core::H256;
 use sp_io::TestExternalities;
@@ -9,6 +15,7 @@ use sp_runtime::{
 	traits::{BlakeTwo256, IdentityLookup},
 	Perbill,
 };
+use std::cell::Cell;
 
 impl_outer_origin! {
 	pub enum Origin for TestRuntime {}
@@ -22,6 +29,8 @@ parameter_types! {
 	pub const MaximumBlockWeight: u32 = 1024;
 	pub const MaximumBlockLength: u32 = 2 * 1024;
 	pub const AvailableBlockRatio: Perbill = Perbill::one();
+
+	pub const ExistentialDeposit: u64 = 1;
 }
 impl system::Trait for TestRuntime {
 	type BaseCallFilter = ();
@@ -45,12 +54,22 @@ impl system::Trait for TestRuntime {
 	type AvailableBlockRatio = AvailableBlockRatio;
 	type Version = ();
 	type PalletInfo = ();
-	type AccountData = ();
+	type AccountData = balances::AccountData<u64>;
 	type OnNewAccount = ();
 	type OnKilledAccount = ();
 	type SystemWeightInfo = ();
 }
 
+impl balances::Trait for TestRuntime {
+	type Balance = u64;
+	type MaxLocks = ();
+	type Event = TestEvent;
+	type DustRemoval = ();
+	type ExistentialDeposit = ExistentialDeposit;
+	type AccountStore = system::Module<TestRuntime>;
+	type WeightInfo = ();
+}
+
 mod ringbuffer {
 	pub use crate::Event;
 }
@@ -59,23 +78,56 @@ impl_outer_event! {
 	pub enum TestEvent for TestRuntime {
 		ringbuffer<T>,
 		system<T>,
+		balances<T>,
 	}
 }
 
+parameter_types! {
+	pub const Capacity: u16 = 3;
+	pub const QueueDeposit: u64 = 5;
+}
+
+thread_local! {
+	static POLICY: Cell<OverflowPolicy> = Cell::new(OverflowPolicy::Overwrite);
+}
+
+/// An overflow policy that each test may change
+pub struct TestPolicy;
+impl Get<OverflowPolicy> for TestPolicy {
+	fn get() -> OverflowPolicy {
+		POLICY.with(|policy| policy.get())
+	}
+}
+
+fn set_policy(policy: OverflowPolicy) {
+	POLICY.with(|p| p.set(policy));
+}
+
 impl Trait for TestRuntime {
 	type Event = TestEvent;
+	type Capacity = Capacity;
+	type OverflowPolicy = TestPolicy;
+	type Currency = balances::Module<Self>;
+	type QueueDeposit = QueueDeposit;
 }
 
 pub type System = system::Module<TestRuntime>;
+pub type Balances = balances::Module<TestRuntime>;
 pub type RingBuffer = Module<TestRuntime>;
 
 struct ExternalityBuilder;
 
 impl ExternalityBuilder {
 	pub fn build() -> TestExternalities {
-		let storage = system::GenesisConfig::default()
+		let mut storage = system::GenesisConfig::default()
 			.build_storage::<TestRuntime>()
 			.unwrap();
+		balances::GenesisConfig::<TestRuntime> {
+			// Accounts 1 and 2 can pay for two queue deposits, account 3 for none
+			balances: vec![(1, 11), (2, 11), (3, 4)],
+		}
+		.assimilate_storage(&mut storage)
+		.unwrap();
 		let mut ext = TestExternalities::from(storage);
 		ext.execute_with(|| System::set_block_number(1));
 		ext
@@ -140,3 +192,132 @@ fn pop_works() {
 		);
 	})
 }
+
+#[test]
+fn overwrite_policy_removes_oldest_item() {
+	ExternalityBuilder::build().execute_with(|| {
+		set_policy(OverflowPolicy::Overwrite);
+		assert_ok!(RingBuffer::add_multiple(
+			Origin::signed(1),
+			vec![1, 2, 3, 4],
+			true
+		));
+		assert_eq!(RingBuffer::range(), (1, 4));
+		assert_eq!(
+			System::events()[0].event,
+			TestEvent::ringbuffer(RawEvent::Overwritten(1, true))
+		);
+	})
+}
+
+#[test]
+fn reject_policy_drops_new_item() {
+	ExternalityBuilder::build().execute_with(|| {
+		set_policy(OverflowPolicy::Reject);
+		assert_ok!(RingBuffer::add_multiple(
+			Origin::signed(1),
+			vec![1, 2, 3, 4],
+			true
+		));
+		assert_eq!(RingBuffer::range(), (0, 3));
+		assert_eq!(
+			System::events()[0].event,
+			TestEvent::ringbuffer(RawEvent::Rejected(4, true))
+		);
+	})
+}
+
+#[test]
+fn error_policy_fails_when_full() {
+	ExternalityBuilder::build().execute_with(|| {
+		set_policy(OverflowPolicy::Error);
+
+		// None of the items are added if they do not all fit
+		assert_noop!(
+			RingBuffer::add_multiple(Origin::signed(1), vec![1, 2, 3, 4], true),
+			Error::<TestRuntime>::QueueFull
+		);
+
+		assert_ok!(RingBuffer::add_multiple(
+			Origin::signed(1),
+			vec![1, 2, 3],
+			true
+		));
+		assert_noop!(
+			RingBuffer::add_to_queue(Origin::signed(1), 4, true),
+			Error::<TestRuntime>::QueueFull
+		);
+		assert_eq!(RingBuffer::range(), (0, 3));
+	})
+}
+
+/// Adds an item with the given integer to a keyed queue of `owner`
+fn add_keyed(owner: u64, id: QueueId, integer: i32) -> DispatchResult {
+	RingBuffer::add_to_keyed_queue(Origin::signed(owner), id, integer, true)
+}
+
+fn pop_keyed(owner: u64, id: QueueId) -> DispatchResult {
+	RingBuffer::pop_from_keyed_queue(Origin::signed(owner), id)
+}
+
+#[test]
+fn keyed_queues_are_independent() {
+	ExternalityBuilder::build().execute_with(|| {
+		assert_ok!(add_keyed(1, 0, 1));
+		assert_ok!(add_keyed(1, 0, 2));
+		assert_ok!(add_keyed(1, 7, 3));
+		assert_ok!(add_keyed(2, 0, 4));
+
+		assert_eq!(RingBuffer::keyed_range(1, 0), (0, 2));
+		assert_eq!(RingBuffer::keyed_range(1, 7), (0, 1));
+		assert_eq!(RingBuffer::keyed_range(2, 0), (0, 1));
+		assert_eq!(
+			RingBuffer::keyed_value(2, (0, 0)),
+			ValueStruct {
+				integer: 4,
+				boolean: true
+			}
+		);
+		// The shared queue is not affected
+		assert_eq!(RingBuffer::range(), (0, 0));
+
+		assert_ok!(pop_keyed(1, 0));
+		assert_eq!(
+			System::events().pop().unwrap().event,
+			TestEvent::ringbuffer(RawEvent::PoppedFrom(1, 0, 1, true))
+		);
+		assert_noop!(pop_keyed(3, 0), Error::<TestRuntime>::NoSuchQueue);
+	})
+}
+
+#[test]
+fn keyed_queue_deposit_is_reserved_and_returned() {
+	ExternalityBuilder::build().execute_with(|| {
+		assert_ok!(add_keyed(1, 0, 1));
+		assert_ok!(add_keyed(1, 0, 2));
+		// Only one deposit is reserved per queue
+		assert_eq!(Balances::reserved_balance(1), 5);
+		assert_eq!(RingBuffer::queue_deposit(1, 0), Some(5));
+
+		assert_ok!(add_keyed(1, 1, 3));
+		assert_eq!(Balances::reserved_balance(1), 10);
+
+		// Emptying a queue returns its deposit and removes it from storage
+		assert_ok!(pop_keyed(1, 0));
+		assert_ok!(pop_keyed(1, 0));
+		assert_eq!(Balances::reserved_balance(1), 5);
+		assert_eq!(RingBuffer::queue_deposit(1, 0), None);
+		assert!(!<crate::KeyedRange<TestRuntime>>::contains_key(1, 0));
+		assert_eq!(
+			System::events().pop().unwrap().event,
+			TestEvent::ringbuffer(RawEvent::QueueRemoved(1, 0, 5))
+		);
+
+		// An account that cannot pay the deposit cannot create a queue
+		assert!(!Balances::can_reserve(&3, 5));
+		assert_noop!(
+			add_keyed(3, 0, 1),
+			balances::Error::<TestRuntime, _>::InsufficientBalance
+		);
+	})
+}
@@ -0,0 +1,126 @@
+//! RPC interface for proving contributions to a crowdfund.
+
+use codec::Encode;
+use jsonrpc_core::{Error as RpcError, ErrorCode, Result};
+use jsonrpc_derive::rpc;
+use sc_client_api::ProofProvider;
+use serde::{Deserialize, Serialize};
+use simple_crowdfund_runtime_api::CrowdfundApi as CrowdfundRuntimeApi;
+use sp_api::ProvideRuntimeApi;
+use sp_blockchain::HeaderBackend;
+use sp_core::{storage::ChildInfo, Bytes};
+use sp_runtime::{generic::BlockId, traits::Block as BlockT};
+use std::sync::Arc;
+
+/// A storage proof of a single contribution to a fund.
+///
+/// The proof can be checked against `root` with `simple_crowdfund::verify_contribution_proof`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContributionProof {
+	/// The root of the fund's child trie
+	pub root: Bytes,
+	/// The trie nodes needed to look up the contribution
+	pub proof: Vec<Bytes>,
+}
+
+#[rpc]
+pub trait CrowdfundApi<BlockHash, AccountId> {
+	/// Returns the child trie root of a fund along with a proof of `who`'s contribution, or
+	/// `None` if the fund does not exist. Proves absence if `who` has not contributed.
+	#[rpc(name = "crowdfund_contributionProof")]
+	fn contribution_proof(
+		&self,
+		index: u32,
+		who: AccountId,
+		at: Option<BlockHash>,
+	) -> Result<Option<ContributionProof>>;
+}
+
+/// A struct that implements the `CrowdfundApi`.
+pub struct Crowdfund<C, M> {
+	client: Arc<C>,
+	_marker: std::marker::PhantomData<M>,
+}
+
+impl<C, M> Crowdfund<C, M> {
+	/// Create new `Crowdfund` instance with the given reference to the client.
+	pub fn new(client: Arc<C>) -> Self {
+		Self {
+			client,
+			_marker: Default::default(),
+		}
+	}
+}
+
+/// Error type of this RPC api.
+pub enum Error {
+	/// The call to runtime failed.
+	RuntimeError,
+	/// The storage proof could not be generated.
+	ProofError,
+}
+
+impl From<Error> for i64 {
+	fn from(e: Error) -> i64 {
+		match e {
+			Error::RuntimeError => 1,
+			Error::ProofError => 2,
+		}
+	}
+}
+
+fn rpc_error(error: Error, message: &str, e: impl std::fmt::Debug) -> RpcError {
+	RpcError {
+		code: ErrorCode::ServerError(error.into()),
+		message: message.into(),
+		data: Some(format!("{:?}", e).into()),
+	}
+}
+
+impl<C, Block, AccountId> CrowdfundApi<<Block as BlockT>::Hash, AccountId> for Crowdfund<C, Block>
+where
+	Block: BlockT,
+	AccountId: Encode,
+	C: Send + Sync + 'static,
+	C: ProvideRuntimeApi<Block>,
+	C: HeaderBackend<Block>,
+	C: ProofProvider<Block>,
+	C::Api: CrowdfundRuntimeApi<Block>,
+{
+	fn contribution_proof(
+		&self,
+		index: u32,
+		who: AccountId,
+		at: Option<<Block as BlockT>::Hash>,
+	) -> Result<Option<ContributionProof>> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(||
+			// If the block hash is not supplied assume the best block.
+			self.client.info().best_hash));
+
+		let query_error = |e| rpc_error(Error::RuntimeError, "Unable to query the fund's child trie.", e);
+		let trie_id = api.fund_trie_id(&at, index).map_err(query_error)?;
+		let root = api.fund_trie_root(&at, index).map_err(query_error)?;
+		let (trie_id, root) = match (trie_id, root) {
+			(Some(trie_id), Some(root)) => (trie_id, root),
+			_ => return Ok(None),
+		};
+
+		// Contributions are keyed by the SCALE encoded account id, see `contribution_put`
+		let key = who.encode();
+		let proof = self
+			.client
+			.read_child_proof(
+				&at,
+				&ChildInfo::new_default(&trie_id),
+				&mut std::iter::once(key.as_slice()),
+			)
+			.map_err(|e| rpc_error(Error::ProofError, "Unable to generate the contribution proof.", e))?;
+
+		Ok(Some(ContributionProof {
+			root: root.into(),
+			proof: proof.iter_nodes().map(Into::into).collect(),
+		}))
+	}
+}
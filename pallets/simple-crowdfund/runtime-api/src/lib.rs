@@ -0,0 +1,17 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+#![allow(clippy::too_many_arguments)]
+#![allow(clippy::unnecessary_mut_passed)]
+
+use sp_std::vec::Vec;
+
+// The runtime API exposes the child trie of each fund so that clients can request and check
+// storage proofs of contributions. It is implemented in the runtime amalgamator file.
+sp_api::decl_runtime_apis! {
+	pub trait CrowdfundApi {
+		/// The unique id of the child trie of a fund, or `None` if the fund does not exist
+		fn fund_trie_id(index: u32) -> Option<Vec<u8>>;
+
+		/// The root of the child trie of a fund, or `None` if the fund does not exist
+		fn fund_trie_root(index: u32) -> Option<Vec<u8>>;
+	}
+}
@@ -3,6 +3,40 @@
 //! This pallet demonstrates a simple on-chain crowdfunding mechanism.
 //! It is based on Polkadot's crowdfund pallet, but is simplified and decoupled
 //! from the parachain logic.
+//!
+//! A fund may optionally declare milestones when it is created. The amounts of the milestones must
+//! add up to the goal. Instead of being dispensed in one go, a successful fund with milestones pays
+//! the beneficiary one tranche at a time. Before each tranche the contributors vote, weighted by
+//! their contribution. If a vote fails, the unreleased funds become refundable to the contributors
+//! pro rata. If the beneficiary does not start the vote on the next tranche within
+//! `MilestoneTimeout`, anyone may start it, so contributors can always reject the remaining
+//! milestones.
+//!
+//! Contributors of an unsuccessful fund may withdraw their contributions until the fund's
+//! `RetirementPeriod` is over. After that, the remaining contributions are refunded automatically
+//! in `on_initialize`, at most `RefundsPerBlock` of them per block. At most
+//! `MaxRetirementsPerBlock` funds retire in the same block; later ones retire in the next block
+//! with room. The refunds walk the fund's child trie with a cursor that is kept in storage between
+//! blocks. Once every contribution is refunded, the fund is removed and the deposit is returned to
+//! the depositor. A refund that fails is recorded in `FailedRefunds` and its funds stay in the fund
+//! account, where the contributor can claim them later with `claim_refund`.
+//!
+//! Each fund is created with `FundOptions`. They can limit how much a single account may contribute,
+//! restrict contributions to the accounts of the `Whitelist` account set, and turn the goal into a
+//! hard cap that `raised` may not exceed. Without a hard cap the goal is a soft cap: the fund
+//! succeeds once it is reached but keeps accepting contributions until it ends. Contributors may
+//! attach a short memo, which is stored next to their contribution in the child trie.
+//!
+//! The options and memos changed the encoding of `FundInfo` and of the contributions in the child
+//! tries. This pallet ships no migration for that change, because the recipe runtimes are only
+//! ever started from a fresh genesis. A live chain would have to rewrite every fund and every
+//! contribution in `on_runtime_upgrade`, guarded by a storage version since the old and new
+//! encodings of a contribution cannot be told apart.
+//!
+//! Contributions can be sold on by transferring them to another account with
+//! `transfer_contribution`. Transfers are possible while a fund is active, during the retirement
+//! period of an unsuccessful fund, and while a fund whose milestone was rejected is refunding.
+//! Refunds and payouts go to whoever holds the contribution at the time.
 
 #![cfg_attr(not(feature = "std"), no_std)]
 
@@ -12,15 +46,19 @@ use frame_support::{
 	traits::{
 		Currency, ExistenceRequirement, Get, ReservableCurrency, WithdrawReason, WithdrawReasons,
 	},
+	weights::Weight,
 };
+use account_set::AccountSetExt;
 use frame_system::{self as system, ensure_signed};
 use parity_scale_codec::{Decode, Encode};
 use sp_core::Hasher;
 use sp_runtime::{
-	traits::{AccountIdConversion, Saturating, Zero},
-	ModuleId,
+	traits::{AccountIdConversion, One, Saturating, Zero},
+	ModuleId, Perbill,
 };
 use sp_std::prelude::*;
+#[cfg(feature = "std")]
+use sp_trie::{read_trie_value, HashDBT, Layout, MemoryDB, EMPTY_PREFIX};
 
 #[cfg(test)]
 mod tests;
@@ -43,8 +81,33 @@ pub trait Trait: system::Trait {
 	type MinContribution: Get<BalanceOf<Self>>;
 
 	/// The period of time (in blocks) after an unsuccessful crowdfund ending during which
-	/// contributors are able to withdraw their funds. After this period, their funds are lost.
+	/// contributors are able to withdraw their funds. After this period, the remaining funds are
+	/// refunded automatically.
 	type RetirementPeriod: Get<Self::BlockNumber>;
+
+	/// The period of time (in blocks) during which contributors may vote on a milestone
+	type MilestoneVotingPeriod: Get<Self::BlockNumber>;
+
+	/// The period of time (in blocks) after the fund ends or a milestone is released during which
+	/// only the beneficiary may start the next milestone vote. After it, anyone may.
+	type MilestoneTimeout: Get<Self::BlockNumber>;
+
+	/// The maximum number of milestones a fund may declare
+	type MaxMilestones: Get<u32>;
+
+	/// The maximum number of contributions refunded automatically in a single block. Bounds the
+	/// weight of `on_initialize`.
+	type RefundsPerBlock: Get<u32>;
+
+	/// The maximum number of funds that retire in a single block. A fund whose retirement block
+	/// is full retires in the next block with room instead.
+	type MaxRetirementsPerBlock: Get<u32>;
+
+	/// The accounts allowed to contribute to funds that are restricted to a whitelist
+	type Whitelist: AccountSetExt<AccountId = Self::AccountId>;
+
+	/// The maximum length in bytes of a contributor's memo
+	type MaxMemoLength: Get<u32>;
 }
 
 /// Simple index for identifying a fund.
@@ -53,20 +116,81 @@ pub type FundIndex = u32;
 type AccountIdOf<T> = <T as system::Trait>::AccountId;
 type BalanceOf<T> = <<T as Trait>::Currency as Currency<AccountIdOf<T>>>::Balance;
 type FundInfoOf<T> = FundInfo<AccountIdOf<T>, BalanceOf<T>, <T as system::Trait>::BlockNumber>;
+type MilestoneInfoOf<T> = MilestoneInfo<BalanceOf<T>, <T as system::Trait>::BlockNumber>;
+type ContributionOf<T> = Contribution<BalanceOf<T>>;
+
+/// Options chosen by the creator of a fund
+#[derive(Encode, Decode, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct FundOptions<Balance> {
+	/// The most a single account may contribute in total, if limited
+	pub max_contribution: Option<Balance>,
+	/// Whether only members of the `Whitelist` account set may contribute
+	pub whitelisted: bool,
+	/// Whether `raised` may not exceed the goal. Otherwise the goal is a soft cap that only
+	/// decides whether the fund is successful.
+	pub hard_cap: bool,
+}
+
+/// A contributor's record in the child trie of a fund
+#[derive(Encode, Decode, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct Contribution<Balance> {
+	/// The total amount contributed
+	pub amount: Balance,
+	/// A short note left by the contributor
+	pub memo: Vec<u8>,
+}
 
 #[derive(Encode, Decode, Default, PartialEq, Eq)]
 #[cfg_attr(feature = "std", derive(Debug))]
 pub struct FundInfo<AccountId, Balance, BlockNumber> {
 	/// The account that will recieve the funds if the campaign is successful
 	beneficiary: AccountId,
+	/// The account that placed the deposit
+	depositor: AccountId,
 	/// The amount of deposit placed
 	deposit: Balance,
 	/// The total amount raised
 	raised: Balance,
 	/// Block number after which funding must have succeeded
 	end: BlockNumber,
-	/// Upper bound on `raised`
+	/// The amount that must be raised for the fund to succeed. Also an upper bound on `raised` if
+	/// the fund has a hard cap.
 	goal: Balance,
+	/// The options chosen by the creator
+	options: FundOptions<Balance>,
+}
+
+/// A contributor vote on the release of the next milestone
+#[derive(Encode, Decode, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct MilestoneVote<Balance, BlockNumber> {
+	/// Block number at which voting ends
+	end: BlockNumber,
+	/// Total contribution of the contributors approving the milestone
+	ayes: Balance,
+	/// Total contribution of the contributors rejecting the milestone
+	nays: Balance,
+}
+
+/// The progress of a fund through its milestones
+#[derive(Encode, Decode, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct MilestoneInfo<Balance, BlockNumber> {
+	/// The amount released at each milestone. The last milestone releases everything that remains.
+	amounts: Vec<Balance>,
+	/// The index of the next milestone to be released
+	next: u32,
+	/// The total amount released to the beneficiary so far
+	released: Balance,
+	/// Block number at which the fund ended or the last milestone was released. The next vote may
+	/// be started by anyone once `MilestoneTimeout` has passed since.
+	waiting_since: BlockNumber,
+	/// The ongoing vote on the next milestone, if any
+	vote: Option<MilestoneVote<Balance, BlockNumber>>,
+	/// Block number at which a milestone was rejected and refunds began
+	refunding_since: Option<BlockNumber>,
 }
 
 decl_storage! {
@@ -78,6 +202,35 @@ decl_storage! {
 		/// The total number of funds that have so far been allocated.
 		FundCount get(fn fund_count): FundIndex;
 
+		/// Milestone progress of the funds that declared milestones.
+		Milestones get(fn milestones):
+			map hasher(blake2_128_concat) FundIndex => Option<MilestoneInfoOf<T>>;
+
+		/// Contributors that have voted on a milestone, keyed by fund and milestone index.
+		MilestoneVoters get(fn milestone_voters):
+			double_map hasher(blake2_128_concat) (FundIndex, u32), hasher(blake2_128_concat) T::AccountId => bool;
+
+		/// The funds that retire at each block, at most `MaxRetirementsPerBlock` of them.
+		Retirements get(fn retirements):
+			map hasher(twox_64_concat) T::BlockNumber => Vec<FundIndex>;
+
+		/// The head and tail of the refund queue. The queue holds the positions from the head up
+		/// to, but not including, the tail.
+		RefundRange get(fn refund_range): (u32, u32);
+
+		/// Unsuccessful funds whose remaining contributions are being refunded, keyed by their
+		/// position in the refund queue.
+		RefundQueue get(fn refund_queue): map hasher(twox_64_concat) u32 => Option<FundIndex>;
+
+		/// The child trie key of the last contribution refunded from the fund at the head of the
+		/// refund queue. Empty when refunds of that fund have not yet started.
+		RefundCursor get(fn refund_cursor): Vec<u8>;
+
+		/// Automatic refunds that failed and may be claimed by the contributor, keyed by fund and
+		/// contributor. The funds stay in the fund account until they are claimed.
+		FailedRefunds get(fn failed_refunds):
+			double_map hasher(blake2_128_concat) FundIndex, hasher(blake2_128_concat) T::AccountId => BalanceOf<T>;
+
 		// Additional information is stored i na child trie. See the helper
 		// functions in the impl<T: Trait> Module<T> block below
 	}
@@ -95,6 +248,26 @@ decl_event! {
 		Retiring(FundIndex, BlockNumber),
 		Dissolved(FundIndex, BlockNumber, AccountId),
 		Dispensed(FundIndex, BlockNumber, AccountId),
+		/// A vote on a milestone started (fund, milestone, voting end)
+		MilestoneVoteStarted(FundIndex, u32, BlockNumber),
+		/// A contributor voted on a milestone (contributor, fund, approve, vote weight)
+		MilestoneVoted(AccountId, FundIndex, bool, Balance),
+		/// A milestone was approved and paid to the beneficiary (fund, milestone, amount)
+		MilestoneReleased(FundIndex, u32, Balance),
+		/// A milestone was rejected and the remaining funds became refundable (fund, milestone)
+		MilestoneRejected(FundIndex, u32),
+		/// An unsuccessful fund retired and its contributions will be refunded automatically
+		RefundsStarted(FundIndex),
+		/// A contribution was refunded automatically (contributor, fund, amount)
+		Refunded(AccountId, FundIndex, Balance),
+		/// An automatic refund failed and can be claimed later (contributor, fund, amount)
+		RefundFailed(AccountId, FundIndex, Balance),
+		/// A failed refund was claimed (contributor, fund, amount)
+		RefundClaimed(AccountId, FundIndex, Balance),
+		/// All contributions to a fund were refunded and the fund was removed
+		RefundsCompleted(FundIndex),
+		/// Contribution credit was transferred (from, to, fund, amount)
+		ContributionTransferred(AccountId, AccountId, FundIndex, Balance),
 	}
 }
 
@@ -116,6 +289,46 @@ decl_error! {
 		FundNotRetired,
 		/// Cannot dispense funds from an unsuccessful fund
 		UnsuccessfulFund,
+		/// The fund declares more milestones than allowed
+		TooManyMilestones,
+		/// The milestone amounts must add up to the goal
+		MilestonesMismatchGoal,
+		/// The contribution would exceed the per-account maximum of the fund
+		ContributionTooLarge,
+		/// The contribution would take the fund beyond its hard cap
+		HardCapExceeded,
+		/// The fund only accepts contributions from whitelisted accounts
+		NotWhitelisted,
+		/// The memo is longer than `MaxMemoLength`
+		MemoTooLong,
+		/// Funds with milestones are released through milestone votes rather than dispensed
+		HasMilestones,
+		/// The fund does not declare milestones
+		NoMilestones,
+		/// Only the beneficiary may start a milestone vote until `MilestoneTimeout` has passed
+		NotBeneficiary,
+		/// A milestone vote is already in progress
+		VoteInProgress,
+		/// There is no milestone vote in progress
+		NoVoteInProgress,
+		/// The milestone vote has ended
+		VotingPeriodOver,
+		/// The milestone vote has not yet ended
+		VotingPeriodNotOver,
+		/// The contributor has already voted on this milestone
+		AlreadyVoted,
+		/// A milestone was rejected, so no further milestones can be released
+		FundRefunding,
+		/// Unsuccessful funds are refunded and removed automatically rather than dissolved
+		RefundsAutomatic,
+		/// Contributions to the fund can no longer be transferred
+		TransfersClosed,
+		/// The amount exceeds the caller's contribution
+		InsufficientContribution,
+		/// Contributions cannot be transferred to oneself
+		SelfTransfer,
+		/// The caller has no failed refund to claim from this fund
+		NoFailedRefund,
 	}
 }
 
@@ -125,6 +338,12 @@ decl_module! {
 
 		type Error = Error<T>;
 
+		const RefundsPerBlock: u32 = T::RefundsPerBlock::get();
+
+		const MaxRetirementsPerBlock: u32 = T::MaxRetirementsPerBlock::get();
+
+		const MaxMemoLength: u32 = T::MaxMemoLength::get();
+
 		/// Create a new fund
 		#[weight = 10_000]
 		fn create(
@@ -132,11 +351,19 @@ decl_module! {
 			beneficiary: AccountIdOf<T>,
 			goal: BalanceOf<T>,
 			end: T::BlockNumber,
+			milestones: Vec<BalanceOf<T>>,
+			options: FundOptions<BalanceOf<T>>,
 		) {
 			let creator = ensure_signed(origin)?;
 			let now = <system::Module<T>>::block_number();
 
 			ensure!(end > now, Error::<T>::EndTooEarly);
+			ensure!(milestones.len() <= T::MaxMilestones::get() as usize, Error::<T>::TooManyMilestones);
+			if !milestones.is_empty() {
+				let total = milestones.iter()
+					.fold(BalanceOf::<T>::zero(), |total, amount| total.saturating_add(*amount));
+				ensure!(total == goal, Error::<T>::MilestonesMismatchGoal);
+			}
 
 			let deposit = T::SubmissionDeposit::get();
 			let imb = T::Currency::withdraw(
@@ -156,18 +383,29 @@ decl_module! {
 
 			<Funds<T>>::insert(index, FundInfo {
 				beneficiary,
+				depositor: creator,
 				deposit,
 				raised: Zero::zero(),
 				end,
 				goal,
+				options,
 			});
+			if !milestones.is_empty() {
+				<Milestones<T>>::insert(index, MilestoneInfo {
+					amounts: milestones,
+					waiting_since: end,
+					..Default::default()
+				});
+			}
+			Self::schedule_retirement(index, end.saturating_add(T::RetirementPeriod::get()));
 
 			Self::deposit_event(RawEvent::Created(index, now));
 		}
 
-		/// Contribute funds to an existing fund
+		/// Contribute funds to an existing fund. A memo, if given, replaces the caller's previous
+		/// memo for this fund.
 		#[weight = 10_000]
-		fn contribute(origin, index: FundIndex, value: BalanceOf<T>) {
+		fn contribute(origin, index: FundIndex, value: BalanceOf<T>, memo: Option<Vec<u8>>) {
 			let who = ensure_signed(origin)?;
 
 			ensure!(value >= T::MinContribution::get(), Error::<T>::ContributionTooSmall);
@@ -177,6 +415,23 @@ decl_module! {
 			let now = <system::Module<T>>::block_number();
 			ensure!(fund.end > now, Error::<T>::ContributionPeriodOver);
 
+			// Enforce the options of the fund
+			let mut contribution = Self::contribution_info(index, &who);
+			let balance = contribution.amount.saturating_add(value);
+			if let Some(max) = fund.options.max_contribution {
+				ensure!(balance <= max, Error::<T>::ContributionTooLarge);
+			}
+			if fund.options.hard_cap {
+				ensure!(fund.raised.saturating_add(value) <= fund.goal, Error::<T>::HardCapExceeded);
+			}
+			if fund.options.whitelisted {
+				ensure!(T::Whitelist::contains(&who), Error::<T>::NotWhitelisted);
+			}
+			if let Some(memo) = memo {
+				ensure!(memo.len() <= T::MaxMemoLength::get() as usize, Error::<T>::MemoTooLong);
+				contribution.memo = memo;
+			}
+
 			// Add contribution to the fund
 			T::Currency::transfer(
 				&who,
@@ -187,13 +442,59 @@ decl_module! {
 			fund.raised += value;
 			Funds::<T>::insert(index, &fund);
 
-			let balance = Self::contribution_get(index, &who);
-			let balance = balance.saturating_add(value);
-			Self::contribution_put(index, &who, &balance);
+			contribution.amount = balance;
+			Self::contribution_put(index, &who, &contribution);
 
 			Self::deposit_event(RawEvent::Contributed(who, index, balance, now));
 		}
 
+		/// Transfer part or all of the caller's contribution to another account. The recipient must
+		/// satisfy the fund's whitelist and per-account maximum. The caller keeps their memo. Both
+		/// the amount moved and the amount left behind, unless it is zero, must be at least
+		/// `MinContribution`.
+		#[weight = 10_000]
+		fn transfer_contribution(
+			origin,
+			index: FundIndex,
+			to: AccountIdOf<T>,
+			amount: BalanceOf<T>,
+		) {
+			let who = ensure_signed(origin)?;
+			ensure!(who != to, Error::<T>::SelfTransfer);
+
+			let fund = Self::funds(index).ok_or(Error::<T>::InvalidIndex)?;
+			let now = <system::Module<T>>::block_number();
+			ensure!(Self::transfers_open(index, &fund, now), Error::<T>::TransfersClosed);
+
+			let mut from_contribution = Self::contribution_info(index, &who);
+			ensure!(from_contribution.amount > Zero::zero(), Error::<T>::NoContribution);
+			ensure!(amount <= from_contribution.amount, Error::<T>::InsufficientContribution);
+			let remaining = from_contribution.amount - amount;
+			let min = T::MinContribution::get();
+			ensure!(!amount.is_zero() && amount >= min, Error::<T>::ContributionTooSmall);
+			ensure!(remaining.is_zero() || remaining >= min, Error::<T>::ContributionTooSmall);
+
+			let mut to_contribution = Self::contribution_info(index, &to);
+			let to_balance = to_contribution.amount.saturating_add(amount);
+			if let Some(max) = fund.options.max_contribution {
+				ensure!(to_balance <= max, Error::<T>::ContributionTooLarge);
+			}
+			if fund.options.whitelisted {
+				ensure!(T::Whitelist::contains(&to), Error::<T>::NotWhitelisted);
+			}
+
+			from_contribution.amount = remaining;
+			if remaining.is_zero() {
+				Self::contribution_kill(index, &who);
+			} else {
+				Self::contribution_put(index, &who, &from_contribution);
+			}
+			to_contribution.amount = to_balance;
+			Self::contribution_put(index, &to, &to_contribution);
+
+			Self::deposit_event(RawEvent::ContributionTransferred(who, to, index, amount));
+		}
+
 		/// Withdraw full balance of a contributor to a fund
 		#[weight = 10_000]
 		fn withdraw(origin, #[compact] index: FundIndex) {
@@ -206,20 +507,56 @@ decl_module! {
 			let balance = Self::contribution_get(index, &who);
 			ensure!(balance > Zero::zero(), Error::<T>::NoContribution);
 
+			// Funds that are being released through milestones can only be withdrawn once a
+			// milestone was rejected. Each contributor then receives their share of what remains.
+			let (refund, pro_rata) = match Self::milestones(index) {
+				Some(milestones) if fund.raised >= fund.goal => {
+					ensure!(milestones.refunding_since.is_some(), Error::<T>::HasMilestones);
+					let remaining = fund.raised.saturating_sub(milestones.released);
+					(Perbill::from_rational_approximation(balance, fund.raised) * remaining, true)
+				}
+				_ => (balance, false),
+			};
+
 			// Return funds to caller without charging a transfer fee
 			let _ = T::Currency::resolve_into_existing(&who, T::Currency::withdraw(
 				&Self::fund_account_id(index),
-				balance,
+				refund,
 				WithdrawReasons::from(WithdrawReason::Transfer),
 				ExistenceRequirement::AllowDeath
 			)?);
 
-			// Update storage
+			// Update storage. Pro rata refunds leave `raised` untouched so that every contributor's
+			// share is computed against the same total.
 			Self::contribution_kill(index, &who);
-			fund.raised = fund.raised.saturating_sub(balance);
-			<Funds<T>>::insert(index, &fund);
+			if !pro_rata {
+				fund.raised = fund.raised.saturating_sub(balance);
+				<Funds<T>>::insert(index, &fund);
+			}
 
-			Self::deposit_event(RawEvent::Withdrew(who, index, balance, now));
+			Self::deposit_event(RawEvent::Withdrew(who, index, refund, now));
+		}
+
+		/// Claim a refund that failed during the automatic refunds of an unsuccessful fund. The
+		/// fund itself may already have been removed.
+		#[weight = 10_000]
+		fn claim_refund(origin, #[compact] index: FundIndex) {
+			let who = ensure_signed(origin)?;
+
+			let balance = Self::failed_refunds(index, &who);
+			ensure!(balance > Zero::zero(), Error::<T>::NoFailedRefund);
+
+			// Return funds to the caller without charging a transfer fee
+			let imbalance = T::Currency::withdraw(
+				&Self::fund_account_id(index),
+				balance,
+				WithdrawReasons::from(WithdrawReason::Transfer),
+				ExistenceRequirement::AllowDeath,
+			)?;
+			T::Currency::resolve_creating(&who, imbalance);
+			<FailedRefunds<T>>::remove(index, &who);
+
+			Self::deposit_event(RawEvent::RefundClaimed(who, index, balance));
 		}
 
 		/// Dissolve an entire crowdfund after its retirement period has expired.
@@ -233,20 +570,38 @@ decl_module! {
 
 			// Check that enough time has passed to remove from storage
 			let now = <system::Module<T>>::block_number();
-			ensure!(now >= fund.end + T::RetirementPeriod::get(), Error::<T>::FundNotRetired);
+			ensure!(
+				now >= fund.end.saturating_add(T::RetirementPeriod::get()),
+				Error::<T>::FundNotRetired
+			);
+
+			// Contributions to an unsuccessful fund are refunded instead
+			ensure!(fund.raised >= fund.goal, Error::<T>::RefundsAutomatic);
+
+			// A successful fund with milestones retires only after a milestone was rejected
+			if let Some(milestones) = Self::milestones(index) {
+				if fund.raised >= fund.goal {
+					let refunding_since = milestones.refunding_since.ok_or(Error::<T>::FundNotRetired)?;
+					ensure!(
+						now >= refunding_since.saturating_add(T::RetirementPeriod::get()),
+						Error::<T>::FundNotRetired
+					);
+				}
+			}
 
 			let account = Self::fund_account_id(index);
 
 			// Dissolver collects the deposit and any remaining funds
 			let _ = T::Currency::resolve_creating(&reporter, T::Currency::withdraw(
 				&account,
-				fund.deposit + fund.raised,
+				T::Currency::free_balance(&account),
 				WithdrawReasons::from(WithdrawReason::Transfer),
 				ExistenceRequirement::AllowDeath,
 			)?);
 
 			// Remove the fund info from storage
 			<Funds<T>>::remove(index);
+			<Milestones<T>>::remove(index);
 			// Remove all the contributor info from storage in a single write.
 			// This is possible thanks to the use of a child tree.
 			Self::crowdfund_kill(index);
@@ -271,6 +626,9 @@ decl_module! {
 			// Check that the fund was actually successful
 			ensure!(fund.raised >= fund.goal, Error::<T>::UnsuccessfulFund);
 
+			// Funds with milestones are paid out through milestone votes instead
+			ensure!(!<Milestones<T>>::contains_key(index), Error::<T>::HasMilestones);
+
 			let account = Self::fund_account_id(index);
 
 			// Beneficiary collects the contributed funds
@@ -297,10 +655,287 @@ decl_module! {
 
 			Self::deposit_event(RawEvent::Dispensed(index, now, caller));
 		}
+
+		/// Start a vote on releasing the next milestone of a successful fund. Only the beneficiary
+		/// may start a vote, until `MilestoneTimeout` has passed since the fund ended or the last
+		/// milestone was released. After that anyone may.
+		#[weight = 10_000]
+		fn start_milestone_vote(origin, index: FundIndex) {
+			let who = ensure_signed(origin)?;
+
+			let fund = Self::funds(index).ok_or(Error::<T>::InvalidIndex)?;
+			let mut milestones = Self::milestones(index).ok_or(Error::<T>::NoMilestones)?;
+
+			let now = <system::Module<T>>::block_number();
+			let timeout = milestones.waiting_since.saturating_add(T::MilestoneTimeout::get());
+			ensure!(who == fund.beneficiary || now >= timeout, Error::<T>::NotBeneficiary);
+			ensure!(now >= fund.end, Error::<T>::FundStillActive);
+			ensure!(fund.raised >= fund.goal, Error::<T>::UnsuccessfulFund);
+			ensure!(milestones.refunding_since.is_none(), Error::<T>::FundRefunding);
+			ensure!(milestones.vote.is_none(), Error::<T>::VoteInProgress);
+
+			let end = now + T::MilestoneVotingPeriod::get();
+			milestones.vote = Some(MilestoneVote { end, ..Default::default() });
+			let milestone = milestones.next;
+			<Milestones<T>>::insert(index, milestones);
+
+			Self::deposit_event(RawEvent::MilestoneVoteStarted(index, milestone, end));
+		}
+
+		/// Vote on the release of the next milestone. The vote is weighted by the caller's
+		/// contribution to the fund.
+		#[weight = 10_000]
+		fn vote_milestone(origin, index: FundIndex, approve: bool) {
+			let who = ensure_signed(origin)?;
+
+			let mut milestones = Self::milestones(index).ok_or(Error::<T>::NoMilestones)?;
+			let mut vote = milestones.vote.clone().ok_or(Error::<T>::NoVoteInProgress)?;
+
+			let now = <system::Module<T>>::block_number();
+			ensure!(now < vote.end, Error::<T>::VotingPeriodOver);
+			ensure!(
+				!<MilestoneVoters<T>>::contains_key((index, milestones.next), &who),
+				Error::<T>::AlreadyVoted
+			);
+
+			let weight = Self::contribution_get(index, &who);
+			ensure!(weight > Zero::zero(), Error::<T>::NoContribution);
+
+			if approve {
+				vote.ayes = vote.ayes.saturating_add(weight);
+			} else {
+				vote.nays = vote.nays.saturating_add(weight);
+			}
+			<MilestoneVoters<T>>::insert((index, milestones.next), &who, approve);
+			milestones.vote = Some(vote);
+			<Milestones<T>>::insert(index, milestones);
+
+			Self::deposit_event(RawEvent::MilestoneVoted(who, index, approve, weight));
+		}
+
+		/// Conclude a milestone vote once its voting period is over. Anyone may call this.
+		///
+		/// If the ayes outweigh the nays, the milestone is paid to the beneficiary. Once the last
+		/// milestone is released the fund is removed and the caller collects the deposit, just like
+		/// `dispense`. Otherwise the fund starts refunding the unreleased funds to contributors.
+		#[weight = 10_000]
+		fn conclude_milestone_vote(origin, index: FundIndex) {
+			let caller = ensure_signed(origin)?;
+
+			let fund = Self::funds(index).ok_or(Error::<T>::InvalidIndex)?;
+			let mut milestones = Self::milestones(index).ok_or(Error::<T>::NoMilestones)?;
+			let vote = milestones.vote.take().ok_or(Error::<T>::NoVoteInProgress)?;
+
+			let now = <system::Module<T>>::block_number();
+			ensure!(now >= vote.end, Error::<T>::VotingPeriodNotOver);
+
+			let milestone = milestones.next;
+			<MilestoneVoters<T>>::remove_prefix((index, milestone));
+
+			if vote.ayes <= vote.nays {
+				milestones.refunding_since = Some(now);
+				<Milestones<T>>::insert(index, milestones);
+
+				Self::deposit_event(RawEvent::MilestoneRejected(index, milestone));
+				return Ok(());
+			}
+
+			let account = Self::fund_account_id(index);
+			let is_last = milestone as usize + 1 >= milestones.amounts.len();
+			// The last milestone releases everything that remains
+			let amount = if is_last {
+				fund.raised.saturating_sub(milestones.released)
+			} else {
+				milestones.amounts[milestone as usize]
+			};
+
+			// Beneficiary collects the milestone
+			let _ = T::Currency::resolve_creating(&fund.beneficiary, T::Currency::withdraw(
+				&account,
+				amount,
+				WithdrawReasons::from(WithdrawReason::Transfer),
+				ExistenceRequirement::AllowDeath,
+			)?);
+			Self::deposit_event(RawEvent::MilestoneReleased(index, milestone, amount));
+
+			if is_last {
+				// Caller collects the deposit
+				let _ = T::Currency::resolve_creating(&caller, T::Currency::withdraw(
+					&account,
+					fund.deposit,
+					WithdrawReasons::from(WithdrawReason::Transfer),
+					ExistenceRequirement::AllowDeath,
+				)?);
+
+				<Funds<T>>::remove(index);
+				<Milestones<T>>::remove(index);
+				Self::crowdfund_kill(index);
+
+				Self::deposit_event(RawEvent::Dispensed(index, now, caller));
+			} else {
+				milestones.next += 1;
+				milestones.released = milestones.released.saturating_add(amount);
+				milestones.waiting_since = now;
+				<Milestones<T>>::insert(index, milestones);
+			}
+		}
+
+		/// Queues the unsuccessful funds that retire in this block for refunds, then refunds up to
+		/// `RefundsPerBlock` contributions.
+		fn on_initialize(n: T::BlockNumber) -> Weight {
+			let retiring = <Retirements<T>>::take(n);
+			for &index in retiring.iter() {
+				match Self::funds(index) {
+					Some(fund) if fund.raised < fund.goal => {
+						let (head, tail) = Self::refund_range();
+						RefundQueue::insert(tail, index);
+						RefundRange::put((head, tail.wrapping_add(1)));
+						Self::deposit_event(RawEvent::RefundsStarted(index));
+					}
+					_ => (),
+				}
+			}
+
+			let steps = Self::process_refunds(T::RefundsPerBlock::get());
+
+			T::DbWeight::get().reads_writes(3, 3)
+				.saturating_add(T::DbWeight::get().reads_writes(2, 2).saturating_mul(retiring.len() as Weight))
+				.saturating_add(T::DbWeight::get().reads_writes(4, 6).saturating_mul(steps as Weight))
+		}
 	}
 }
 
 impl<T: Trait> Module<T> {
+	/// Schedules the retirement of a fund in the first block from `at` that has room for it
+	fn schedule_retirement(index: FundIndex, at: T::BlockNumber) {
+		let limit = T::MaxRetirementsPerBlock::get().max(1) as usize;
+		let mut at = at;
+		while <Retirements<T>>::decode_len(at).unwrap_or(0) >= limit {
+			at = at.saturating_add(One::one());
+		}
+		<Retirements<T>>::append(at, index);
+	}
+
+	/// Works through the refund queue, taking at most `limit` steps. Each step either refunds the
+	/// next contribution of the fund at the head of the queue or, once there are none left,
+	/// removes that fund. Returns the number of steps taken.
+	fn process_refunds(limit: u32) -> u32 {
+		let (mut head, tail) = Self::refund_range();
+		if head == tail {
+			return 0;
+		}
+		let mut cursor = Self::refund_cursor();
+
+		let mut steps = 0;
+		while steps < limit && head != tail {
+			steps += 1;
+			let index = match RefundQueue::get(head) {
+				Some(index) => index,
+				// Every position between head and tail holds a fund, but never stall the queue
+				None => {
+					head = head.wrapping_add(1);
+					continue;
+				}
+			};
+			let id = Self::id_from_index(index);
+			match sp_io::default_child_storage::next_key(id.storage_key(), &cursor) {
+				Some(key) => {
+					Self::refund_contribution(index, &key);
+					cursor = key;
+				}
+				None => {
+					Self::finish_refunds(index);
+					RefundQueue::remove(head);
+					head = head.wrapping_add(1);
+					cursor = Vec::new();
+				}
+			}
+		}
+
+		RefundRange::put((head, tail));
+		RefundCursor::put(cursor);
+		steps
+	}
+
+	/// Refunds the contribution stored under `key` in the child trie of a fund. If the refund
+	/// fails, the contribution is recorded in `FailedRefunds` instead. It stays counted in
+	/// `raised`, so that `finish_refunds` leaves its funds in the fund account.
+	fn refund_contribution(index: FundIndex, key: &[u8]) {
+		let who = match T::AccountId::decode(&mut &key[..]) {
+			Ok(who) => who,
+			Err(_) => return,
+		};
+		let mut fund = match Self::funds(index) {
+			Some(fund) => fund,
+			None => return,
+		};
+		let balance = Self::contribution_get(index, &who);
+
+		// Return funds to the contributor without charging a transfer fee
+		let refund = T::Currency::withdraw(
+			&Self::fund_account_id(index),
+			balance,
+			WithdrawReasons::from(WithdrawReason::Transfer),
+			ExistenceRequirement::AllowDeath,
+		);
+		Self::contribution_kill(index, &who);
+		match refund {
+			Ok(imbalance) => {
+				T::Currency::resolve_creating(&who, imbalance);
+				fund.raised = fund.raised.saturating_sub(balance);
+				<Funds<T>>::insert(index, &fund);
+
+				Self::deposit_event(RawEvent::Refunded(who, index, balance));
+			}
+			Err(_) => {
+				<FailedRefunds<T>>::mutate(index, &who, |failed| {
+					*failed = failed.saturating_add(balance)
+				});
+
+				Self::deposit_event(RawEvent::RefundFailed(who, index, balance));
+			}
+		}
+	}
+
+	/// Removes a fund whose contributions have all been refunded. The depositor collects the
+	/// deposit and anything else left in the fund account, except for the failed refunds that
+	/// are still counted in `raised`.
+	fn finish_refunds(index: FundIndex) {
+		if let Some(fund) = Self::funds(index) {
+			let account = Self::fund_account_id(index);
+			let remaining = T::Currency::withdraw(
+				&account,
+				T::Currency::free_balance(&account).saturating_sub(fund.raised),
+				WithdrawReasons::from(WithdrawReason::Transfer),
+				ExistenceRequirement::AllowDeath,
+			);
+			if let Ok(imbalance) = remaining {
+				T::Currency::resolve_creating(&fund.depositor, imbalance);
+			}
+		}
+
+		<Funds<T>>::remove(index);
+		<Milestones<T>>::remove(index);
+		Self::crowdfund_kill(index);
+
+		Self::deposit_event(RawEvent::RefundsCompleted(index));
+	}
+
+	/// Whether contributions to a fund may be transferred. This is the case while the fund is
+	/// active, during the retirement period of an unsuccessful fund, and while a fund whose
+	/// milestone was rejected is refunding. Once automatic refunds begin, the refund cursor could
+	/// skip a newly created entry, so transfers are closed. Successful funds are closed as well,
+	/// which also keeps credit from being moved between voters on a milestone.
+	fn transfers_open(index: FundIndex, fund: &FundInfoOf<T>, now: T::BlockNumber) -> bool {
+		if now < fund.end {
+			return true;
+		}
+		if fund.raised < fund.goal {
+			return now < fund.end.saturating_add(T::RetirementPeriod::get());
+		}
+		Self::milestones(index).map_or(false, |milestones| milestones.refunding_since.is_some())
+	}
+
 	/// The account ID of the fund pot.
 	///
 	/// This actually does computation. If you need to keep using it, then make sure you cache the
@@ -322,15 +957,20 @@ impl<T: Trait> Module<T> {
 	}
 
 	/// Record a contribution in the associated child trie.
-	pub fn contribution_put(index: FundIndex, who: &T::AccountId, balance: &BalanceOf<T>) {
+	pub fn contribution_put(index: FundIndex, who: &T::AccountId, contribution: &ContributionOf<T>) {
 		let id = Self::id_from_index(index);
-		who.using_encoded(|b| child::put(&id, b, &balance));
+		who.using_encoded(|b| child::put(&id, b, contribution));
 	}
 
-	/// Lookup a contribution in the associated child trie.
-	pub fn contribution_get(index: FundIndex, who: &T::AccountId) -> BalanceOf<T> {
+	/// Lookup a contribution and its memo in the associated child trie.
+	pub fn contribution_info(index: FundIndex, who: &T::AccountId) -> ContributionOf<T> {
 		let id = Self::id_from_index(index);
-		who.using_encoded(|b| child::get_or_default::<BalanceOf<T>>(&id, b))
+		who.using_encoded(|b| child::get_or_default::<ContributionOf<T>>(&id, b))
+	}
+
+	/// Lookup the amount of a contribution in the associated child trie.
+	pub fn contribution_get(index: FundIndex, who: &T::AccountId) -> BalanceOf<T> {
+		Self::contribution_info(index, who).amount
 	}
 
 	/// Remove a contribution from an associated child trie.
@@ -345,4 +985,46 @@ impl<T: Trait> Module<T> {
 		let id = Self::id_from_index(index);
 		child::kill_storage(&id);
 	}
+
+	/// The unique id of the child trie of a fund, or `None` if the fund does not exist.
+	/// Clients use it to request storage proofs of contributions.
+	pub fn fund_trie_id(index: FundIndex) -> Option<Vec<u8>> {
+		Self::funds(index).map(|_| Self::id_from_index(index).storage_key().to_vec())
+	}
+
+	/// The root of the child trie of a fund, or `None` if the fund does not exist.
+	pub fn fund_trie_root(index: FundIndex) -> Option<Vec<u8>> {
+		Self::funds(index).map(|_| child::root(&Self::id_from_index(index)))
+	}
+}
+
+/// Checks a storage proof of a contribution against the root of a fund's child trie.
+///
+/// Returns the proven contribution of `who`, or `None` if the proof shows that `who` has not
+/// contributed. Fails if the proof does not lead from the root to the contributor's entry.
+#[cfg(feature = "std")]
+pub fn verify_contribution_proof<H, AccountId, Balance>(
+	root: &[u8],
+	proof: Vec<Vec<u8>>,
+	who: &AccountId,
+) -> Result<Option<Contribution<Balance>>, &'static str>
+where
+	H: Hasher,
+	AccountId: Encode,
+	Balance: Decode,
+{
+	let mut trie_root = H::Out::default();
+	ensure!(trie_root.as_ref().len() == root.len(), "Root has the wrong length");
+	trie_root.as_mut().copy_from_slice(root);
+
+	let mut db = MemoryDB::<H>::default();
+	for node in proof {
+		db.insert(EMPTY_PREFIX, &node);
+	}
+
+	let value = read_trie_value::<Layout<H>, _>(&db, &trie_root, &who.encode())
+		.map_err(|_| "Proof does not match the root")?;
+	value
+		.map(|v| Contribution::decode(&mut &v[..]).map_err(|_| "Not a valid contribution"))
+		.transpose()
 }
@@ -1,8 +1,9 @@
 use super::*;
 
+use account_set::AccountSet;
 use frame_support::{
 	assert_noop, assert_ok, impl_outer_origin, parameter_types,
-	traits::{OnFinalize, OnInitialize},
+	traits::{LockableCurrency, OnFinalize, OnInitialize},
 };
 use sp_core::H256;
 // The testing primitives are very useful for avoiding having to work with signatures
@@ -12,6 +13,7 @@ use sp_runtime::{
 	traits::{BlakeTwo256, IdentityLookup},
 	Perbill, Percent, Permill,
 };
+use sp_std::collections::btree_set::BTreeSet;
 
 impl_outer_origin! {
 	pub enum Origin for Test {}
@@ -84,13 +86,38 @@ parameter_types! {
 	pub const SubmissionDeposit: u64 = 1;
 	pub const MinContribution: u64 = 10;
 	pub const RetirementPeriod: u64 = 5;
+	pub const MilestoneVotingPeriod: u64 = 3;
+	pub const MilestoneTimeout: u64 = 5;
+	pub const MaxMilestones: u32 = 3;
+	pub const RefundsPerBlock: u32 = 2;
+	pub const MaxRetirementsPerBlock: u32 = 2;
+	pub const MaxMemoLength: u32 = 8;
 }
+
+/// Accounts 1 and 2 may contribute to whitelisted funds
+pub struct TestWhitelist;
+impl AccountSet for TestWhitelist {
+	type AccountId = u64;
+
+	fn accounts() -> BTreeSet<u64> {
+		vec![1, 2].into_iter().collect()
+	}
+}
+impl AccountSetExt for TestWhitelist {}
+
 impl Trait for Test {
 	type Event = ();
 	type Currency = Balances;
 	type SubmissionDeposit = SubmissionDeposit;
 	type MinContribution = MinContribution;
 	type RetirementPeriod = RetirementPeriod;
+	type MilestoneVotingPeriod = MilestoneVotingPeriod;
+	type MilestoneTimeout = MilestoneTimeout;
+	type MaxMilestones = MaxMilestones;
+	type RefundsPerBlock = RefundsPerBlock;
+	type MaxRetirementsPerBlock = MaxRetirementsPerBlock;
+	type Whitelist = TestWhitelist;
+	type MaxMemoLength = MaxMemoLength;
 }
 
 type System = system::Module<Test>;
@@ -138,16 +165,18 @@ fn basic_setup_works() {
 fn create_works() {
 	new_test_ext().execute_with(|| {
 		// Now try to create a crowdfund campaign
-		assert_ok!(Crowdfund::create(Origin::signed(1), 2, 1000, 9));
+		assert_ok!(Crowdfund::create(Origin::signed(1), 2, 1000, 9, vec![], FundOptions::default()));
 		assert_eq!(Crowdfund::fund_count(), 1);
 		// This is what the initial `fund_info` should look like
 		let fund_info = FundInfo {
 			beneficiary: 2,
+			depositor: 1,
 			deposit: 1,
 			raised: 0,
 			// 5 blocks length + 3 block ending period + 1 starting block
 			end: 9,
 			goal: 1000,
+			options: FundOptions::default(),
 		};
 		assert_eq!(Crowdfund::funds(0), Some(fund_info));
 		// User has deposit removed from their free balance
@@ -161,7 +190,7 @@ fn create_works() {
 fn create_handles_insufficient_balance() {
 	new_test_ext().execute_with(|| {
 		assert_noop!(
-			Crowdfund::create(Origin::signed(1337), 2, 1000, 9),
+			Crowdfund::create(Origin::signed(1337), 2, 1000, 9, vec![], FundOptions::default()),
 			BalancesError::<Test, _>::InsufficientBalance
 		);
 	});
@@ -171,7 +200,7 @@ fn create_handles_insufficient_balance() {
 fn contribute_works() {
 	new_test_ext().execute_with(|| {
 		// Set up a crowdfund
-		assert_ok!(Crowdfund::create(Origin::signed(1), 2, 1000, 9));
+		assert_ok!(Crowdfund::create(Origin::signed(1), 2, 1000, 9, vec![], FundOptions::default()));
 		assert_eq!(Balances::free_balance(1), 999);
 		assert_eq!(Balances::free_balance(Crowdfund::fund_account_id(0)), 1);
 
@@ -179,7 +208,7 @@ fn contribute_works() {
 		assert_eq!(Crowdfund::contribution_get(0, &1), 0);
 
 		// User 1 contributes to their own crowdfund
-		assert_ok!(Crowdfund::contribute(Origin::signed(1), 0, 49));
+		assert_ok!(Crowdfund::contribute(Origin::signed(1), 0, 49, None));
 		// User 1 has spent some funds to do this, transfer fees **are** taken
 		assert_eq!(Balances::free_balance(1), 950);
 		// Contributions are stored in the trie
@@ -196,25 +225,25 @@ fn contribute_handles_basic_errors() {
 	new_test_ext().execute_with(|| {
 		// Cannot contribute to non-existing fund
 		assert_noop!(
-			Crowdfund::contribute(Origin::signed(1), 0, 49),
+			Crowdfund::contribute(Origin::signed(1), 0, 49, None),
 			Error::<Test>::InvalidIndex
 		);
 		// Cannot contribute below minimum contribution
 		assert_noop!(
-			Crowdfund::contribute(Origin::signed(1), 0, 9),
+			Crowdfund::contribute(Origin::signed(1), 0, 9, None),
 			Error::<Test>::ContributionTooSmall
 		);
 
 		// Set up a crowdfund
-		assert_ok!(Crowdfund::create(Origin::signed(1), 2, 1000, 9));
-		assert_ok!(Crowdfund::contribute(Origin::signed(1), 0, 101));
+		assert_ok!(Crowdfund::create(Origin::signed(1), 2, 1000, 9, vec![], FundOptions::default()));
+		assert_ok!(Crowdfund::contribute(Origin::signed(1), 0, 101, None));
 
 		// Move past end date
 		run_to_block(10);
 
 		// Cannot contribute to ended fund
 		assert_noop!(
-			Crowdfund::contribute(Origin::signed(1), 0, 49),
+			Crowdfund::contribute(Origin::signed(1), 0, 49, None),
 			Error::<Test>::ContributionPeriodOver
 		);
 	});
@@ -224,15 +253,15 @@ fn contribute_handles_basic_errors() {
 fn withdraw_works() {
 	new_test_ext().execute_with(|| {
 		// Set up a crowdfund
-		assert_ok!(Crowdfund::create(Origin::signed(1), 2, 1000, 9));
+		assert_ok!(Crowdfund::create(Origin::signed(1), 2, 1000, 9, vec![], FundOptions::default()));
 		// Transfer fees are taken here
-		assert_ok!(Crowdfund::contribute(Origin::signed(1), 0, 100));
-		assert_ok!(Crowdfund::contribute(Origin::signed(2), 0, 200));
-		assert_ok!(Crowdfund::contribute(Origin::signed(3), 0, 300));
+		assert_ok!(Crowdfund::contribute(Origin::signed(1), 0, 100, None));
+		assert_ok!(Crowdfund::contribute(Origin::signed(2), 0, 200, None));
+		assert_ok!(Crowdfund::contribute(Origin::signed(3), 0, 300, None));
 
-		// Skip all the way to the end
+		// Skip to the end of the contribution period
 		// Crowdfund is unsuccessful 100 + 200 + 300 < 1000
-		run_to_block(50);
+		run_to_block(10);
 
 		// User can withdraw their full balance without fees
 		assert_ok!(Crowdfund::withdraw(Origin::signed(1), 0));
@@ -250,9 +279,9 @@ fn withdraw_works() {
 fn withdraw_handles_basic_errors() {
 	new_test_ext().execute_with(|| {
 		// Set up a crowdfund
-		assert_ok!(Crowdfund::create(Origin::signed(1), 2, 1000, 9));
+		assert_ok!(Crowdfund::create(Origin::signed(1), 2, 1000, 9, vec![], FundOptions::default()));
 		// Transfer fee is taken here
-		assert_ok!(Crowdfund::contribute(Origin::signed(1), 0, 49));
+		assert_ok!(Crowdfund::contribute(Origin::signed(1), 0, 49, None));
 		assert_eq!(Balances::free_balance(1), 950);
 
 		run_to_block(5);
@@ -284,20 +313,20 @@ fn withdraw_handles_basic_errors() {
 fn dissolve_works() {
 	new_test_ext().execute_with(|| {
 		// Set up a crowdfund
-		assert_ok!(Crowdfund::create(Origin::signed(1), 2, 1000, 9));
+		assert_ok!(Crowdfund::create(Origin::signed(1), 2, 1000, 9, vec![], FundOptions::default()));
 		// Transfer fee is taken here
-		assert_ok!(Crowdfund::contribute(Origin::signed(1), 0, 100));
-		assert_ok!(Crowdfund::contribute(Origin::signed(2), 0, 200));
-		assert_ok!(Crowdfund::contribute(Origin::signed(3), 0, 300));
+		assert_ok!(Crowdfund::contribute(Origin::signed(1), 0, 100, None));
+		assert_ok!(Crowdfund::contribute(Origin::signed(2), 0, 400, None));
+		assert_ok!(Crowdfund::contribute(Origin::signed(3), 0, 500, None));
 
 		// Skip all the way to the end
-		// Crowdfund is unsuccessful 100 + 200 + 300 < 1000
+		// Crowdfund is successful 100 + 400 + 500 = 1000, but never dispensed
 		run_to_block(50);
 
 		// Check initiator's balance.
 		assert_eq!(Balances::free_balance(1), 899);
 		// Check current funds (contributions + deposit)
-		assert_eq!(Balances::free_balance(Crowdfund::fund_account_id(0)), 601);
+		assert_eq!(Balances::free_balance(Crowdfund::fund_account_id(0)), 1001);
 
 		// Account 7 dissolves the crowdfund claiming the remaining funds
 		assert_ok!(Crowdfund::dissolve(Origin::signed(7), 0));
@@ -305,7 +334,7 @@ fn dissolve_works() {
 		// Fund account is emptied
 		assert_eq!(Balances::free_balance(Crowdfund::fund_account_id(0)), 0);
 		// Dissolver account is rewarded
-		assert_eq!(Balances::free_balance(7), 601);
+		assert_eq!(Balances::free_balance(7), 1001);
 
 		// Storage trie is removed
 		assert_eq!(Crowdfund::contribution_get(0, &0), 0);
@@ -318,11 +347,11 @@ fn dissolve_works() {
 fn dissolve_handles_basic_errors() {
 	new_test_ext().execute_with(|| {
 		// Set up a crowdfund
-		assert_ok!(Crowdfund::create(Origin::signed(1), 2, 1000, 9));
+		assert_ok!(Crowdfund::create(Origin::signed(1), 2, 1000, 9, vec![], FundOptions::default()));
 		// Transfer fee is taken here
-		assert_ok!(Crowdfund::contribute(Origin::signed(1), 0, 100));
-		assert_ok!(Crowdfund::contribute(Origin::signed(2), 0, 200));
-		assert_ok!(Crowdfund::contribute(Origin::signed(3), 0, 300));
+		assert_ok!(Crowdfund::contribute(Origin::signed(1), 0, 100, None));
+		assert_ok!(Crowdfund::contribute(Origin::signed(2), 0, 200, None));
+		assert_ok!(Crowdfund::contribute(Origin::signed(3), 0, 300, None));
 
 		// Cannot dissolve an invalid fund index
 		assert_noop!(
@@ -342,6 +371,13 @@ fn dissolve_handles_basic_errors() {
 			Crowdfund::dissolve(Origin::signed(1), 0),
 			Error::<Test>::FundNotRetired
 		);
+
+		// Cannot dissolve an unsuccessful fund that is being refunded
+		run_to_block(14);
+		assert_noop!(
+			Crowdfund::dissolve(Origin::signed(1), 0),
+			Error::<Test>::RefundsAutomatic
+		);
 	});
 }
 
@@ -349,12 +385,12 @@ fn dissolve_handles_basic_errors() {
 fn dispense_works() {
 	new_test_ext().execute_with(|| {
 		// Set up a crowdfund
-		assert_ok!(Crowdfund::create(Origin::signed(1), 20, 1000, 9));
+		assert_ok!(Crowdfund::create(Origin::signed(1), 20, 1000, 9, vec![], FundOptions::default()));
 		// Transfer fee is taken here
-		assert_ok!(Crowdfund::contribute(Origin::signed(1), 0, 100));
-		assert_ok!(Crowdfund::contribute(Origin::signed(2), 0, 200));
-		assert_ok!(Crowdfund::contribute(Origin::signed(3), 0, 300));
-		assert_ok!(Crowdfund::contribute(Origin::signed(3), 0, 400));
+		assert_ok!(Crowdfund::contribute(Origin::signed(1), 0, 100, None));
+		assert_ok!(Crowdfund::contribute(Origin::signed(2), 0, 200, None));
+		assert_ok!(Crowdfund::contribute(Origin::signed(3), 0, 300, None));
+		assert_ok!(Crowdfund::contribute(Origin::signed(3), 0, 400, None));
 
 		// Skip to the retirement period
 		// Crowdfund is successful 100 + 200 + 300 + 400  >= 1000
@@ -386,11 +422,11 @@ fn dispense_works() {
 fn dispense_handles_basic_errors() {
 	new_test_ext().execute_with(|| {
 		// Set up a crowdfund
-		assert_ok!(Crowdfund::create(Origin::signed(1), 2, 1000, 9));
+		assert_ok!(Crowdfund::create(Origin::signed(1), 2, 1000, 9, vec![], FundOptions::default()));
 		// Transfer fee is taken here
-		assert_ok!(Crowdfund::contribute(Origin::signed(1), 0, 100));
-		assert_ok!(Crowdfund::contribute(Origin::signed(2), 0, 200));
-		assert_ok!(Crowdfund::contribute(Origin::signed(3), 0, 300));
+		assert_ok!(Crowdfund::contribute(Origin::signed(1), 0, 100, None));
+		assert_ok!(Crowdfund::contribute(Origin::signed(2), 0, 200, None));
+		assert_ok!(Crowdfund::contribute(Origin::signed(3), 0, 300, None));
 
 		// Cannot dispense an invalid fund index
 		assert_noop!(
@@ -414,3 +450,603 @@ fn dispense_handles_basic_errors() {
 		);
 	});
 }
+
+#[test]
+fn create_with_milestones_works() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Crowdfund::create(Origin::signed(1), 2, 1000, 9, vec![300, 700], FundOptions::default()));
+		assert_eq!(
+			Crowdfund::milestones(0),
+			Some(MilestoneInfo {
+				amounts: vec![300, 700],
+				next: 0,
+				released: 0,
+				waiting_since: 9,
+				vote: None,
+				refunding_since: None,
+			})
+		);
+
+		// Milestones must add up to the goal
+		assert_noop!(
+			Crowdfund::create(Origin::signed(1), 2, 1000, 9, vec![300, 600], FundOptions::default()),
+			Error::<Test>::MilestonesMismatchGoal
+		);
+		// Cannot declare more than `MaxMilestones`
+		assert_noop!(
+			Crowdfund::create(Origin::signed(1), 2, 1000, 9, vec![250, 250, 250, 250], FundOptions::default()),
+			Error::<Test>::TooManyMilestones
+		);
+	});
+}
+
+/// Creates a fund for beneficiary 20 with two milestones that raises 1000 from 1, 2 and 3 and
+/// moves to the end of the contribution period
+fn successful_milestone_fund() {
+	assert_ok!(Crowdfund::create(Origin::signed(1), 20, 1000, 9, vec![300, 700], FundOptions::default()));
+	assert_ok!(Crowdfund::contribute(Origin::signed(1), 0, 200, None));
+	assert_ok!(Crowdfund::contribute(Origin::signed(2), 0, 300, None));
+	assert_ok!(Crowdfund::contribute(Origin::signed(3), 0, 500, None));
+	run_to_block(10);
+}
+
+#[test]
+fn milestone_funds_cannot_be_dispensed_or_withdrawn() {
+	new_test_ext().execute_with(|| {
+		successful_milestone_fund();
+		assert_noop!(
+			Crowdfund::dispense(Origin::signed(7), 0),
+			Error::<Test>::HasMilestones
+		);
+		assert_noop!(
+			Crowdfund::withdraw(Origin::signed(1), 0),
+			Error::<Test>::HasMilestones
+		);
+		run_to_block(50);
+		assert_noop!(
+			Crowdfund::dissolve(Origin::signed(7), 0),
+			Error::<Test>::FundNotRetired
+		);
+	});
+}
+
+#[test]
+fn milestones_are_released_after_approval() {
+	new_test_ext().execute_with(|| {
+		successful_milestone_fund();
+
+		// Only the beneficiary can start a vote before `MilestoneTimeout`
+		assert_noop!(
+			Crowdfund::start_milestone_vote(Origin::signed(1), 0),
+			Error::<Test>::NotBeneficiary
+		);
+		assert_ok!(Crowdfund::start_milestone_vote(Origin::signed(20), 0));
+		assert_noop!(
+			Crowdfund::start_milestone_vote(Origin::signed(20), 0),
+			Error::<Test>::VoteInProgress
+		);
+
+		// 2 and 3 approve with a weight of 800, 1 rejects with a weight of 200
+		assert_ok!(Crowdfund::vote_milestone(Origin::signed(1), 0, false));
+		assert_ok!(Crowdfund::vote_milestone(Origin::signed(2), 0, true));
+		assert_ok!(Crowdfund::vote_milestone(Origin::signed(3), 0, true));
+		assert_noop!(
+			Crowdfund::vote_milestone(Origin::signed(3), 0, true),
+			Error::<Test>::AlreadyVoted
+		);
+		assert_noop!(
+			Crowdfund::vote_milestone(Origin::signed(4), 0, true),
+			Error::<Test>::NoContribution
+		);
+		assert_noop!(
+			Crowdfund::conclude_milestone_vote(Origin::signed(7), 0),
+			Error::<Test>::VotingPeriodNotOver
+		);
+
+		run_to_block(13);
+		assert_ok!(Crowdfund::conclude_milestone_vote(Origin::signed(7), 0));
+		assert_eq!(Balances::free_balance(20), 300);
+		assert_eq!(Crowdfund::milestones(0).unwrap().next, 1);
+		assert_eq!(Crowdfund::milestones(0).unwrap().released, 300);
+
+		// The second and last milestone releases the rest and cleans up the fund
+		assert_ok!(Crowdfund::start_milestone_vote(Origin::signed(20), 0));
+		assert_ok!(Crowdfund::vote_milestone(Origin::signed(3), 0, true));
+		run_to_block(16);
+		assert_ok!(Crowdfund::conclude_milestone_vote(Origin::signed(7), 0));
+
+		assert_eq!(Balances::free_balance(20), 1000);
+		assert_eq!(Balances::free_balance(7), 1);
+		assert_eq!(Balances::free_balance(Crowdfund::fund_account_id(0)), 0);
+		assert_eq!(Crowdfund::funds(0), None);
+		assert_eq!(Crowdfund::milestones(0), None);
+	});
+}
+
+#[test]
+fn rejected_milestone_refunds_pro_rata() {
+	new_test_ext().execute_with(|| {
+		successful_milestone_fund();
+
+		// The first milestone is approved
+		assert_ok!(Crowdfund::start_milestone_vote(Origin::signed(20), 0));
+		assert_ok!(Crowdfund::vote_milestone(Origin::signed(3), 0, true));
+		run_to_block(13);
+		assert_ok!(Crowdfund::conclude_milestone_vote(Origin::signed(7), 0));
+		assert_eq!(Balances::free_balance(20), 300);
+
+		// The second milestone is rejected, with a tie counting as a rejection
+		assert_ok!(Crowdfund::start_milestone_vote(Origin::signed(20), 0));
+		assert_ok!(Crowdfund::vote_milestone(Origin::signed(1), 0, false));
+		assert_ok!(Crowdfund::vote_milestone(Origin::signed(2), 0, false));
+		assert_ok!(Crowdfund::vote_milestone(Origin::signed(3), 0, true));
+		run_to_block(16);
+		assert_ok!(Crowdfund::conclude_milestone_vote(Origin::signed(7), 0));
+		assert_eq!(Crowdfund::milestones(0).unwrap().refunding_since, Some(16));
+		assert_noop!(
+			Crowdfund::start_milestone_vote(Origin::signed(20), 0),
+			Error::<Test>::FundRefunding
+		);
+
+		// Contributors get their share of the 700 that was not released
+		assert_ok!(Crowdfund::withdraw(Origin::signed(1), 0));
+		assert_eq!(Balances::free_balance(1), 799 + 140);
+		assert_ok!(Crowdfund::withdraw(Origin::signed(2), 0));
+		assert_eq!(Balances::free_balance(2), 1700 + 210);
+		assert_ok!(Crowdfund::withdraw(Origin::signed(3), 0));
+		assert_eq!(Balances::free_balance(3), 2500 + 350);
+
+		// Only the deposit remains, which can be collected after the retirement period
+		assert_eq!(Balances::free_balance(Crowdfund::fund_account_id(0)), 1);
+		assert_noop!(
+			Crowdfund::dissolve(Origin::signed(7), 0),
+			Error::<Test>::FundNotRetired
+		);
+		run_to_block(21);
+		assert_ok!(Crowdfund::dissolve(Origin::signed(7), 0));
+		assert_eq!(Balances::free_balance(7), 1);
+		assert_eq!(Crowdfund::milestones(0), None);
+	});
+}
+
+#[test]
+fn anyone_may_start_milestone_vote_after_timeout() {
+	new_test_ext().execute_with(|| {
+		successful_milestone_fund();
+
+		// The fund ended at block 9, so the beneficiary has until block 14 to start the vote
+		run_to_block(13);
+		assert_noop!(
+			Crowdfund::start_milestone_vote(Origin::signed(1), 0),
+			Error::<Test>::NotBeneficiary
+		);
+		run_to_block(14);
+		assert_ok!(Crowdfund::start_milestone_vote(Origin::signed(1), 0));
+		assert_ok!(Crowdfund::vote_milestone(Origin::signed(3), 0, true));
+		run_to_block(17);
+		assert_ok!(Crowdfund::conclude_milestone_vote(Origin::signed(7), 0));
+		assert_eq!(Crowdfund::milestones(0).unwrap().waiting_since, 17);
+
+		// The timeout starts again once a milestone is released
+		run_to_block(21);
+		assert_noop!(
+			Crowdfund::start_milestone_vote(Origin::signed(1), 0),
+			Error::<Test>::NotBeneficiary
+		);
+		run_to_block(22);
+		assert_ok!(Crowdfund::start_milestone_vote(Origin::signed(1), 0));
+	});
+}
+
+/// Generates a storage proof of `who`'s contribution to fund `index` from the state of `ext`,
+/// much like the node does when serving `crowdfund_contributionProof`.
+fn contribution_proof(ext: &sp_io::TestExternalities, index: FundIndex, who: u64) -> Vec<Vec<u8>> {
+	let backend = ext.commit_all();
+	sp_state_machine::prove_child_read(backend, &Crowdfund::id_from_index(index), &[who.encode()])
+		.unwrap()
+		.iter_nodes()
+		.collect()
+}
+
+#[test]
+fn fund_trie_is_exposed() {
+	new_test_ext().execute_with(|| {
+		assert_eq!(Crowdfund::fund_trie_id(0), None);
+		assert_eq!(Crowdfund::fund_trie_root(0), None);
+
+		assert_ok!(Crowdfund::create(Origin::signed(1), 2, 1000, 9, vec![], FundOptions::default()));
+		assert_eq!(
+			Crowdfund::fund_trie_id(0),
+			Some(Crowdfund::id_from_index(0).storage_key().to_vec())
+		);
+		let empty_root = Crowdfund::fund_trie_root(0).unwrap();
+
+		// The root changes with each contribution
+		assert_ok!(Crowdfund::contribute(Origin::signed(1), 0, 49, None));
+		assert_ne!(Crowdfund::fund_trie_root(0).unwrap(), empty_root);
+	});
+}
+
+#[test]
+fn contribution_proof_verifies() {
+	let mut ext = new_test_ext();
+	let root = ext.execute_with(|| {
+		assert_ok!(Crowdfund::create(Origin::signed(1), 2, 1000, 9, vec![], FundOptions::default()));
+		assert_ok!(Crowdfund::contribute(Origin::signed(1), 0, 49, None));
+		assert_ok!(Crowdfund::contribute(Origin::signed(2), 0, 100, None));
+		Crowdfund::fund_trie_root(0).unwrap()
+	});
+
+	let proof = contribution_proof(&ext, 0, 2);
+	assert_eq!(verify_contribution_proof::<BlakeTwo256, u64, u64>(&root, proof, &2), Ok(Some(Contribution { amount: 100, memo: vec![] })));
+
+	// A proof of absence shows that account 3 has not contributed
+	let proof = contribution_proof(&ext, 0, 3);
+	assert_eq!(verify_contribution_proof::<BlakeTwo256, u64, u64>(&root, proof, &3), Ok(None));
+}
+
+#[test]
+fn contribution_proof_rejects_wrong_root() {
+	let mut ext = new_test_ext();
+	let other_root = ext.execute_with(|| {
+		assert_ok!(Crowdfund::create(Origin::signed(1), 2, 1000, 9, vec![], FundOptions::default()));
+		assert_ok!(Crowdfund::create(Origin::signed(1), 2, 1000, 9, vec![], FundOptions::default()));
+		assert_ok!(Crowdfund::contribute(Origin::signed(1), 0, 49, None));
+		assert_ok!(Crowdfund::contribute(Origin::signed(2), 1, 100, None));
+		Crowdfund::fund_trie_root(1).unwrap()
+	});
+
+	// A proof for fund 0 does not verify against the root of fund 1
+	let proof = contribution_proof(&ext, 0, 1);
+	assert!(verify_contribution_proof::<BlakeTwo256, u64, u64>(&other_root, proof.clone(), &1).is_err());
+	// Nor does it verify against a root of the wrong length
+	assert!(verify_contribution_proof::<BlakeTwo256, u64, u64>(&[0u8; 4], proof, &1).is_err());
+}
+
+/// The funds in the refund queue, from head to tail
+fn queued_funds() -> Vec<FundIndex> {
+	let (head, tail) = Crowdfund::refund_range();
+	(head..tail).filter_map(Crowdfund::refund_queue).collect()
+}
+
+#[test]
+fn failed_fund_is_refunded_automatically() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Crowdfund::create(Origin::signed(1), 2, 1000, 9, vec![], FundOptions::default()));
+		assert_ok!(Crowdfund::contribute(Origin::signed(1), 0, 100, None));
+		assert_ok!(Crowdfund::contribute(Origin::signed(2), 0, 200, None));
+		assert_ok!(Crowdfund::contribute(Origin::signed(3), 0, 300, None));
+
+		// Nothing happens during the retirement period
+		run_to_block(13);
+		assert!(queued_funds().is_empty());
+		assert_eq!(Balances::free_balance(Crowdfund::fund_account_id(0)), 601);
+
+		// Once the fund retires, `RefundsPerBlock` contributions are refunded per block
+		run_to_block(14);
+		assert_eq!(queued_funds(), vec![0]);
+		assert_eq!(Balances::free_balance(1), 899 + 100);
+		assert_eq!(Balances::free_balance(2), 2000);
+		assert_eq!(Balances::free_balance(3), 2700);
+		assert_eq!(Crowdfund::refund_cursor(), 2u64.encode());
+		assert_eq!(Crowdfund::funds(0).unwrap().raised, 300);
+
+		// The last contribution is refunded and the fund is removed in the next block
+		run_to_block(15);
+		assert_eq!(Balances::free_balance(3), 3000);
+		// The depositor collects the deposit
+		assert_eq!(Balances::free_balance(1), 1000);
+		assert_eq!(Balances::free_balance(Crowdfund::fund_account_id(0)), 0);
+		assert_eq!(Crowdfund::funds(0), None);
+		assert!(queued_funds().is_empty());
+		assert!(Crowdfund::refund_cursor().is_empty());
+	});
+}
+
+#[test]
+fn contributors_may_withdraw_during_refunds() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Crowdfund::create(Origin::signed(1), 2, 1000, 9, vec![], FundOptions::default()));
+		assert_ok!(Crowdfund::contribute(Origin::signed(1), 0, 100, None));
+		assert_ok!(Crowdfund::contribute(Origin::signed(2), 0, 200, None));
+		assert_ok!(Crowdfund::contribute(Origin::signed(3), 0, 300, None));
+
+		// Account 3 withdraws before the automatic refunds reach it
+		run_to_block(14);
+		assert_ok!(Crowdfund::withdraw(Origin::signed(3), 0));
+		assert_eq!(Balances::free_balance(3), 3000);
+
+		// The refunds skip the withdrawn contribution and remove the fund
+		run_to_block(15);
+		assert_eq!(Crowdfund::funds(0), None);
+		assert_eq!(Balances::free_balance(1), 1000);
+		assert_eq!(Balances::free_balance(3), 3000);
+	});
+}
+
+#[test]
+fn failed_refund_can_be_claimed_later() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Crowdfund::create(Origin::signed(1), 2, 1000, 9, vec![], FundOptions::default()));
+		assert_ok!(Crowdfund::contribute(Origin::signed(1), 0, 100, None));
+		assert_ok!(Crowdfund::contribute(Origin::signed(2), 0, 200, None));
+		assert_ok!(Crowdfund::contribute(Origin::signed(3), 0, 300, None));
+
+		// A lock on the fund account keeps the last refund from being paid
+		let account = Crowdfund::fund_account_id(0);
+		Balances::set_lock(*b"testlock", &account, 300, WithdrawReasons::all());
+
+		run_to_block(15);
+		assert_eq!(Crowdfund::funds(0), None);
+		assert_eq!(Balances::free_balance(3), 2700);
+		assert_eq!(Crowdfund::failed_refunds(0, 3), 300);
+
+		// The depositor collects the deposit, but the failed refund stays in the fund account
+		assert_eq!(Balances::free_balance(1), 1000);
+		assert_eq!(Balances::free_balance(account), 300);
+
+		assert_noop!(
+			Crowdfund::claim_refund(Origin::signed(2), 0),
+			Error::<Test>::NoFailedRefund
+		);
+		assert_noop!(
+			Crowdfund::claim_refund(Origin::signed(3), 0),
+			BalancesError::<Test, _>::LiquidityRestrictions
+		);
+
+		Balances::remove_lock(*b"testlock", &account);
+		assert_ok!(Crowdfund::claim_refund(Origin::signed(3), 0));
+		assert_eq!(Balances::free_balance(3), 3000);
+		assert_eq!(Balances::free_balance(account), 0);
+		assert_eq!(Crowdfund::failed_refunds(0, 3), 0);
+	});
+}
+
+#[test]
+fn refunds_of_several_funds_are_chunked() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Crowdfund::create(Origin::signed(1), 2, 1000, 9, vec![], FundOptions::default()));
+		assert_ok!(Crowdfund::create(Origin::signed(1), 2, 1000, 9, vec![], FundOptions::default()));
+		assert_ok!(Crowdfund::contribute(Origin::signed(2), 0, 200, None));
+		assert_ok!(Crowdfund::contribute(Origin::signed(3), 1, 300, None));
+
+		// Refunding the contribution to fund 0 and removing it uses up the block
+		run_to_block(14);
+		assert_eq!(Crowdfund::funds(0), None);
+		assert_eq!(Balances::free_balance(2), 2000);
+		assert_eq!(queued_funds(), vec![1]);
+		assert_eq!(Balances::free_balance(3), 2700);
+
+		// Fund 1 is handled in the following block
+		run_to_block(15);
+		assert_eq!(Crowdfund::funds(1), None);
+		assert_eq!(Balances::free_balance(3), 3000);
+		assert_eq!(Balances::free_balance(1), 1000);
+		assert!(queued_funds().is_empty());
+	});
+}
+
+#[test]
+fn retirements_are_capped_per_block() {
+	new_test_ext().execute_with(|| {
+		for _ in 0..3 {
+			assert_ok!(Crowdfund::create(Origin::signed(1), 2, 1000, 9, vec![], FundOptions::default()));
+		}
+
+		// The third fund retires in the next block with room
+		assert_eq!(Crowdfund::retirements(14), vec![0, 1]);
+		assert_eq!(Crowdfund::retirements(15), vec![2]);
+
+		run_to_block(14);
+		assert_eq!(Crowdfund::funds(0), None);
+		assert_eq!(Crowdfund::funds(1), None);
+		assert!(Crowdfund::funds(2).is_some());
+
+		run_to_block(15);
+		assert_eq!(Crowdfund::funds(2), None);
+		assert!(queued_funds().is_empty());
+		assert_eq!(Crowdfund::refund_range(), (3, 3));
+	});
+}
+
+#[test]
+fn successful_funds_are_not_refunded() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Crowdfund::create(Origin::signed(1), 2, 1000, 9, vec![], FundOptions::default()));
+		assert_ok!(Crowdfund::contribute(Origin::signed(3), 0, 1000, None));
+
+		run_to_block(20);
+		assert!(queued_funds().is_empty());
+		assert_eq!(Crowdfund::contribution_get(0, &3), 1000);
+		assert_ok!(Crowdfund::dispense(Origin::signed(7), 0));
+	});
+}
+
+#[test]
+fn per_account_maximum_is_enforced() {
+	new_test_ext().execute_with(|| {
+		let options = FundOptions { max_contribution: Some(300), ..Default::default() };
+		assert_ok!(Crowdfund::create(Origin::signed(1), 2, 1000, 9, vec![], options));
+
+		assert_ok!(Crowdfund::contribute(Origin::signed(2), 0, 200, None));
+		// The maximum applies to the total contributed by an account
+		assert_noop!(
+			Crowdfund::contribute(Origin::signed(2), 0, 200, None),
+			Error::<Test>::ContributionTooLarge
+		);
+		assert_ok!(Crowdfund::contribute(Origin::signed(2), 0, 100, None));
+		assert_eq!(Crowdfund::contribution_get(0, &2), 300);
+	});
+}
+
+#[test]
+fn hard_cap_limits_raised() {
+	new_test_ext().execute_with(|| {
+		let options = FundOptions { hard_cap: true, ..Default::default() };
+		assert_ok!(Crowdfund::create(Origin::signed(1), 2, 1000, 9, vec![], options));
+
+		assert_ok!(Crowdfund::contribute(Origin::signed(3), 0, 900, None));
+		assert_noop!(
+			Crowdfund::contribute(Origin::signed(2), 0, 200, None),
+			Error::<Test>::HardCapExceeded
+		);
+		assert_ok!(Crowdfund::contribute(Origin::signed(2), 0, 100, None));
+		assert_eq!(Crowdfund::funds(0).unwrap().raised, 1000);
+	});
+}
+
+#[test]
+fn soft_cap_accepts_more_than_goal() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Crowdfund::create(Origin::signed(1), 20, 1000, 9, vec![], FundOptions::default()));
+
+		assert_ok!(Crowdfund::contribute(Origin::signed(3), 0, 900, None));
+		assert_ok!(Crowdfund::contribute(Origin::signed(2), 0, 200, None));
+		assert_eq!(Crowdfund::funds(0).unwrap().raised, 1100);
+
+		// The beneficiary receives everything that was raised
+		run_to_block(10);
+		assert_ok!(Crowdfund::dispense(Origin::signed(7), 0));
+		assert_eq!(Balances::free_balance(20), 1100);
+	});
+}
+
+#[test]
+fn whitelist_is_enforced() {
+	new_test_ext().execute_with(|| {
+		let options = FundOptions { whitelisted: true, ..Default::default() };
+		assert_ok!(Crowdfund::create(Origin::signed(1), 2, 1000, 9, vec![], options));
+
+		assert_ok!(Crowdfund::contribute(Origin::signed(2), 0, 100, None));
+		assert_noop!(
+			Crowdfund::contribute(Origin::signed(3), 0, 100, None),
+			Error::<Test>::NotWhitelisted
+		);
+	});
+}
+
+#[test]
+fn memo_is_stored_with_contribution() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Crowdfund::create(Origin::signed(1), 2, 1000, 9, vec![], FundOptions::default()));
+
+		assert_ok!(Crowdfund::contribute(Origin::signed(2), 0, 100, Some(b"go team".to_vec())));
+		// Contributing again without a memo keeps the previous one
+		assert_ok!(Crowdfund::contribute(Origin::signed(2), 0, 50, None));
+		assert_eq!(
+			Crowdfund::contribution_info(0, &2),
+			Contribution { amount: 150, memo: b"go team".to_vec() }
+		);
+
+		assert_noop!(
+			Crowdfund::contribute(Origin::signed(2), 0, 100, Some(b"too long memo".to_vec())),
+			Error::<Test>::MemoTooLong
+		);
+	});
+}
+
+#[test]
+fn transfer_contribution_works() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(Crowdfund::create(Origin::signed(1), 2, 1000, 9, vec![], FundOptions::default()));
+		assert_ok!(Crowdfund::contribute(Origin::signed(2), 0, 300, Some(b"memo".to_vec())));
+
+		// Part of the contribution is sold to account 4; account 2 keeps its memo
+		assert_ok!(Crowdfund::transfer_contribution(Origin::signed(2), 0, 4, 100));
+		assert_eq!(
+			Crowdfund::contribution_info(0, &2),
+			Contribution { amount: 200, memo: b"memo".to_vec() }
+		);
+		assert_eq!(Crowdfund::contribution_get(0, &4), 100);
+		assert_eq!(Crowdfund::funds(0).unwrap().raised, 300);
+
+		// Transferring the rest removes account 2's entry
+		assert_ok!(Crowdfund::transfer_contribution(Origin::signed(2), 0, 4, 200));
+		assert_eq!(Crowdfund::contribution_get(0, &4), 300);
+		assert_eq!(Crowdfund::contribution_info(0, &2), Contribution::default());
+
+		// The new holder receives the refund of the unsuccessful fund
+		run_to_block(10);
+		assert_noop!(
+			Crowdfund::withdraw(Origin::signed(2), 0),
+			Error::<Test>::NoContribution
+		);
+		assert_ok!(Crowdfund::withdraw(Origin::signed(4), 0));
+		assert_eq!(Balances::free_balance(4), 4300);
+	});
+}
+
+#[test]
+fn transfer_contribution_handles_errors() {
+	new_test_ext().execute_with(|| {
+		let options = FundOptions { max_contribution: Some(300), whitelisted: true, ..Default::default() };
+		assert_ok!(Crowdfund::create(Origin::signed(1), 2, 1000, 9, vec![], options));
+		assert_ok!(Crowdfund::contribute(Origin::signed(1), 0, 200, None));
+		assert_ok!(Crowdfund::contribute(Origin::signed(2), 0, 200, None));
+
+		assert_noop!(
+			Crowdfund::transfer_contribution(Origin::signed(1), 0, 1, 100),
+			Error::<Test>::SelfTransfer
+		);
+		assert_noop!(
+			Crowdfund::transfer_contribution(Origin::signed(3), 0, 1, 100),
+			Error::<Test>::NoContribution
+		);
+		assert_noop!(
+			Crowdfund::transfer_contribution(Origin::signed(1), 0, 2, 300),
+			Error::<Test>::InsufficientContribution
+		);
+		// Neither the amount moved nor the amount left behind may be dust
+		assert_noop!(
+			Crowdfund::transfer_contribution(Origin::signed(1), 0, 4, 0),
+			Error::<Test>::ContributionTooSmall
+		);
+		assert_noop!(
+			Crowdfund::transfer_contribution(Origin::signed(1), 0, 4, 5),
+			Error::<Test>::ContributionTooSmall
+		);
+		assert_noop!(
+			Crowdfund::transfer_contribution(Origin::signed(1), 0, 4, 195),
+			Error::<Test>::ContributionTooSmall
+		);
+		// The recipient is bound by the fund's options
+		assert_noop!(
+			Crowdfund::transfer_contribution(Origin::signed(1), 0, 2, 200),
+			Error::<Test>::ContributionTooLarge
+		);
+		assert_noop!(
+			Crowdfund::transfer_contribution(Origin::signed(1), 0, 3, 100),
+			Error::<Test>::NotWhitelisted
+		);
+
+		// Transfers close once automatic refunds begin
+		run_to_block(13);
+		assert_ok!(Crowdfund::transfer_contribution(Origin::signed(1), 0, 2, 50));
+		run_to_block(14);
+		assert_noop!(
+			Crowdfund::transfer_contribution(Origin::signed(2), 0, 1, 50),
+			Error::<Test>::TransfersClosed
+		);
+	});
+}
+
+#[test]
+fn milestone_fund_transfers_only_while_refunding() {
+	new_test_ext().execute_with(|| {
+		successful_milestone_fund();
+		assert_noop!(
+			Crowdfund::transfer_contribution(Origin::signed(1), 0, 4, 100),
+			Error::<Test>::TransfersClosed
+		);
+
+		assert_ok!(Crowdfund::start_milestone_vote(Origin::signed(20), 0));
+		assert_ok!(Crowdfund::vote_milestone(Origin::signed(3), 0, false));
+		run_to_block(13);
+		assert_ok!(Crowdfund::conclude_milestone_vote(Origin::signed(7), 0));
+
+		// Account 4 bought account 1's position and receives its share of the refund
+		assert_ok!(Crowdfund::transfer_contribution(Origin::signed(1), 0, 4, 200));
+		assert_ok!(Crowdfund::withdraw(Origin::signed(4), 0));
+		assert_eq!(Balances::free_balance(4), 4200);
+	});
+}
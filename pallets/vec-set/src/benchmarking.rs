@@ -0,0 +1,115 @@
+//! Benchmarks for the vec set, parameterized over the size of the set. Compare them with the
+//! benchmarks of the `map-set` pallet to see how the two sets scale.
+
+use super::*;
+use frame_benchmarking::{account, benchmarks};
+use frame_system::RawOrigin;
+
+const SEED: u32 = 0;
+
+/// Fills the set with `m` members other than the `new_member`
+fn fill_members<T: Trait>(m: u32) {
+	let mut members = (0..m)
+		.map(|i| account("member", i, SEED))
+		.collect::<Vec<T::AccountId>>();
+	members.sort();
+	Members::<T>::put(members);
+}
+
+benchmarks! {
+	_ { }
+
+	add_member {
+		let m in 0 .. T::MaxMembers::get() - 1;
+		fill_members::<T>(m);
+		let new_member: T::AccountId = account("new_member", 0, SEED);
+		let origin = T::AddOrigin::successful_origin();
+	}: {
+		Module::<T>::add_member(origin, new_member.clone())?;
+	}
+	verify {
+		assert!(Module::<T>::is_member(&new_member));
+	}
+
+	remove_member {
+		let m in 0 .. T::MaxMembers::get() - 1;
+		fill_members::<T>(m);
+		let old_member: T::AccountId = account("new_member", 0, SEED);
+		Module::<T>::add_member(T::AddOrigin::successful_origin(), old_member.clone())?;
+		let origin = T::RemoveOrigin::successful_origin();
+	}: {
+		Module::<T>::remove_member(origin, old_member.clone())?;
+	}
+	verify {
+		assert!(!Module::<T>::is_member(&old_member));
+	}
+
+	// The add origin must also pass as the remove origin, as it does in the recipe runtimes
+	swap_member {
+		let m in 1 .. T::MaxMembers::get();
+		fill_members::<T>(m);
+		let old_member: T::AccountId = account("member", 0, SEED);
+		let new_member: T::AccountId = account("new_member", 0, SEED);
+		let origin = T::AddOrigin::successful_origin();
+	}: {
+		Module::<T>::swap_member(origin, old_member.clone(), new_member.clone())?;
+	}
+	verify {
+		assert!(!Module::<T>::is_member(&old_member));
+		assert!(Module::<T>::is_member(&new_member));
+	}
+
+	reset_members {
+		let m in 0 .. T::MaxMembers::get();
+		let n in 0 .. T::MaxMembers::get();
+		fill_members::<T>(m);
+		let new_members = (0..n)
+			.map(|i| account("new_member", i, SEED))
+			.collect::<Vec<T::AccountId>>();
+		let origin = T::AddOrigin::successful_origin();
+	}: {
+		Module::<T>::reset_members(origin, new_members)?;
+	}
+	verify {
+		assert_eq!(Module::<T>::accounts().len() as u32, n);
+	}
+
+	change_key {
+		let m in 1 .. T::MaxMembers::get();
+		fill_members::<T>(m);
+		let old_member: T::AccountId = account("member", 0, SEED);
+		let new_member: T::AccountId = account("new_member", 0, SEED);
+	}: {
+		Module::<T>::change_key(RawOrigin::Signed(old_member.clone()).into(), new_member.clone())?;
+	}
+	verify {
+		assert!(Module::<T>::is_member(&new_member));
+	}
+
+	is_member {
+		let m in 1 .. T::MaxMembers::get();
+		fill_members::<T>(m);
+		let member: T::AccountId = account("member", m - 1, SEED);
+	}: {
+		assert!(Module::<T>::is_member(&member));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::tests::{ExternalityBuilder, TestRuntime};
+	use frame_support::assert_ok;
+
+	#[test]
+	fn benchmarks_work() {
+		ExternalityBuilder::build().execute_with(|| {
+			assert_ok!(test_benchmark_add_member::<TestRuntime>());
+			assert_ok!(test_benchmark_remove_member::<TestRuntime>());
+			assert_ok!(test_benchmark_swap_member::<TestRuntime>());
+			assert_ok!(test_benchmark_reset_members::<TestRuntime>());
+			assert_ok!(test_benchmark_change_key::<TestRuntime>());
+			assert_ok!(test_benchmark_is_member::<TestRuntime>());
+		});
+	}
+}
@@ -0,0 +1,51 @@
+//! Default weights of the vec set. Every call decodes and re-encodes the whole set, so each weight
+//! is a base cost plus a cost per member. The values are estimated by hand, not measured, and
+//! follow the shape of the benchmarks in `benchmarking.rs`. Runtimes should measure weights for
+//! their own hardware with the kitchen node's `benchmark` subcommand and implement `WeightInfo`
+//! with them.
+//!
+//! Each weight also covers the `OnMembersChanged` hook, assuming it deposits about one event per
+//! account that joins or leaves the set, as the loosely coupled check membership pallet does.
+
+use frame_support::weights::{constants::RocksDbWeight as DbWeight, Weight};
+
+/// The weight of the `OnMembersChanged` hook for `changed` accounts joining or leaving the set
+fn on_members_changed(changed: u32) -> Weight {
+	(5_000_000 as Weight)
+		.saturating_add(DbWeight::get().writes(1))
+		.saturating_mul(changed as Weight)
+}
+
+impl crate::WeightInfo for () {
+	fn add_member(m: u32) -> Weight {
+		(30_000_000 as Weight)
+			.saturating_add((150_000 as Weight).saturating_mul(m as Weight))
+			.saturating_add(DbWeight::get().reads_writes(1, 1))
+			.saturating_add(on_members_changed(1))
+	}
+	fn remove_member(m: u32) -> Weight {
+		(28_000_000 as Weight)
+			.saturating_add((150_000 as Weight).saturating_mul(m as Weight))
+			.saturating_add(DbWeight::get().reads_writes(1, 1))
+			.saturating_add(on_members_changed(1))
+	}
+	fn swap_member(m: u32) -> Weight {
+		(35_000_000 as Weight)
+			.saturating_add((250_000 as Weight).saturating_mul(m as Weight))
+			.saturating_add(DbWeight::get().reads_writes(1, 1))
+			.saturating_add(on_members_changed(2))
+	}
+	fn reset_members(m: u32, n: u32) -> Weight {
+		(30_000_000 as Weight)
+			.saturating_add((200_000 as Weight).saturating_mul(m as Weight))
+			.saturating_add((700_000 as Weight).saturating_mul(n as Weight))
+			.saturating_add(DbWeight::get().reads_writes(1, 1))
+			.saturating_add(on_members_changed(m.saturating_add(n)))
+	}
+	fn change_key(m: u32) -> Weight {
+		(32_000_000 as Weight)
+			.saturating_add((250_000 as Weight).saturating_mul(m as Weight))
+			.saturating_add(DbWeight::get().reads_writes(1, 1))
+			.saturating_add(on_members_changed(2))
+	}
+}
@@ -2,21 +2,56 @@
 
 //! A pallet that implements a storage set on top of a sorted vec and demonstrates performance
 //! tradeoffs when using map sets.
+//!
+//! The weights of the calls grow with the size of the set. They come from `WeightInfo`, which the
+//! benchmarks measure, and are evaluated at `MaxMembers`.
+//!
+//! Earlier versions of this pallet stored the members in insertion order. `on_runtime_upgrade`
+//! sorts such a set once, because the binary searches rely on the order.
 
-use frame_support::{decl_error, decl_event, decl_module, decl_storage, dispatch::DispatchResult, ensure};
+use frame_support::{
+	decl_error, decl_event, decl_module, decl_storage, dispatch::DispatchResult, ensure,
+	traits::{EnsureOrigin, Get},
+	weights::Weight,
+};
 use frame_system::{self as system, ensure_signed};
 use sp_std::prelude::*;
 use sp_std::collections::btree_set::BTreeSet;
-use account_set::AccountSet;
+use account_set::{AccountSet, AccountSetExt, OnMembersChanged};
 
+#[cfg(feature = "runtime-benchmarks")]
+mod benchmarking;
+mod default_weights;
 #[cfg(test)]
 mod tests;
 
-/// A maximum number of members. When membership reaches this number, no new members may join.
-pub const MAX_MEMBERS: usize = 16;
+/// The weights of this pallet's calls, as functions of the number of members
+pub trait WeightInfo {
+	fn add_member(m: u32) -> Weight;
+	fn remove_member(m: u32) -> Weight;
+	fn swap_member(m: u32) -> Weight;
+	fn reset_members(m: u32, n: u32) -> Weight;
+	fn change_key(m: u32) -> Weight;
+}
 
 pub trait Trait: system::Trait {
 	type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
+
+	/// A maximum number of members. When membership reaches this number, no new members may join.
+	type MaxMembers: Get<u32>;
+
+	/// The origin that may add members. Swapping and resetting members requires both origins.
+	type AddOrigin: EnsureOrigin<Self::Origin>;
+
+	/// The origin that may remove members
+	type RemoveOrigin: EnsureOrigin<Self::Origin>;
+
+	/// A hook that is notified whenever accounts join or leave the set. Its cost is part of the
+	/// weights in `WeightInfo`.
+	type OnMembersChanged: OnMembersChanged<Self::AccountId>;
+
+	/// The weights of this pallet's calls
+	type WeightInfo: WeightInfo;
 }
 
 decl_storage! {
@@ -35,14 +70,20 @@ decl_event!(
 		MemberAdded(AccountId),
 		/// Removed a member
 		MemberRemoved(AccountId),
+		/// Swapped a member for a new one (removed, added)
+		MembersSwapped(AccountId, AccountId),
+		/// Replaced the whole membership
+		MembersReset,
+		/// A member changed their account (old, new)
+		KeyChanged(AccountId, AccountId),
 	}
 );
 
 decl_error! {
 	pub enum Error for Module<T: Trait> {
-		/// Cannot join as a member because you are already a member
+		/// Cannot add the account as a member because it is already a member
 		AlreadyMember,
-		/// Cannot give up membership because you are not currently a member
+		/// Cannot remove the account because it is not currently a member
 		NotMember,
 		/// Cannot add another member because the limit is already reached
 		MembershipLimitReached,
@@ -55,25 +96,46 @@ decl_module! {
 
 		type Error = Error<T>;
 
+		/// A maximum number of members
+		const MaxMembers: u32 = T::MaxMembers::get();
+
+		/// Sorts and deduplicates a set that was stored by an earlier version of this pallet.
+		/// The set is only written if it was out of order.
+		fn on_runtime_upgrade() -> Weight {
+			let mut members = Members::<T>::get();
+			if members.windows(2).all(|pair| pair[0] < pair[1]) {
+				return T::DbWeight::get().reads(1);
+			}
+
+			members.sort();
+			members.dedup();
+			Members::<T>::put(members);
+			T::DbWeight::get().reads_writes(1, 1)
+		}
+
 		/// Adds a member to the membership set unless the max is reached
-		#[weight = 10_000]
-		pub fn add_member(origin) -> DispatchResult {
-			let new_member = ensure_signed(origin)?;
+		#[weight = T::WeightInfo::add_member(T::MaxMembers::get())]
+		pub fn add_member(origin, new_member: T::AccountId) -> DispatchResult {
+			T::AddOrigin::ensure_origin(origin)?;
 
 			let mut members = Members::<T>::get();
-			ensure!(members.len() < MAX_MEMBERS, Error::<T>::MembershipLimitReached);
+			ensure!(
+				(members.len() as u32) < T::MaxMembers::get(),
+				Error::<T>::MembershipLimitReached
+			);
 
 			// We don't want to add duplicate members, so we check whether the potential new
 			// member is already present in the list. Because the list is always ordered, we can
 			// leverage the binary search which makes this check O(log n).
 			match members.binary_search(&new_member) {
-				// If the search succeeds, the caller is already a member, so just return
+				// If the search succeeds, the account is already a member, so just return
 				Ok(_) => Err(Error::<T>::AlreadyMember.into()),
-				// If the search fails, the caller is not a member and we learned the index where
-				// they should be inserted
+				// If the search fails, the account is not a member and we learned the index where
+				// it should be inserted
 				Err(index) => {
 					members.insert(index, new_member.clone());
 					Members::<T>::put(members);
+					T::OnMembersChanged::on_members_changed(&[new_member.clone()], &[]);
 					Self::deposit_event(RawEvent::MemberAdded(new_member));
 					Ok(())
 				}
@@ -81,30 +143,119 @@ decl_module! {
 		}
 
 		/// Removes a member.
-		#[weight = 10_000]
-		fn remove_member(origin) -> DispatchResult {
-			let old_member = ensure_signed(origin)?;
+		#[weight = T::WeightInfo::remove_member(T::MaxMembers::get())]
+		fn remove_member(origin, old_member: T::AccountId) -> DispatchResult {
+			T::RemoveOrigin::ensure_origin(origin)?;
 
 			let mut members = Members::<T>::get();
 
 			// We have to find out if the member exists in the sorted vec, and, if so, where.
 			match members.binary_search(&old_member) {
-				// If the search succeeds, the caller is a member, so remove her
+				// If the search succeeds, the account is a member, so remove it
 				Ok(index) => {
 					members.remove(index);
 					Members::<T>::put(members);
+					T::OnMembersChanged::on_members_changed(&[], &[old_member.clone()]);
 					Self::deposit_event(RawEvent::MemberRemoved(old_member));
 					Ok(())
 				},
-				// If the search fails, the caller is not a member, so just return
+				// If the search fails, the account is not a member, so just return
 				Err(_) => Err(Error::<T>::NotMember.into()),
 			}
 		}
 
+		/// Replaces the member `remove` with the new member `add` in a single call.
+		/// The size of the set does not change, so this works even when the set is full.
+		#[weight = T::WeightInfo::swap_member(T::MaxMembers::get())]
+		fn swap_member(origin, remove: T::AccountId, add: T::AccountId) -> DispatchResult {
+			T::AddOrigin::ensure_origin(origin.clone())?;
+			T::RemoveOrigin::ensure_origin(origin)?;
+
+			if remove == add {
+				return Ok(());
+			}
+
+			let mut members = Members::<T>::get();
+			let remove_index = members.binary_search(&remove).map_err(|_| Error::<T>::NotMember)?;
+			members.remove(remove_index);
+			// A failed search tells us where to insert the new member
+			let add_index = members.binary_search(&add).err().ok_or(Error::<T>::AlreadyMember)?;
+			members.insert(add_index, add.clone());
+			Members::<T>::put(members);
+
+			T::OnMembersChanged::on_members_changed(&[add.clone()], &[remove.clone()]);
+			Self::deposit_event(RawEvent::MembersSwapped(remove, add));
+			Ok(())
+		}
+
+		/// Replaces the whole membership with `members`. Duplicate accounts are only added once.
+		#[weight = T::WeightInfo::reset_members(T::MaxMembers::get(), members.len() as u32)]
+		fn reset_members(origin, members: Vec<T::AccountId>) -> DispatchResult {
+			T::AddOrigin::ensure_origin(origin.clone())?;
+			T::RemoveOrigin::ensure_origin(origin)?;
+
+			let mut new_members = members;
+			new_members.sort();
+			new_members.dedup();
+			ensure!(
+				(new_members.len() as u32) <= T::MaxMembers::get(),
+				Error::<T>::MembershipLimitReached
+			);
+
+			let old_members = Members::<T>::get();
+			let (incoming, outgoing) = Self::changes(&old_members, &new_members);
+			Members::<T>::put(new_members);
+
+			T::OnMembersChanged::on_members_changed(&incoming, &outgoing);
+			Self::deposit_event(RawEvent::MembersReset);
+			Ok(())
+		}
+
+		/// Lets a member move their membership to a new account.
+		#[weight = T::WeightInfo::change_key(T::MaxMembers::get())]
+		fn change_key(origin, new: T::AccountId) -> DispatchResult {
+			let old = ensure_signed(origin)?;
+
+			if old == new {
+				return Ok(());
+			}
+
+			let mut members = Members::<T>::get();
+			let old_index = members.binary_search(&old).map_err(|_| Error::<T>::NotMember)?;
+			members.remove(old_index);
+			// A failed search tells us where to insert the new member
+			let new_index = members.binary_search(&new).err().ok_or(Error::<T>::AlreadyMember)?;
+			members.insert(new_index, new.clone());
+			Members::<T>::put(members);
+
+			T::OnMembersChanged::on_members_changed(&[new.clone()], &[old.clone()]);
+			Self::deposit_event(RawEvent::KeyChanged(old, new));
+			Ok(())
+		}
+
 		// also see `append_or_insert`, `append_or_put` in pallet-elections/phragmen, democracy
 	}
 }
 
+impl<T: Trait> Module<T> {
+	/// Checks whether `who` is a member. Because the list is always ordered, this is a binary
+	/// search after a single storage read.
+	pub fn is_member(who: &T::AccountId) -> bool {
+		Self::members().binary_search(who).is_ok()
+	}
+
+	/// Compares two sorted lists of members, returning the accounts that are only in `new`
+	/// (incoming) and the accounts that are only in `old` (outgoing).
+	fn changes(
+		old: &[T::AccountId],
+		new: &[T::AccountId],
+	) -> (Vec<T::AccountId>, Vec<T::AccountId>) {
+		let incoming = new.iter().filter(|m| old.binary_search(m).is_err()).cloned().collect();
+		let outgoing = old.iter().filter(|m| new.binary_search(m).is_err()).cloned().collect();
+		(incoming, outgoing)
+	}
+}
+
 impl<T: Trait> AccountSet for Module<T> {
 	type AccountId = T::AccountId;
 
@@ -112,3 +263,9 @@ impl<T: Trait> AccountSet for Module<T> {
 		Self::members().into_iter().collect::<BTreeSet<_>>()
 	}
 }
+
+impl<T: Trait> AccountSetExt for Module<T> {
+	fn contains(who: &T::AccountId) -> bool {
+		Self::is_member(who)
+	}
+}
@@ -23,7 +23,7 @@ use sp_runtime::{
 	transaction_validity::{TransactionSource, TransactionValidity},
 	ApplyExtrinsicResult, MultiSignature,
 };
-use sp_std::prelude::*;
+use sp_std::{collections::btree_set::BTreeSet, prelude::*};
 #[cfg(feature = "std")]
 use sp_version::NativeVersion;
 use sp_version::RuntimeVersion;
@@ -41,7 +41,7 @@ pub use frame_support::{
 };
 #[cfg(any(feature = "std", test))]
 pub use sp_runtime::BuildStorage;
-pub use sp_runtime::{Perbill, Permill};
+pub use sp_runtime::{FixedU128, Perbill, Permill};
 pub use pallet_timestamp::Call as TimestampCall;
 
 /// An index to a block.
@@ -226,6 +226,73 @@ impl sum_storage::Trait for Runtime {
 	type Event = Event;
 }
 
+/// This runtime has no membership pallet, so no account may contribute to whitelisted funds
+pub struct NoWhitelist;
+impl account_set::AccountSet for NoWhitelist {
+	type AccountId = AccountId;
+
+	fn accounts() -> BTreeSet<AccountId> {
+		BTreeSet::new()
+	}
+}
+impl account_set::AccountSetExt for NoWhitelist {
+	fn contains(_who: &AccountId) -> bool {
+		false
+	}
+}
+
+parameter_types! {
+	pub const SubmissionDeposit: u128 = 10;
+	pub const MinContribution: u128 = 10;
+	pub const RetirementPeriod: u32 = 10;
+	pub const MilestoneVotingPeriod: u32 = 10;
+	pub const MilestoneTimeout: u32 = 100;
+	pub const MaxMilestones: u32 = 10;
+	pub const RefundsPerBlock: u32 = 50;
+	pub const MaxRetirementsPerBlock: u32 = 50;
+	pub const MaxMemoLength: u32 = 64;
+}
+
+impl simple_crowdfund::Trait for Runtime {
+	type Event = Event;
+	type Currency = Balances;
+	type SubmissionDeposit = SubmissionDeposit;
+	type MinContribution = MinContribution;
+	type RetirementPeriod = RetirementPeriod;
+	type MilestoneVotingPeriod = MilestoneVotingPeriod;
+	type MilestoneTimeout = MilestoneTimeout;
+	type MaxMilestones = MaxMilestones;
+	type RefundsPerBlock = RefundsPerBlock;
+	type MaxRetirementsPerBlock = MaxRetirementsPerBlock;
+	type Whitelist = NoWhitelist;
+	type MaxMemoLength = MaxMemoLength;
+}
+
+parameter_types! {
+	pub const PriceHistoryDepth: BlockNumber = 14_400;
+	pub const PriceSmoothing: Permill = Permill::from_percent(10);
+}
+
+impl fixed_point::Trait for Runtime {
+	type Event = Event;
+	type HistoryDepth = PriceHistoryDepth;
+	type Smoothing = PriceSmoothing;
+	type OracleOrigin = system::EnsureRoot<AccountId>;
+}
+
+parameter_types! {
+	pub const MaxMembers: u32 = 1_000;
+}
+
+impl map_set::Trait for Runtime {
+	type Event = Event;
+	type MaxMembers = MaxMembers;
+	type AddOrigin = system::EnsureRoot<AccountId>;
+	type RemoveOrigin = system::EnsureRoot<AccountId>;
+	type OnMembersChanged = ();
+	type WeightInfo = ();
+}
+
 construct_runtime!(
 	pub enum Runtime where
 		Block = Block,
@@ -239,6 +306,9 @@ construct_runtime!(
 		Sudo: pallet_sudo::{Module, Call, Config<T>, Storage, Event<T>},
 		TransactionPayment: pallet_transaction_payment::{Module, Storage},
 		SumStorage: sum_storage::{Module, Call, Storage, Event},
+		SimpleCrowdfund: simple_crowdfund::{Module, Call, Storage, Event<T>},
+		FixedPoint: fixed_point::{Module, Call, Storage, Event},
+		MapSet: map_set::{Module, Call, Storage, Event<T>},
 	}
 );
 
@@ -342,6 +412,32 @@ impl_runtime_apis! {
 		}
 	}
 
+	impl map_set_runtime_api::MapSetApi<Block, AccountId> for Runtime {
+		fn members_paged(start_key: Option<AccountId>, limit: u32) -> Vec<AccountId> {
+			MapSet::members_paged(start_key, limit)
+		}
+	}
+
+	impl simple_crowdfund_runtime_api::CrowdfundApi<Block> for Runtime {
+		fn fund_trie_id(index: u32) -> Option<Vec<u8>> {
+			SimpleCrowdfund::fund_trie_id(index)
+		}
+
+		fn fund_trie_root(index: u32) -> Option<Vec<u8>> {
+			SimpleCrowdfund::fund_trie_root(index)
+		}
+	}
+
+	impl fixed_point_runtime_api::FixedPointApi<Block, BlockNumber> for Runtime {
+		fn time_weighted_average(from: BlockNumber, to: BlockNumber) -> Option<FixedU128> {
+			FixedPoint::time_weighted_average(from, to)
+		}
+
+		fn moving_average() -> Option<FixedU128> {
+			FixedPoint::moving_average()
+		}
+	}
+
 	impl sp_session::SessionKeys<Block> for Runtime {
 		fn generate_session_keys(_seed: Option<Vec<u8>>) -> Vec<u8> {
 			Vec::new()
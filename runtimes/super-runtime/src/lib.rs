@@ -236,13 +236,36 @@ impl basic_token::Trait for Runtime {
 	type Event = Event;
 }
 
+parameter_types! {
+	pub const GrantProposalBond: u128 = 100;
+	pub const GrantVotingPeriod: u32 = 20;
+	pub const CharitySpendPeriod: u32 = 50;
+	pub const CharityBudget: Permill = Permill::from_percent(10);
+	pub const CharityBurn: Permill = Permill::from_percent(1);
+	pub const MaxGrantPayoutPeriods: u32 = 10;
+	pub DonorTiers: Vec<Balance> = vec![1_000, 10_000, 100_000];
+	pub const RecurringDonationDeposit: u128 = 100;
+	pub const MaxRecurringDonationsPerBlock: u32 = 50;
+}
+
 impl charity::Trait for Runtime {
 	type Event = Event;
 	type Currency = Balances;
+	type Members = VecSet;
+	type ProposalBond = GrantProposalBond;
+	type VotingPeriod = GrantVotingPeriod;
+	type SpendPeriod = CharitySpendPeriod;
+	type Budget = CharityBudget;
+	type Burn = CharityBurn;
+	type MaxPayoutPeriods = MaxGrantPayoutPeriods;
+	type DonorTiers = DonorTiers;
+	type RecurringDeposit = RecurringDonationDeposit;
+	type MaxRecurringPerBlock = MaxRecurringDonationsPerBlock;
 }
 
 impl compounding_interest::Trait for Runtime {
 	type Event = Event;
+	type Currency = Balances;
 }
 
 parameter_types! {
@@ -283,8 +306,16 @@ impl double_map::Trait for Runtime {
 	type Event = Event;
 }
 
+parameter_types! {
+	pub const PriceHistoryDepth: BlockNumber = 14_400;
+	pub const PriceSmoothing: Permill = Permill::from_percent(10);
+}
+
 impl fixed_point::Trait for Runtime {
 	type Event = Event;
+	type HistoryDepth = PriceHistoryDepth;
+	type Smoothing = PriceSmoothing;
+	type OracleOrigin = system::EnsureRoot<AccountId>;
 }
 
 impl generic_event::Trait for Runtime {
@@ -293,6 +324,24 @@ impl generic_event::Trait for Runtime {
 
 impl hello_substrate::Trait for Runtime {}
 
+parameter_types! {
+	pub const JobDeposit: Balance = 1_000;
+	pub const JobQueueCapacity: u16 = 100;
+	/// Scheduled jobs may use up to 10% of the block weight.
+	pub JobWeightLimit: Weight = Perbill::from_percent(10) * MaximumBlockWeight::get();
+	pub const MaxJobRetries: u8 = 3;
+}
+
+impl job_queue::Trait for Runtime {
+	type Event = Event;
+	type Call = Call;
+	type Currency = Balances;
+	type JobDeposit = JobDeposit;
+	type QueueCapacity = JobQueueCapacity;
+	type MaxBlockWeight = JobWeightLimit;
+	type MaxRetries = MaxJobRetries;
+}
+
 // The following two configuration traits are for two different instances of the last-caller pallet
 impl last_caller::Trait<last_caller::Instance1> for Runtime {
 	type Event = Event;
@@ -302,23 +351,90 @@ impl last_caller::Trait<last_caller::Instance2> for Runtime {
 	type Event = Event;
 }
 
+parameter_types! {
+	pub const LotteryTicketPrice: Balance = 1_000;
+	pub const LotteryRoundLength: BlockNumber = 100;
+	// Two rounds of the randomness beacon, so that a whole beacon round starts after ticket sales
+	// close and ends before the draw. Both lengths are multiples of a beacon round.
+	pub const LotteryDrawDelay: BlockNumber = 40;
+	pub const LotteryFee: Permill = Permill::from_percent(5);
+	pub const MaxLotteryTickets: u32 = 1_000;
+}
+
+impl lottery::Trait for Runtime {
+	type Event = Event;
+	type Currency = Balances;
+	type RandomnessSource = RandomnessBeacon;
+	type TicketPrice = LotteryTicketPrice;
+	type RoundLength = LotteryRoundLength;
+	type DrawDelay = LotteryDrawDelay;
+	type Fee = LotteryFee;
+	type FeeDestination = Charity;
+	type MaxTickets = MaxLotteryTickets;
+}
+
+// Both sets share the same bound, so their benchmarks can be compared directly
+parameter_types! {
+	pub const MaxMembers: u32 = 1_000;
+}
+
 impl map_set::Trait for Runtime {
 	type Event = Event;
+	type MaxMembers = MaxMembers;
+	type AddOrigin = system::EnsureRoot<AccountId>;
+	type RemoveOrigin = system::EnsureRoot<AccountId>;
+	type OnMembersChanged = ();
+	type WeightInfo = ();
+}
+
+parameter_types! {
+	pub const QueueCapacity: u16 = 1_000;
+	pub const QueueOverflowPolicy: ringbuffer_queue::OverflowPolicy =
+		ringbuffer_queue::OverflowPolicy::Overwrite;
+	pub const QueueDeposit: Balance = 1_000;
 }
 
 impl ringbuffer_queue::Trait for Runtime {
 	type Event = Event;
+	type Capacity = QueueCapacity;
+	type OverflowPolicy = QueueOverflowPolicy;
+	type Currency = Balances;
+	type QueueDeposit = QueueDeposit;
 }
 
 impl randomness::Trait for Runtime {
 	type Event = Event;
-	type RandomnessSource = RandomnessCollectiveFlip;
+	type RandomnessSource = RandomnessBeacon;
+}
+
+parameter_types! {
+	pub const BeaconCommitDeposit: Balance = 1_000;
+	pub const BeaconCommitPeriod: BlockNumber = 10;
+	pub const BeaconRevealPeriod: BlockNumber = 10;
+	pub const MaxBeaconParticipants: u32 = 100;
+}
+
+impl randomness_beacon::Trait for Runtime {
+	type Event = Event;
+	type Currency = Balances;
+	type CommitDeposit = BeaconCommitDeposit;
+	type CommitPeriod = BeaconCommitPeriod;
+	type RevealPeriod = BeaconRevealPeriod;
+	type MaxParticipants = MaxBeaconParticipants;
+	// Deposits of participants who do not reveal go to the charity
+	type Slash = Charity;
 }
 
 parameter_types! {
 	pub const SubmissionDeposit: u128 = 10;
 	pub const MinContribution: u128 = 10;
 	pub const RetirementPeriod: u32 = 10;
+	pub const MilestoneVotingPeriod: u32 = 10;
+	pub const MilestoneTimeout: u32 = 100;
+	pub const MaxMilestones: u32 = 10;
+	pub const RefundsPerBlock: u32 = 50;
+	pub const MaxRetirementsPerBlock: u32 = 50;
+	pub const MaxMemoLength: u32 = 64;
 }
 
 impl simple_crowdfund::Trait for Runtime {
@@ -327,6 +443,13 @@ impl simple_crowdfund::Trait for Runtime {
 	type SubmissionDeposit = SubmissionDeposit;
 	type MinContribution = MinContribution;
 	type RetirementPeriod = RetirementPeriod;
+	type MilestoneVotingPeriod = MilestoneVotingPeriod;
+	type MilestoneTimeout = MilestoneTimeout;
+	type MaxMilestones = MaxMilestones;
+	type RefundsPerBlock = RefundsPerBlock;
+	type MaxRetirementsPerBlock = MaxRetirementsPerBlock;
+	type Whitelist = VecSet;
+	type MaxMemoLength = MaxMemoLength;
 }
 
 impl simple_event::Trait for Runtime {
@@ -347,6 +470,13 @@ impl struct_storage::Trait for Runtime {
 
 impl vec_set::Trait for Runtime {
 	type Event = Event;
+	type MaxMembers = MaxMembers;
+	// Members are admitted and removed through sudo
+	type AddOrigin = system::EnsureRoot<AccountId>;
+	type RemoveOrigin = system::EnsureRoot<AccountId>;
+	// The loosely coupled check membership pallet reports when accounts gain or lose access
+	type OnMembersChanged = CheckMembershipLoose;
+	type WeightInfo = ();
 }
 
 // ---------------------- End of Recipe Pallet Configurations ----------------------
@@ -368,7 +498,7 @@ construct_runtime!(
 		Charity: charity::{Module, Call, Storage, Config, Event<T>},
 		CheckMembershipLoose: check_membership_loose::{Module, Call, Event<T>},
 		CheckMembershipTight: check_membership_tight::{Module, Call, Event<T>},
-		CompoundingInterest: compounding_interest::{Module, Call, Storage, Event},
+		CompoundingInterest: compounding_interest::{Module, Call, Storage, Event<T>},
 		ConstantConfig: constant_config::{Module, Call, Storage, Event},
 		DefaultInstance1: default_instance::{Module, Call, Storage, Event<T>},
 		DefaultInstance2: default_instance::<Instance2>::{Module, Call, Storage, Event<T>},
@@ -376,11 +506,14 @@ construct_runtime!(
 		FixedPoint: fixed_point::{Module, Call, Storage, Event},
 		HelloSubstrate: hello_substrate::{Module, Call},
 		GenericEvent: generic_event::{Module, Call, Event<T>},
+		JobQueue: job_queue::{Module, Call, Storage, Event<T>},
 		LastCaller1: last_caller::<Instance1>::{Module, Call, Storage, Event<T>},
 		LastCaller2: last_caller::<Instance2>::{Module, Call, Storage, Event<T>},
+		Lottery: lottery::{Module, Call, Storage, Event<T>},
 		MapSet: map_set::{Module, Call, Storage, Event<T>},
 		RingbufferQueue: ringbuffer_queue::{Module, Call, Storage, Event<T>},
 		RandomnessDemo: randomness::{Module, Call, Storage, Event},
+		RandomnessBeacon: randomness_beacon::{Module, Call, Storage, Event<T>},
 		SimpleCrowdfund: simple_crowdfund::{Module, Call, Storage, Event<T>},
 		SimpleEvent: simple_event::{Module, Call, Event},
 		SimpleMap: simple_map::{Module, Call, Storage, Event<T>},
@@ -490,4 +623,23 @@ impl_runtime_apis! {
 			None
 		}
 	}
+
+	#[cfg(feature = "runtime-benchmarks")]
+	impl frame_benchmarking::Benchmark<Block> for Runtime {
+		fn dispatch_benchmark(
+			config: frame_benchmarking::BenchmarkConfig
+		) -> Result<Vec<frame_benchmarking::BenchmarkBatch>, sp_runtime::RuntimeString> {
+			use frame_benchmarking::{Benchmarking, BenchmarkBatch, add_benchmark};
+
+			let whitelist = vec![];
+			let params = (&config, &whitelist);
+			let mut batches = Vec::<BenchmarkBatch>::new();
+
+			add_benchmark!(params, batches, map_set, MapSet);
+			add_benchmark!(params, batches, vec_set, VecSet);
+
+			if batches.is_empty() { return Err("Benchmark not found for this pallet.".into()) }
+			Ok(batches)
+		}
+	}
 }
@@ -9,3 +9,28 @@ pub trait AccountSet {
 
 	fn accounts() -> BTreeSet<Self::AccountId>;
 }
+
+/// Extends the AccountSet trait with a membership check for a single account
+/// Sets that can answer it without building the whole set, for example from a storage map,
+/// should override the default implementation.
+pub trait AccountSetExt: AccountSet {
+	fn contains(who: &Self::AccountId) -> bool
+	where
+		Self::AccountId: Ord,
+	{
+		Self::accounts().contains(who)
+	}
+}
+
+/// Types that need to react when accounts join or leave a set implement the OnMembersChanged trait
+/// The trait is generic over the notion of Account used.
+pub trait OnMembersChanged<AccountId> {
+	/// Called after the set changed with the accounts that joined (`incoming`) and left
+	/// (`outgoing`) it.
+	fn on_members_changed(incoming: &[AccountId], outgoing: &[AccountId]);
+}
+
+/// The unit type ignores membership changes, for sets that nothing depends on
+impl<AccountId> OnMembersChanged<AccountId> for () {
+	fn on_members_changed(_incoming: &[AccountId], _outgoing: &[AccountId]) {}
+}